@@ -11,16 +11,42 @@ fn main() {
     } else if args[1].ends_with(".spv") {
         let input = fs::read(&args[1]).unwrap();
         naga::front::spirv::parse_u8_slice(&input).unwrap()
+    } else if args[1].ends_with(".spvasm") {
+        let input = fs::read_to_string(&args[1]).unwrap();
+        let words = naga::front::spvasm::assemble(&input).unwrap();
+        naga::front::spirv::Parser::new(words.into_iter()).parse().unwrap()
+    } else if args[1].ends_with(".ir") {
+        let input = fs::read_to_string(&args[1]).unwrap();
+        naga::front::ir::parse_str(&input).unwrap()
     } else if args[1].ends_with(".wgsl") {
         let input = fs::read_to_string(&args[1]).unwrap();
-        naga::front::wgsl::parse_str(&input).unwrap()
+        match naga::front::wgsl::parse_str(&input) {
+            Ok(module) => module,
+            Err(error) => {
+                eprint!("{}", error.render(&input));
+                std::process::exit(1)
+            }
+        }
+    } else if args[1].ends_with(".vert") || args[1].ends_with(".frag") || args[1].ends_with(".comp") {
+        let input = fs::read_to_string(&args[1]).unwrap();
+        let exec_model = if args[1].ends_with(".vert") {
+            spirv_headers::ExecutionModel::Vertex
+        } else if args[1].ends_with(".frag") {
+            spirv_headers::ExecutionModel::Fragment
+        } else {
+            spirv_headers::ExecutionModel::GLCompute
+        };
+        let options = naga::front::glsl::Options {
+            entry_point: (exec_model, "main".to_string()),
+            defines: Default::default(),
+        };
+        naga::front::glsl::parse_str(&input, &options).unwrap()
     } else {
         panic!("Unknown input: {:?}", args[1]);
     };
 
     if args.len() <= 2 {
-        println!("{:#?}", module);
-        return
+        print!("{}", naga::back::ir::write_string(&module).unwrap());
     } else if args[2].ends_with(".metal") {
         use naga::back::msl;
         let mut binding_map = msl::BindingMap::default();
@@ -37,6 +63,64 @@ fn main() {
         };
         let msl = msl::write_string(&module, options).unwrap();
         fs::write(&args[2], msl).unwrap();
+    } else if args[2].ends_with(".spv") {
+        use naga::back::spv;
+        let options = spv::Options {
+            debug_names: true,
+        };
+        let words = spv::write_vec(&module, &options).unwrap();
+        let bytes = words
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        fs::write(&args[2], bytes).unwrap();
+    } else if args[2].ends_with(".vert") || args[2].ends_with(".frag") || args[2].ends_with(".comp") {
+        use naga::back::glsl;
+        let exec_model = if args[2].ends_with(".vert") {
+            spirv_headers::ExecutionModel::Vertex
+        } else if args[2].ends_with(".frag") {
+            spirv_headers::ExecutionModel::Fragment
+        } else {
+            spirv_headers::ExecutionModel::GLCompute
+        };
+        let options = glsl::Options {
+            version: glsl::Version::Desktop(450),
+            entry_point: (exec_model, "main".to_owned()),
+        };
+        let glsl = glsl::write_string(&module, &options).unwrap();
+        fs::write(&args[2], glsl).unwrap();
+    } else if args[2].ends_with(".hlsl") {
+        use naga::back::hlsl;
+        let mut binding_map = hlsl::BindingMap::default();
+        binding_map.insert(
+            hlsl::BindSource { set: 0, binding: 0 },
+            hlsl::BindTarget { register: 0, space: 0 },
+        );
+        binding_map.insert(
+            hlsl::BindSource { set: 0, binding: 1 },
+            hlsl::BindTarget { register: 0, space: 0 },
+        );
+        let options = hlsl::Options {
+            shader_model: hlsl::ShaderModel::V5_1,
+            binding_map: &binding_map,
+        };
+        let hlsl = hlsl::write_string(&module, &options).unwrap();
+        fs::write(&args[2], hlsl).unwrap();
+    } else if args[2].ends_with(".spvasm") {
+        use naga::back::{spv, spvasm};
+        let options = spv::Options {
+            debug_names: true,
+        };
+        let words = spv::write_vec(&module, &options).unwrap();
+        fs::write(&args[2], spvasm::disassemble(&words).unwrap()).unwrap();
+    } else if args[2].ends_with(".ir") {
+        use naga::back::ir;
+        let text = ir::write_string(&module).unwrap();
+        fs::write(&args[2], text).unwrap();
+    } else if args[2].ends_with(".wgsl") {
+        use naga::back::wgsl;
+        let wgsl = wgsl::write_string(&module).unwrap();
+        fs::write(&args[2], wgsl).unwrap();
     } else {
         panic!("Unknown output: {:?}", args[2]);
     }
@@ -0,0 +1,1359 @@
+/*! OpenGL Shading Language (GLSL) backend
+
+GLSL describes a single shader stage, so the output is produced for one entry
+point of the module, which becomes the `main` function. Only the functions and
+the global variables it uses are written out.
+
+## Versions
+
+Both the desktop core profile, starting from 3.30, and GLSL ES, starting
+from 3.00, are supported. Anything the chosen version can't express results
+in `Error::MissingFeature`.
+
+Explicit locations of the variables passed between stages need 4.10 or ES 3.10.
+On the older versions these variables are matched by name instead, so they are
+named after their location.
+
+## Binding model
+
+OpenGL has no descriptor sets: only the `binding` part of `Binding::Descriptor`
+is used, and only on the versions supporting `layout(binding = N)`.
+Uniform and storage buffer structures are written as interface blocks.
+
+Images and samplers are separate in the IR, but combined in GLSL. Images are
+declared as samplers of the corresponding type, and sampler variables are omitted.
+!*/
+
+use std::fmt::{
+    Display, Error as FmtError, Formatter, Write,
+};
+
+use crate::{
+    back::{assign_names, call_order, global_value_type, NameKey, Namer},
+    proc::{ResolveContext, ResolveError, Typifier},
+    storage::Token,
+    FastHashMap, FastHashSet,
+};
+
+/// Version of the shading language.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Version {
+    /// Desktop GLSL with the core profile, e.g. `Desktop(330)`.
+    Desktop(u16),
+    /// GLSL ES, e.g. `Embedded(300)`.
+    Embedded(u16),
+}
+
+impl Version {
+    fn is_supported(&self) -> bool {
+        match *self {
+            Version::Desktop(v) => [330, 400, 410, 420, 430, 440, 450, 460].contains(&v),
+            Version::Embedded(v) => [300, 310, 320].contains(&v),
+        }
+    }
+
+    fn at_least(&self, desktop: u16, embedded: Option<u16>) -> bool {
+        match *self {
+            Version::Desktop(v) => v >= desktop,
+            Version::Embedded(v) => embedded.map_or(false, |e| v >= e),
+        }
+    }
+
+    fn supports(&self, feature: Feature) -> bool {
+        let (desktop, embedded) = match feature {
+            Feature::ComputeShader => (430, Some(310)),
+            Feature::StorageBuffer => (430, Some(310)),
+            Feature::DoublePrecision => (400, None),
+            Feature::ClipDistance => (330, None),
+            Feature::Texture1D => (330, None),
+            Feature::CubeArrayTexture => (400, Some(320)),
+            Feature::MultisampledTexture => (330, Some(310)),
+            Feature::MultisampledArrayTexture => (330, Some(320)),
+            Feature::MathFunction(fun) => match fun {
+                crate::MathFunction::Fma => (400, Some(320)),
+                crate::MathFunction::Ldexp |
+                crate::MathFunction::FindILsb |
+                crate::MathFunction::FindSMsb |
+                crate::MathFunction::FindUMsb |
+                crate::MathFunction::PackSnorm4x8 |
+                crate::MathFunction::PackUnorm4x8 |
+                crate::MathFunction::UnpackSnorm4x8 |
+                crate::MathFunction::UnpackUnorm4x8 => (400, Some(310)),
+                crate::MathFunction::PackUnorm2x16 |
+                crate::MathFunction::UnpackUnorm2x16 => (400, Some(300)),
+                crate::MathFunction::PackSnorm2x16 |
+                crate::MathFunction::UnpackSnorm2x16 |
+                crate::MathFunction::PackHalf2x16 |
+                crate::MathFunction::UnpackHalf2x16 => (420, Some(300)),
+                _ => (330, Some(300)),
+            },
+        };
+        self.at_least(desktop, embedded)
+    }
+
+    fn supports_explicit_bindings(&self) -> bool {
+        self.at_least(420, Some(310))
+    }
+
+    fn supports_varying_locations(&self) -> bool {
+        self.at_least(410, Some(310))
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match *self {
+            Version::Desktop(v) => write!(formatter, "{} core", v),
+            Version::Embedded(v) => write!(formatter, "{} es", v),
+        }
+    }
+}
+
+/// A construct that is only available in some of the versions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    ComputeShader,
+    StorageBuffer,
+    DoublePrecision,
+    ClipDistance,
+    Texture1D,
+    CubeArrayTexture,
+    MultisampledTexture,
+    MultisampledArrayTexture,
+    MathFunction(crate::MathFunction),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Format(FmtError),
+    UnsupportedVersion(Version),
+    MissingFeature(Feature),
+    MissingEntryPoint(spirv::ExecutionModel, String),
+    UnsupportedExecutionModel(spirv::ExecutionModel),
+    UnsupportedStorageClass(spirv::StorageClass),
+    UnsupportedBuiltIn(spirv::BuiltIn),
+    UnsupportedScalar(crate::ScalarKind, crate::Bytes),
+    UnsupportedImage(spirv::Dim, crate::ImageFlags),
+    UnsupportedConstant(Token<crate::Constant>),
+    /// Samplers only exist combined with the images they are used with.
+    SeparateSampler(Token<crate::Type>),
+    Type(ResolveError),
+}
+
+impl From<FmtError> for Error {
+    fn from(e: FmtError) -> Self {
+        Error::Format(e)
+    }
+}
+
+impl From<ResolveError> for Error {
+    fn from(e: ResolveError) -> Self {
+        Error::Type(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub version: Version,
+    /// Stage and name of the entry point to write.
+    pub entry_point: (spirv::ExecutionModel, String),
+}
+
+const NAME_MAIN: &str = "main";
+const NAME_LOOP_INIT: &str = "loop_init";
+const COMPONENTS: &[char] = &['x', 'y', 'z', 'w'];
+
+const RESERVED_NAMES: &[&str] = &[
+    NAME_MAIN, NAME_LOOP_INIT,
+    "active", "asm", "atomic_uint", "attribute", "bool", "break", "buffer",
+    "bvec2", "bvec3", "bvec4", "case", "cast", "centroid", "class", "coherent",
+    "common", "const", "continue", "default", "discard", "dmat2", "dmat3",
+    "dmat4", "do", "double", "dvec2", "dvec3", "dvec4", "else", "enum",
+    "extern", "external", "false", "filter", "fixed", "flat", "float", "for",
+    "goto", "half", "highp", "if", "in", "inline", "inout", "input", "int",
+    "interface", "invariant", "isampler2D", "isampler3D", "ivec2", "ivec3",
+    "ivec4", "layout", "long", "lowp", "mat2", "mat3", "mat4", "mediump",
+    "namespace", "noinline", "noperspective", "out", "output", "partition",
+    "patch", "precise", "precision", "public", "readonly", "resource",
+    "restrict", "return", "sample", "sampler1D", "sampler2D", "sampler3D",
+    "samplerCube", "shared", "short", "sizeof", "smooth", "static", "struct",
+    "subroutine", "superp", "switch", "template", "this", "true", "typedef",
+    "uint", "uniform", "union", "unsigned", "using", "usampler2D",
+    "usampler3D", "uvec2", "uvec3", "uvec4", "varying", "vec2", "vec3",
+    "vec4", "void", "volatile", "while", "writeonly",
+];
+
+#[derive(Clone, Copy, Debug)]
+struct Level(usize);
+impl Level {
+    fn next(&self) -> Self {
+        Level(self.0 + 1)
+    }
+}
+impl Display for Level {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
+        (0 .. self.0).try_for_each(|_| formatter.write_str("    "))
+    }
+}
+
+fn vector_size_string(size: crate::VectorSize) -> &'static str {
+    match size {
+        crate::VectorSize::Bi => "2",
+        crate::VectorSize::Tri => "3",
+        crate::VectorSize::Quad => "4",
+    }
+}
+
+fn math_function_name(fun: crate::MathFunction) -> &'static str {
+    match fun {
+        crate::MathFunction::Round => "round",
+        crate::MathFunction::RoundEven => "roundEven",
+        crate::MathFunction::Trunc => "trunc",
+        crate::MathFunction::FAbs => "abs",
+        crate::MathFunction::SAbs => "abs",
+        crate::MathFunction::FSign => "sign",
+        crate::MathFunction::SSign => "sign",
+        crate::MathFunction::Floor => "floor",
+        crate::MathFunction::Ceil => "ceil",
+        crate::MathFunction::Fract => "fract",
+        crate::MathFunction::Radians => "radians",
+        crate::MathFunction::Degrees => "degrees",
+        crate::MathFunction::Sin => "sin",
+        crate::MathFunction::Cos => "cos",
+        crate::MathFunction::Tan => "tan",
+        crate::MathFunction::Asin => "asin",
+        crate::MathFunction::Acos => "acos",
+        crate::MathFunction::Atan => "atan",
+        crate::MathFunction::Sinh => "sinh",
+        crate::MathFunction::Cosh => "cosh",
+        crate::MathFunction::Tanh => "tanh",
+        crate::MathFunction::Asinh => "asinh",
+        crate::MathFunction::Acosh => "acosh",
+        crate::MathFunction::Atanh => "atanh",
+        crate::MathFunction::Atan2 => "atan",
+        crate::MathFunction::Pow => "pow",
+        crate::MathFunction::Exp => "exp",
+        crate::MathFunction::Log => "log",
+        crate::MathFunction::Exp2 => "exp2",
+        crate::MathFunction::Log2 => "log2",
+        crate::MathFunction::Sqrt => "sqrt",
+        crate::MathFunction::InverseSqrt => "inversesqrt",
+        crate::MathFunction::Determinant => "determinant",
+        crate::MathFunction::MatrixInverse => "inverse",
+        crate::MathFunction::FMin => "min",
+        crate::MathFunction::UMin => "min",
+        crate::MathFunction::SMin => "min",
+        crate::MathFunction::FMax => "max",
+        crate::MathFunction::UMax => "max",
+        crate::MathFunction::SMax => "max",
+        crate::MathFunction::FClamp => "clamp",
+        crate::MathFunction::UClamp => "clamp",
+        crate::MathFunction::SClamp => "clamp",
+        crate::MathFunction::FMix => "mix",
+        crate::MathFunction::IMix => "mix",
+        crate::MathFunction::Step => "step",
+        crate::MathFunction::SmoothStep => "smoothstep",
+        crate::MathFunction::Fma => "fma",
+        crate::MathFunction::Ldexp => "ldexp",
+        crate::MathFunction::PackSnorm4x8 => "packSnorm4x8",
+        crate::MathFunction::PackUnorm4x8 => "packUnorm4x8",
+        crate::MathFunction::PackSnorm2x16 => "packSnorm2x16",
+        crate::MathFunction::PackUnorm2x16 => "packUnorm2x16",
+        crate::MathFunction::PackHalf2x16 => "packHalf2x16",
+        crate::MathFunction::UnpackSnorm2x16 => "unpackSnorm2x16",
+        crate::MathFunction::UnpackUnorm2x16 => "unpackUnorm2x16",
+        crate::MathFunction::UnpackHalf2x16 => "unpackHalf2x16",
+        crate::MathFunction::UnpackSnorm4x8 => "unpackSnorm4x8",
+        crate::MathFunction::UnpackUnorm4x8 => "unpackUnorm4x8",
+        crate::MathFunction::Length => "length",
+        crate::MathFunction::Distance => "distance",
+        crate::MathFunction::Cross => "cross",
+        crate::MathFunction::Normalize => "normalize",
+        crate::MathFunction::FaceForward => "faceforward",
+        crate::MathFunction::Reflect => "reflect",
+        crate::MathFunction::Refract => "refract",
+        crate::MathFunction::FindILsb => "findLSB",
+        crate::MathFunction::FindSMsb => "findMSB",
+        crate::MathFunction::FindUMsb => "findMSB",
+        crate::MathFunction::NMin => "min",
+        crate::MathFunction::NMax => "max",
+        crate::MathFunction::NClamp => "clamp",
+    }
+}
+
+struct FunctionContext<'a> {
+    token: Token<crate::Function>,
+    function: &'a crate::Function,
+    module: &'a crate::Module,
+}
+
+pub struct Writer<W> {
+    out: W,
+    version: Version,
+    exec_model: spirv::ExecutionModel,
+    entry_point_name: String,
+    names: FastHashMap<NameKey, String>,
+    /// Names of the members of input and output structures, which are
+    /// declared as separate variables.
+    member_names: FastHashMap<(Token<crate::GlobalVariable>, u32), String>,
+    typifier: Typifier,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W, options: &Options) -> Self {
+        Writer {
+            out,
+            version: options.version,
+            exec_model: options.entry_point.0,
+            entry_point_name: options.entry_point.1.clone(),
+            names: FastHashMap::default(),
+            member_names: FastHashMap::default(),
+            typifier: Typifier::new(),
+        }
+    }
+
+    pub fn finish(self) -> W {
+        self.out
+    }
+
+    fn require(&self, feature: Feature) -> Result<(), Error> {
+        if self.version.supports(feature) {
+            Ok(())
+        } else {
+            Err(Error::MissingFeature(feature))
+        }
+    }
+
+    fn built_in_name(&self, built_in: spirv::BuiltIn, class: spirv::StorageClass) -> Result<&'static str, Error> {
+        Ok(match built_in {
+            spirv::BuiltIn::Position
+                if self.exec_model == spirv::ExecutionModel::Fragment
+                && class == spirv::StorageClass::Input => "gl_FragCoord",
+            spirv::BuiltIn::Position => "gl_Position",
+            spirv::BuiltIn::PointSize => "gl_PointSize",
+            spirv::BuiltIn::ClipDistance => {
+                self.require(Feature::ClipDistance)?;
+                "gl_ClipDistance"
+            }
+            spirv::BuiltIn::VertexIndex => "gl_VertexID",
+            spirv::BuiltIn::InstanceIndex => "gl_InstanceID",
+            spirv::BuiltIn::FragCoord => "gl_FragCoord",
+            spirv::BuiltIn::FrontFacing => "gl_FrontFacing",
+            spirv::BuiltIn::FragDepth => "gl_FragDepth",
+            spirv::BuiltIn::LocalInvocationId => "gl_LocalInvocationID",
+            spirv::BuiltIn::LocalInvocationIndex => "gl_LocalInvocationIndex",
+            spirv::BuiltIn::GlobalInvocationId => "gl_GlobalInvocationID",
+            spirv::BuiltIn::WorkgroupId => "gl_WorkGroupID",
+            spirv::BuiltIn::NumWorkgroups => "gl_NumWorkGroups",
+            other => return Err(Error::UnsupportedBuiltIn(other)),
+        })
+    }
+
+    /// Checks if a variable of the given class is passed between shader stages.
+    fn is_varying(&self, class: spirv::StorageClass) -> bool {
+        matches!(
+            (self.exec_model, class),
+            (spirv::ExecutionModel::Vertex, spirv::StorageClass::Output) |
+            (spirv::ExecutionModel::Fragment, spirv::StorageClass::Input)
+        )
+    }
+
+    fn scalar_string(&self, kind: crate::ScalarKind, width: crate::Bytes) -> Result<&'static str, Error> {
+        match (kind, width) {
+            (crate::ScalarKind::Float, 32) => Ok("float"),
+            (crate::ScalarKind::Float, 64) => {
+                self.require(Feature::DoublePrecision)?;
+                Ok("double")
+            }
+            (crate::ScalarKind::Sint, 32) => Ok("int"),
+            (crate::ScalarKind::Uint, 32) => Ok("uint"),
+            (crate::ScalarKind::Bool, _) => Ok("bool"),
+            _ => Err(Error::UnsupportedScalar(kind, width)),
+        }
+    }
+
+    fn vector_prefix(&self, kind: crate::ScalarKind, width: crate::Bytes) -> Result<&'static str, Error> {
+        Ok(match self.scalar_string(kind, width)? {
+            "float" => "",
+            "double" => "d",
+            "int" => "i",
+            "uint" => "u",
+            _ => "b",
+        })
+    }
+
+    /// Writes the type without the array dimensions.
+    fn put_base_type(&mut self, ty: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        match module.types[ty].inner {
+            crate::TypeInner::Scalar { kind, width } => {
+                write!(self.out, "{}", self.scalar_string(kind, width)?)?;
+            }
+            crate::TypeInner::Vector { size, kind, width } => {
+                write!(self.out, "{}vec{}", self.vector_prefix(kind, width)?, vector_size_string(size))?;
+            }
+            crate::TypeInner::Matrix { columns, rows, kind, width } => {
+                let prefix = self.vector_prefix(kind, width)?;
+                write!(self.out, "{}mat{}x{}", prefix, vector_size_string(columns), vector_size_string(rows))?;
+            }
+            crate::TypeInner::Pointer { base, .. } |
+            crate::TypeInner::Array { base, .. } => {
+                self.put_base_type(base, module)?;
+            }
+            crate::TypeInner::Struct { .. } => {
+                write!(self.out, "{}", self.names[&NameKey::Type(ty)])?;
+            }
+            crate::TypeInner::Image { base, dim, flags } => {
+                let unsupported = Error::UnsupportedImage(dim, flags);
+                if !flags.contains(crate::ImageFlags::SAMPLED) {
+                    return Err(unsupported);
+                }
+                let (kind, width) = match module.types[base].inner {
+                    crate::TypeInner::Scalar { kind, width } |
+                    crate::TypeInner::Vector { kind, width, .. } => (kind, width),
+                    _ => return Err(unsupported),
+                };
+                let prefix = match kind {
+                    crate::ScalarKind::Float => "",
+                    crate::ScalarKind::Sint => "i",
+                    crate::ScalarKind::Uint => "u",
+                    crate::ScalarKind::Bool => return Err(Error::UnsupportedScalar(kind, width)),
+                };
+                let arrayed = flags.contains(crate::ImageFlags::ARRAYED);
+                let dim_str = match (dim, flags.contains(crate::ImageFlags::MULTISAMPLED)) {
+                    (spirv::Dim::Dim1D, false) => {
+                        self.require(Feature::Texture1D)?;
+                        "1D"
+                    }
+                    (spirv::Dim::Dim2D, false) => "2D",
+                    (spirv::Dim::Dim2D, true) => {
+                        self.require(if arrayed {
+                            Feature::MultisampledArrayTexture
+                        } else {
+                            Feature::MultisampledTexture
+                        })?;
+                        "2DMS"
+                    }
+                    (spirv::Dim::Dim3D, false) if !arrayed => "3D",
+                    (spirv::Dim::DimCube, false) => {
+                        if arrayed {
+                            self.require(Feature::CubeArrayTexture)?;
+                        }
+                        "Cube"
+                    }
+                    _ => return Err(unsupported),
+                };
+                write!(self.out, "{}sampler{}{}", prefix, dim_str, if arrayed { "Array" } else { "" })?;
+            }
+            crate::TypeInner::Sampler { .. } => return Err(Error::SeparateSampler(ty)),
+        }
+        Ok(())
+    }
+
+    /// Writes the array dimensions of the type, outermost first.
+    fn put_array_dimensions(&mut self, ty: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        if let crate::TypeInner::Array { base, size } = module.types[ty].inner {
+            match size {
+                crate::ArraySize::Static(length) => write!(self.out, "[{}]", length)?,
+                crate::ArraySize::Dynamic => write!(self.out, "[]")?,
+            }
+            self.put_array_dimensions(base, module)?;
+        }
+        Ok(())
+    }
+
+    fn put_type(&mut self, ty: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        self.put_base_type(ty, module)?;
+        self.put_array_dimensions(ty, module)
+    }
+
+    fn put_declaration(&mut self, ty: Token<crate::Type>, name: &str, module: &crate::Module) -> Result<(), Error> {
+        self.put_base_type(ty, module)?;
+        write!(self.out, " {}", name)?;
+        self.put_array_dimensions(ty, module)
+    }
+
+    fn put_constant(&mut self, token: Token<crate::Constant>, module: &crate::Module) -> Result<(), Error> {
+        match module.constants[token].inner {
+            crate::ConstantInner::Sint(value) => write!(self.out, "{}", value)?,
+            crate::ConstantInner::Uint(value) => write!(self.out, "{}u", value)?,
+            crate::ConstantInner::Float(value) => {
+                if !value.is_finite() {
+                    return Err(Error::UnsupportedConstant(token));
+                }
+                let text = value.to_string();
+                if text.contains('.') {
+                    write!(self.out, "{}", text)?;
+                } else {
+                    write!(self.out, "{}.0", text)?;
+                }
+            }
+            crate::ConstantInner::Bool(value) => write!(self.out, "{}", value)?,
+        }
+        Ok(())
+    }
+
+    fn put_arguments(
+        &mut self,
+        arguments: &[Token<crate::Expression>],
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        write!(self.out, "(")?;
+        for (i, &argument) in arguments.iter().enumerate() {
+            if i != 0 {
+                write!(self.out, ", ")?;
+            }
+            self.put_expression(argument, context)?;
+        }
+        write!(self.out, ")")?;
+        Ok(())
+    }
+
+    fn put_expression(
+        &mut self,
+        expr_token: Token<crate::Expression>,
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        let module = context.module;
+        let expression = &context.function.expressions[expr_token];
+        log::trace!("expression {:?}", expression);
+        match *expression {
+            crate::Expression::Access { base, index } => {
+                self.put_expression(base, context)?;
+                write!(self.out, "[")?;
+                self.put_expression(index, context)?;
+                write!(self.out, "]")?;
+            }
+            crate::Expression::AccessIndex { base, index } => {
+                // members of input and output structures are separate variables
+                if let crate::Expression::GlobalVariable(var_token) = context.function.expressions[base] {
+                    let var = &module.global_variables[var_token];
+                    let ty = global_value_type(var, module);
+                    if let crate::TypeInner::Struct { ref members } = module.types[ty].inner {
+                        if let Some(crate::Binding::BuiltIn(built_in)) = members[index as usize].binding {
+                            write!(self.out, "{}", self.built_in_name(built_in, var.class)?)?;
+                            return Ok(());
+                        }
+                        if let Some(name) = self.member_names.get(&(var_token, index)) {
+                            write!(self.out, "{}", name)?;
+                            return Ok(());
+                        }
+                    }
+                }
+                self.put_expression(base, context)?;
+                let (base_ty, base_inner) = match *self.typifier.get(base, &module.types) {
+                    crate::TypeInner::Pointer { base, .. } => (Some(base), &module.types[base].inner),
+                    ref other => (self.typifier.get_handle(base), other),
+                };
+                match *base_inner {
+                    crate::TypeInner::Struct { .. } => {
+                        let ty = base_ty.ok_or(ResolveError::InvalidAccessIndex(index))?;
+                        write!(self.out, ".{}", self.names[&NameKey::StructMember(ty, index)])?;
+                    }
+                    crate::TypeInner::Vector { .. } => {
+                        write!(self.out, ".{}", COMPONENTS[index as usize])?;
+                    }
+                    _ => {
+                        write!(self.out, "[{}]", index)?;
+                    }
+                }
+            }
+            crate::Expression::Constant(token) => {
+                self.put_constant(token, module)?;
+            }
+            crate::Expression::Compose { ty, ref components } => {
+                self.put_type(ty, module)?;
+                self.put_arguments(components, context)?;
+            }
+            crate::Expression::FunctionParameter(index) => {
+                let key = NameKey::FunctionParameter(context.token, index);
+                write!(self.out, "{}", self.names[&key])?;
+            }
+            crate::Expression::GlobalVariable(token) => {
+                let var = &module.global_variables[token];
+                match var.binding {
+                    Some(crate::Binding::BuiltIn(built_in)) => {
+                        write!(self.out, "{}", self.built_in_name(built_in, var.class)?)?;
+                    }
+                    _ => {
+                        write!(self.out, "{}", self.names[&NameKey::GlobalVariable(token)])?;
+                    }
+                }
+            }
+            crate::Expression::LocalVariable(token) => {
+                let key = NameKey::LocalVariable(context.token, token);
+                write!(self.out, "{}", self.names[&key])?;
+            }
+            crate::Expression::Load { pointer } => {
+                self.put_expression(pointer, context)?;
+            }
+            crate::Expression::ImageSample { image, coordinate, .. } => {
+                write!(self.out, "texture(")?;
+                self.put_expression(image, context)?;
+                write!(self.out, ", ")?;
+                self.put_expression(coordinate, context)?;
+                write!(self.out, ")")?;
+                self.put_depth_swizzle(image, context)?;
+            }
+            crate::Expression::ImageLoad { image, coordinate, index } => {
+                match index {
+                    Some(index) => {
+                        write!(self.out, "texelFetch(")?;
+                        self.put_expression(image, context)?;
+                        write!(self.out, ", ")?;
+                        self.put_expression(coordinate, context)?;
+                        write!(self.out, ", ")?;
+                        self.put_expression(index, context)?;
+                    }
+                    None => {
+                        write!(self.out, "imageLoad(")?;
+                        self.put_expression(image, context)?;
+                        write!(self.out, ", ")?;
+                        self.put_expression(coordinate, context)?;
+                    }
+                }
+                write!(self.out, ")")?;
+                self.put_depth_swizzle(image, context)?;
+            }
+            crate::Expression::ImageDimensions { image, level } => {
+                let (dim, flags) = match *self.typifier.get(image, &module.types) {
+                    crate::TypeInner::Image { dim, flags, .. } => (dim, flags),
+                    _ => return Err(Error::Type(ResolveError::InvalidImage(image))),
+                };
+                if !flags.contains(crate::ImageFlags::SAMPLED) {
+                    write!(self.out, "imageSize(")?;
+                    self.put_expression(image, context)?;
+                } else {
+                    write!(self.out, "textureSize(")?;
+                    self.put_expression(image, context)?;
+                    // multisampled textures have a single level
+                    if !flags.contains(crate::ImageFlags::MULTISAMPLED) {
+                        write!(self.out, ", ")?;
+                        match level {
+                            Some(level) => self.put_expression(level, context)?,
+                            None => write!(self.out, "0")?,
+                        }
+                    }
+                }
+                write!(self.out, ")")?;
+                // the array layers come last
+                if flags.contains(crate::ImageFlags::ARRAYED) {
+                    let size = match dim {
+                        spirv::Dim::Dim1D => 1,
+                        spirv::Dim::Dim3D => 3,
+                        _ => 2,
+                    };
+                    write!(self.out, ".{}", COMPONENTS[.. size].iter().collect::<String>())?;
+                }
+            }
+            crate::Expression::Unary { op, expr } => {
+                let op_str = match op {
+                    crate::UnaryOperator::Negate => "-",
+                    crate::UnaryOperator::Not => "!",
+                    crate::UnaryOperator::BitwiseNot => "~",
+                };
+                write!(self.out, "({}", op_str)?;
+                self.put_expression(expr, context)?;
+                write!(self.out, ")")?;
+            }
+            crate::Expression::Binary { op, left, right } => {
+                let (kind, size) = match *self.typifier.get(left, &module.types) {
+                    crate::TypeInner::Scalar { kind, .. } => (Some(kind), None),
+                    crate::TypeInner::Vector { kind, size, .. } => (Some(kind), Some(size as u8)),
+                    _ => (None, None),
+                };
+                let is_vector = size.is_some();
+                let is_float = kind == Some(crate::ScalarKind::Float);
+                // vectors are compared component-wise by functions
+                let function = match op {
+                    crate::BinaryOperator::Equal if is_vector => Some("equal"),
+                    crate::BinaryOperator::NotEqual if is_vector => Some("notEqual"),
+                    crate::BinaryOperator::Less if is_vector => Some("lessThan"),
+                    crate::BinaryOperator::LessEqual if is_vector => Some("lessThanEqual"),
+                    crate::BinaryOperator::Greater if is_vector => Some("greaterThan"),
+                    crate::BinaryOperator::GreaterEqual if is_vector => Some("greaterThanEqual"),
+                    _ => None,
+                };
+                if let Some(function) = function {
+                    write!(self.out, "{}", function)?;
+                    self.put_arguments(&[left, right], context)?;
+                    return Ok(());
+                }
+                if op == crate::BinaryOperator::Modulo && is_float {
+                    // `%` is only defined for integers, and `mod()` rounds
+                    // towards negative infinity, unlike the IR remainder
+                    write!(self.out, "(")?;
+                    self.put_expression(left, context)?;
+                    write!(self.out, " - ")?;
+                    self.put_expression(right, context)?;
+                    write!(self.out, " * trunc(")?;
+                    self.put_expression(left, context)?;
+                    write!(self.out, " / ")?;
+                    self.put_expression(right, context)?;
+                    write!(self.out, "))")?;
+                    return Ok(());
+                }
+                // `>>` follows the signedness of the left operand
+                let shift_cast = match (op, kind) {
+                    (crate::BinaryOperator::ShiftRightLogical, Some(crate::ScalarKind::Sint)) => Some(("int", "uint")),
+                    (crate::BinaryOperator::ShiftRightArithmetic, Some(crate::ScalarKind::Uint)) => Some(("uint", "int")),
+                    _ => None,
+                };
+                if let Some((result, operand)) = shift_cast {
+                    match size {
+                        Some(size) => write!(self.out, "{}vec{}({}vec{}(", &result[.. 1], size, &operand[.. 1], size)?,
+                        None => write!(self.out, "{}({}(", result, operand)?,
+                    }
+                    self.put_expression(left, context)?;
+                    write!(self.out, ") >> ")?;
+                    self.put_expression(right, context)?;
+                    write!(self.out, ")")?;
+                    return Ok(());
+                }
+                let op_str = match op {
+                    crate::BinaryOperator::Add => "+",
+                    crate::BinaryOperator::Subtract => "-",
+                    crate::BinaryOperator::Multiply => "*",
+                    crate::BinaryOperator::Divide => "/",
+                    crate::BinaryOperator::Modulo => "%",
+                    crate::BinaryOperator::Equal => "==",
+                    crate::BinaryOperator::NotEqual => "!=",
+                    crate::BinaryOperator::Less => "<",
+                    crate::BinaryOperator::LessEqual => "<=",
+                    crate::BinaryOperator::Greater => ">",
+                    crate::BinaryOperator::GreaterEqual => ">=",
+                    crate::BinaryOperator::And => "&",
+                    crate::BinaryOperator::ExclusiveOr => "^",
+                    crate::BinaryOperator::InclusiveOr => "|",
+                    crate::BinaryOperator::LogicalAnd => "&&",
+                    crate::BinaryOperator::LogicalOr => "||",
+                    crate::BinaryOperator::ShiftLeftLogical => "<<",
+                    crate::BinaryOperator::ShiftRightLogical => ">>",
+                    crate::BinaryOperator::ShiftRightArithmetic => ">>",
+                };
+                write!(self.out, "(")?;
+                self.put_expression(left, context)?;
+                write!(self.out, " {} ", op_str)?;
+                self.put_expression(right, context)?;
+                write!(self.out, ")")?;
+            }
+            crate::Expression::Call { function, ref arguments } => {
+                write!(self.out, "{}", self.names[&NameKey::Function(function)])?;
+                self.put_arguments(arguments, context)?;
+            }
+            crate::Expression::Math { fun, ref arguments } => {
+                self.require(Feature::MathFunction(fun))?;
+                write!(self.out, "{}", math_function_name(fun))?;
+                self.put_arguments(arguments, context)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_block(
+        &mut self,
+        level: Level,
+        statements: &[crate::Statement],
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        for statement in statements {
+            self.put_statement(level, statement, context)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the selection of the depth out of the texel, if the image holds depth values.
+    fn put_depth_swizzle(
+        &mut self,
+        image: Token<crate::Expression>,
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        match *self.typifier.get(image, &context.module.types) {
+            crate::TypeInner::Image { flags, .. } if flags.contains(crate::ImageFlags::DEPTH) => {
+                write!(self.out, ".x")?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn put_statement(
+        &mut self,
+        level: Level,
+        statement: &crate::Statement,
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        log::trace!("statement[{}] {:?}", level.0, statement);
+        match *statement {
+            crate::Statement::Block(ref block) => {
+                writeln!(self.out, "{}{{", level)?;
+                self.put_block(level.next(), block, context)?;
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::If { condition, ref accept, ref reject } => {
+                write!(self.out, "{}if (", level)?;
+                self.put_expression(condition, context)?;
+                writeln!(self.out, ") {{")?;
+                self.put_block(level.next(), accept, context)?;
+                if !reject.is_empty() {
+                    writeln!(self.out, "{}}} else {{", level)?;
+                    self.put_block(level.next(), reject, context)?;
+                }
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Switch { selector, ref cases, ref default } => {
+                write!(self.out, "{}switch (", level)?;
+                self.put_expression(selector, context)?;
+                writeln!(self.out, ") {{")?;
+                let lcase = level.next();
+                for &(value, ref block, ref fall_through) in cases.iter() {
+                    writeln!(self.out, "{}case {}: {{", lcase, value)?;
+                    self.put_block(lcase.next(), block, context)?;
+                    if fall_through.is_none() {
+                        writeln!(self.out, "{}break;", lcase.next())?;
+                    }
+                    writeln!(self.out, "{}}}", lcase)?;
+                }
+                writeln!(self.out, "{}default: {{", lcase)?;
+                self.put_block(lcase.next(), default, context)?;
+                writeln!(self.out, "{}break;", lcase.next())?;
+                writeln!(self.out, "{}}}", lcase)?;
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Loop { ref body, ref continuing } => {
+                if continuing.is_empty() {
+                    writeln!(self.out, "{}while (true) {{", level)?;
+                    self.put_block(level.next(), body, context)?;
+                    writeln!(self.out, "{}}}", level)?;
+                } else {
+                    // the continuing block has to run on `continue` as well,
+                    // so it's executed at the start of every iteration but the first
+                    let lwhile = level.next();
+                    writeln!(self.out, "{}{{", level)?;
+                    writeln!(self.out, "{}bool {} = true;", lwhile, NAME_LOOP_INIT)?;
+                    writeln!(self.out, "{}while (true) {{", lwhile)?;
+                    writeln!(self.out, "{}if (!{}) {{", lwhile.next(), NAME_LOOP_INIT)?;
+                    self.put_block(lwhile.next().next(), continuing, context)?;
+                    writeln!(self.out, "{}}}", lwhile.next())?;
+                    writeln!(self.out, "{}{} = false;", lwhile.next(), NAME_LOOP_INIT)?;
+                    self.put_block(lwhile.next(), body, context)?;
+                    writeln!(self.out, "{}}}", lwhile)?;
+                    writeln!(self.out, "{}}}", level)?;
+                }
+            }
+            crate::Statement::Break => {
+                writeln!(self.out, "{}break;", level)?;
+            }
+            crate::Statement::Continue => {
+                writeln!(self.out, "{}continue;", level)?;
+            }
+            crate::Statement::Return { value } => {
+                write!(self.out, "{}return", level)?;
+                if let Some(expr_token) = value {
+                    write!(self.out, " ")?;
+                    self.put_expression(expr_token, context)?;
+                }
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::Kill => {
+                writeln!(self.out, "{}discard;", level)?;
+            }
+            crate::Statement::Store { pointer, value } => {
+                write!(self.out, "{}", level)?;
+                self.put_expression(pointer, context)?;
+                write!(self.out, " = ")?;
+                self.put_expression(value, context)?;
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::ImageStore { image, coordinate, value } => {
+                write!(self.out, "{}imageStore(", level)?;
+                self.put_expression(image, context)?;
+                write!(self.out, ", ")?;
+                self.put_expression(coordinate, context)?;
+                write!(self.out, ", ")?;
+                self.put_expression(value, context)?;
+                writeln!(self.out, ");")?;
+            }
+            crate::Statement::Call { function, ref arguments } => {
+                write!(self.out, "{}{}", level, self.names[&NameKey::Function(function)])?;
+                self.put_arguments(arguments, context)?;
+                writeln!(self.out, ";")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_function(&mut self, token: Token<crate::Function>, module: &crate::Module) -> Result<(), Error> {
+        let fun = &module.functions[token];
+        match fun.return_type {
+            Some(ty) => self.put_type(ty, module)?,
+            None => write!(self.out, "void")?,
+        }
+        write!(self.out, " {}(", self.names[&NameKey::Function(token)])?;
+        for (index, &ty) in fun.parameter_types.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            // pointers can only be passed as references to the variables
+            if let crate::TypeInner::Pointer { .. } = module.types[ty].inner {
+                write!(self.out, "inout ")?;
+            }
+            let name = self.names[&NameKey::FunctionParameter(token, index as u32)].clone();
+            self.put_declaration(ty, &name, module)?;
+        }
+        writeln!(self.out, ") {{")?;
+
+        self.typifier.resolve_all(&fun.expressions, &ResolveContext {
+            types: &module.types,
+            constants: &module.constants,
+            global_vars: &module.global_variables,
+            local_vars: &fun.local_variables,
+            functions: &module.functions,
+            parameter_types: &fun.parameter_types,
+        })?;
+        let level = Level(1);
+        for (local_token, local) in fun.local_variables.iter() {
+            let name = self.names[&NameKey::LocalVariable(token, local_token)].clone();
+            write!(self.out, "{}", level)?;
+            self.put_declaration(local.ty, &name, module)?;
+            if let Some(init) = local.init {
+                write!(self.out, " = ")?;
+                self.put_constant(init, module)?;
+            }
+            writeln!(self.out, ";")?;
+        }
+        let context = FunctionContext {
+            token,
+            function: fun,
+            module,
+        };
+        self.put_block(level, &fun.body, &context)?;
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    /// Writes an input or output variable with a location.
+    fn put_interface(
+        &mut self,
+        location: spirv::Word,
+        class: spirv::StorageClass,
+        ty: Token<crate::Type>,
+        name: &str,
+        module: &crate::Module,
+    ) -> Result<(), Error> {
+        let is_varying = self.is_varying(class);
+        if !is_varying || self.version.supports_varying_locations() {
+            write!(self.out, "layout(location = {}) ", location)?;
+        }
+        if is_varying {
+            // integers can't be interpolated
+            match module.types[ty].inner {
+                crate::TypeInner::Scalar { kind, .. } |
+                crate::TypeInner::Vector { kind, .. } if kind != crate::ScalarKind::Float => {
+                    write!(self.out, "flat ")?;
+                }
+                _ => (),
+            }
+        }
+        let qualifier = if class == spirv::StorageClass::Input { "in" } else { "out" };
+        write!(self.out, "{} ", qualifier)?;
+        self.put_declaration(ty, name, module)?;
+        writeln!(self.out, ";")?;
+        Ok(())
+    }
+
+    fn put_block_variable(
+        &mut self,
+        token: Token<crate::GlobalVariable>,
+        ty: Token<crate::Type>,
+        module: &crate::Module,
+    ) -> Result<(), Error> {
+        let var = &module.global_variables[token];
+        let (layout, qualifier) = if var.class == spirv::StorageClass::StorageBuffer {
+            self.require(Feature::StorageBuffer)?;
+            ("std430", "buffer")
+        } else {
+            ("std140", "uniform")
+        };
+        write!(self.out, "layout({}", layout)?;
+        if let Some(crate::Binding::Descriptor { binding, .. }) = var.binding {
+            if self.version.supports_explicit_bindings() {
+                write!(self.out, ", binding = {}", binding)?;
+            }
+        }
+        let name = self.names[&NameKey::GlobalVariable(token)].clone();
+        writeln!(self.out, ") {} {}_block {{", qualifier, name)?;
+        if let crate::TypeInner::Struct { ref members } = module.types[ty].inner {
+            for (index, member) in members.iter().enumerate() {
+                let member_name = self.names[&NameKey::StructMember(ty, index as u32)].clone();
+                write!(self.out, "{}", Level(1))?;
+                self.put_declaration(member.ty, &member_name, module)?;
+                writeln!(self.out, ";")?;
+            }
+        }
+        writeln!(self.out, "}} {};", name)?;
+        Ok(())
+    }
+
+    fn put_global(&mut self, token: Token<crate::GlobalVariable>, module: &crate::Module) -> Result<(), Error> {
+        let var = &module.global_variables[token];
+        let ty = global_value_type(var, module);
+        let name = self.names[&NameKey::GlobalVariable(token)].clone();
+        match var.class {
+            spirv::StorageClass::Input |
+            spirv::StorageClass::Output => match (&var.binding, &module.types[ty].inner) {
+                (&Some(crate::Binding::BuiltIn(_)), _) => (),
+                (&Some(crate::Binding::Location(location)), _) => {
+                    self.put_interface(location, var.class, ty, &name, module)?;
+                }
+                (&None, crate::TypeInner::Struct { members }) => {
+                    for (index, member) in members.iter().enumerate() {
+                        if let Some(crate::Binding::Location(location)) = member.binding {
+                            let member_name = self.member_names[&(token, index as u32)].clone();
+                            self.put_interface(location, var.class, member.ty, &member_name, module)?;
+                        }
+                    }
+                }
+                _ => {
+                    let qualifier = if var.class == spirv::StorageClass::Input { "in" } else { "out" };
+                    write!(self.out, "{} ", qualifier)?;
+                    self.put_declaration(ty, &name, module)?;
+                    writeln!(self.out, ";")?;
+                }
+            },
+            spirv::StorageClass::Uniform |
+            spirv::StorageClass::StorageBuffer |
+            spirv::StorageClass::UniformConstant => match module.types[ty].inner {
+                crate::TypeInner::Struct { .. } => {
+                    self.put_block_variable(token, ty, module)?;
+                }
+                crate::TypeInner::Sampler { .. } => (),
+                _ => {
+                    if let Some(crate::Binding::Descriptor { binding, .. }) = var.binding {
+                        if self.version.supports_explicit_bindings() {
+                            write!(self.out, "layout(binding = {}) ", binding)?;
+                        }
+                    }
+                    write!(self.out, "uniform ")?;
+                    self.put_declaration(ty, &name, module)?;
+                    writeln!(self.out, ";")?;
+                }
+            },
+            spirv::StorageClass::Private => {
+                self.put_declaration(ty, &name, module)?;
+                writeln!(self.out, ";")?;
+            }
+            spirv::StorageClass::Workgroup => {
+                write!(self.out, "shared ")?;
+                self.put_declaration(ty, &name, module)?;
+                writeln!(self.out, ";")?;
+            }
+            other => return Err(Error::UnsupportedStorageClass(other)),
+        }
+        Ok(())
+    }
+
+    /// Names the variables passed between stages after their locations,
+    /// if the version doesn't allow matching them by location.
+    fn rename_varyings(&mut self, globals: &[Token<crate::GlobalVariable>], module: &crate::Module) {
+        let prefix = match self.exec_model {
+            spirv::ExecutionModel::Vertex |
+            spirv::ExecutionModel::Fragment => "_vs2fs_location",
+            _ => return,
+        };
+        for &token in globals {
+            let var = &module.global_variables[token];
+            let by_name = self.is_varying(var.class) && !self.version.supports_varying_locations();
+            let ty = global_value_type(var, module);
+            match (&var.binding, &module.types[ty].inner) {
+                (&Some(crate::Binding::Location(location)), _) if by_name => {
+                    let name = format!("{}{}", prefix, location);
+                    self.names.insert(NameKey::GlobalVariable(token), name);
+                }
+                (&None, crate::TypeInner::Struct { members }) => {
+                    for (index, member) in members.iter().enumerate() {
+                        let name = match member.binding {
+                            Some(crate::Binding::Location(location)) if by_name => {
+                                format!("{}{}", prefix, location)
+                            }
+                            Some(crate::Binding::Location(_)) => format!(
+                                "{}_{}",
+                                self.names[&NameKey::GlobalVariable(token)],
+                                self.names[&NameKey::StructMember(ty, index as u32)],
+                            ),
+                            _ => continue,
+                        };
+                        self.member_names.insert((token, index as u32), name);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    pub fn write(&mut self, module: &crate::Module) -> Result<(), Error> {
+        if !self.version.is_supported() {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        let exec_model = self.exec_model;
+        let ep = module.entry_points
+            .iter()
+            .find(|ep| ep.exec_model == exec_model && ep.name == self.entry_point_name)
+            .ok_or_else(|| Error::MissingEntryPoint(exec_model, self.entry_point_name.clone()))?;
+        match exec_model {
+            spirv::ExecutionModel::Vertex |
+            spirv::ExecutionModel::Fragment => (),
+            spirv::ExecutionModel::GLCompute => self.require(Feature::ComputeShader)?,
+            other => return Err(Error::UnsupportedExecutionModel(other)),
+        }
+
+        self.names = assign_names(module, &mut Namer::new(RESERVED_NAMES, &["gl_"]));
+        self.names.insert(NameKey::Function(ep.function), NAME_MAIN.to_owned());
+        self.member_names.clear();
+
+        let functions = call_order(module, Some(ep.function));
+        let mut used_globals = FastHashSet::default();
+        for &fun_token in functions.iter() {
+            for (_, expr) in module.functions[fun_token].expressions.iter() {
+                if let crate::Expression::GlobalVariable(token) = *expr {
+                    used_globals.insert(token);
+                }
+            }
+        }
+        let globals = module.global_variables
+            .iter()
+            .map(|(token, _)| token)
+            .filter(|token| used_globals.contains(token))
+            .collect::<Vec<_>>();
+        self.rename_varyings(&globals, module);
+
+        writeln!(self.out, "#version {}", self.version)?;
+        if let Version::Embedded(_) = self.version {
+            writeln!(self.out, "precision highp float;")?;
+            writeln!(self.out, "precision highp int;")?;
+        }
+        if exec_model == spirv::ExecutionModel::GLCompute {
+            writeln!(self.out, "layout(local_size_x = 1, local_size_y = 1, local_size_z = 1) in;")?;
+        }
+        writeln!(self.out)?;
+
+        // interface structures are split into variables, and
+        // the ones with runtime-sized arrays can only be blocks
+        for (token, ty) in module.types.iter() {
+            if let crate::TypeInner::Struct { ref members } = ty.inner {
+                let is_plain = members.iter().all(|member| {
+                    member.binding.is_none() && match module.types[member.ty].inner {
+                        crate::TypeInner::Array { size, .. } => size != crate::ArraySize::Dynamic,
+                        _ => true,
+                    }
+                });
+                if !is_plain {
+                    continue;
+                }
+                writeln!(self.out, "struct {} {{", self.names[&NameKey::Type(token)])?;
+                for (index, member) in members.iter().enumerate() {
+                    let name = self.names[&NameKey::StructMember(token, index as u32)].clone();
+                    write!(self.out, "{}", Level(1))?;
+                    self.put_declaration(member.ty, &name, module)?;
+                    writeln!(self.out, ";")?;
+                }
+                writeln!(self.out, "}};")?;
+                writeln!(self.out)?;
+            }
+        }
+
+        for &token in globals.iter() {
+            self.put_global(token, module)?;
+        }
+        if !globals.is_empty() {
+            writeln!(self.out)?;
+        }
+
+        for (i, &token) in functions.iter().enumerate() {
+            if i != 0 {
+                writeln!(self.out)?;
+            }
+            self.put_function(token, module)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn write_string(module: &crate::Module, options: &Options) -> Result<String, Error> {
+    let mut w = Writer::new(String::new(), options);
+    w.write(module)?;
+    Ok(w.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Error, Feature, Options, Version};
+    use crate::storage::Storage;
+
+    const SOURCE: &str = "
+        import \"GLSL.std.450\" as std;
+        [[location 0]] var<in> a_pos : vec2<f32>;
+        [[location 0]] var<out> v_uv : vec2<f32>;
+        [[builtin position]] var<out> o_position : vec4<f32>;
+        fn vs_main() -> void {
+            v_uv = a_pos;
+            o_position = vec4<f32>(a_pos, 0.0, 1.0);
+            return;
+        }
+        entry_point vertex as \"main\" = vs_main;
+        [[location 0]] var<in> f_uv : vec2<f32>;
+        [[location 0]] var<out> o_color : vec4<f32>;
+        fn fs_main() -> void {
+            o_color = vec4<f32>(f_uv, std::fma(f_uv, f_uv, f_uv));
+            return;
+        }
+        entry_point fragment as \"main\" = fs_main;
+    ";
+
+    fn write(exec_model: spirv::ExecutionModel, version: Version) -> Result<String, Error> {
+        let module = crate::front::wgsl::parse_str(SOURCE).unwrap();
+        let options = Options {
+            version,
+            entry_point: (exec_model, "main".to_owned()),
+        };
+        super::write_string(&module, &options)
+    }
+
+    #[test]
+    fn write_vertex() {
+        let text = write(spirv::ExecutionModel::Vertex, Version::Desktop(330)).unwrap();
+        assert!(text.starts_with("#version 330 core\n"));
+        assert!(text.contains("layout(location = 0) in vec2 a_pos;"));
+        // varyings are matched by name before 4.10
+        assert!(text.contains("\nout vec2 _vs2fs_location0;"));
+        assert!(text.contains("gl_Position = vec4(a_pos, 0.0, 1.0);"));
+        assert!(text.contains("void main() {"));
+        // variables of the other stage are left out
+        assert!(!text.contains("o_color"));
+
+        let text = write(spirv::ExecutionModel::Vertex, Version::Desktop(450)).unwrap();
+        assert!(text.contains("layout(location = 0) out vec2 v_uv;"));
+    }
+
+    #[test]
+    fn write_fragment() {
+        let text = write(spirv::ExecutionModel::Fragment, Version::Desktop(400)).unwrap();
+        assert!(text.contains("\nin vec2 _vs2fs_location0;"));
+        assert!(text.contains("layout(location = 0) out vec4 o_color;"));
+        assert!(text.contains("fma(_vs2fs_location0, _vs2fs_location0, _vs2fs_location0)"));
+
+        let error = write(spirv::ExecutionModel::Fragment, Version::Embedded(300)).unwrap_err();
+        match error {
+            Error::MissingFeature(Feature::MathFunction(crate::MathFunction::Fma)) => (),
+            other => panic!("Unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn write_uniform_block() {
+        let mut module = crate::Module::generate_empty();
+        let ty_float = module.types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 },
+        });
+        let ty_vec = module.types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Vector { size: crate::VectorSize::Quad, kind: crate::ScalarKind::Float, width: 32 },
+        });
+        let ty_struct = module.types.append(crate::Type {
+            name: Some("Globals".to_owned()),
+            inner: crate::TypeInner::Struct {
+                members: vec![crate::StructMember {
+                    name: Some("color".to_owned()),
+                    binding: None,
+                    offset: None,
+                    ty: ty_vec,
+                }, crate::StructMember {
+                    name: Some("scale".to_owned()),
+                    binding: None,
+                    offset: None,
+                    ty: ty_float,
+                }],
+            },
+        });
+        let globals = module.global_variables.append(crate::GlobalVariable {
+            name: Some("globals".to_owned()),
+            class: spirv::StorageClass::Uniform,
+            binding: Some(crate::Binding::Descriptor { set: 0, binding: 2 }),
+            ty: ty_struct,
+        });
+        let output = module.global_variables.append(crate::GlobalVariable {
+            name: Some("gl_FragColor".to_owned()),
+            class: spirv::StorageClass::Output,
+            binding: Some(crate::Binding::Location(0)),
+            ty: ty_vec,
+        });
+        let mut expressions = Storage::new();
+        let base = expressions.append(crate::Expression::GlobalVariable(globals));
+        let value = expressions.append(crate::Expression::AccessIndex { base, index: 0 });
+        let pointer = expressions.append(crate::Expression::GlobalVariable(output));
+        let function = module.functions.append(crate::Function {
+            name: None,
+            control: spirv::FunctionControl::empty(),
+            parameter_types: Vec::new(),
+            return_type: None,
+            local_variables: Storage::new(),
+            expressions,
+            body: vec![crate::Statement::Store { pointer, value }],
+            spans: Default::default(),
+        });
+        module.entry_points.push(crate::EntryPoint {
+            exec_model: spirv::ExecutionModel::Fragment,
+            name: "main".to_owned(),
+            inputs: Vec::new(),
+            outputs: vec![output],
+            function,
+        });
+
+        let mut options = Options {
+            version: Version::Embedded(300),
+            entry_point: (spirv::ExecutionModel::Fragment, "main".to_owned()),
+        };
+        let text = super::write_string(&module, &options).unwrap();
+        assert!(text.contains("precision highp float;"));
+        assert!(text.contains("layout(std140) uniform globals_block {\n    vec4 color;\n    float scale;\n} globals;"));
+        assert!(text.contains("layout(location = 0) out vec4 FragColor;"));
+        assert!(text.contains("FragColor = globals.color;"));
+
+        options.version = Version::Embedded(310);
+        let text = super::write_string(&module, &options).unwrap();
+        assert!(text.contains("layout(std140, binding = 2) uniform globals_block {"));
+
+        module.global_variables[globals].class = spirv::StorageClass::StorageBuffer;
+        let text = super::write_string(&module, &options).unwrap();
+        assert!(text.contains("layout(std430, binding = 2) buffer globals_block {"));
+        options.version = Version::Desktop(330);
+        match super::write_string(&module, &options) {
+            Err(Error::MissingFeature(Feature::StorageBuffer)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn write_signed_shifts() {
+        let module = crate::front::wgsl::parse_str("
+            [[location 0]] var<in> value : i32;
+            [[location 1]] var<in> mask : vec2<u32>;
+            [[location 0]] var<out> o_value : i32;
+            [[location 1]] var<out> o_mask : vec2<u32>;
+            fn main() -> void {
+                o_value = value >> 2;
+                o_mask = mask >>> mask;
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap();
+        let options = Options {
+            version: Version::Desktop(450),
+            entry_point: (spirv::ExecutionModel::Fragment, "main".to_owned()),
+        };
+        let text = super::write_string(&module, &options).unwrap();
+        assert!(text.contains("int(uint(value) >> 2)"), "{}", text);
+        assert!(text.contains("uvec2(ivec2(mask) >> "), "{}", text);
+    }
+}
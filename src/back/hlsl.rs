@@ -0,0 +1,1340 @@
+/*! High-Level Shading Language (HLSL) backend
+
+The output targets Shader Model 5.0 or 5.1, and all the entry points of the
+module are written into a single file, each to be compiled separately.
+
+## Entry points
+
+HLSL passes the stage inputs and outputs through the parameters and the return
+value of the entry point, attaching a semantic to each of them. The IR uses
+global variables instead, so those are declared `static`, and every entry point
+gets a wrapper function copying them from an input structure and into an output
+structure, around a call to the original function, which gets an `_impl` suffix.
+
+Locations are mapped to `TEXCOORDn` semantics, apart from the fragment
+outputs, which become `SV_Targetn`. Built-ins are mapped to the corresponding
+system values.
+
+## Binding model
+
+Like in Metal, the registers are flat per resource kind, so the mapping from
+the descriptor sets and bindings is provided in the options. The kind of the
+register is implied by the variable:
+  - uniform buffers go to the `b` registers, and are wrapped into `cbuffer` blocks
+  - sampled textures go to the `t` registers
+  - samplers go to the `s` registers
+  - storage buffers and storage textures go to the `u` registers
+
+Register spaces need Shader Model 5.1, and only space 0 is allowed with 5.0.
+
+## Matrices
+
+Matrices are declared with the numbers of columns and rows swapped, so that
+indexing them yields a column, like in the IR. Consequently, the operands of
+matrix multiplications are swapped as well, and the matrices stored in buffers
+are declared `row_major`.
+!*/
+
+use std::fmt::{
+    Display, Error as FmtError, Formatter, Write,
+};
+
+use crate::{
+    back::{assign_names, call_order, global_value_type, NameKey, Namer},
+    proc::{ResolveContext, ResolveError, Typifier},
+    storage::Token,
+    FastHashMap, FastHashSet,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BindTarget {
+    pub register: u8,
+    pub space: u8,
+}
+
+#[derive(Clone, Debug, Hash, Eq, Ord, PartialEq, PartialOrd)]
+pub struct BindSource {
+    pub set: spirv::Word,
+    pub binding: spirv::Word,
+}
+
+pub type BindingMap = FastHashMap<BindSource, BindTarget>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShaderModel {
+    V5_0,
+    V5_1,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Format(FmtError),
+    UnsupportedExecutionModel(spirv::ExecutionModel),
+    UnsupportedStorageClass(spirv::StorageClass),
+    UnsupportedBuiltIn(spirv::BuiltIn),
+    UnsupportedScalar(crate::ScalarKind, crate::Bytes),
+    UnsupportedImage(spirv::Dim, crate::ImageFlags),
+    UnsupportedConstant(Token<crate::Constant>),
+    UnsupportedMathFunction(crate::MathFunction),
+    /// Arrays can't be constructed outside of initializers.
+    UnsupportedCompose(Token<crate::Type>),
+    UnsupportedDynamicArray(Token<crate::Type>),
+    /// The image sizes are only returned through the output parameters of `GetDimensions`.
+    UnsupportedImageQuery(Token<crate::Expression>),
+    MissingBinding(Token<crate::GlobalVariable>),
+    MissingBindTarget(BindSource),
+    /// Register spaces other than 0 need Shader Model 5.1.
+    UnsupportedRegisterSpace(u8),
+    Type(ResolveError),
+}
+
+impl Error {
+    /// Returns the span of the module entity that the error is about.
+    pub fn span(&self, module: &crate::Module) -> crate::Span {
+        match *self {
+            Error::MissingBinding(token) => module.spans.global_variable(token),
+            _ => crate::Span::default(),
+        }
+    }
+}
+
+impl From<FmtError> for Error {
+    fn from(e: FmtError) -> Self {
+        Error::Format(e)
+    }
+}
+
+impl From<ResolveError> for Error {
+    fn from(e: ResolveError) -> Self {
+        Error::Type(e)
+    }
+}
+
+pub struct Options<'a> {
+    pub shader_model: ShaderModel,
+    pub binding_map: &'a BindingMap,
+}
+
+const NAME_INPUT: &str = "input";
+const NAME_OUTPUT: &str = "output";
+const NAME_LOOP_INIT: &str = "loop_init";
+const COMPONENTS: &[char] = &['x', 'y', 'z', 'w'];
+
+const RESERVED_NAMES: &[&str] = &[
+    NAME_INPUT, NAME_OUTPUT, NAME_LOOP_INIT,
+    "AppendStructuredBuffer", "asm", "asm_fragment", "atan2", "BlendState",
+    "bool", "bool2", "bool3", "bool4", "break", "Buffer", "ByteAddressBuffer",
+    "case", "cbuffer", "centroid", "class", "column_major", "compile",
+    "compile_fragment", "CompileShader", "const", "continue",
+    "ComputeShader", "ConsumeStructuredBuffer", "default", "DepthStencilState",
+    "DepthStencilView", "discard", "do", "double", "double2", "double3",
+    "double4", "DomainShader", "dword", "else", "export", "extern", "false",
+    "firstbithigh", "firstbitlow", "float", "float2", "float3", "float4",
+    "float2x2", "float2x3", "float2x4", "float3x2", "float3x3", "float3x4",
+    "float4x2", "float4x3", "float4x4", "fmod", "for", "frac", "fxgroup",
+    "GeometryShader", "groupshared", "half", "Hullshader", "if", "in",
+    "inline", "inout", "InputPatch", "int", "int2", "int3", "int4",
+    "interface", "lerp", "line", "lineadj", "linear", "LineStream", "mad",
+    "matrix", "min16float", "min10float", "min16int", "min12int", "min16uint",
+    "mul", "namespace", "nointerpolation", "noperspective", "NULL", "out",
+    "OutputPatch", "packoffset", "pass", "pixelfragment", "PixelShader",
+    "point", "PointStream", "precise", "RasterizerState", "register",
+    "RenderTargetView", "return", "row_major", "rsqrt", "RWBuffer",
+    "RWByteAddressBuffer", "RWStructuredBuffer", "RWTexture1D",
+    "RWTexture1DArray", "RWTexture2D", "RWTexture2DArray", "RWTexture3D",
+    "sample", "sampler", "SamplerState", "SamplerComparisonState",
+    "saturate", "shared", "snorm", "stateblock", "stateblock_state", "static",
+    "string", "struct", "switch", "StructuredBuffer", "tbuffer", "technique",
+    "technique10", "technique11", "texture", "Texture1D", "Texture1DArray",
+    "Texture2D", "Texture2DArray", "Texture2DMS", "Texture2DMSArray",
+    "Texture3D", "TextureCube", "TextureCubeArray", "true", "typedef",
+    "triangle", "triangleadj", "TriangleStream", "uint", "uint2", "uint3",
+    "uint4", "uniform", "unorm", "unsigned", "vector", "vertexfragment",
+    "VertexShader", "void", "volatile", "while",
+];
+
+#[derive(Clone, Copy, Debug)]
+struct Level(usize);
+impl Level {
+    fn next(&self) -> Self {
+        Level(self.0 + 1)
+    }
+}
+impl Display for Level {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
+        (0 .. self.0).try_for_each(|_| formatter.write_str("    "))
+    }
+}
+
+fn vector_size_string(size: crate::VectorSize) -> &'static str {
+    match size {
+        crate::VectorSize::Bi => "2",
+        crate::VectorSize::Tri => "3",
+        crate::VectorSize::Quad => "4",
+    }
+}
+
+fn math_function_name(fun: crate::MathFunction) -> Result<&'static str, Error> {
+    Ok(match fun {
+        crate::MathFunction::Round => "round",
+        crate::MathFunction::RoundEven => "round",
+        crate::MathFunction::Trunc => "trunc",
+        crate::MathFunction::FAbs => "abs",
+        crate::MathFunction::SAbs => "abs",
+        crate::MathFunction::FSign => "sign",
+        crate::MathFunction::SSign => "sign",
+        crate::MathFunction::Floor => "floor",
+        crate::MathFunction::Ceil => "ceil",
+        crate::MathFunction::Fract => "frac",
+        crate::MathFunction::Radians => "radians",
+        crate::MathFunction::Degrees => "degrees",
+        crate::MathFunction::Sin => "sin",
+        crate::MathFunction::Cos => "cos",
+        crate::MathFunction::Tan => "tan",
+        crate::MathFunction::Asin => "asin",
+        crate::MathFunction::Acos => "acos",
+        crate::MathFunction::Atan => "atan",
+        crate::MathFunction::Sinh => "sinh",
+        crate::MathFunction::Cosh => "cosh",
+        crate::MathFunction::Tanh => "tanh",
+        crate::MathFunction::Atan2 => "atan2",
+        crate::MathFunction::Pow => "pow",
+        crate::MathFunction::Exp => "exp",
+        crate::MathFunction::Log => "log",
+        crate::MathFunction::Exp2 => "exp2",
+        crate::MathFunction::Log2 => "log2",
+        crate::MathFunction::Sqrt => "sqrt",
+        crate::MathFunction::InverseSqrt => "rsqrt",
+        crate::MathFunction::Determinant => "determinant",
+        crate::MathFunction::FMin => "min",
+        crate::MathFunction::UMin => "min",
+        crate::MathFunction::SMin => "min",
+        crate::MathFunction::FMax => "max",
+        crate::MathFunction::UMax => "max",
+        crate::MathFunction::SMax => "max",
+        crate::MathFunction::FClamp => "clamp",
+        crate::MathFunction::UClamp => "clamp",
+        crate::MathFunction::SClamp => "clamp",
+        crate::MathFunction::FMix => "lerp",
+        crate::MathFunction::IMix => "lerp",
+        crate::MathFunction::Step => "step",
+        crate::MathFunction::SmoothStep => "smoothstep",
+        crate::MathFunction::Fma => "mad",
+        crate::MathFunction::Ldexp => "ldexp",
+        crate::MathFunction::Length => "length",
+        crate::MathFunction::Distance => "distance",
+        crate::MathFunction::Cross => "cross",
+        crate::MathFunction::Normalize => "normalize",
+        crate::MathFunction::FaceForward => "faceforward",
+        crate::MathFunction::Reflect => "reflect",
+        crate::MathFunction::Refract => "refract",
+        crate::MathFunction::FindILsb => "firstbitlow",
+        crate::MathFunction::FindSMsb => "firstbithigh",
+        crate::MathFunction::FindUMsb => "firstbithigh",
+        crate::MathFunction::NMin => "min",
+        crate::MathFunction::NMax => "max",
+        crate::MathFunction::NClamp => "clamp",
+        other => return Err(Error::UnsupportedMathFunction(other)),
+    })
+}
+
+/// Checks if the type is a matrix or an array of them.
+fn is_matrix(ty: Token<crate::Type>, module: &crate::Module) -> bool {
+    match module.types[ty].inner {
+        crate::TypeInner::Matrix { .. } => true,
+        crate::TypeInner::Array { base, .. } => is_matrix(base, module),
+        _ => false,
+    }
+}
+
+/// Returns the letter of the register kind the resource is bound to.
+fn register_kind(var: &crate::GlobalVariable, module: &crate::Module) -> char {
+    match module.types[global_value_type(var, module)].inner {
+        crate::TypeInner::Image { flags, .. } if flags.contains(crate::ImageFlags::SAMPLED) => 't',
+        crate::TypeInner::Image { .. } => 'u',
+        crate::TypeInner::Sampler { .. } => 's',
+        _ if var.class == spirv::StorageClass::StorageBuffer => 'u',
+        _ => 'b',
+    }
+}
+
+/// An input or output of an entry point.
+struct Interface {
+    /// The global variable, and the member of its structure, if any.
+    source: (Token<crate::GlobalVariable>, Option<u32>),
+    ty: Token<crate::Type>,
+    name: String,
+    semantic: String,
+}
+
+struct FunctionContext<'a> {
+    token: Token<crate::Function>,
+    function: &'a crate::Function,
+    module: &'a crate::Module,
+}
+
+pub struct Writer<W> {
+    out: W,
+    names: FastHashMap<NameKey, String>,
+    /// Names of the functions constructing the structure types.
+    constructors: FastHashMap<Token<crate::Type>, String>,
+    typifier: Typifier,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Writer {
+            out,
+            names: FastHashMap::default(),
+            constructors: FastHashMap::default(),
+            typifier: Typifier::new(),
+        }
+    }
+
+    pub fn finish(self) -> W {
+        self.out
+    }
+
+    fn semantic(
+        &self,
+        binding: &crate::Binding,
+        class: spirv::StorageClass,
+        exec_model: spirv::ExecutionModel,
+    ) -> Result<String, Error> {
+        Ok(match *binding {
+            crate::Binding::Location(location)
+                if exec_model == spirv::ExecutionModel::Fragment
+                && class == spirv::StorageClass::Output => format!("SV_Target{}", location),
+            crate::Binding::Location(location) => format!("TEXCOORD{}", location),
+            crate::Binding::BuiltIn(built_in) => match built_in {
+                spirv::BuiltIn::Position |
+                spirv::BuiltIn::FragCoord => "SV_Position",
+                spirv::BuiltIn::ClipDistance => "SV_ClipDistance",
+                spirv::BuiltIn::VertexIndex => "SV_VertexID",
+                spirv::BuiltIn::InstanceIndex => "SV_InstanceID",
+                spirv::BuiltIn::FrontFacing => "SV_IsFrontFace",
+                spirv::BuiltIn::FragDepth => "SV_Depth",
+                spirv::BuiltIn::LocalInvocationId => "SV_GroupThreadID",
+                spirv::BuiltIn::LocalInvocationIndex => "SV_GroupIndex",
+                spirv::BuiltIn::GlobalInvocationId => "SV_DispatchThreadID",
+                spirv::BuiltIn::WorkgroupId => "SV_GroupID",
+                other => return Err(Error::UnsupportedBuiltIn(other)),
+            }.to_owned(),
+            crate::Binding::Descriptor { .. } => return Err(Error::UnsupportedStorageClass(class)),
+        })
+    }
+
+    fn scalar_string(&self, kind: crate::ScalarKind, width: crate::Bytes) -> Result<&'static str, Error> {
+        match (kind, width) {
+            (crate::ScalarKind::Float, 32) => Ok("float"),
+            (crate::ScalarKind::Float, 64) => Ok("double"),
+            (crate::ScalarKind::Sint, 32) => Ok("int"),
+            (crate::ScalarKind::Uint, 32) => Ok("uint"),
+            (crate::ScalarKind::Bool, _) => Ok("bool"),
+            _ => Err(Error::UnsupportedScalar(kind, width)),
+        }
+    }
+
+    /// Writes the type without the array dimensions.
+    fn put_base_type(&mut self, ty: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        match module.types[ty].inner {
+            crate::TypeInner::Scalar { kind, width } => {
+                write!(self.out, "{}", self.scalar_string(kind, width)?)?;
+            }
+            crate::TypeInner::Vector { size, kind, width } => {
+                write!(self.out, "{}{}", self.scalar_string(kind, width)?, vector_size_string(size))?;
+            }
+            crate::TypeInner::Matrix { columns, rows, kind, width } => {
+                let scalar = self.scalar_string(kind, width)?;
+                write!(self.out, "{}{}x{}", scalar, vector_size_string(columns), vector_size_string(rows))?;
+            }
+            crate::TypeInner::Pointer { base, .. } |
+            crate::TypeInner::Array { base, .. } => {
+                self.put_base_type(base, module)?;
+            }
+            crate::TypeInner::Struct { .. } => {
+                write!(self.out, "{}", self.names[&NameKey::Type(ty)])?;
+            }
+            crate::TypeInner::Image { base, dim, flags } => {
+                let unsupported = Error::UnsupportedImage(dim, flags);
+                let sampled = flags.contains(crate::ImageFlags::SAMPLED);
+                let arrayed = flags.contains(crate::ImageFlags::ARRAYED);
+                let multisampled = flags.contains(crate::ImageFlags::MULTISAMPLED);
+                let dim_str = match (dim, multisampled) {
+                    (spirv::Dim::Dim1D, false) => "1D",
+                    (spirv::Dim::Dim2D, false) => "2D",
+                    (spirv::Dim::Dim2D, true) if sampled => "2DMS",
+                    (spirv::Dim::Dim3D, false) if !arrayed => "3D",
+                    (spirv::Dim::DimCube, false) if sampled => "Cube",
+                    _ => return Err(unsupported),
+                };
+                match module.types[base].inner {
+                    crate::TypeInner::Scalar { .. } |
+                    crate::TypeInner::Vector { .. } => (),
+                    _ => return Err(unsupported),
+                }
+                let prefix = if sampled { "" } else { "RW" };
+                write!(self.out, "{}Texture{}{}<", prefix, dim_str, if arrayed { "Array" } else { "" })?;
+                self.put_base_type(base, module)?;
+                write!(self.out, ">")?;
+            }
+            crate::TypeInner::Sampler { comparison: false } => write!(self.out, "SamplerState")?,
+            crate::TypeInner::Sampler { comparison: true } => write!(self.out, "SamplerComparisonState")?,
+        }
+        Ok(())
+    }
+
+    /// Writes the array dimensions of the type, outermost first.
+    fn put_array_dimensions(&mut self, ty: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        if let crate::TypeInner::Array { base, size } = module.types[ty].inner {
+            match size {
+                crate::ArraySize::Static(length) => write!(self.out, "[{}]", length)?,
+                crate::ArraySize::Dynamic => return Err(Error::UnsupportedDynamicArray(ty)),
+            }
+            self.put_array_dimensions(base, module)?;
+        }
+        Ok(())
+    }
+
+    fn put_declaration(&mut self, ty: Token<crate::Type>, name: &str, module: &crate::Module) -> Result<(), Error> {
+        self.put_base_type(ty, module)?;
+        write!(self.out, " {}", name)?;
+        self.put_array_dimensions(ty, module)
+    }
+
+    fn put_constant(&mut self, token: Token<crate::Constant>, module: &crate::Module) -> Result<(), Error> {
+        match module.constants[token].inner {
+            crate::ConstantInner::Sint(value) => write!(self.out, "{}", value)?,
+            crate::ConstantInner::Uint(value) => write!(self.out, "{}u", value)?,
+            crate::ConstantInner::Float(value) => {
+                if !value.is_finite() {
+                    return Err(Error::UnsupportedConstant(token));
+                }
+                let text = value.to_string();
+                if text.contains('.') {
+                    write!(self.out, "{}", text)?;
+                } else {
+                    write!(self.out, "{}.0", text)?;
+                }
+            }
+            crate::ConstantInner::Bool(value) => write!(self.out, "{}", value)?,
+        }
+        Ok(())
+    }
+
+    fn put_arguments(
+        &mut self,
+        arguments: &[Token<crate::Expression>],
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        write!(self.out, "(")?;
+        for (i, &argument) in arguments.iter().enumerate() {
+            if i != 0 {
+                write!(self.out, ", ")?;
+            }
+            self.put_expression(argument, context)?;
+        }
+        write!(self.out, ")")?;
+        Ok(())
+    }
+
+    fn put_expression(
+        &mut self,
+        expr_token: Token<crate::Expression>,
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        let module = context.module;
+        let expression = &context.function.expressions[expr_token];
+        log::trace!("expression {:?}", expression);
+        match *expression {
+            crate::Expression::Access { base, index } => {
+                self.put_expression(base, context)?;
+                write!(self.out, "[")?;
+                self.put_expression(index, context)?;
+                write!(self.out, "]")?;
+            }
+            crate::Expression::AccessIndex { base, index } => {
+                self.put_expression(base, context)?;
+                let (base_ty, base_inner) = match *self.typifier.get(base, &module.types) {
+                    crate::TypeInner::Pointer { base, .. } => (Some(base), &module.types[base].inner),
+                    ref other => (self.typifier.get_handle(base), other),
+                };
+                match *base_inner {
+                    crate::TypeInner::Struct { .. } => {
+                        let ty = base_ty.ok_or(ResolveError::InvalidAccessIndex(index))?;
+                        write!(self.out, ".{}", self.names[&NameKey::StructMember(ty, index)])?;
+                    }
+                    crate::TypeInner::Vector { .. } => {
+                        write!(self.out, ".{}", COMPONENTS[index as usize])?;
+                    }
+                    _ => {
+                        write!(self.out, "[{}]", index)?;
+                    }
+                }
+            }
+            crate::Expression::Constant(token) => {
+                self.put_constant(token, module)?;
+            }
+            crate::Expression::Compose { ty, ref components } => {
+                match module.types[ty].inner {
+                    crate::TypeInner::Struct { .. } => {
+                        write!(self.out, "{}", self.constructors[&ty])?;
+                    }
+                    crate::TypeInner::Array { .. } => return Err(Error::UnsupportedCompose(ty)),
+                    _ => self.put_base_type(ty, module)?,
+                }
+                self.put_arguments(components, context)?;
+            }
+            crate::Expression::FunctionParameter(index) => {
+                let key = NameKey::FunctionParameter(context.token, index);
+                write!(self.out, "{}", self.names[&key])?;
+            }
+            crate::Expression::GlobalVariable(token) => {
+                let name = &self.names[&NameKey::GlobalVariable(token)];
+                // storage buffers are structured buffers with a single element
+                if module.global_variables[token].class == spirv::StorageClass::StorageBuffer {
+                    write!(self.out, "{}[0]", name)?;
+                } else {
+                    write!(self.out, "{}", name)?;
+                }
+            }
+            crate::Expression::LocalVariable(token) => {
+                let key = NameKey::LocalVariable(context.token, token);
+                write!(self.out, "{}", self.names[&key])?;
+            }
+            crate::Expression::Load { pointer } => {
+                self.put_expression(pointer, context)?;
+            }
+            crate::Expression::ImageSample { image, sampler, coordinate } => {
+                self.put_expression(image, context)?;
+                write!(self.out, ".Sample")?;
+                self.put_arguments(&[sampler, coordinate], context)?;
+            }
+            crate::Expression::ImageLoad { image, coordinate, index } => {
+                let multisampled = match *self.typifier.get(image, &module.types) {
+                    crate::TypeInner::Image { flags, .. } => flags.contains(crate::ImageFlags::MULTISAMPLED),
+                    _ => return Err(Error::Type(ResolveError::InvalidImage(image))),
+                };
+                self.put_expression(image, context)?;
+                match index {
+                    Some(index) if multisampled => {
+                        write!(self.out, ".Load")?;
+                        self.put_arguments(&[coordinate, index], context)?;
+                    }
+                    // the level of detail goes after the coordinate
+                    Some(index) => {
+                        let size = match *self.typifier.get(coordinate, &module.types) {
+                            crate::TypeInner::Vector { size, .. } => size as u8,
+                            _ => 1,
+                        };
+                        write!(self.out, ".Load(int{}", size + 1)?;
+                        self.put_arguments(&[coordinate, index], context)?;
+                        write!(self.out, ")")?;
+                    }
+                    None => {
+                        write!(self.out, "[")?;
+                        self.put_expression(coordinate, context)?;
+                        write!(self.out, "]")?;
+                    }
+                }
+            }
+            crate::Expression::ImageDimensions { .. } => {
+                return Err(Error::UnsupportedImageQuery(expr_token));
+            }
+            crate::Expression::Unary { op, expr } => {
+                let op_str = match op {
+                    crate::UnaryOperator::Negate => "-",
+                    crate::UnaryOperator::Not => "!",
+                    crate::UnaryOperator::BitwiseNot => "~",
+                };
+                write!(self.out, "({}", op_str)?;
+                self.put_expression(expr, context)?;
+                write!(self.out, ")")?;
+            }
+            crate::Expression::Binary { op, left, right } => {
+                let left_inner = self.typifier.get(left, &module.types);
+                let right_inner = self.typifier.get(right, &module.types);
+                let (kind, size) = match *left_inner {
+                    crate::TypeInner::Scalar { kind, .. } => (Some(kind), None),
+                    crate::TypeInner::Vector { kind, size, .. } => (Some(kind), Some(size as u8)),
+                    _ => (None, None),
+                };
+                let is_float = kind == Some(crate::ScalarKind::Float);
+                let is_matrix_product = matches!(
+                    (left_inner, right_inner),
+                    (&crate::TypeInner::Matrix { .. }, &crate::TypeInner::Matrix { .. }) |
+                    (&crate::TypeInner::Matrix { .. }, &crate::TypeInner::Vector { .. }) |
+                    (&crate::TypeInner::Vector { .. }, &crate::TypeInner::Matrix { .. })
+                );
+                // `*` is component-wise, and the matrices are transposed
+                if op == crate::BinaryOperator::Multiply && is_matrix_product {
+                    write!(self.out, "mul")?;
+                    self.put_arguments(&[right, left], context)?;
+                    return Ok(());
+                }
+                if op == crate::BinaryOperator::Modulo && is_float {
+                    write!(self.out, "fmod")?;
+                    self.put_arguments(&[left, right], context)?;
+                    return Ok(());
+                }
+                // `>>` follows the signedness of the left operand
+                let shift_cast = match (op, kind) {
+                    (crate::BinaryOperator::ShiftRightLogical, Some(crate::ScalarKind::Sint)) => Some(("int", "uint")),
+                    (crate::BinaryOperator::ShiftRightArithmetic, Some(crate::ScalarKind::Uint)) => Some(("uint", "int")),
+                    _ => None,
+                };
+                if let Some((result, operand)) = shift_cast {
+                    match size {
+                        Some(size) => write!(self.out, "{}{}({}{}(", result, size, operand, size)?,
+                        None => write!(self.out, "{}({}(", result, operand)?,
+                    }
+                    self.put_expression(left, context)?;
+                    write!(self.out, ") >> ")?;
+                    self.put_expression(right, context)?;
+                    write!(self.out, ")")?;
+                    return Ok(());
+                }
+                let op_str = match op {
+                    crate::BinaryOperator::Add => "+",
+                    crate::BinaryOperator::Subtract => "-",
+                    crate::BinaryOperator::Multiply => "*",
+                    crate::BinaryOperator::Divide => "/",
+                    crate::BinaryOperator::Modulo => "%",
+                    crate::BinaryOperator::Equal => "==",
+                    crate::BinaryOperator::NotEqual => "!=",
+                    crate::BinaryOperator::Less => "<",
+                    crate::BinaryOperator::LessEqual => "<=",
+                    crate::BinaryOperator::Greater => ">",
+                    crate::BinaryOperator::GreaterEqual => ">=",
+                    crate::BinaryOperator::And => "&",
+                    crate::BinaryOperator::ExclusiveOr => "^",
+                    crate::BinaryOperator::InclusiveOr => "|",
+                    crate::BinaryOperator::LogicalAnd => "&&",
+                    crate::BinaryOperator::LogicalOr => "||",
+                    crate::BinaryOperator::ShiftLeftLogical => "<<",
+                    crate::BinaryOperator::ShiftRightLogical => ">>",
+                    crate::BinaryOperator::ShiftRightArithmetic => ">>",
+                };
+                write!(self.out, "(")?;
+                self.put_expression(left, context)?;
+                write!(self.out, " {} ", op_str)?;
+                self.put_expression(right, context)?;
+                write!(self.out, ")")?;
+            }
+            crate::Expression::Call { function, ref arguments } => {
+                write!(self.out, "{}", self.names[&NameKey::Function(function)])?;
+                self.put_arguments(arguments, context)?;
+            }
+            crate::Expression::Math { fun, ref arguments } => {
+                write!(self.out, "{}", math_function_name(fun)?)?;
+                self.put_arguments(arguments, context)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_block(
+        &mut self,
+        level: Level,
+        statements: &[crate::Statement],
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        for statement in statements {
+            self.put_statement(level, statement, context)?;
+        }
+        Ok(())
+    }
+
+    fn put_statement(
+        &mut self,
+        level: Level,
+        statement: &crate::Statement,
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        log::trace!("statement[{}] {:?}", level.0, statement);
+        match *statement {
+            crate::Statement::Block(ref block) => {
+                writeln!(self.out, "{}{{", level)?;
+                self.put_block(level.next(), block, context)?;
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::If { condition, ref accept, ref reject } => {
+                write!(self.out, "{}if (", level)?;
+                self.put_expression(condition, context)?;
+                writeln!(self.out, ") {{")?;
+                self.put_block(level.next(), accept, context)?;
+                if !reject.is_empty() {
+                    writeln!(self.out, "{}}} else {{", level)?;
+                    self.put_block(level.next(), reject, context)?;
+                }
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Switch { selector, ref cases, ref default } => {
+                write!(self.out, "{}switch (", level)?;
+                self.put_expression(selector, context)?;
+                writeln!(self.out, ") {{")?;
+                let lcase = level.next();
+                for &(value, ref block, ref fall_through) in cases.iter() {
+                    writeln!(self.out, "{}case {}: {{", lcase, value)?;
+                    self.put_block(lcase.next(), block, context)?;
+                    if fall_through.is_none() {
+                        writeln!(self.out, "{}break;", lcase.next())?;
+                    }
+                    writeln!(self.out, "{}}}", lcase)?;
+                }
+                writeln!(self.out, "{}default: {{", lcase)?;
+                self.put_block(lcase.next(), default, context)?;
+                writeln!(self.out, "{}break;", lcase.next())?;
+                writeln!(self.out, "{}}}", lcase)?;
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Loop { ref body, ref continuing } => {
+                if continuing.is_empty() {
+                    writeln!(self.out, "{}while (true) {{", level)?;
+                    self.put_block(level.next(), body, context)?;
+                    writeln!(self.out, "{}}}", level)?;
+                } else {
+                    // the continuing block has to run on `continue` as well,
+                    // so it's executed at the start of every iteration but the first
+                    let lwhile = level.next();
+                    writeln!(self.out, "{}{{", level)?;
+                    writeln!(self.out, "{}bool {} = true;", lwhile, NAME_LOOP_INIT)?;
+                    writeln!(self.out, "{}while (true) {{", lwhile)?;
+                    writeln!(self.out, "{}if (!{}) {{", lwhile.next(), NAME_LOOP_INIT)?;
+                    self.put_block(lwhile.next().next(), continuing, context)?;
+                    writeln!(self.out, "{}}}", lwhile.next())?;
+                    writeln!(self.out, "{}{} = false;", lwhile.next(), NAME_LOOP_INIT)?;
+                    self.put_block(lwhile.next(), body, context)?;
+                    writeln!(self.out, "{}}}", lwhile)?;
+                    writeln!(self.out, "{}}}", level)?;
+                }
+            }
+            crate::Statement::Break => {
+                writeln!(self.out, "{}break;", level)?;
+            }
+            crate::Statement::Continue => {
+                writeln!(self.out, "{}continue;", level)?;
+            }
+            crate::Statement::Return { value } => {
+                write!(self.out, "{}return", level)?;
+                if let Some(expr_token) = value {
+                    write!(self.out, " ")?;
+                    self.put_expression(expr_token, context)?;
+                }
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::Kill => {
+                writeln!(self.out, "{}discard;", level)?;
+            }
+            crate::Statement::Store { pointer, value } => {
+                write!(self.out, "{}", level)?;
+                self.put_expression(pointer, context)?;
+                write!(self.out, " = ")?;
+                self.put_expression(value, context)?;
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::ImageStore { image, coordinate, value } => {
+                write!(self.out, "{}", level)?;
+                self.put_expression(image, context)?;
+                write!(self.out, "[")?;
+                self.put_expression(coordinate, context)?;
+                write!(self.out, "] = ")?;
+                self.put_expression(value, context)?;
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::Call { function, ref arguments } => {
+                write!(self.out, "{}{}", level, self.names[&NameKey::Function(function)])?;
+                self.put_arguments(arguments, context)?;
+                writeln!(self.out, ";")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_function(&mut self, token: Token<crate::Function>, module: &crate::Module) -> Result<(), Error> {
+        let fun = &module.functions[token];
+        match fun.return_type {
+            Some(ty) => {
+                self.put_base_type(ty, module)?;
+                self.put_array_dimensions(ty, module)?;
+            }
+            None => write!(self.out, "void")?,
+        }
+        write!(self.out, " {}(", self.names[&NameKey::Function(token)])?;
+        for (index, &ty) in fun.parameter_types.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            // pointers can only be passed as references to the variables
+            if let crate::TypeInner::Pointer { .. } = module.types[ty].inner {
+                write!(self.out, "inout ")?;
+            }
+            let name = self.names[&NameKey::FunctionParameter(token, index as u32)].clone();
+            self.put_declaration(ty, &name, module)?;
+        }
+        writeln!(self.out, ") {{")?;
+
+        self.typifier.resolve_all(&fun.expressions, &ResolveContext {
+            types: &module.types,
+            constants: &module.constants,
+            global_vars: &module.global_variables,
+            local_vars: &fun.local_variables,
+            functions: &module.functions,
+            parameter_types: &fun.parameter_types,
+        })?;
+        let level = Level(1);
+        for (local_token, local) in fun.local_variables.iter() {
+            let name = self.names[&NameKey::LocalVariable(token, local_token)].clone();
+            write!(self.out, "{}", level)?;
+            self.put_declaration(local.ty, &name, module)?;
+            if let Some(init) = local.init {
+                write!(self.out, " = ")?;
+                self.put_constant(init, module)?;
+            }
+            writeln!(self.out, ";")?;
+        }
+        let context = FunctionContext {
+            token,
+            function: fun,
+            module,
+        };
+        self.put_block(level, &fun.body, &context)?;
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    fn put_struct(&mut self, token: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        writeln!(self.out, "struct {} {{", self.names[&NameKey::Type(token)])?;
+        if let crate::TypeInner::Struct { ref members } = module.types[token].inner {
+            for (index, member) in members.iter().enumerate() {
+                let name = self.names[&NameKey::StructMember(token, index as u32)].clone();
+                write!(self.out, "{}", Level(1))?;
+                if is_matrix(member.ty, module) {
+                    write!(self.out, "row_major ")?;
+                }
+                self.put_declaration(member.ty, &name, module)?;
+                writeln!(self.out, ";")?;
+            }
+        }
+        writeln!(self.out, "}};")?;
+        Ok(())
+    }
+
+    fn put_constructor(&mut self, token: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        let type_name = self.names[&NameKey::Type(token)].clone();
+        let members = match module.types[token].inner {
+            crate::TypeInner::Struct { ref members } => members,
+            _ => return Ok(()),
+        };
+        write!(self.out, "{} {}(", type_name, self.constructors[&token])?;
+        for (index, member) in members.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            self.put_declaration(member.ty, &format!("arg{}", index), module)?;
+        }
+        writeln!(self.out, ") {{")?;
+        writeln!(self.out, "{}{} result;", Level(1), type_name)?;
+        for index in 0 .. members.len() {
+            let name = &self.names[&NameKey::StructMember(token, index as u32)];
+            writeln!(self.out, "{}result.{} = arg{};", Level(1), name, index)?;
+        }
+        writeln!(self.out, "{}return result;", Level(1))?;
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    fn put_register(
+        &mut self,
+        token: Token<crate::GlobalVariable>,
+        module: &crate::Module,
+        options: &Options,
+    ) -> Result<(), Error> {
+        let var = &module.global_variables[token];
+        let source = match var.binding {
+            Some(crate::Binding::Descriptor { set, binding }) => BindSource { set, binding },
+            _ => return Err(Error::MissingBinding(token)),
+        };
+        let target = options.binding_map
+            .get(&source)
+            .ok_or_else(|| Error::MissingBindTarget(source.clone()))?;
+        write!(self.out, " : register({}{}", register_kind(var, module), target.register)?;
+        match options.shader_model {
+            ShaderModel::V5_0 if target.space != 0 => {
+                return Err(Error::UnsupportedRegisterSpace(target.space));
+            }
+            ShaderModel::V5_0 => (),
+            ShaderModel::V5_1 => write!(self.out, ", space{}", target.space)?,
+        }
+        write!(self.out, ")")?;
+        Ok(())
+    }
+
+    fn put_global(
+        &mut self,
+        token: Token<crate::GlobalVariable>,
+        module: &crate::Module,
+        options: &Options,
+    ) -> Result<(), Error> {
+        let var = &module.global_variables[token];
+        let ty = global_value_type(var, module);
+        let name = self.names[&NameKey::GlobalVariable(token)].clone();
+        match var.class {
+            spirv::StorageClass::Input |
+            spirv::StorageClass::Output |
+            spirv::StorageClass::Private => {
+                write!(self.out, "static ")?;
+                self.put_declaration(ty, &name, module)?;
+            }
+            spirv::StorageClass::Workgroup => {
+                write!(self.out, "groupshared ")?;
+                self.put_declaration(ty, &name, module)?;
+            }
+            spirv::StorageClass::StorageBuffer => {
+                if let crate::TypeInner::Array { .. } = module.types[ty].inner {
+                    return Err(Error::UnsupportedDynamicArray(ty));
+                }
+                write!(self.out, "RWStructuredBuffer<")?;
+                self.put_base_type(ty, module)?;
+                write!(self.out, "> {}", name)?;
+                self.put_register(token, module, options)?;
+            }
+            spirv::StorageClass::Uniform |
+            spirv::StorageClass::UniformConstant => match module.types[ty].inner {
+                crate::TypeInner::Image { .. } |
+                crate::TypeInner::Sampler { .. } => {
+                    self.put_declaration(ty, &name, module)?;
+                    self.put_register(token, module, options)?;
+                }
+                _ => {
+                    write!(self.out, "cbuffer {}_block", name)?;
+                    self.put_register(token, module, options)?;
+                    writeln!(self.out, " {{")?;
+                    write!(self.out, "{}", Level(1))?;
+                    if is_matrix(ty, module) {
+                        write!(self.out, "row_major ")?;
+                    }
+                    self.put_declaration(ty, &name, module)?;
+                    writeln!(self.out, ";")?;
+                    write!(self.out, "}}")?;
+                }
+            },
+            other => return Err(Error::UnsupportedStorageClass(other)),
+        }
+        writeln!(self.out, ";")?;
+        Ok(())
+    }
+
+    /// Collects the inputs and outputs of an entry point, with their semantics.
+    fn collect_interface(
+        &self,
+        ep: &crate::EntryPoint,
+        globals: &[Token<crate::GlobalVariable>],
+        class: spirv::StorageClass,
+        module: &crate::Module,
+    ) -> Result<Vec<Interface>, Error> {
+        let mut namer = Namer::new(RESERVED_NAMES, &[]);
+        let mut interface = Vec::new();
+        for &token in globals {
+            let var = &module.global_variables[token];
+            if var.class != class {
+                continue;
+            }
+            let ty = global_value_type(var, module);
+            let var_name = &self.names[&NameKey::GlobalVariable(token)];
+            match (&var.binding, &module.types[ty].inner) {
+                (Some(binding), _) => interface.push(Interface {
+                    source: (token, None),
+                    ty,
+                    name: namer.call(Some(var_name), "member"),
+                    semantic: self.semantic(binding, class, ep.exec_model)?,
+                }),
+                (None, crate::TypeInner::Struct { members }) => {
+                    for (index, member) in members.iter().enumerate() {
+                        let binding = match member.binding {
+                            Some(ref binding) => binding,
+                            None => continue,
+                        };
+                        let member_name = &self.names[&NameKey::StructMember(ty, index as u32)];
+                        interface.push(Interface {
+                            source: (token, Some(index as u32)),
+                            ty: member.ty,
+                            name: namer.call(Some(&format!("{}_{}", var_name, member_name)), "member"),
+                            semantic: self.semantic(binding, class, ep.exec_model)?,
+                        });
+                    }
+                }
+                _ => return Err(Error::MissingBinding(token)),
+            }
+        }
+        Ok(interface)
+    }
+
+    fn put_interface_struct(
+        &mut self,
+        name: &str,
+        interface: &[Interface],
+        interpolated: bool,
+        module: &crate::Module,
+    ) -> Result<(), Error> {
+        writeln!(self.out, "struct {} {{", name)?;
+        for item in interface {
+            write!(self.out, "{}", Level(1))?;
+            if interpolated {
+                // integers can't be interpolated
+                match module.types[item.ty].inner {
+                    crate::TypeInner::Scalar { kind, .. } |
+                    crate::TypeInner::Vector { kind, .. } if kind != crate::ScalarKind::Float => {
+                        write!(self.out, "nointerpolation ")?;
+                    }
+                    _ => (),
+                }
+            }
+            self.put_declaration(item.ty, &item.name, module)?;
+            writeln!(self.out, " : {};", item.semantic)?;
+        }
+        writeln!(self.out, "}};")?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    fn put_interface_source(&mut self, item: &Interface, module: &crate::Module) -> Result<(), Error> {
+        let (token, member) = item.source;
+        write!(self.out, "{}", self.names[&NameKey::GlobalVariable(token)])?;
+        if let Some(index) = member {
+            let ty = global_value_type(&module.global_variables[token], module);
+            write!(self.out, ".{}", self.names[&NameKey::StructMember(ty, index)])?;
+        }
+        Ok(())
+    }
+
+    fn put_entry_point(
+        &mut self,
+        ep: &crate::EntryPoint,
+        name: &str,
+        namer: &mut Namer,
+        module: &crate::Module,
+    ) -> Result<(), Error> {
+        let mut globals = ep.inputs
+            .iter()
+            .chain(ep.outputs.iter())
+            .cloned()
+            .collect::<FastHashSet<_>>();
+        for fun_token in call_order(module, Some(ep.function)) {
+            for (_, expr) in module.functions[fun_token].expressions.iter() {
+                if let crate::Expression::GlobalVariable(token) = *expr {
+                    globals.insert(token);
+                }
+            }
+        }
+        let mut globals = globals.into_iter().collect::<Vec<_>>();
+        globals.sort_by_key(|token| token.index());
+
+        let inputs = self.collect_interface(ep, &globals, spirv::StorageClass::Input, module)?;
+        let outputs = self.collect_interface(ep, &globals, spirv::StorageClass::Output, module)?;
+        let input_name = namer.call(Some(&format!("{}_Input", name)), "Input");
+        let output_name = namer.call(Some(&format!("{}_Output", name)), "Output");
+        if !inputs.is_empty() {
+            let interpolated = ep.exec_model == spirv::ExecutionModel::Fragment;
+            self.put_interface_struct(&input_name, &inputs, interpolated, module)?;
+        }
+        if !outputs.is_empty() {
+            let interpolated = ep.exec_model == spirv::ExecutionModel::Vertex;
+            self.put_interface_struct(&output_name, &outputs, interpolated, module)?;
+        }
+
+        if ep.exec_model == spirv::ExecutionModel::GLCompute {
+            writeln!(self.out, "[numthreads(1, 1, 1)]")?;
+        }
+        let return_type = if outputs.is_empty() { "void" } else { &output_name };
+        write!(self.out, "{} {}(", return_type, name)?;
+        if !inputs.is_empty() {
+            write!(self.out, "{} {}", input_name, NAME_INPUT)?;
+        }
+        writeln!(self.out, ") {{")?;
+        let level = Level(1);
+        for item in inputs.iter() {
+            write!(self.out, "{}", level)?;
+            self.put_interface_source(item, module)?;
+            writeln!(self.out, " = {}.{};", NAME_INPUT, item.name)?;
+        }
+        writeln!(self.out, "{}{}();", level, self.names[&NameKey::Function(ep.function)])?;
+        if !outputs.is_empty() {
+            writeln!(self.out, "{}{} {};", level, output_name, NAME_OUTPUT)?;
+            for item in outputs.iter() {
+                write!(self.out, "{}{}.{} = ", level, NAME_OUTPUT, item.name)?;
+                self.put_interface_source(item, module)?;
+                writeln!(self.out, ";")?;
+            }
+            writeln!(self.out, "{}return {};", level, NAME_OUTPUT)?;
+        }
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    pub fn write(&mut self, module: &crate::Module, options: &Options) -> Result<(), Error> {
+        for ep in module.entry_points.iter() {
+            match ep.exec_model {
+                spirv::ExecutionModel::Vertex |
+                spirv::ExecutionModel::Fragment |
+                spirv::ExecutionModel::GLCompute => (),
+                other => return Err(Error::UnsupportedExecutionModel(other)),
+            }
+        }
+
+        let mut namer = Namer::new(RESERVED_NAMES, &[]);
+        self.names = assign_names(module, &mut namer);
+
+        // the entry points take the names of their functions,
+        // which are then called from them
+        let mut ep_names = Vec::new();
+        let mut renamed = FastHashSet::default();
+        for ep in module.entry_points.iter() {
+            let key = NameKey::Function(ep.function);
+            if renamed.insert(ep.function) {
+                let name = self.names[&key].clone();
+                let impl_name = namer.call(Some(&format!("{}_impl", name)), "entry_point");
+                self.names.insert(key, impl_name);
+                ep_names.push(name);
+            } else {
+                ep_names.push(namer.call(Some(&ep.name), "entry_point"));
+            }
+        }
+
+        let functions = call_order(module, module.entry_points.iter().map(|ep| ep.function));
+        let mut used_globals = FastHashSet::default();
+        self.constructors.clear();
+        for &fun_token in functions.iter() {
+            for (_, expr) in module.functions[fun_token].expressions.iter() {
+                match *expr {
+                    crate::Expression::GlobalVariable(token) => {
+                        used_globals.insert(token);
+                    }
+                    crate::Expression::Compose { ty, .. } => {
+                        if let crate::TypeInner::Struct { .. } = module.types[ty].inner {
+                            if !self.constructors.contains_key(&ty) {
+                                let type_name = &self.names[&NameKey::Type(ty)];
+                                let name = namer.call(Some(&format!("{}_construct", type_name)), "construct");
+                                self.constructors.insert(ty, name);
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for ep in module.entry_points.iter() {
+            used_globals.extend(ep.inputs.iter().chain(ep.outputs.iter()).cloned());
+        }
+
+        // structures with runtime-sized arrays can't be expressed
+        for (token, ty) in module.types.iter() {
+            if let crate::TypeInner::Struct { ref members } = ty.inner {
+                let has_dynamic_array = members.iter().any(|member| match module.types[member.ty].inner {
+                    crate::TypeInner::Array { size, .. } => size == crate::ArraySize::Dynamic,
+                    _ => false,
+                });
+                if has_dynamic_array {
+                    continue;
+                }
+                self.put_struct(token, module)?;
+                writeln!(self.out)?;
+                if self.constructors.contains_key(&token) {
+                    self.put_constructor(token, module)?;
+                    writeln!(self.out)?;
+                }
+            }
+        }
+
+        let mut has_globals = false;
+        for (token, _) in module.global_variables.iter() {
+            if used_globals.contains(&token) {
+                self.put_global(token, module, options)?;
+                has_globals = true;
+            }
+        }
+        if has_globals {
+            writeln!(self.out)?;
+        }
+
+        for &token in functions.iter() {
+            self.put_function(token, module)?;
+            writeln!(self.out)?;
+        }
+
+        for (i, (ep, name)) in module.entry_points.iter().zip(ep_names.iter()).enumerate() {
+            if i != 0 {
+                writeln!(self.out)?;
+            }
+            self.put_entry_point(ep, name, &mut namer, module)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn write_string(module: &crate::Module, options: &Options) -> Result<String, Error> {
+    let mut w = Writer::new(String::new());
+    w.write(module, options)?;
+    Ok(w.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BindSource, BindTarget, BindingMap, Error, Options, ShaderModel};
+    use crate::storage::Storage;
+
+    const SOURCE: &str = "
+        import \"GLSL.std.450\" as std;
+        [[location 0]] var<in> a_pos : vec2<f32>;
+        [[builtin vertex_idx]] var<in> vertex_index : u32;
+        [[location 0]] var<out> v_uv : vec2<f32>;
+        [[builtin position]] var<out> o_position : vec4<f32>;
+        fn vs_main() -> void {
+            v_uv = a_pos;
+            o_position = vec4<f32>(a_pos, 0.0, 1.0);
+            return;
+        }
+        entry_point vertex as \"main\" = vs_main;
+        [[location 0]] var<in> f_uv : vec2<f32>;
+        [[location 0]] var<out> o_color : vec4<f32>;
+        fn fs_main() -> void {
+            o_color = vec4<f32>(f_uv, std::fma(f_uv, f_uv, f_uv));
+            return;
+        }
+        entry_point fragment as \"main\" = fs_main;
+    ";
+
+    #[test]
+    fn write_entry_points() {
+        let module = crate::front::wgsl::parse_str(SOURCE).unwrap();
+        let options = Options {
+            shader_model: ShaderModel::V5_0,
+            binding_map: &BindingMap::default(),
+        };
+        let text = super::write_string(&module, &options).unwrap();
+        assert!(text.contains("static float2 a_pos;"));
+        assert!(text.contains("void vs_main_impl() {\n    v_uv = a_pos;\n"));
+        assert!(text.contains("o_position = float4(a_pos, 0.0, 1.0);"));
+        assert!(text.contains("struct vs_main_Input {\n    float2 a_pos : TEXCOORD0;\n    uint vertex_index : SV_VertexID;\n};"));
+        assert!(text.contains("struct vs_main_Output {\n    float2 v_uv : TEXCOORD0;\n    float4 o_position : SV_Position;\n};"));
+        assert!(text.contains("vs_main_Output vs_main(vs_main_Input input) {\n    a_pos = input.a_pos;\n"));
+        assert!(text.contains("    vs_main_impl();\n    vs_main_Output output;\n    output.v_uv = v_uv;\n"));
+        assert!(text.contains("float2 f_uv : TEXCOORD0;"));
+        assert!(text.contains("float4 o_color : SV_Target0;"));
+        assert!(text.contains("o_color = float4(f_uv, mad(f_uv, f_uv, f_uv));"));
+    }
+
+    #[test]
+    fn write_registers() {
+        let mut module = crate::Module::generate_empty();
+        let ty_float = module.types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 },
+        });
+        let ty_vec = module.types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Vector { size: crate::VectorSize::Quad, kind: crate::ScalarKind::Float, width: 32 },
+        });
+        let ty_struct = module.types.append(crate::Type {
+            name: Some("Globals".to_owned()),
+            inner: crate::TypeInner::Struct {
+                members: vec![crate::StructMember {
+                    name: Some("color".to_owned()),
+                    binding: None,
+                    offset: None,
+                    ty: ty_vec,
+                }, crate::StructMember {
+                    name: Some("scale".to_owned()),
+                    binding: None,
+                    offset: None,
+                    ty: ty_float,
+                }],
+            },
+        });
+        let globals = module.global_variables.append(crate::GlobalVariable {
+            name: Some("globals".to_owned()),
+            class: spirv::StorageClass::Uniform,
+            binding: Some(crate::Binding::Descriptor { set: 1, binding: 2 }),
+            ty: ty_struct,
+        });
+        let output = module.global_variables.append(crate::GlobalVariable {
+            name: Some("color".to_owned()),
+            class: spirv::StorageClass::Output,
+            binding: Some(crate::Binding::Location(1)),
+            ty: ty_vec,
+        });
+        let mut expressions = Storage::new();
+        let base = expressions.append(crate::Expression::GlobalVariable(globals));
+        let value = expressions.append(crate::Expression::AccessIndex { base, index: 0 });
+        let pointer = expressions.append(crate::Expression::GlobalVariable(output));
+        let function = module.functions.append(crate::Function {
+            name: Some("main".to_owned()),
+            control: spirv::FunctionControl::empty(),
+            parameter_types: Vec::new(),
+            return_type: None,
+            local_variables: Storage::new(),
+            expressions,
+            body: vec![crate::Statement::Store { pointer, value }],
+            spans: Default::default(),
+        });
+        module.entry_points.push(crate::EntryPoint {
+            exec_model: spirv::ExecutionModel::Fragment,
+            name: "main".to_owned(),
+            inputs: Vec::new(),
+            outputs: vec![output],
+            function,
+        });
+
+        let source = BindSource { set: 1, binding: 2 };
+        let mut binding_map = BindingMap::default();
+        let write = |module: &crate::Module, shader_model, binding_map: &BindingMap| {
+            super::write_string(module, &Options { shader_model, binding_map })
+        };
+        match write(&module, ShaderModel::V5_1, &binding_map) {
+            Err(Error::MissingBindTarget(ref s)) if *s == source => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        binding_map.insert(source.clone(), BindTarget { register: 3, space: 1 });
+        let text = write(&module, ShaderModel::V5_1, &binding_map).unwrap();
+        assert!(text.contains("cbuffer globals_block : register(b3, space1) {\n    Globals globals;\n};"));
+        assert!(text.contains("    color = globals.color;\n"));
+        assert!(text.contains("float4 color : SV_Target1;"));
+        assert!(text.contains("main_Output main() {"));
+
+        match write(&module, ShaderModel::V5_0, &binding_map) {
+            Err(Error::UnsupportedRegisterSpace(1)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        module.global_variables[globals].class = spirv::StorageClass::StorageBuffer;
+        binding_map.insert(source, BindTarget { register: 0, space: 0 });
+        let text = write(&module, ShaderModel::V5_0, &binding_map).unwrap();
+        assert!(text.contains("RWStructuredBuffer<Globals> globals : register(u0);"));
+        assert!(text.contains("    color = globals[0].color;\n"));
+    }
+
+    #[test]
+    fn write_signed_shifts() {
+        let module = crate::front::wgsl::parse_str("
+            [[location 0]] var<in> value : i32;
+            [[location 1]] var<in> mask : vec2<u32>;
+            [[location 0]] var<out> o_value : i32;
+            [[location 1]] var<out> o_mask : vec2<u32>;
+            fn main() -> void {
+                o_value = value >> 2;
+                o_mask = mask >>> mask;
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap();
+        let options = Options {
+            shader_model: ShaderModel::V5_0,
+            binding_map: &BindingMap::default(),
+        };
+        let text = super::write_string(&module, &options).unwrap();
+        assert!(text.contains("int(uint(value) >> 2)"), "{}", text);
+        assert!(text.contains("uint2(int2(mask) >> "), "{}", text);
+    }
+}
@@ -0,0 +1,607 @@
+/*! Textual IR backend
+
+Prints a module in a canonical text format, which `front::ir` parses back
+into an identical module. Every arena is printed in order, so the tokens
+keep their indices:
+
+```text
+type %f32 = scalar Float 32
+global %color "color" Output %vec4_f32 location(0)
+
+function %main "main"() -> void {
+    %0 = global %color
+    %1 = constant %const0
+    %2 = compose %vec4_f32(%1, %1, %1, %1)
+    store %0 %2
+    return
+}
+```
+
+The entities are referred to by labels derived from their names, or from
+the types themselves, while the expressions are referred to by index.
+
+Spans are not part of the format, as they point into the source the module
+was parsed from, so the module parsed back is identical except for having
+no spans.
+!*/
+
+use std::fmt::{Error as FmtError, Write};
+
+use crate::{
+    back::Namer,
+    front::spvasm::grammar::{EnumKind, MaskKind},
+    storage::Token,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Format(FmtError),
+}
+
+impl From<FmtError> for Error {
+    fn from(e: FmtError) -> Self {
+        Error::Format(e)
+    }
+}
+
+const INDENT: &str = "    ";
+
+pub(crate) fn scalar_kind_name(kind: crate::ScalarKind) -> &'static str {
+    match kind {
+        crate::ScalarKind::Sint => "Sint",
+        crate::ScalarKind::Uint => "Uint",
+        crate::ScalarKind::Float => "Float",
+        crate::ScalarKind::Bool => "Bool",
+    }
+}
+
+/// Returns a label describing an unnamed type.
+fn type_label(inner: &crate::TypeInner, labels: &[String]) -> String {
+    let scalar = |kind, width| match kind {
+        crate::ScalarKind::Sint => format!("i{}", width),
+        crate::ScalarKind::Uint => format!("u{}", width),
+        crate::ScalarKind::Float => format!("f{}", width),
+        crate::ScalarKind::Bool => "bool".to_owned(),
+    };
+    match *inner {
+        crate::TypeInner::Scalar { kind, width } => scalar(kind, width),
+        crate::TypeInner::Vector { size, kind, width } => format!("vec{}_{}", size as u8, scalar(kind, width)),
+        crate::TypeInner::Matrix { columns, rows, kind, width } => {
+            format!("mat{}x{}_{}", columns as u8, rows as u8, scalar(kind, width))
+        }
+        crate::TypeInner::Pointer { base, class } => {
+            format!("ptr_{:?}_{}", class, labels[base.index()]).to_lowercase()
+        }
+        crate::TypeInner::Array { base, size: crate::ArraySize::Static(length) } => {
+            format!("array_{}_{}", labels[base.index()], length)
+        }
+        crate::TypeInner::Array { base, size: crate::ArraySize::Dynamic } => {
+            format!("array_{}", labels[base.index()])
+        }
+        crate::TypeInner::Struct { .. } => "struct".to_owned(),
+        crate::TypeInner::Image { dim, .. } => {
+            format!("image{}", EnumKind::Dim.name(dim as u32).unwrap_or_default()).to_lowercase()
+        }
+        crate::TypeInner::Sampler { .. } => "sampler".to_owned(),
+    }
+}
+
+pub(crate) const IMAGE_FLAGS: &[(&str, crate::ImageFlags)] = &[
+    ("arrayed", crate::ImageFlags::ARRAYED),
+    ("multisampled", crate::ImageFlags::MULTISAMPLED),
+    ("sampled", crate::ImageFlags::SAMPLED),
+    ("depth", crate::ImageFlags::DEPTH),
+    ("can_load", crate::ImageFlags::CAN_LOAD),
+    ("can_store", crate::ImageFlags::CAN_STORE),
+];
+
+pub(crate) const UNARY_OPERATORS: &[crate::UnaryOperator] = &[
+    crate::UnaryOperator::Negate,
+    crate::UnaryOperator::Not,
+    crate::UnaryOperator::BitwiseNot,
+];
+
+pub(crate) const BINARY_OPERATORS: &[crate::BinaryOperator] = &[
+    crate::BinaryOperator::Add,
+    crate::BinaryOperator::Subtract,
+    crate::BinaryOperator::Multiply,
+    crate::BinaryOperator::Divide,
+    crate::BinaryOperator::Modulo,
+    crate::BinaryOperator::Equal,
+    crate::BinaryOperator::NotEqual,
+    crate::BinaryOperator::Less,
+    crate::BinaryOperator::LessEqual,
+    crate::BinaryOperator::Greater,
+    crate::BinaryOperator::GreaterEqual,
+    crate::BinaryOperator::And,
+    crate::BinaryOperator::ExclusiveOr,
+    crate::BinaryOperator::InclusiveOr,
+    crate::BinaryOperator::LogicalAnd,
+    crate::BinaryOperator::LogicalOr,
+    crate::BinaryOperator::ShiftLeftLogical,
+    crate::BinaryOperator::ShiftRightLogical,
+    crate::BinaryOperator::ShiftRightArithmetic,
+];
+
+pub(crate) const MATH_FUNCTIONS: &[crate::MathFunction] = {
+    use crate::MathFunction as Mf;
+    &[
+        Mf::Round, Mf::RoundEven, Mf::Trunc, Mf::FAbs, Mf::SAbs, Mf::FSign,
+        Mf::SSign, Mf::Floor, Mf::Ceil, Mf::Fract, Mf::Radians, Mf::Degrees,
+        Mf::Sin, Mf::Cos, Mf::Tan, Mf::Asin, Mf::Acos, Mf::Atan, Mf::Sinh,
+        Mf::Cosh, Mf::Tanh, Mf::Asinh, Mf::Acosh, Mf::Atanh, Mf::Atan2,
+        Mf::Pow, Mf::Exp, Mf::Log, Mf::Exp2, Mf::Log2, Mf::Sqrt,
+        Mf::InverseSqrt, Mf::Determinant, Mf::MatrixInverse, Mf::FMin,
+        Mf::UMin, Mf::SMin, Mf::FMax, Mf::UMax, Mf::SMax, Mf::FClamp,
+        Mf::UClamp, Mf::SClamp, Mf::FMix, Mf::IMix, Mf::Step, Mf::SmoothStep,
+        Mf::Fma, Mf::Ldexp, Mf::PackSnorm4x8, Mf::PackUnorm4x8,
+        Mf::PackSnorm2x16, Mf::PackUnorm2x16, Mf::PackHalf2x16,
+        Mf::UnpackSnorm2x16, Mf::UnpackUnorm2x16, Mf::UnpackHalf2x16,
+        Mf::UnpackSnorm4x8, Mf::UnpackUnorm4x8, Mf::Length, Mf::Distance,
+        Mf::Cross, Mf::Normalize, Mf::FaceForward, Mf::Reflect, Mf::Refract,
+        Mf::FindILsb, Mf::FindSMsb, Mf::FindUMsb, Mf::NMin, Mf::NMax,
+        Mf::NClamp,
+    ]
+};
+
+pub struct Writer<W> {
+    out: W,
+    types: Vec<String>,
+    constants: Vec<String>,
+    globals: Vec<String>,
+    functions: Vec<String>,
+    locals: Vec<String>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Writer {
+            out,
+            types: Vec::new(),
+            constants: Vec::new(),
+            globals: Vec::new(),
+            functions: Vec::new(),
+            locals: Vec::new(),
+        }
+    }
+
+    pub fn finish(self) -> W {
+        self.out
+    }
+
+    fn put_string(&mut self, string: &str) -> Result<(), Error> {
+        write!(self.out, "\"")?;
+        for c in string.chars() {
+            if c == '"' || c == '\\' {
+                write!(self.out, "\\")?;
+            }
+            write!(self.out, "{}", c)?;
+        }
+        write!(self.out, "\"")?;
+        Ok(())
+    }
+
+    /// Writes the label of a declaration, followed by its name if any.
+    fn put_label(&mut self, label: &str, name: &Option<String>) -> Result<(), Error> {
+        write!(self.out, "%{}", label)?;
+        if let Some(ref name) = *name {
+            write!(self.out, " ")?;
+            self.put_string(name)?;
+        }
+        Ok(())
+    }
+
+    fn put_binding(&mut self, binding: &Option<crate::Binding>) -> Result<(), Error> {
+        match *binding {
+            Some(crate::Binding::BuiltIn(built_in)) => write!(self.out, " builtin({:?})", built_in)?,
+            Some(crate::Binding::Location(location)) => write!(self.out, " location({})", location)?,
+            Some(crate::Binding::Descriptor { set, binding }) => {
+                write!(self.out, " descriptor({}, {})", set, binding)?
+            }
+            None => (),
+        }
+        Ok(())
+    }
+
+    fn put_expressions(&mut self, expressions: &[Token<crate::Expression>]) -> Result<(), Error> {
+        write!(self.out, "(")?;
+        for (index, expr) in expressions.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            write!(self.out, "%{}", expr.index())?;
+        }
+        write!(self.out, ")")?;
+        Ok(())
+    }
+
+    fn put_type_inner(&mut self, inner: &crate::TypeInner) -> Result<(), Error> {
+        match *inner {
+            crate::TypeInner::Scalar { kind, width } => {
+                write!(self.out, "scalar {} {}", scalar_kind_name(kind), width)?;
+            }
+            crate::TypeInner::Vector { size, kind, width } => {
+                write!(self.out, "vector {} {} {}", size as u8, scalar_kind_name(kind), width)?;
+            }
+            crate::TypeInner::Matrix { columns, rows, kind, width } => {
+                write!(
+                    self.out,
+                    "matrix {} {} {} {}",
+                    columns as u8,
+                    rows as u8,
+                    scalar_kind_name(kind),
+                    width,
+                )?;
+            }
+            crate::TypeInner::Pointer { base, class } => {
+                write!(self.out, "pointer {:?} %{}", class, self.types[base.index()])?;
+            }
+            crate::TypeInner::Array { base, size } => {
+                write!(self.out, "array %{}", self.types[base.index()])?;
+                match size {
+                    crate::ArraySize::Static(length) => write!(self.out, " {}", length)?,
+                    crate::ArraySize::Dynamic => write!(self.out, " dynamic")?,
+                }
+            }
+            crate::TypeInner::Struct { ref members } => {
+                writeln!(self.out, "struct {{")?;
+                for member in members {
+                    write!(self.out, "{}%{}", INDENT, self.types[member.ty.index()])?;
+                    if let Some(ref name) = member.name {
+                        write!(self.out, " ")?;
+                        self.put_string(name)?;
+                    }
+                    self.put_binding(&member.binding)?;
+                    if let Some(offset) = member.offset {
+                        write!(self.out, " offset({})", offset)?;
+                    }
+                    writeln!(self.out)?;
+                }
+                write!(self.out, "}}")?;
+            }
+            crate::TypeInner::Image { base, dim, flags } => {
+                write!(
+                    self.out,
+                    "image %{} {} ",
+                    self.types[base.index()],
+                    EnumKind::Dim.name(dim as u32).unwrap_or_default(),
+                )?;
+                let names = IMAGE_FLAGS
+                    .iter()
+                    .filter(|&&(_, flag)| flags.contains(flag))
+                    .map(|&(name, _)| name)
+                    .collect::<Vec<_>>();
+                if names.is_empty() {
+                    write!(self.out, "none")?;
+                } else {
+                    write!(self.out, "{}", names.join("|"))?;
+                }
+            }
+            crate::TypeInner::Sampler { comparison } => {
+                write!(self.out, "sampler")?;
+                if comparison {
+                    write!(self.out, " comparison")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn put_expression(&mut self, expr: &crate::Expression) -> Result<(), Error> {
+        match *expr {
+            crate::Expression::Access { base, index } => {
+                write!(self.out, "access %{} %{}", base.index(), index.index())?;
+            }
+            crate::Expression::AccessIndex { base, index } => {
+                write!(self.out, "access_index %{} {}", base.index(), index)?;
+            }
+            crate::Expression::Constant(constant) => {
+                write!(self.out, "constant %{}", self.constants[constant.index()])?;
+            }
+            crate::Expression::Compose { ty, ref components } => {
+                write!(self.out, "compose %{}", self.types[ty.index()])?;
+                self.put_expressions(components)?;
+            }
+            crate::Expression::FunctionParameter(index) => write!(self.out, "parameter {}", index)?,
+            crate::Expression::GlobalVariable(var) => {
+                write!(self.out, "global %{}", self.globals[var.index()])?;
+            }
+            crate::Expression::LocalVariable(var) => {
+                write!(self.out, "local %{}", self.locals[var.index()])?;
+            }
+            crate::Expression::Load { pointer } => write!(self.out, "load %{}", pointer.index())?,
+            crate::Expression::ImageSample { image, sampler, coordinate } => {
+                write!(
+                    self.out,
+                    "image_sample %{} %{} %{}",
+                    image.index(),
+                    sampler.index(),
+                    coordinate.index(),
+                )?;
+            }
+            crate::Expression::ImageLoad { image, coordinate, index } => {
+                write!(self.out, "image_load %{} %{}", image.index(), coordinate.index())?;
+                if let Some(index) = index {
+                    write!(self.out, " %{}", index.index())?;
+                }
+            }
+            crate::Expression::ImageDimensions { image, level } => {
+                write!(self.out, "image_dimensions %{}", image.index())?;
+                if let Some(level) = level {
+                    write!(self.out, " %{}", level.index())?;
+                }
+            }
+            crate::Expression::Unary { op, expr } => {
+                write!(self.out, "unary {:?} %{}", op, expr.index())?;
+            }
+            crate::Expression::Binary { op, left, right } => {
+                write!(self.out, "binary {:?} %{} %{}", op, left.index(), right.index())?;
+            }
+            crate::Expression::Call { function, ref arguments } => {
+                write!(self.out, "call %{}", self.functions[function.index()])?;
+                self.put_expressions(arguments)?;
+            }
+            crate::Expression::Math { fun, ref arguments } => {
+                write!(self.out, "math {:?}", fun)?;
+                self.put_expressions(arguments)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_block(&mut self, block: &[crate::Statement], level: usize) -> Result<(), Error> {
+        writeln!(self.out, "{{")?;
+        for statement in block {
+            self.put_statement(statement, level + 1)?;
+        }
+        write!(self.out, "{}}}", INDENT.repeat(level))?;
+        Ok(())
+    }
+
+    fn put_statement(&mut self, statement: &crate::Statement, level: usize) -> Result<(), Error> {
+        let indent = INDENT.repeat(level);
+        write!(self.out, "{}", indent)?;
+        match *statement {
+            crate::Statement::Block(ref block) => {
+                write!(self.out, "block ")?;
+                self.put_block(block, level)?;
+            }
+            crate::Statement::If { condition, ref accept, ref reject } => {
+                write!(self.out, "if %{} ", condition.index())?;
+                self.put_block(accept, level)?;
+                if !reject.is_empty() {
+                    write!(self.out, " else ")?;
+                    self.put_block(reject, level)?;
+                }
+            }
+            crate::Statement::Switch { selector, ref cases, ref default } => {
+                writeln!(self.out, "switch %{} {{", selector.index())?;
+                for &(value, ref block, fall_through) in cases {
+                    write!(self.out, "{}{}case {} ", indent, INDENT, value)?;
+                    if fall_through.is_some() {
+                        write!(self.out, "fallthrough ")?;
+                    }
+                    self.put_block(block, level + 1)?;
+                    writeln!(self.out)?;
+                }
+                write!(self.out, "{}{}default ", indent, INDENT)?;
+                self.put_block(default, level + 1)?;
+                write!(self.out, "\n{}}}", indent)?;
+            }
+            crate::Statement::Loop { ref body, ref continuing } => {
+                write!(self.out, "loop ")?;
+                self.put_block(body, level)?;
+                if !continuing.is_empty() {
+                    write!(self.out, " continuing ")?;
+                    self.put_block(continuing, level)?;
+                }
+            }
+            crate::Statement::Break => write!(self.out, "break")?,
+            crate::Statement::Continue => write!(self.out, "continue")?,
+            crate::Statement::Return { value: Some(value) } => write!(self.out, "return %{}", value.index())?,
+            crate::Statement::Return { value: None } => write!(self.out, "return")?,
+            crate::Statement::Kill => write!(self.out, "kill")?,
+            crate::Statement::Store { pointer, value } => {
+                write!(self.out, "store %{} %{}", pointer.index(), value.index())?;
+            }
+            crate::Statement::ImageStore { image, coordinate, value } => {
+                write!(self.out, "image_store %{} %{} %{}", image.index(), coordinate.index(), value.index())?;
+            }
+            crate::Statement::Call { function, ref arguments } => {
+                write!(self.out, "call %{}", self.functions[function.index()])?;
+                self.put_expressions(arguments)?;
+            }
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    fn put_function(&mut self, token: Token<crate::Function>, fun: &crate::Function) -> Result<(), Error> {
+        let mut namer = Namer::new(&[], &[]);
+        self.locals = fun.local_variables
+            .iter()
+            .map(|(token, var)| namer.call(var.name.as_deref(), &format!("local{}", token.index())))
+            .collect();
+
+        write!(self.out, "function ")?;
+        let label = self.functions[token.index()].clone();
+        self.put_label(&label, &fun.name)?;
+        write!(self.out, "(")?;
+        for (index, &ty) in fun.parameter_types.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            write!(self.out, "%{}", self.types[ty.index()])?;
+        }
+        write!(self.out, ") -> ")?;
+        match fun.return_type {
+            Some(ty) => write!(self.out, "%{}", self.types[ty.index()])?,
+            None => write!(self.out, "void")?,
+        }
+        if !fun.control.is_empty() {
+            let names = MaskKind::FunctionControl
+                .bits()
+                .iter()
+                .filter(|&&(_, bit, _)| fun.control.bits() & bit != 0)
+                .map(|&(name, _, _)| name)
+                .collect::<Vec<_>>();
+            write!(self.out, " control {}", names.join("|"))?;
+        }
+        writeln!(self.out, " {{")?;
+
+        for (token, var) in fun.local_variables.iter() {
+            write!(self.out, "{}local ", INDENT)?;
+            let label = self.locals[token.index()].clone();
+            self.put_label(&label, &var.name)?;
+            write!(self.out, " %{}", self.types[var.ty.index()])?;
+            if let Some(init) = var.init {
+                write!(self.out, " = %{}", self.constants[init.index()])?;
+            }
+            writeln!(self.out)?;
+        }
+        for (token, expr) in fun.expressions.iter() {
+            write!(self.out, "{}%{} = ", INDENT, token.index())?;
+            self.put_expression(expr)?;
+            writeln!(self.out)?;
+        }
+        for statement in fun.body.iter() {
+            self.put_statement(statement, 1)?;
+        }
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    pub fn write(&mut self, module: &crate::Module) -> Result<(), Error> {
+        let (major, minor, patch) = module.header.version;
+        writeln!(self.out, "version {}.{}.{}", major, minor, patch)?;
+        writeln!(self.out, "generator {}", module.header.generator)?;
+
+        let mut namer = Namer::new(&[], &[]);
+        self.types.clear();
+        for (_, ty) in module.types.iter() {
+            let label = namer.call(ty.name.as_deref(), &type_label(&ty.inner, &self.types));
+            self.types.push(label);
+        }
+        let mut namer = Namer::new(&[], &[]);
+        self.constants = module.constants
+            .iter()
+            .map(|(token, constant)| namer.call(constant.name.as_deref(), &format!("const{}", token.index())))
+            .collect();
+        let mut namer = Namer::new(&[], &[]);
+        self.globals = module.global_variables
+            .iter()
+            .map(|(token, var)| namer.call(var.name.as_deref(), &format!("global{}", token.index())))
+            .collect();
+        let mut namer = Namer::new(&[], &[]);
+        self.functions = module.functions
+            .iter()
+            .map(|(token, fun)| namer.call(fun.name.as_deref(), &format!("function{}", token.index())))
+            .collect();
+
+        if !module.types.is_empty() {
+            writeln!(self.out)?;
+        }
+        for (token, ty) in module.types.iter() {
+            write!(self.out, "type ")?;
+            let label = self.types[token.index()].clone();
+            self.put_label(&label, &ty.name)?;
+            write!(self.out, " = ")?;
+            self.put_type_inner(&ty.inner)?;
+            writeln!(self.out)?;
+        }
+
+        if !module.constants.is_empty() {
+            writeln!(self.out)?;
+        }
+        for (token, constant) in module.constants.iter() {
+            write!(self.out, "const ")?;
+            let label = self.constants[token.index()].clone();
+            self.put_label(&label, &constant.name)?;
+            if let Some(id) = constant.specialization {
+                write!(self.out, " spec({})", id)?;
+            }
+            match constant.inner {
+                crate::ConstantInner::Sint(value) => writeln!(self.out, " = Sint {}", value)?,
+                crate::ConstantInner::Uint(value) => writeln!(self.out, " = Uint {}", value)?,
+                crate::ConstantInner::Float(value) => writeln!(self.out, " = Float {:?}", value)?,
+                crate::ConstantInner::Bool(value) => writeln!(self.out, " = Bool {}", value)?,
+            }
+        }
+
+        if !module.global_variables.is_empty() {
+            writeln!(self.out)?;
+        }
+        for (token, var) in module.global_variables.iter() {
+            write!(self.out, "global ")?;
+            let label = self.globals[token.index()].clone();
+            self.put_label(&label, &var.name)?;
+            write!(self.out, " {:?} %{}", var.class, self.types[var.ty.index()])?;
+            self.put_binding(&var.binding)?;
+            writeln!(self.out)?;
+        }
+
+        for (token, fun) in module.functions.iter() {
+            writeln!(self.out)?;
+            self.put_function(token, fun)?;
+        }
+
+        if !module.entry_points.is_empty() {
+            writeln!(self.out)?;
+        }
+        for ep in module.entry_points.iter() {
+            write!(self.out, "entry_point {:?} ", ep.exec_model)?;
+            self.put_string(&ep.name)?;
+            write!(self.out, " = %{}", self.functions[ep.function.index()])?;
+            for &(keyword, vars) in [("in", &ep.inputs), ("out", &ep.outputs)].iter() {
+                write!(self.out, " {}(", keyword)?;
+                for (index, var) in vars.iter().enumerate() {
+                    if index != 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    write!(self.out, "%{}", self.globals[var.index()])?;
+                }
+                write!(self.out, ")")?;
+            }
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn write_string(module: &crate::Module) -> Result<String, Error> {
+    let mut w = Writer::new(String::new());
+    w.write(module)?;
+    Ok(w.finish())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn write_names_and_floats() {
+        let mut module = crate::Module::generate_empty();
+        let ty = module.types.append(crate::Type {
+            name: Some("a \"quoted\" type".to_owned()),
+            inner: crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 },
+        });
+        for &value in [1e-7, -0.0, f64::INFINITY, f64::NEG_INFINITY].iter() {
+            module.constants.append(crate::Constant {
+                name: None,
+                specialization: None,
+                inner: crate::ConstantInner::Float(value),
+            });
+        }
+        module.global_variables.append(crate::GlobalVariable {
+            name: Some("a \"quoted\" type".to_owned()),
+            class: spirv::StorageClass::Private,
+            binding: None,
+            ty,
+        });
+        let text = super::write_string(&module).unwrap();
+        assert!(text.contains("type %a_quoted_type \"a \\\"quoted\\\" type\" = scalar Float 32\n"));
+        assert!(text.contains("const %const0 = Float 1e-7\n"));
+        assert!(text.contains("const %const3 = Float -inf\n"));
+        assert!(text.contains("global %a_quoted_type \"a \\\"quoted\\\" type\" Private %a_quoted_type\n"));
+        let parsed = crate::front::ir::parse_str(&text).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", module));
+    }
+}
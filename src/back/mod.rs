@@ -1 +1,187 @@
-pub mod msl;
\ No newline at end of file
+pub mod glsl;
+pub mod hlsl;
+pub mod ir;
+pub mod msl;
+pub mod spv;
+pub mod spvasm;
+pub mod wgsl;
+
+use crate::{
+    storage::Token,
+    FastHashMap, FastHashSet,
+};
+
+/// An entity of the module that gets a name in the text backends.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub(crate) enum NameKey {
+    Type(Token<crate::Type>),
+    StructMember(Token<crate::Type>, u32),
+    GlobalVariable(Token<crate::GlobalVariable>),
+    Function(Token<crate::Function>),
+    FunctionParameter(Token<crate::Function>, u32),
+    LocalVariable(Token<crate::Function>, Token<crate::LocalVariable>),
+}
+
+/// Produces unique identifiers within a scope.
+#[derive(Clone)]
+pub(crate) struct Namer {
+    reserved: &'static [&'static str],
+    reserved_prefixes: &'static [&'static str],
+    used: FastHashSet<String>,
+}
+
+impl Namer {
+    pub(crate) fn new(
+        reserved: &'static [&'static str],
+        reserved_prefixes: &'static [&'static str],
+    ) -> Self {
+        Namer {
+            reserved,
+            reserved_prefixes,
+            used: FastHashSet::default(),
+        }
+    }
+
+    /// Returns an identifier based on `name`, or on `fallback` if there
+    /// is nothing usable in the name.
+    pub(crate) fn call(&mut self, name: Option<&str>, fallback: &str) -> String {
+        // identifiers have to start with a letter and continue with
+        // letters, digits or single underscores
+        let mut trimmed = name.unwrap_or_default();
+        loop {
+            trimmed = trimmed.trim_start_matches(|c: char| !c.is_ascii_alphabetic());
+            match self.reserved_prefixes.iter().find(|&p| trimmed.starts_with(p)) {
+                Some(prefix) => trimmed = &trimmed[prefix.len() ..],
+                None => break,
+            }
+        }
+        let mut sanitized = String::new();
+        for c in trimmed.chars() {
+            if c.is_ascii_alphanumeric() {
+                sanitized.push(c);
+            } else if !sanitized.ends_with('_') {
+                sanitized.push('_');
+            }
+        }
+        let base = if sanitized.is_empty() {
+            fallback.to_owned()
+        } else {
+            sanitized
+        };
+        let mut candidate = base.clone();
+        let mut suffix = 1;
+        while self.reserved.contains(&candidate.as_str()) || self.used.contains(&candidate) {
+            candidate = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+        self.used.insert(candidate.clone());
+        candidate
+    }
+}
+
+/// Assigns unique names to all the entities of a module.
+///
+/// Parameters and local variables of a function don't shadow anything global.
+pub(crate) fn assign_names(module: &crate::Module, namer: &mut Namer) -> FastHashMap<NameKey, String> {
+    let mut names = FastHashMap::default();
+    for (token, ty) in module.types.iter() {
+        if let crate::TypeInner::Struct { ref members } = ty.inner {
+            let name = namer.call(ty.name.as_deref(), &format!("Type{}", token.index()));
+            names.insert(NameKey::Type(token), name);
+            let mut member_namer = Namer::new(namer.reserved, namer.reserved_prefixes);
+            for (index, member) in members.iter().enumerate() {
+                let name = member_namer.call(member.name.as_deref(), &format!("field{}", index));
+                names.insert(NameKey::StructMember(token, index as u32), name);
+            }
+        }
+    }
+    for (token, var) in module.global_variables.iter() {
+        let name = namer.call(var.name.as_deref(), &format!("global{}", token.index()));
+        names.insert(NameKey::GlobalVariable(token), name);
+    }
+    for (token, fun) in module.functions.iter() {
+        let name = namer.call(fun.name.as_deref(), &format!("function{}", token.index()));
+        names.insert(NameKey::Function(token), name);
+    }
+    for (fun_token, fun) in module.functions.iter() {
+        let mut local_namer = namer.clone();
+        for index in 0 .. fun.parameter_types.len() as u32 {
+            let name = local_namer.call(None, &format!("param{}", index));
+            names.insert(NameKey::FunctionParameter(fun_token, index), name);
+        }
+        for (token, var) in fun.local_variables.iter() {
+            let name = local_namer.call(var.name.as_deref(), &format!("local{}", token.index()));
+            names.insert(NameKey::LocalVariable(fun_token, token), name);
+        }
+    }
+    names
+}
+
+/// Collects the functions called from the given block.
+fn collect_calls(block: &[crate::Statement], calls: &mut Vec<Token<crate::Function>>) {
+    for statement in block {
+        match *statement {
+            crate::Statement::Block(ref block) => collect_calls(block, calls),
+            crate::Statement::If { ref accept, ref reject, .. } => {
+                collect_calls(accept, calls);
+                collect_calls(reject, calls);
+            }
+            crate::Statement::Switch { ref cases, ref default, .. } => {
+                for (_, block, _) in cases.iter() {
+                    collect_calls(block, calls);
+                }
+                collect_calls(default, calls);
+            }
+            crate::Statement::Loop { ref body, ref continuing } => {
+                collect_calls(body, calls);
+                collect_calls(continuing, calls);
+            }
+            crate::Statement::Call { function, .. } => calls.push(function),
+            _ => (),
+        }
+    }
+}
+
+/// Returns the functions reachable from `roots`, ordered so that
+/// every function comes after all the functions it calls.
+pub(crate) fn call_order(
+    module: &crate::Module,
+    roots: impl IntoIterator<Item = Token<crate::Function>>,
+) -> Vec<Token<crate::Function>> {
+    let mut order = Vec::new();
+    let mut visited = FastHashSet::default();
+    for root in roots {
+        let mut stack = vec![(root, false)];
+        while let Some((token, finished)) = stack.pop() {
+            if finished {
+                order.push(token);
+                continue;
+            }
+            // recursion is not allowed, so seeing a function again
+            // means it's either done or already on the stack
+            if !visited.insert(token) {
+                continue;
+            }
+            stack.push((token, true));
+            let fun = &module.functions[token];
+            let mut calls = Vec::new();
+            collect_calls(&fun.body, &mut calls);
+            for (_, expr) in fun.expressions.iter() {
+                if let crate::Expression::Call { function, .. } = *expr {
+                    calls.push(function);
+                }
+            }
+            stack.extend(calls.into_iter().map(|call| (call, false)));
+        }
+    }
+    order
+}
+
+/// Returns the type of the value a global variable holds.
+pub(crate) fn global_value_type(var: &crate::GlobalVariable, module: &crate::Module) -> Token<crate::Type> {
+    // variables coming from SPIR-V are typed by their pointers
+    match module.types[var.ty].inner {
+        crate::TypeInner::Pointer { base, .. } => base,
+        _ => var.ty,
+    }
+}
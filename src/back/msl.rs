@@ -21,7 +21,8 @@ use std::{
 };
 
 use crate::{
-    storage::Token,
+    back::global_value_type,
+    proc::{ResolveContext, ResolveError, Typifier},
     FastHashMap, FastHashSet
 };
 
@@ -57,7 +58,21 @@ pub enum Error {
     MissingBinding(crate::Token<crate::GlobalVariable>),
     MissingBindTarget(BindSource),
     InvalidImageFlags(crate::ImageFlags),
+    /// The function has no equivalent in the standard library.
+    UnsupportedMathFunction(crate::MathFunction),
     BadName(String),
+    Type(ResolveError),
+}
+
+impl Error {
+    /// Returns the span of the module entity that the error is about.
+    pub fn span(&self, module: &crate::Module) -> crate::Span {
+        match *self {
+            Error::MixedExecutionModels(token) => module.spans.function(token),
+            Error::MissingBinding(token) => module.spans.global_variable(token),
+            _ => crate::Span::default(),
+        }
+    }
 }
 
 impl From<FmtError> for Error {
@@ -66,6 +81,12 @@ impl From<FmtError> for Error {
     }
 }
 
+impl From<ResolveError> for Error {
+    fn from(e: ResolveError) -> Self {
+        Error::Type(e)
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum LocationMode {
     VertexInput,
@@ -119,6 +140,10 @@ impl Indexed for crate::Token<crate::GlobalVariable> {
     const CLASS: &'static str = "global";
     fn id(&self) -> usize { self.index() }
 }
+impl Indexed for crate::Token<crate::LocalVariable> {
+    const CLASS: &'static str = "local";
+    fn id(&self) -> usize { self.index() }
+}
 impl Indexed for crate::Token<crate::Function> {
     const CLASS: &'static str = "function";
     fn id(&self) -> usize { self.index() }
@@ -185,10 +210,10 @@ impl<I: Indexed> From<I> for Name<'_> {
 }
 
 trait AsName {
-    fn or_index<I: Indexed>(&self, index: I) -> Name;
+    fn or_index<I: Indexed>(&self, index: I) -> Name<'_>;
 }
 impl AsName for Option<String> {
-    fn or_index<I: Indexed>(&self, index: I) -> Name {
+    fn or_index<I: Indexed>(&self, index: I) -> Name<'_> {
         Name {
             class: I::CLASS,
             source: match *self {
@@ -199,13 +224,6 @@ impl AsName for Option<String> {
     }
 }
 
-struct Starred<T>(T);
-impl<T: Display> Display for Starred<T> {
-    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
-        write!(formatter, "*{}", self.0)
-    }
-}
-
 struct TypedGlobalVariable<'a> {
     module: &'a crate::Module,
     token: crate::Token<crate::GlobalVariable>,
@@ -214,20 +232,16 @@ impl Display for TypedGlobalVariable<'_> {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
         let var = &self.module.global_variables[self.token];
         let name = var.name.or_index(self.token);
-        let ty = &self.module.types[var.ty];
-        match ty.inner {
-            crate::TypeInner::Pointer { base, class }  => {
-                let ty_token = match class {
-                    spirv::StorageClass::Input |
-                    spirv::StorageClass::Output |
-                    spirv::StorageClass::UniformConstant => base,
-                    _ => var.ty
-                };
-                let ty_name = self.module.types[ty_token].name.or_index(ty_token);
-                write!(formatter, "{} {}", ty_name, name)
-            }
-            _ => panic!("Unexpected global type {:?}", var.ty),
-        }
+        // buffers are passed by their pointer types
+        let ty_token = match self.module.types[var.ty].inner {
+            crate::TypeInner::Pointer { class, .. } if !matches!(
+                class,
+                spirv::StorageClass::Input | spirv::StorageClass::Output | spirv::StorageClass::UniformConstant
+            ) => var.ty,
+            _ => global_value_type(var, self.module),
+        };
+        let ty_name = self.module.types[ty_token].name.or_index(ty_token);
+        write!(formatter, "{} {}", ty_name, name)
     }
 }
 
@@ -267,8 +281,96 @@ impl Display for ResolvedBinding {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+struct Level(usize);
+impl Level {
+    fn next(&self) -> Self {
+        Level(self.0 + 1)
+    }
+}
+impl Display for Level {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
+        (0 .. self.0).try_for_each(|_| formatter.write_str("\t"))
+    }
+}
+
+struct StatementContext<'a> {
+    function: &'a crate::Function,
+    module: &'a crate::Module,
+    exec_model: Option<spirv::ExecutionModel>,
+}
+
 pub struct Writer<W> {
     out: W,
+    typifier: Typifier,
+}
+
+fn math_function_name(fun: crate::MathFunction) -> Result<&'static str, Error> {
+    Ok(match fun {
+        crate::MathFunction::Round => "round",
+        crate::MathFunction::RoundEven => "rint",
+        crate::MathFunction::Trunc => "trunc",
+        crate::MathFunction::FAbs => "abs",
+        crate::MathFunction::SAbs => "abs",
+        crate::MathFunction::FSign => "sign",
+        crate::MathFunction::Floor => "floor",
+        crate::MathFunction::Ceil => "ceil",
+        crate::MathFunction::Fract => "fract",
+        crate::MathFunction::Sin => "sin",
+        crate::MathFunction::Cos => "cos",
+        crate::MathFunction::Tan => "tan",
+        crate::MathFunction::Asin => "asin",
+        crate::MathFunction::Acos => "acos",
+        crate::MathFunction::Atan => "atan",
+        crate::MathFunction::Sinh => "sinh",
+        crate::MathFunction::Cosh => "cosh",
+        crate::MathFunction::Tanh => "tanh",
+        crate::MathFunction::Asinh => "asinh",
+        crate::MathFunction::Acosh => "acosh",
+        crate::MathFunction::Atanh => "atanh",
+        crate::MathFunction::Atan2 => "atan2",
+        crate::MathFunction::Pow => "pow",
+        crate::MathFunction::Exp => "exp",
+        crate::MathFunction::Log => "log",
+        crate::MathFunction::Exp2 => "exp2",
+        crate::MathFunction::Log2 => "log2",
+        crate::MathFunction::Sqrt => "sqrt",
+        crate::MathFunction::InverseSqrt => "rsqrt",
+        crate::MathFunction::Determinant => "determinant",
+        crate::MathFunction::FMin => "min",
+        crate::MathFunction::UMin => "min",
+        crate::MathFunction::SMin => "min",
+        crate::MathFunction::FMax => "max",
+        crate::MathFunction::UMax => "max",
+        crate::MathFunction::SMax => "max",
+        crate::MathFunction::FClamp => "clamp",
+        crate::MathFunction::UClamp => "clamp",
+        crate::MathFunction::SClamp => "clamp",
+        crate::MathFunction::FMix => "mix",
+        crate::MathFunction::Step => "step",
+        crate::MathFunction::SmoothStep => "smoothstep",
+        crate::MathFunction::Fma => "fma",
+        crate::MathFunction::Ldexp => "ldexp",
+        crate::MathFunction::PackSnorm4x8 => "pack_float_to_snorm4x8",
+        crate::MathFunction::PackUnorm4x8 => "pack_float_to_unorm4x8",
+        crate::MathFunction::PackSnorm2x16 => "pack_float_to_snorm2x16",
+        crate::MathFunction::PackUnorm2x16 => "pack_float_to_unorm2x16",
+        crate::MathFunction::UnpackSnorm2x16 => "unpack_snorm2x16_to_float",
+        crate::MathFunction::UnpackUnorm2x16 => "unpack_unorm2x16_to_float",
+        crate::MathFunction::UnpackSnorm4x8 => "unpack_snorm4x8_to_float",
+        crate::MathFunction::UnpackUnorm4x8 => "unpack_unorm4x8_to_float",
+        crate::MathFunction::Length => "length",
+        crate::MathFunction::Distance => "distance",
+        crate::MathFunction::Cross => "cross",
+        crate::MathFunction::Normalize => "normalize",
+        crate::MathFunction::FaceForward => "faceforward",
+        crate::MathFunction::Reflect => "reflect",
+        crate::MathFunction::Refract => "refract",
+        crate::MathFunction::NMin => "fmin",
+        crate::MathFunction::NMax => "fmax",
+        crate::MathFunction::NClamp => "clamp",
+        other => return Err(Error::UnsupportedMathFunction(other)),
+    })
 }
 
 fn scalar_kind_string(kind: crate::ScalarKind) -> &'static str {
@@ -290,114 +392,132 @@ fn vector_size_string(size: crate::VectorSize) -> &'static str {
 
 const NAME_INPUT: &str = "input";
 const NAME_OUTPUT: &str = "output";
+const NAME_LOOP_INIT: &str = "loop_init";
 const COMPONENTS: &[char] = &['x', 'y', 'z', 'w'];
 
-#[derive(Debug)]
-enum MaybeOwned<'a, T: 'a> {
-    Borrowed(&'a T),
-    Owned(T),
-}
 
-impl<T> MaybeOwned<'_, T> {
-    fn borrow(&self) -> &T {
-        match *self {
-            MaybeOwned::Borrowed(inner) => inner,
-            MaybeOwned::Owned(ref inner) => inner,
+impl<W: Write> Writer<W> {
+    fn put_constant(&mut self, constant: &crate::Constant) -> Result<(), Error> {
+        match constant.inner {
+            crate::ConstantInner::Sint(value) => write!(self.out, "{}", value)?,
+            crate::ConstantInner::Uint(value) => write!(self.out, "{}", value)?,
+            crate::ConstantInner::Float(value) => write!(self.out, "{}", value)?,
+            crate::ConstantInner::Bool(value) => write!(self.out, "{}", value)?,
         }
+        Ok(())
     }
-}
 
-impl crate::Module {
-    fn borrow_type(&self, token: Token<crate::Type>) -> MaybeOwned<crate::TypeInner> {
-        MaybeOwned::Borrowed(&self.types[token].inner)
+    /// Writes an integer expression converted to unsigned, as the texture methods expect.
+    fn put_unsigned(
+        &mut self,
+        expr_token: crate::Token<crate::Expression>,
+        context: &StatementContext,
+    ) -> Result<(), Error> {
+        match *self.typifier.get(expr_token, &context.module.types) {
+            crate::TypeInner::Vector { size, .. } => write!(self.out, "uint{}(", vector_size_string(size))?,
+            _ => write!(self.out, "uint(")?,
+        }
+        self.put_expression(expr_token, context)?;
+        write!(self.out, ")")?;
+        Ok(())
     }
-}
 
-impl<W: Write> Writer<W> {
-    fn put_expression<'a>(
+    /// Writes the texel coordinate of a `.read` or `.write` call, which
+    /// takes the array layer of arrayed images as a separate argument.
+    fn put_image_coordinate(
+        &mut self,
+        image: crate::Token<crate::Expression>,
+        coordinate: crate::Token<crate::Expression>,
+        context: &StatementContext,
+    ) -> Result<(), Error> {
+        let arrayed = match *self.typifier.get(image, &context.module.types) {
+            crate::TypeInner::Image { flags, .. } => flags.contains(crate::ImageFlags::ARRAYED),
+            _ => return Err(Error::Type(ResolveError::InvalidImage(image))),
+        };
+        let size = match *self.typifier.get(coordinate, &context.module.types) {
+            crate::TypeInner::Vector { size, .. } if arrayed => size as usize,
+            _ => return self.put_unsigned(coordinate, context),
+        };
+        match size - 1 {
+            1 => write!(self.out, "uint(")?,
+            count => write!(self.out, "uint{}(", count)?,
+        }
+        self.put_expression(coordinate, context)?;
+        write!(self.out, ".{}), uint(", COMPONENTS[.. size - 1].iter().collect::<String>())?;
+        self.put_expression(coordinate, context)?;
+        write!(self.out, ".{})", COMPONENTS[size - 1])?;
+        Ok(())
+    }
+
+    fn put_expression(
         &mut self,
         expr_token: crate::Token<crate::Expression>,
-        expressions: &crate::Storage<crate::Expression>,
-        module: &'a crate::Module,
-    ) -> Result<MaybeOwned<'a, crate::TypeInner>, Error> {
-        let expression = &expressions[expr_token];
+        context: &StatementContext,
+    ) -> Result<(), Error> {
+        let module = context.module;
+        let expression = &context.function.expressions[expr_token];
         log::trace!("expression {:?}", expression);
         match *expression {
+            crate::Expression::Access { base, index } => {
+                self.put_expression(base, context)?;
+                write!(self.out, "[")?;
+                self.put_expression(index, context)?;
+                write!(self.out, "]")?;
+            }
             crate::Expression::AccessIndex { base, index } => {
-                match *self.put_expression(base, expressions, module)?.borrow() {
+                self.put_expression(base, context)?;
+                let base_inner = match *self.typifier.get(base, &module.types) {
+                    crate::TypeInner::Pointer { base, .. } => &module.types[base].inner,
+                    ref other => other,
+                };
+                match *base_inner {
                     crate::TypeInner::Struct { ref members } => {
                         let member = &members[index as usize];
                         let name = member.name.or_index(MemberIndex(index as usize));
                         write!(self.out, ".{}", name)?;
-                        Ok(module.borrow_type(member.ty))
                     }
-                    crate::TypeInner::Matrix { rows, kind, width, .. } => {
+                    crate::TypeInner::Matrix { .. } |
+                    crate::TypeInner::Vector { .. } => {
                         write!(self.out, ".{}", COMPONENTS[index as usize])?;
-                        Ok(MaybeOwned::Owned(crate::TypeInner::Vector { size: rows, kind, width }))
                     }
-                    crate::TypeInner::Vector { kind, width, .. } => {
-                        write!(self.out, ".{}", COMPONENTS[index as usize])?;
-                        Ok(MaybeOwned::Owned(crate::TypeInner::Scalar { kind, width }))
-                    }
-                    crate::TypeInner::Array { base, size } => {
+                    crate::TypeInner::Array { size, .. } => {
                         if let crate::ArraySize::Static(length) = size {
                             assert!(index < length);
                         }
                         write!(self.out, "[{}]", index)?;
-                        Ok(module.borrow_type(base))
                     }
                     ref other => panic!("Unexpected indexing of {:?}", other),
                 }
             }
             crate::Expression::Constant(token) => {
-                let kind = match module.constants[token].inner {
-                    crate::ConstantInner::Sint(value) => {
-                        write!(self.out, "{}", value)?;
-                        crate::ScalarKind::Sint
-                    }
-                    crate::ConstantInner::Uint(value) => {
-                        write!(self.out, "{}", value)?;
-                        crate::ScalarKind::Uint
-                    }
-                    crate::ConstantInner::Float(value) => {
-                        write!(self.out, "{}", value)?;
-                        crate::ScalarKind::Float
-                    }
-                    crate::ConstantInner::Bool(value) => {
-                        write!(self.out, "{}", value)?;
-                        crate::ScalarKind::Bool
-                    }
-                };
-                let width = 32; //TODO: not sure how to get that...
-                Ok(MaybeOwned::Owned(crate::TypeInner::Scalar { kind, width }))
+                self.put_constant(&module.constants[token])?;
             }
             crate::Expression::Compose { ty, ref components } => {
-                let inner = &module.types[ty].inner;
-                match *inner {
+                match module.types[ty].inner {
                     crate::TypeInner::Vector { size, kind, .. } => {
                         write!(self.out, "{}{}(", scalar_kind_string(kind), vector_size_string(size))?;
                         for (i, &token) in components.iter().enumerate() {
                             if i != 0 {
                                 write!(self.out, ",")?;
                             }
-                            self.put_expression(token, expressions, module)?;
+                            self.put_expression(token, context)?;
                         }
                         write!(self.out, ")")?;
                     }
                     _ => panic!("Unsupported compose {:?}", ty),
                 }
-                Ok(MaybeOwned::Borrowed(inner))
+            }
+            crate::Expression::FunctionParameter(index) => {
+                write!(self.out, "{}", Name::from(ParameterIndex(index as usize)))?;
             }
             crate::Expression::GlobalVariable(token) => {
                 let var = &module.global_variables[token];
-                let inner = &module.types[var.ty].inner;
                 match var.class {
                     spirv::StorageClass::Output => {
                         self.out.write_str(NAME_OUTPUT)?;
-                        if let crate::TypeInner::Pointer { base, .. } = *inner {
-                            let base_inner = &module.types[base].inner;
-                            if let crate::TypeInner::Struct { .. } = *base_inner {
-                                return Ok(MaybeOwned::Borrowed(base_inner));
+                        if let crate::TypeInner::Pointer { base, .. } = module.types[var.ty].inner {
+                            if let crate::TypeInner::Struct { .. } = module.types[base].inner {
+                                return Ok(());
                             }
                         }
                         self.out.write_str(".")?;
@@ -409,43 +529,281 @@ impl<W: Write> Writer<W> {
                 }
                 let name = var.name.or_index(token);
                 write!(self.out, "{}", name)?;
-                Ok(MaybeOwned::Borrowed(inner))
+            }
+            crate::Expression::LocalVariable(token) => {
+                let var = &context.function.local_variables[token];
+                write!(self.out, "{}", var.name.or_index(token))?;
             }
             crate::Expression::Load { pointer } => {
                 //write!(self.out, "*")?;
-                match *self.put_expression(pointer, expressions, module)?.borrow() {
-                    crate::TypeInner::Pointer { base, .. } => {
-                        Ok(module.borrow_type(base))
+                self.put_expression(pointer, context)?;
+            }
+            crate::Expression::Math { fun, ref arguments } => {
+                write!(self.out, "metal::{}(", math_function_name(fun)?)?;
+                for (i, &argument) in arguments.iter().enumerate() {
+                    if i != 0 {
+                        write!(self.out, ", ")?;
                     }
-                    ref other => panic!("Unexpected load pointer {:?}", other),
+                    self.put_expression(argument, context)?;
                 }
+                write!(self.out, ")")?;
             }
-            crate::Expression::Mul(left, right) => {
-                write!(self.out, "(")?;
-                let ty_left = self.put_expression(left, expressions, module)?;
-                write!(self.out, " * ")?;
-                let ty_right = self.put_expression(right, expressions, module)?;
+            crate::Expression::Unary { op, expr } => {
+                let op_str = match op {
+                    crate::UnaryOperator::Negate => "-",
+                    crate::UnaryOperator::Not => "!",
+                    crate::UnaryOperator::BitwiseNot => "~",
+                };
+                write!(self.out, "({}", op_str)?;
+                self.put_expression(expr, context)?;
                 write!(self.out, ")")?;
-                Ok(match (ty_left.borrow(), ty_right.borrow()) {
-                    (&crate::TypeInner::Vector { size, kind, width }, &crate::TypeInner::Scalar { .. }) =>
-                        MaybeOwned::Owned(crate::TypeInner::Vector { size, kind, width }),
-                    other => panic!("Unable to infer Mul for {:?}", other),
-                })
+            }
+            crate::Expression::Binary { op, left, right } => {
+                let op_str = match op {
+                    crate::BinaryOperator::Add => "+",
+                    crate::BinaryOperator::Subtract => "-",
+                    crate::BinaryOperator::Multiply => "*",
+                    crate::BinaryOperator::Divide => "/",
+                    crate::BinaryOperator::Modulo => "%",
+                    crate::BinaryOperator::Equal => "==",
+                    crate::BinaryOperator::NotEqual => "!=",
+                    crate::BinaryOperator::Less => "<",
+                    crate::BinaryOperator::LessEqual => "<=",
+                    crate::BinaryOperator::Greater => ">",
+                    crate::BinaryOperator::GreaterEqual => ">=",
+                    crate::BinaryOperator::And => "&",
+                    crate::BinaryOperator::ExclusiveOr => "^",
+                    crate::BinaryOperator::InclusiveOr => "|",
+                    crate::BinaryOperator::LogicalAnd => "&&",
+                    crate::BinaryOperator::LogicalOr => "||",
+                    crate::BinaryOperator::ShiftLeftLogical => "<<",
+                    crate::BinaryOperator::ShiftRightLogical => ">>",
+                    crate::BinaryOperator::ShiftRightArithmetic => ">>",
+                };
+                let (kind, size) = match *self.typifier.get(left, &module.types) {
+                    crate::TypeInner::Scalar { kind, .. } => (Some(kind), ""),
+                    crate::TypeInner::Vector { kind, size, .. } => (Some(kind), vector_size_string(size)),
+                    _ => (None, ""),
+                };
+                // `>>` follows the signedness of the left operand
+                let shift_cast = match (op, kind) {
+                    (crate::BinaryOperator::ShiftRightLogical, Some(crate::ScalarKind::Sint)) => Some(("int", "uint")),
+                    (crate::BinaryOperator::ShiftRightArithmetic, Some(crate::ScalarKind::Uint)) => Some(("uint", "int")),
+                    _ => None,
+                };
+                if let Some((result, operand)) = shift_cast {
+                    write!(self.out, "{}{}({}{}(", result, size, operand, size)?;
+                    self.put_expression(left, context)?;
+                    write!(self.out, ") >> ")?;
+                    self.put_expression(right, context)?;
+                    write!(self.out, ")")?;
+                } else if op == crate::BinaryOperator::Modulo && kind == Some(crate::ScalarKind::Float) {
+                    // `%` is only defined for integers
+                    write!(self.out, "fmod(")?;
+                    self.put_expression(left, context)?;
+                    write!(self.out, ", ")?;
+                    self.put_expression(right, context)?;
+                    write!(self.out, ")")?;
+                } else {
+                    write!(self.out, "(")?;
+                    self.put_expression(left, context)?;
+                    write!(self.out, " {} ", op_str)?;
+                    self.put_expression(right, context)?;
+                    write!(self.out, ")")?;
+                }
             }
             crate::Expression::ImageSample { image, sampler, coordinate } => {
-                let ty_image = self.put_expression(image, expressions, module)?;
+                self.put_expression(image, context)?;
                 write!(self.out, ".sample(")?;
-                self.put_expression(sampler, expressions, module)?;
+                self.put_expression(sampler, context)?;
                 write!(self.out, ", ")?;
-                self.put_expression(coordinate, expressions, module)?;
+                self.put_expression(coordinate, context)?;
                 write!(self.out, ")")?;
-                match *ty_image.borrow() {
-                    crate::TypeInner::Image { base, .. } => Ok(module.borrow_type(base)),
-                    ref other => panic!("Unexpected image type {:?}", other),
+            }
+            crate::Expression::ImageLoad { image, coordinate, index } => {
+                self.put_expression(image, context)?;
+                write!(self.out, ".read(")?;
+                self.put_image_coordinate(image, coordinate, context)?;
+                if let Some(index) = index {
+                    write!(self.out, ", ")?;
+                    self.put_unsigned(index, context)?;
                 }
+                write!(self.out, ")")?;
+            }
+            crate::Expression::ImageDimensions { image, level } => {
+                let (dim, flags) = match *self.typifier.get(image, &module.types) {
+                    crate::TypeInner::Image { dim, flags, .. } => (dim, flags),
+                    _ => return Err(Error::Type(ResolveError::InvalidImage(image))),
+                };
+                let queries: &[&str] = match dim {
+                    spirv::Dim::Dim1D => &["width"],
+                    spirv::Dim::Dim3D => &["width", "height", "depth"],
+                    _ => &["width", "height"],
+                };
+                // multisampled images have a single level
+                let has_levels = !flags.contains(crate::ImageFlags::MULTISAMPLED);
+                write!(self.out, "int")?;
+                if queries.len() > 1 {
+                    write!(self.out, "{}", queries.len())?;
+                }
+                write!(self.out, "(")?;
+                for (i, query) in queries.iter().enumerate() {
+                    if i != 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    self.put_expression(image, context)?;
+                    write!(self.out, ".get_{}(", query)?;
+                    match level {
+                        Some(level) if has_levels => self.put_unsigned(level, context)?,
+                        _ => (),
+                    }
+                    write!(self.out, ")")?;
+                }
+                write!(self.out, ")")?;
+            }
+            crate::Expression::Call { function, ref arguments } => {
+                let fun = &module.functions[function];
+                write!(self.out, "{}(", fun.name.or_index(function))?;
+                for (i, &argument) in arguments.iter().enumerate() {
+                    if i != 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    self.put_expression(argument, context)?;
+                }
+                write!(self.out, ")")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_block(
+        &mut self,
+        level: Level,
+        statements: &[crate::Statement],
+        context: &StatementContext,
+    ) -> Result<(), Error> {
+        for statement in statements {
+            self.put_statement(level, statement, context)?;
+        }
+        Ok(())
+    }
+
+    fn put_statement(
+        &mut self,
+        level: Level,
+        statement: &crate::Statement,
+        context: &StatementContext,
+    ) -> Result<(), Error> {
+        log::trace!("statement[{}] {:?}", level.0, statement);
+        match *statement {
+            crate::Statement::Block(ref block) => {
+                writeln!(self.out, "{}{{", level)?;
+                self.put_block(level.next(), block, context)?;
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::If { condition, ref accept, ref reject } => {
+                write!(self.out, "{}if (", level)?;
+                self.put_expression(condition, context)?;
+                writeln!(self.out, ") {{")?;
+                self.put_block(level.next(), accept, context)?;
+                if !reject.is_empty() {
+                    writeln!(self.out, "{}}} else {{", level)?;
+                    self.put_block(level.next(), reject, context)?;
+                }
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Switch { selector, ref cases, ref default } => {
+                write!(self.out, "{}switch(", level)?;
+                self.put_expression(selector, context)?;
+                writeln!(self.out, ") {{")?;
+                let lcase = level.next();
+                for &(value, ref block, ref fall_through) in cases.iter() {
+                    writeln!(self.out, "{}case {}: {{", lcase, value)?;
+                    self.put_block(lcase.next(), block, context)?;
+                    if fall_through.is_none() {
+                        writeln!(self.out, "{}break;", lcase.next())?;
+                    }
+                    writeln!(self.out, "{}}}", lcase)?;
+                }
+                writeln!(self.out, "{}default: {{", lcase)?;
+                self.put_block(lcase.next(), default, context)?;
+                writeln!(self.out, "{}}}", lcase)?;
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Loop { ref body, ref continuing } => {
+                if continuing.is_empty() {
+                    writeln!(self.out, "{}while(true) {{", level)?;
+                    self.put_block(level.next(), body, context)?;
+                    writeln!(self.out, "{}}}", level)?;
+                } else {
+                    // the continuing block has to run on `continue` as well,
+                    // so it's executed at the start of every iteration but the first
+                    let lwhile = level.next();
+                    writeln!(self.out, "{}{{", level)?;
+                    writeln!(self.out, "{}bool {} = true;", lwhile, NAME_LOOP_INIT)?;
+                    writeln!(self.out, "{}while(true) {{", lwhile)?;
+                    writeln!(self.out, "{}if (!{}) {{", lwhile.next(), NAME_LOOP_INIT)?;
+                    self.put_block(lwhile.next().next(), continuing, context)?;
+                    writeln!(self.out, "{}}}", lwhile.next())?;
+                    writeln!(self.out, "{}{} = false;", lwhile.next(), NAME_LOOP_INIT)?;
+                    self.put_block(lwhile.next(), body, context)?;
+                    writeln!(self.out, "{}}}", lwhile)?;
+                    writeln!(self.out, "{}}}", level)?;
+                }
+            }
+            crate::Statement::Break => {
+                writeln!(self.out, "{}break;", level)?;
+            }
+            crate::Statement::Continue => {
+                writeln!(self.out, "{}continue;", level)?;
+            }
+            crate::Statement::Store { pointer, value } => {
+                //write!(self.out, "*")?;
+                write!(self.out, "{}", level)?;
+                self.put_expression(pointer, context)?;
+                write!(self.out, " = ")?;
+                self.put_expression(value, context)?;
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::Return { value } => {
+                write!(self.out, "{}return ", level)?;
+                match (value, context.exec_model) {
+                    (None, None) => (),
+                    (None, Some(_)) => self.out.write_str(NAME_OUTPUT)?,
+                    (Some(expr_token), None) => {
+                        self.put_expression(expr_token, context)?;
+                    }
+                    (Some(expr_token), Some(_)) => {
+                        panic!("Unable to return value {:?} from an entry point!", expr_token)
+                    }
+                }
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::Kill => {
+                writeln!(self.out, "{}discard_fragment();", level)?;
+            }
+            crate::Statement::ImageStore { image, coordinate, value } => {
+                write!(self.out, "{}", level)?;
+                self.put_expression(image, context)?;
+                write!(self.out, ".write(")?;
+                self.put_expression(value, context)?;
+                write!(self.out, ", ")?;
+                self.put_image_coordinate(image, coordinate, context)?;
+                writeln!(self.out, ");")?;
+            }
+            crate::Statement::Call { function, ref arguments } => {
+                let name = context.module.functions[function].name.or_index(function);
+                write!(self.out, "{}{}(", level, name)?;
+                for (i, &argument) in arguments.iter().enumerate() {
+                    if i != 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    self.put_expression(argument, context)?;
+                }
+                writeln!(self.out, ");")?;
             }
-            ref other => panic!("Unsupported {:?}", other),
         }
+        Ok(())
     }
 
     pub fn write(&mut self, module: &crate::Module, options: Options) -> Result<(), Error> {
@@ -454,7 +812,7 @@ impl<W: Write> Writer<W> {
         writeln!(self.out, "using namespace metal;")?;
 
         // write down complex types
-        writeln!(self.out, "")?;
+        writeln!(self.out)?;
         for (token, ty) in module.types.iter() {
             let name = ty.name.or_index(token);
             match ty.inner {
@@ -525,9 +883,16 @@ impl<W: Write> Writer<W> {
                     } else {
                         return Err(Error::InvalidImageFlags(flags));
                     };
-                    write!(self.out, "typedef texture{}<{}, access::{}> {}", dim, base_name, access, name)?;
+                    let (kind, array) = match (flags.contains(crate::ImageFlags::DEPTH), flags.contains(crate::ImageFlags::ARRAYED)) {
+                        (false, false) => ("texture", ""),
+                        (false, true) => ("texture", "_array"),
+                        (true, false) => ("depth", ""),
+                        (true, true) => ("depth", "_array"),
+                    };
+                    let ms = if flags.contains(crate::ImageFlags::MULTISAMPLED) { "_ms" } else { "" };
+                    write!(self.out, "typedef {}{}{}{}<{}, access::{}> {}", kind, dim, ms, array, base_name, access, name)?;
                 }
-                crate::TypeInner::Sampler => {
+                crate::TypeInner::Sampler { .. } => {
                     write!(self.out, "typedef sampler {}", name)?;
                 }
             }
@@ -536,7 +901,7 @@ impl<W: Write> Writer<W> {
 
         // write down functions
         let mut uniforms_used = FastHashSet::default();
-        writeln!(self.out, "")?;
+        writeln!(self.out)?;
         for (fun_token, fun) in module.functions.iter() {
             let fun_name = fun.name.or_index(fun_token);
             // find the entry point(s) and inputs/outputs
@@ -619,10 +984,12 @@ impl<W: Write> Writer<W> {
                 for (index, &ty) in fun.parameter_types.iter().enumerate() {
                     let name = Name::from(ParameterIndex(index));
                     let member_type_name = module.types[ty].name.or_index(ty);
-                    writeln!(self.out, "\t{} {},", member_type_name, name)?;
+                    let separator = if index + 1 == fun.parameter_types.len() { "" } else { "," };
+                    writeln!(self.out, "\t{} {}{}", member_type_name, name, separator)?;
                 }
             }
-            for (_, expr) in fun.expressions.iter() {
+            // resources are only bound to the entry points
+            for (_, expr) in fun.expressions.iter().filter(|_| exec_model.is_some()) {
                 if let crate::Expression::GlobalVariable(token) = *expr {
                     let var = &module.global_variables[token];
                     if var.class == spirv::StorageClass::UniformConstant && !uniforms_used.contains(&token) {
@@ -647,42 +1014,37 @@ impl<W: Write> Writer<W> {
                 Some(spirv::ExecutionModel::GLCompute) => {
                     writeln!(self.out, "\tunsigned _dummy [[threads_per_grid]]")?;
                 }
-                _ => {
-                    writeln!(self.out, "\tint _dummy")?;
-                }
+                _ => (),
             }
             writeln!(self.out, ") {{")?;
             // write down function body
             if exec_model.is_some() {
                 writeln!(self.out, "\t{} {};", output_name, NAME_OUTPUT)?;
             }
-            for statement in fun.body.iter() {
-                log::trace!("statement {:?}", statement);
-                match *statement {
-                    crate::Statement::Store { pointer, value } => {
-                        //write!(self.out, "\t*")?;
-                        write!(self.out, "\t")?;
-                        self.put_expression(pointer, &fun.expressions, module)?;
-                        write!(self.out, " = ")?;
-                        self.put_expression(value, &fun.expressions, module)?;
-                        writeln!(self.out, ";")?;
-                    }
-                    crate::Statement::Return { value } => {
-                        write!(self.out, "\treturn ")?;
-                        match (value, exec_model) {
-                            (None, None) => (),
-                            (None, Some(_)) => self.out.write_str(NAME_OUTPUT)?,
-                            (Some(expr_token), None) => {
-                                self.put_expression(expr_token, &fun.expressions, module)?;
-                            }
-                            (Some(expr_token), Some(_)) => {
-                                panic!("Unable to return value {:?} from an entry point!", expr_token)
-                            }
-                        }
-                        writeln!(self.out, ";")?;
-                    }
-                    _ => panic!("Unsupported {:?}", statement),
+            self.typifier.resolve_all(&fun.expressions, &ResolveContext {
+                types: &module.types,
+                constants: &module.constants,
+                global_vars: &module.global_variables,
+                local_vars: &fun.local_variables,
+                functions: &module.functions,
+                parameter_types: &fun.parameter_types,
+            })?;
+            for (local_token, local) in fun.local_variables.iter() {
+                let ty_name = module.types[local.ty].name.or_index(local.ty);
+                write!(self.out, "\t{} {}", ty_name, local.name.or_index(local_token))?;
+                if let Some(init) = local.init {
+                    write!(self.out, " = ")?;
+                    self.put_constant(&module.constants[init])?;
                 }
+                writeln!(self.out, ";")?;
+            }
+            let context = StatementContext {
+                function: fun,
+                module,
+                exec_model,
+            };
+            for statement in fun.body.iter() {
+                self.put_statement(Level(1), statement, &context)?;
             }
             writeln!(self.out, "}}")?;
         }
@@ -692,7 +1054,123 @@ impl<W: Write> Writer<W> {
 }
 
 pub fn write_string(module: &crate::Module, options: Options) -> Result<String, Error> {
-    let mut w = Writer { out: String::new() };
+    let mut w = Writer { out: String::new(), typifier: Typifier::new() };
     w.write(module, options)?;
     Ok(w.out)
 }
+
+#[cfg(test)]
+mod test {
+    fn write(source: &str) -> Result<String, super::Error> {
+        write_with_bindings(source, &super::BindingMap::default())
+    }
+
+    fn write_with_bindings(source: &str, binding_map: &super::BindingMap) -> Result<String, super::Error> {
+        let module = crate::front::wgsl::parse_str(source).unwrap();
+        super::write_string(&module, super::Options { binding_map })
+    }
+
+    #[test]
+    fn math_functions() {
+        let text = write("
+            import \"GLSL.std.450\" as std;
+            [[location 0]] var<in> angle : f32;
+            [[location 0]] var<out> color : vec4<f32>;
+            fn main() -> void {
+                color = vec4<f32>(std::sin(angle), std::fmax(angle, 0.5), 0.0, 1.0);
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap();
+        assert!(text.contains("metal::sin("));
+        assert!(text.contains("metal::max("));
+        let error = write("
+            import \"GLSL.std.450\" as std;
+            [[location 0]] var<in> angle : f32;
+            [[location 0]] var<out> color : vec4<f32>;
+            fn main() -> void {
+                color = vec4<f32>(std::degrees(angle), 0.0, 0.0, 1.0);
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap_err();
+        assert!(matches!(error, super::Error::UnsupportedMathFunction(crate::MathFunction::Degrees)));
+    }
+
+    #[test]
+    fn signed_shifts() {
+        let text = write("
+            [[location 0]] var<in> value : i32;
+            [[location 1]] var<in> mask : vec2<u32>;
+            [[location 0]] var<out> o_value : i32;
+            [[location 1]] var<out> o_mask : vec2<u32>;
+            fn main() -> void {
+                o_value = value >> 2;
+                o_mask = mask >>> mask;
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap();
+        assert!(text.contains("int(uint(input.value) >> 2)"), "{}", text);
+        assert!(text.contains("uint2(int2(input.mask) >> "), "{}", text);
+    }
+
+    #[test]
+    fn arrayed_images() {
+        let target = |texture, sampler| super::BindTarget { buffer: None, texture, sampler };
+        let binding_map = [
+            (0, target(Some(0), None)),
+            (1, target(Some(1), None)),
+            (2, target(None, Some(0))),
+            (3, target(Some(2), None)),
+        ].iter().map(|&(binding, ref target)| (super::BindSource { set: 0, binding }, target.clone())).collect();
+        let text = write_with_bindings("
+            [[set 0, binding 0]] var layers : texture_2d_array<f32>;
+            [[set 0, binding 1]] var storage : texture_storage_wo_2d_array<f32>;
+            [[set 0, binding 2]] var linear : sampler;
+            [[set 0, binding 3]] var color_map : texture_2d<f32>;
+            [[location 0]] var<out> color : vec4<f32>;
+            fn main() -> void {
+                var coord : vec2<i32> = vec2<i32>(1, 2);
+                var texel : vec4<f32> = textureLoad(layers, coord, 3, 0);
+                textureStore(storage, coord, 4, texel);
+                color = texel + textureSample(color_map, linear, vec2<f32>(0.5, 0.5));
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ", &binding_map).unwrap();
+        assert!(text.contains("\tType1 layers [[texture(0)]],\n"), "{}", text);
+        assert!(text.contains("\tType2 storage [[texture(1)]],\n"), "{}", text);
+        assert!(text.contains("\tType3 linear [[sampler(0)]],\n"), "{}", text);
+        assert!(text.contains("\tType4 color_map [[texture(2)]],\n"), "{}", text);
+        assert!(text.contains("color_map.sample(linear, "), "{}", text);
+        let reads = text.matches(".read(uint2(").count();
+        let writes = text.matches(".write(texel, uint2(").count();
+        assert_eq!((reads, writes), (1, 1), "{}", text);
+        assert_eq!(text.matches(".xy), uint(").count(), 2, "{}", text);
+        assert_eq!(text.matches(".z)").count(), 2, "{}", text);
+    }
+
+    #[test]
+    fn unary_operators() {
+        let text = write("
+            fn main(a : i32, b : bool) -> bool {
+                return !b || -a == ~a;
+            }
+        ").unwrap();
+        assert!(text.contains("return ((!param1) || ((-param0) == (~param0)));"), "{}", text);
+    }
+
+    #[test]
+    fn dynamic_indexing() {
+        let text = write("
+            fn main(i : i32, v : vec4<f32>) -> f32 {
+                var a : array<f32, 4>;
+                a[i] = v[i + 1];
+                return a[i];
+            }
+        ").unwrap();
+        assert!(text.contains("a[param0] = param1[(param0 + 1)];"), "{}", text);
+        assert!(text.contains("return a[param0];"), "{}", text);
+    }
+}
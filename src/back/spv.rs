@@ -0,0 +1,1569 @@
+/*! Standard Portable Intermediate Representation (SPIR-V) backend
+
+## Types
+
+SPIR-V requires the non-aggregate types to be unique, while the IR may
+contain duplicates, and the typifier produces types that are not in the
+module at all. Such types are looked up by their instruction operands,
+so that each one is only declared once.
+
+## Global variables
+
+Global variables that are not of a pointer type, as produced by the WGSL
+frontend, are declared with a pointer to their storage class, and loaded
+from whenever they are used as values.
+
+## Buffer layout
+
+The members of the uniform and storage blocks have their offsets in the IR.
+The strides of the arrays and of the matrix columns are derived from them,
+following the std140 rules in uniform blocks and the std430 ones in storage
+blocks. A type shared by both kinds of blocks has the layout of the first
+one it is found in.
+!*/
+
+use crate::{
+    proc::{ResolveContext, ResolveError, Typifier},
+    storage::Token,
+    FastHashMap,
+};
+use spirv::Word;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidPointer(Token<crate::Expression>),
+    InvalidOperands(Token<crate::Expression>),
+    UnsupportedAccess(Token<crate::Expression>),
+    UnsupportedImage(Token<crate::Type>),
+    /// A member of a structure in a buffer has no offset.
+    MissingOffset(Token<crate::Type>, u32),
+    /// The type has no memory layout, so it can't be in a buffer.
+    UnsupportedBufferType(Token<crate::Type>),
+    /// The structure type of an expression isn't in the module.
+    UnknownStructure,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    Type(ResolveError),
+    /// An error in the body of the given function.
+    Function(Token<crate::Function>, Box<Error>),
+}
+
+impl Error {
+    /// Returns the span of the module entity that the error is about.
+    pub fn span(&self, module: &crate::Module) -> crate::Span {
+        let (token, error) = match *self {
+            Error::Function(token, ref error) => (token, error),
+            _ => return crate::Span::default(),
+        };
+        let span = match (module.functions.get(token), &**error) {
+            (Some(fun), &Error::InvalidPointer(expression)) |
+            (Some(fun), &Error::InvalidOperands(expression)) |
+            (Some(fun), &Error::UnsupportedAccess(expression)) => fun.spans.expression(expression),
+            _ => crate::Span::default(),
+        };
+        if span.is_unknown() {
+            module.spans.function(token)
+        } else {
+            span
+        }
+    }
+}
+
+impl From<ResolveError> for Error {
+    fn from(e: ResolveError) -> Self {
+        Error::Type(e)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Emit `OpName` and `OpMemberName` for all the named objects.
+    pub debug_names: bool,
+}
+
+/// Sections of the binary, in the order of the logical module layout.
+#[derive(Debug, Default)]
+struct LogicalLayout {
+    capabilities: Vec<Word>,
+    extensions: Vec<Word>,
+    ext_inst_imports: Vec<Word>,
+    memory_model: Vec<Word>,
+    entry_points: Vec<Word>,
+    execution_modes: Vec<Word>,
+    debugs: Vec<Word>,
+    annotations: Vec<Word>,
+    declarations: Vec<Word>,
+    functions: Vec<Word>,
+}
+
+fn push_instruction(sink: &mut Vec<Word>, op: spirv::Op, operands: &[Word]) {
+    sink.push(((operands.len() as Word + 1) << 16) | op as Word);
+    sink.extend_from_slice(operands);
+}
+
+fn string_to_words(value: &str) -> Vec<Word> {
+    let bytes = value.as_bytes();
+    let mut words = bytes
+        .chunks(4)
+        .map(|chunk| {
+            let mut raw = [0u8; 4];
+            raw[.. chunk.len()].copy_from_slice(chunk);
+            Word::from_le_bytes(raw)
+        })
+        .collect::<Vec<_>>();
+    // the string has to be null-terminated
+    if bytes.len() % 4 == 0 {
+        words.push(0);
+    }
+    words
+}
+
+fn map_math_function(fun: crate::MathFunction) -> spirv::GLOp {
+    use spirv::GLOp as Gl;
+    use crate::MathFunction as Mf;
+    match fun {
+        Mf::Round => Gl::Round,
+        Mf::RoundEven => Gl::RoundEven,
+        Mf::Trunc => Gl::Trunc,
+        Mf::FAbs => Gl::FAbs,
+        Mf::SAbs => Gl::SAbs,
+        Mf::FSign => Gl::FSign,
+        Mf::SSign => Gl::SSign,
+        Mf::Floor => Gl::Floor,
+        Mf::Ceil => Gl::Ceil,
+        Mf::Fract => Gl::Fract,
+        Mf::Radians => Gl::Radians,
+        Mf::Degrees => Gl::Degrees,
+        Mf::Sin => Gl::Sin,
+        Mf::Cos => Gl::Cos,
+        Mf::Tan => Gl::Tan,
+        Mf::Asin => Gl::Asin,
+        Mf::Acos => Gl::Acos,
+        Mf::Atan => Gl::Atan,
+        Mf::Sinh => Gl::Sinh,
+        Mf::Cosh => Gl::Cosh,
+        Mf::Tanh => Gl::Tanh,
+        Mf::Asinh => Gl::Asinh,
+        Mf::Acosh => Gl::Acosh,
+        Mf::Atanh => Gl::Atanh,
+        Mf::Atan2 => Gl::Atan2,
+        Mf::Pow => Gl::Pow,
+        Mf::Exp => Gl::Exp,
+        Mf::Log => Gl::Log,
+        Mf::Exp2 => Gl::Exp2,
+        Mf::Log2 => Gl::Log2,
+        Mf::Sqrt => Gl::Sqrt,
+        Mf::InverseSqrt => Gl::InverseSqrt,
+        Mf::Determinant => Gl::Determinant,
+        Mf::MatrixInverse => Gl::MatrixInverse,
+        Mf::FMin => Gl::FMin,
+        Mf::UMin => Gl::UMin,
+        Mf::SMin => Gl::SMin,
+        Mf::FMax => Gl::FMax,
+        Mf::UMax => Gl::UMax,
+        Mf::SMax => Gl::SMax,
+        Mf::FClamp => Gl::FClamp,
+        Mf::UClamp => Gl::UClamp,
+        Mf::SClamp => Gl::SClamp,
+        Mf::FMix => Gl::FMix,
+        Mf::IMix => Gl::IMix,
+        Mf::Step => Gl::Step,
+        Mf::SmoothStep => Gl::SmoothStep,
+        Mf::Fma => Gl::Fma,
+        Mf::Ldexp => Gl::Ldexp,
+        Mf::PackSnorm4x8 => Gl::PackSnorm4x8,
+        Mf::PackUnorm4x8 => Gl::PackUnorm4x8,
+        Mf::PackSnorm2x16 => Gl::PackSnorm2x16,
+        Mf::PackUnorm2x16 => Gl::PackUnorm2x16,
+        Mf::PackHalf2x16 => Gl::PackHalf2x16,
+        Mf::UnpackSnorm2x16 => Gl::UnpackSnorm2x16,
+        Mf::UnpackUnorm2x16 => Gl::UnpackUnorm2x16,
+        Mf::UnpackHalf2x16 => Gl::UnpackHalf2x16,
+        Mf::UnpackSnorm4x8 => Gl::UnpackSnorm4x8,
+        Mf::UnpackUnorm4x8 => Gl::UnpackUnorm4x8,
+        Mf::Length => Gl::Length,
+        Mf::Distance => Gl::Distance,
+        Mf::Cross => Gl::Cross,
+        Mf::Normalize => Gl::Normalize,
+        Mf::FaceForward => Gl::FaceForward,
+        Mf::Reflect => Gl::Reflect,
+        Mf::Refract => Gl::Refract,
+        Mf::FindILsb => Gl::FindILsb,
+        Mf::FindSMsb => Gl::FindSMsb,
+        Mf::FindUMsb => Gl::FindUMsb,
+        Mf::NMin => Gl::NMin,
+        Mf::NMax => Gl::NMax,
+        Mf::NClamp => Gl::NClamp,
+    }
+}
+
+fn round_up(value: Word, align: Word) -> Word {
+    match value % align {
+        0 => value,
+        rest => value + align - rest,
+    }
+}
+
+/// Returns the alignment of a vector in a buffer, where the ones
+/// of three components are aligned like the ones of four.
+fn vector_alignment(size: crate::VectorSize, bytes: Word) -> Word {
+    match size {
+        crate::VectorSize::Bi => 2 * bytes,
+        crate::VectorSize::Tri |
+        crate::VectorSize::Quad => 4 * bytes,
+    }
+}
+
+/// Everything the statements of a function refer to.
+struct FunctionContext<'a> {
+    function: &'a crate::Function,
+    module: &'a crate::Module,
+    parameter_ids: Vec<Word>,
+    local_ids: Vec<Word>,
+}
+
+/// Branch targets of the innermost constructs.
+#[derive(Clone, Copy, Debug, Default)]
+struct FlowContext {
+    break_id: Option<Word>,
+    continue_id: Option<Word>,
+}
+
+pub struct Writer {
+    layout: LogicalLayout,
+    id_count: Word,
+    debug_names: bool,
+    typifier: Typifier,
+    lookup_type: FastHashMap<Token<crate::Type>, Word>,
+    lookup_type_operands: FastHashMap<(spirv::Op, Vec<Word>), Word>,
+    lookup_constant: FastHashMap<Token<crate::Constant>, Word>,
+    lookup_uint_constant: FastHashMap<Word, Word>,
+    lookup_global_variable: FastHashMap<Token<crate::GlobalVariable>, Word>,
+    lookup_function: FastHashMap<Token<crate::Function>, Word>,
+    block_ids: Vec<Word>,
+    /// Arrays and structures that have their layout decorated.
+    layout_ids: Vec<Word>,
+    glsl_id: Option<Word>,
+    capabilities: Vec<spirv::Capability>,
+}
+
+impl Writer {
+    pub fn new(options: &Options) -> Self {
+        Writer {
+            layout: LogicalLayout::default(),
+            id_count: 0,
+            debug_names: options.debug_names,
+            typifier: Typifier::new(),
+            lookup_type: FastHashMap::default(),
+            lookup_type_operands: FastHashMap::default(),
+            lookup_constant: FastHashMap::default(),
+            lookup_uint_constant: FastHashMap::default(),
+            lookup_global_variable: FastHashMap::default(),
+            lookup_function: FastHashMap::default(),
+            block_ids: Vec::new(),
+            layout_ids: Vec::new(),
+            glsl_id: None,
+            capabilities: vec![spirv::Capability::Shader],
+        }
+    }
+
+    fn generate_id(&mut self) -> Word {
+        self.id_count += 1;
+        self.id_count
+    }
+
+    fn require_capability(&mut self, capability: spirv::Capability) {
+        if !self.capabilities.contains(&capability) {
+            self.capabilities.push(capability);
+        }
+    }
+
+    fn write_name(&mut self, id: Word, name: &Option<String>) {
+        match *name {
+            Some(ref name) if self.debug_names && !name.is_empty() => {
+                let mut operands = vec![id];
+                operands.extend(string_to_words(name));
+                push_instruction(&mut self.layout.debugs, spirv::Op::Name, &operands);
+            }
+            _ => (),
+        }
+    }
+
+    fn write_decoration(&mut self, id: Word, member: Option<Word>, binding: &crate::Binding) {
+        let (op, mut operands) = match member {
+            Some(index) => (spirv::Op::MemberDecorate, vec![id, index]),
+            None => (spirv::Op::Decorate, vec![id]),
+        };
+        let decorations = match *binding {
+            crate::Binding::BuiltIn(built_in) => {
+                vec![(spirv::Decoration::BuiltIn, built_in as Word)]
+            }
+            crate::Binding::Location(location) => {
+                vec![(spirv::Decoration::Location, location)]
+            }
+            crate::Binding::Descriptor { set, binding } => vec![
+                (spirv::Decoration::DescriptorSet, set),
+                (spirv::Decoration::Binding, binding),
+            ],
+        };
+        let base_len = operands.len();
+        for (decoration, value) in decorations {
+            operands.truncate(base_len);
+            operands.push(decoration as Word);
+            operands.push(value);
+            push_instruction(&mut self.layout.annotations, op, &operands);
+        }
+    }
+
+    /// Declare a non-aggregate type, unless it's already known.
+    fn declare_type(&mut self, op: spirv::Op, operands: Vec<Word>) -> Word {
+        if let Some(&id) = self.lookup_type_operands.get(&(op, operands.clone())) {
+            return id;
+        }
+        let id = self.generate_id();
+        let mut full = vec![id];
+        full.extend_from_slice(&operands);
+        push_instruction(&mut self.layout.declarations, op, &full);
+        self.lookup_type_operands.insert((op, operands), id);
+        id
+    }
+
+    fn get_void_type_id(&mut self) -> Word {
+        self.declare_type(spirv::Op::TypeVoid, Vec::new())
+    }
+
+    fn get_pointer_type_id(&mut self, base_id: Word, class: spirv::StorageClass) -> Word {
+        self.declare_type(spirv::Op::TypePointer, vec![class as Word, base_id])
+    }
+
+    fn get_uint_constant_id(&mut self, value: Word) -> Word {
+        if let Some(&id) = self.lookup_uint_constant.get(&value) {
+            return id;
+        }
+        let type_id = self.declare_type(spirv::Op::TypeInt, vec![32, 0]);
+        let id = self.generate_id();
+        push_instruction(&mut self.layout.declarations, spirv::Op::Constant, &[type_id, id, value]);
+        self.lookup_uint_constant.insert(value, id);
+        id
+    }
+
+    fn get_type_id(&mut self, token: Token<crate::Type>, module: &crate::Module) -> Result<Word, Error> {
+        if let Some(&id) = self.lookup_type.get(&token) {
+            return Ok(id);
+        }
+        let ty = &module.types[token];
+        let id = match ty.inner {
+            crate::TypeInner::Struct { ref members } => {
+                let mut operands = Vec::with_capacity(members.len() + 1);
+                for member in members {
+                    operands.push(self.get_type_id(member.ty, module)?);
+                }
+                let id = self.generate_id();
+                operands.insert(0, id);
+                push_instruction(&mut self.layout.declarations, spirv::Op::TypeStruct, &operands);
+                for (index, member) in members.iter().enumerate() {
+                    if self.debug_names {
+                        if let Some(ref name) = member.name {
+                            let mut operands = vec![id, index as Word];
+                            operands.extend(string_to_words(name));
+                            push_instruction(&mut self.layout.debugs, spirv::Op::MemberName, &operands);
+                        }
+                    }
+                    if let Some(ref binding) = member.binding {
+                        self.write_decoration(id, Some(index as Word), binding);
+                    }
+                    if let Some(offset) = member.offset {
+                        push_instruction(
+                            &mut self.layout.annotations,
+                            spirv::Op::MemberDecorate,
+                            &[id, index as Word, spirv::Decoration::Offset as Word, offset],
+                        );
+                    }
+                }
+                id
+            }
+            crate::TypeInner::Image { base, .. } => {
+                match module.types[base].inner {
+                    crate::TypeInner::Scalar { .. } => (),
+                    _ => return Err(Error::UnsupportedImage(token)),
+                }
+                self.get_type_inner_id(&ty.inner, module)?
+            }
+            ref other => self.get_type_inner_id(other, module)?,
+        };
+        self.write_name(id, &ty.name);
+        self.lookup_type.insert(token, id);
+        Ok(id)
+    }
+
+    fn get_type_inner_id(&mut self, inner: &crate::TypeInner, module: &crate::Module) -> Result<Word, Error> {
+        let (op, operands) = match *inner {
+            crate::TypeInner::Scalar { kind, width } => match kind {
+                crate::ScalarKind::Sint => (spirv::Op::TypeInt, vec![width as Word, 1]),
+                crate::ScalarKind::Uint => (spirv::Op::TypeInt, vec![width as Word, 0]),
+                crate::ScalarKind::Float => (spirv::Op::TypeFloat, vec![width as Word]),
+                crate::ScalarKind::Bool => (spirv::Op::TypeBool, Vec::new()),
+            },
+            crate::TypeInner::Vector { size, kind, width } => {
+                let scalar_id = self.get_type_inner_id(&crate::TypeInner::Scalar { kind, width }, module)?;
+                (spirv::Op::TypeVector, vec![scalar_id, size as Word])
+            }
+            crate::TypeInner::Matrix { columns, rows, kind, width } => {
+                let column_id = self.get_type_inner_id(&crate::TypeInner::Vector { size: rows, kind, width }, module)?;
+                (spirv::Op::TypeMatrix, vec![column_id, columns as Word])
+            }
+            crate::TypeInner::Pointer { base, class } => {
+                let base_id = self.get_type_id(base, module)?;
+                (spirv::Op::TypePointer, vec![class as Word, base_id])
+            }
+            crate::TypeInner::Array { base, size } => {
+                let base_id = self.get_type_id(base, module)?;
+                match size {
+                    crate::ArraySize::Static(length) => {
+                        let length_id = self.get_uint_constant_id(length);
+                        (spirv::Op::TypeArray, vec![base_id, length_id])
+                    }
+                    crate::ArraySize::Dynamic => (spirv::Op::TypeRuntimeArray, vec![base_id]),
+                }
+            }
+            crate::TypeInner::Struct { .. } => {
+                // structures are only referenced by their handles
+                let token = module.types
+                    .iter()
+                    .find(|&(_, ty)| ty.inner == *inner)
+                    .map(|(token, _)| token)
+                    .ok_or(Error::UnknownStructure)?;
+                return self.get_type_id(token, module);
+            }
+            crate::TypeInner::Image { base, dim, flags } => {
+                let base_id = self.get_type_id(base, module)?;
+                let sampled = if flags.contains(crate::ImageFlags::SAMPLED) { 1 } else { 2 };
+                (spirv::Op::TypeImage, vec![
+                    base_id,
+                    dim as Word,
+                    flags.contains(crate::ImageFlags::DEPTH) as Word,
+                    flags.contains(crate::ImageFlags::ARRAYED) as Word,
+                    flags.contains(crate::ImageFlags::MULTISAMPLED) as Word,
+                    sampled,
+                    spirv::ImageFormat::Unknown as Word,
+                ])
+            }
+            crate::TypeInner::Sampler { .. } => (spirv::Op::TypeSampler, Vec::new()),
+        };
+        Ok(self.declare_type(op, operands))
+    }
+
+    fn get_expression_type_id(
+        &mut self,
+        token: Token<crate::Expression>,
+        context: &FunctionContext,
+    ) -> Result<Word, Error> {
+        let inner = self.typifier.get(token, &context.module.types).clone();
+        self.get_type_inner_id(&inner, context.module)
+    }
+
+    fn get_glsl_id(&mut self) -> Word {
+        match self.glsl_id {
+            Some(id) => id,
+            None => {
+                let id = self.generate_id();
+                let mut operands = vec![id];
+                operands.extend(string_to_words("GLSL.std.450"));
+                push_instruction(&mut self.layout.ext_inst_imports, spirv::Op::ExtInstImport, &operands);
+                self.glsl_id = Some(id);
+                id
+            }
+        }
+    }
+
+    fn write_constant(&mut self, token: Token<crate::Constant>, module: &crate::Module) -> Result<(), Error> {
+        let constant = &module.constants[token];
+        let id = self.generate_id();
+        // constants are resolved to 32-bit types, same as in the typifier
+        let (kind, width) = match constant.inner {
+            crate::ConstantInner::Sint(_) => (crate::ScalarKind::Sint, 32),
+            crate::ConstantInner::Uint(_) => (crate::ScalarKind::Uint, 32),
+            crate::ConstantInner::Float(_) => (crate::ScalarKind::Float, 32),
+            crate::ConstantInner::Bool(_) => (crate::ScalarKind::Bool, 1),
+        };
+        let type_id = self.get_type_inner_id(&crate::TypeInner::Scalar { kind, width }, module)?;
+        let sink = &mut self.layout.declarations;
+        match constant.inner {
+            crate::ConstantInner::Sint(value) => {
+                push_instruction(sink, spirv::Op::Constant, &[type_id, id, value as i32 as Word]);
+            }
+            crate::ConstantInner::Uint(value) => {
+                push_instruction(sink, spirv::Op::Constant, &[type_id, id, value as Word]);
+            }
+            crate::ConstantInner::Float(value) => {
+                push_instruction(sink, spirv::Op::Constant, &[type_id, id, (value as f32).to_bits()]);
+            }
+            crate::ConstantInner::Bool(value) => {
+                let op = if value { spirv::Op::ConstantTrue } else { spirv::Op::ConstantFalse };
+                push_instruction(sink, op, &[type_id, id]);
+            }
+        }
+        self.write_name(id, &constant.name);
+        self.lookup_constant.insert(token, id);
+        Ok(())
+    }
+
+    fn write_global_variable(
+        &mut self,
+        token: Token<crate::GlobalVariable>,
+        module: &crate::Module,
+    ) -> Result<(), Error> {
+        let var = &module.global_variables[token];
+        let (pointer_type_id, class, value_type) = match module.types[var.ty].inner {
+            crate::TypeInner::Pointer { base, class } => (self.get_type_id(var.ty, module)?, class, base),
+            _ => {
+                let base_id = self.get_type_id(var.ty, module)?;
+                (self.get_pointer_type_id(base_id, var.class), var.class, var.ty)
+            }
+        };
+        let id = self.generate_id();
+        push_instruction(&mut self.layout.declarations, spirv::Op::Variable, &[pointer_type_id, id, class as Word]);
+
+        if let Some(ref binding) = var.binding {
+            self.write_decoration(id, None, binding);
+        }
+        match class {
+            spirv::StorageClass::Uniform |
+            spirv::StorageClass::StorageBuffer => {
+                // interface blocks have to be decorated as such
+                if let crate::TypeInner::Struct { .. } = module.types[value_type].inner {
+                    let type_id = self.get_type_id(value_type, module)?;
+                    if !self.block_ids.contains(&type_id) {
+                        self.block_ids.push(type_id);
+                        push_instruction(&mut self.layout.annotations, spirv::Op::Decorate, &[type_id, spirv::Decoration::Block as Word]);
+                        self.write_buffer_layout(value_type, class, module)?;
+                    }
+                }
+            }
+            _ => (),
+        }
+        self.write_name(id, &var.name);
+        self.lookup_global_variable.insert(token, id);
+        Ok(())
+    }
+
+    /// Decorates the strides of the arrays and matrices in a buffer type,
+    /// returning the size and the alignment of the type.
+    fn write_buffer_layout(
+        &mut self,
+        token: Token<crate::Type>,
+        class: spirv::StorageClass,
+        module: &crate::Module,
+    ) -> Result<(Word, Word), Error> {
+        // std140 aligns the arrays, the matrix columns and the structures to 16 bytes
+        let extended_alignment = |align: Word| match class {
+            spirv::StorageClass::Uniform => round_up(align, 16),
+            _ => align,
+        };
+        Ok(match module.types[token].inner {
+            crate::TypeInner::Scalar { kind, width } if kind != crate::ScalarKind::Bool => {
+                let bytes = width as Word / 8;
+                (bytes, bytes)
+            }
+            crate::TypeInner::Vector { size, kind, width } if kind != crate::ScalarKind::Bool => {
+                let bytes = width as Word / 8;
+                (size as Word * bytes, vector_alignment(size, bytes))
+            }
+            crate::TypeInner::Matrix { columns, rows, width, .. } => {
+                let stride = extended_alignment(vector_alignment(rows, width as Word / 8));
+                (columns as Word * stride, stride)
+            }
+            crate::TypeInner::Array { base, size } => {
+                let (base_size, base_align) = self.write_buffer_layout(base, class, module)?;
+                let align = extended_alignment(base_align);
+                let stride = round_up(base_size, align);
+                let id = self.get_type_id(token, module)?;
+                if !self.layout_ids.contains(&id) {
+                    self.layout_ids.push(id);
+                    push_instruction(&mut self.layout.annotations, spirv::Op::Decorate, &[id, spirv::Decoration::ArrayStride as Word, stride]);
+                }
+                match size {
+                    crate::ArraySize::Static(length) => (length * stride, align),
+                    crate::ArraySize::Dynamic => (stride, align),
+                }
+            }
+            crate::TypeInner::Struct { ref members } => {
+                let id = self.get_type_id(token, module)?;
+                let decorate = !self.layout_ids.contains(&id);
+                if decorate {
+                    self.layout_ids.push(id);
+                }
+                let mut size = 0;
+                let mut align = 1;
+                for (index, member) in members.iter().enumerate() {
+                    let offset = member.offset.ok_or(Error::MissingOffset(token, index as u32))?;
+                    let (member_size, member_align) = self.write_buffer_layout(member.ty, class, module)?;
+                    // the matrices, and the arrays of them, are decorated in the structure
+                    let mut member_ty = member.ty;
+                    while let crate::TypeInner::Array { base, .. } = module.types[member_ty].inner {
+                        member_ty = base;
+                    }
+                    if let crate::TypeInner::Matrix { rows, width, .. } = module.types[member_ty].inner {
+                        if decorate {
+                            let stride = extended_alignment(vector_alignment(rows, width as Word / 8));
+                            let index = index as Word;
+                            push_instruction(&mut self.layout.annotations, spirv::Op::MemberDecorate, &[id, index, spirv::Decoration::ColMajor as Word]);
+                            push_instruction(&mut self.layout.annotations, spirv::Op::MemberDecorate, &[id, index, spirv::Decoration::MatrixStride as Word, stride]);
+                        }
+                    }
+                    size = size.max(offset + member_size);
+                    align = align.max(member_align);
+                }
+                let align = extended_alignment(align);
+                (round_up(size, align), align)
+            }
+            _ => return Err(Error::UnsupportedBufferType(token)),
+        })
+    }
+
+    /// Return the storage class of the memory an expression refers to,
+    /// if it's a reference at all.
+    fn reference_class(
+        &self,
+        token: Token<crate::Expression>,
+        context: &FunctionContext,
+    ) -> Option<spirv::StorageClass> {
+        if let crate::TypeInner::Pointer { class, .. } = *self.typifier.get(token, &context.module.types) {
+            return Some(class);
+        }
+        match context.function.expressions[token] {
+            crate::Expression::GlobalVariable(var) => Some(context.module.global_variables[var].class),
+            crate::Expression::Access { base, .. } |
+            crate::Expression::AccessIndex { base, .. } => self.reference_class(base, context),
+            _ => None,
+        }
+    }
+
+    /// Write an expression that refers to memory, returning the pointer ID.
+    fn write_pointer(
+        &mut self,
+        token: Token<crate::Expression>,
+        context: &FunctionContext,
+        out: &mut Vec<Word>,
+    ) -> Result<Word, Error> {
+        let module = context.module;
+        match context.function.expressions[token] {
+            crate::Expression::GlobalVariable(var) => Ok(self.lookup_global_variable[&var]),
+            crate::Expression::LocalVariable(var) => Ok(context.local_ids[var.index()]),
+            crate::Expression::Access { base, .. } |
+            crate::Expression::AccessIndex { base, .. } => {
+                let class = self.reference_class(base, context)
+                    .ok_or(Error::InvalidPointer(token))?;
+                let base_id = self.write_pointer(base, context, out)?;
+                let index_id = match context.function.expressions[token] {
+                    crate::Expression::Access { index, .. } => self.write_expression(index, context, out)?,
+                    crate::Expression::AccessIndex { index, .. } => self.get_uint_constant_id(index),
+                    _ => unreachable!(),
+                };
+                let pointer_type_id = match *self.typifier.get(token, &module.types) {
+                    crate::TypeInner::Pointer { .. } => self.get_expression_type_id(token, context)?,
+                    _ => {
+                        let element_id = self.get_expression_type_id(token, context)?;
+                        self.get_pointer_type_id(element_id, class)
+                    }
+                };
+                let id = self.generate_id();
+                push_instruction(out, spirv::Op::AccessChain, &[pointer_type_id, id, base_id, index_id]);
+                Ok(id)
+            }
+            _ => match *self.typifier.get(token, &module.types) {
+                crate::TypeInner::Pointer { .. } => self.write_expression(token, context, out),
+                _ => Err(Error::InvalidPointer(token)),
+            },
+        }
+    }
+
+    fn write_splat(
+        &mut self,
+        scalar_id: Word,
+        vector_token: Token<crate::Expression>,
+        context: &FunctionContext,
+        out: &mut Vec<Word>,
+    ) -> Result<Word, Error> {
+        let size = match *self.typifier.get(vector_token, &context.module.types) {
+            crate::TypeInner::Vector { size, .. } => size,
+            _ => return Err(Error::InvalidOperands(vector_token)),
+        };
+        let type_id = self.get_expression_type_id(vector_token, context)?;
+        let id = self.generate_id();
+        let mut operands = vec![type_id, id];
+        operands.extend((0 .. size as usize).map(|_| scalar_id));
+        push_instruction(out, spirv::Op::CompositeConstruct, &operands);
+        Ok(id)
+    }
+
+    fn write_binary(
+        &mut self,
+        token: Token<crate::Expression>,
+        op: crate::BinaryOperator,
+        left: Token<crate::Expression>,
+        right: Token<crate::Expression>,
+        context: &FunctionContext,
+        out: &mut Vec<Word>,
+    ) -> Result<Word, Error> {
+        use crate::{BinaryOperator as Bo, ScalarKind as Sk, TypeInner as Ti};
+        use spirv::Op;
+
+        let types = &context.module.types;
+        let left_inner = self.typifier.get(left, types).clone();
+        let right_inner = self.typifier.get(right, types).clone();
+        let kind = match left_inner {
+            Ti::Scalar { kind, .. } |
+            Ti::Vector { kind, .. } |
+            Ti::Matrix { kind, .. } => kind,
+            _ => return Err(Error::InvalidOperands(token)),
+        };
+        let mut left_id = self.write_expression(left, context, out)?;
+        let mut right_id = self.write_expression(right, context, out)?;
+
+        let spirv_op = match op {
+            Bo::Add => if kind == Sk::Float { Op::FAdd } else { Op::IAdd },
+            Bo::Subtract => if kind == Sk::Float { Op::FSub } else { Op::ISub },
+            Bo::Multiply => match (&left_inner, &right_inner) {
+                (&Ti::Vector { .. }, &Ti::Scalar { .. }) if kind == Sk::Float => Op::VectorTimesScalar,
+                (&Ti::Scalar { .. }, &Ti::Vector { .. }) if kind == Sk::Float => {
+                    std::mem::swap(&mut left_id, &mut right_id);
+                    Op::VectorTimesScalar
+                }
+                // there is no integer vector-scalar multiplication, so the scalar is splatted
+                (&Ti::Vector { .. }, &Ti::Scalar { .. }) => {
+                    right_id = self.write_splat(right_id, left, context, out)?;
+                    Op::IMul
+                }
+                (&Ti::Scalar { .. }, &Ti::Vector { .. }) => {
+                    left_id = self.write_splat(left_id, right, context, out)?;
+                    Op::IMul
+                }
+                (&Ti::Matrix { .. }, &Ti::Scalar { .. }) => Op::MatrixTimesScalar,
+                (&Ti::Scalar { .. }, &Ti::Matrix { .. }) => {
+                    std::mem::swap(&mut left_id, &mut right_id);
+                    Op::MatrixTimesScalar
+                }
+                (&Ti::Matrix { .. }, &Ti::Vector { .. }) => Op::MatrixTimesVector,
+                (&Ti::Vector { .. }, &Ti::Matrix { .. }) => Op::VectorTimesMatrix,
+                (&Ti::Matrix { .. }, &Ti::Matrix { .. }) => Op::MatrixTimesMatrix,
+                _ => if kind == Sk::Float { Op::FMul } else { Op::IMul },
+            },
+            Bo::Divide => match kind {
+                Sk::Float => Op::FDiv,
+                Sk::Sint => Op::SDiv,
+                Sk::Uint => Op::UDiv,
+                Sk::Bool => return Err(Error::InvalidOperands(token)),
+            },
+            Bo::Modulo => match kind {
+                Sk::Float => Op::FRem,
+                Sk::Sint => Op::SRem,
+                Sk::Uint => Op::UMod,
+                Sk::Bool => return Err(Error::InvalidOperands(token)),
+            },
+            Bo::Equal => match kind {
+                Sk::Float => Op::FOrdEqual,
+                Sk::Bool => Op::LogicalEqual,
+                _ => Op::IEqual,
+            },
+            Bo::NotEqual => match kind {
+                Sk::Float => Op::FOrdNotEqual,
+                Sk::Bool => Op::LogicalNotEqual,
+                _ => Op::INotEqual,
+            },
+            Bo::Less => match kind {
+                Sk::Float => Op::FOrdLessThan,
+                Sk::Sint => Op::SLessThan,
+                Sk::Uint => Op::ULessThan,
+                Sk::Bool => return Err(Error::InvalidOperands(token)),
+            },
+            Bo::LessEqual => match kind {
+                Sk::Float => Op::FOrdLessThanEqual,
+                Sk::Sint => Op::SLessThanEqual,
+                Sk::Uint => Op::ULessThanEqual,
+                Sk::Bool => return Err(Error::InvalidOperands(token)),
+            },
+            Bo::Greater => match kind {
+                Sk::Float => Op::FOrdGreaterThan,
+                Sk::Sint => Op::SGreaterThan,
+                Sk::Uint => Op::UGreaterThan,
+                Sk::Bool => return Err(Error::InvalidOperands(token)),
+            },
+            Bo::GreaterEqual => match kind {
+                Sk::Float => Op::FOrdGreaterThanEqual,
+                Sk::Sint => Op::SGreaterThanEqual,
+                Sk::Uint => Op::UGreaterThanEqual,
+                Sk::Bool => return Err(Error::InvalidOperands(token)),
+            },
+            Bo::And => if kind == Sk::Bool { Op::LogicalAnd } else { Op::BitwiseAnd },
+            Bo::ExclusiveOr => if kind == Sk::Bool { Op::LogicalNotEqual } else { Op::BitwiseXor },
+            Bo::InclusiveOr => if kind == Sk::Bool { Op::LogicalOr } else { Op::BitwiseOr },
+            Bo::LogicalAnd => Op::LogicalAnd,
+            Bo::LogicalOr => Op::LogicalOr,
+            Bo::ShiftLeftLogical => Op::ShiftLeftLogical,
+            Bo::ShiftRightLogical => Op::ShiftRightLogical,
+            Bo::ShiftRightArithmetic => Op::ShiftRightArithmetic,
+        };
+
+        let type_id = self.get_expression_type_id(token, context)?;
+        let id = self.generate_id();
+        push_instruction(out, spirv_op, &[type_id, id, left_id, right_id]);
+        Ok(id)
+    }
+
+    /// Write an expression, returning the ID of its value.
+    fn write_expression(
+        &mut self,
+        token: Token<crate::Expression>,
+        context: &FunctionContext,
+        out: &mut Vec<Word>,
+    ) -> Result<Word, Error> {
+        let module = context.module;
+        let expression = &context.function.expressions[token];
+        log::trace!("expression {:?}", expression);
+        let id = match *expression {
+            crate::Expression::Access { base, index } => {
+                if self.reference_class(base, context).is_some() {
+                    return self.write_reference_value(token, context, out);
+                }
+                let type_id = self.get_expression_type_id(token, context)?;
+                match *self.typifier.get(base, &module.types) {
+                    crate::TypeInner::Vector { .. } => (),
+                    _ => return Err(Error::UnsupportedAccess(token)),
+                }
+                let base_id = self.write_expression(base, context, out)?;
+                let index_id = self.write_expression(index, context, out)?;
+                let id = self.generate_id();
+                push_instruction(out, spirv::Op::VectorExtractDynamic, &[type_id, id, base_id, index_id]);
+                id
+            }
+            crate::Expression::AccessIndex { base, index } => {
+                if self.reference_class(base, context).is_some() {
+                    return self.write_reference_value(token, context, out);
+                }
+                let type_id = self.get_expression_type_id(token, context)?;
+                let base_id = self.write_expression(base, context, out)?;
+                let id = self.generate_id();
+                push_instruction(out, spirv::Op::CompositeExtract, &[type_id, id, base_id, index]);
+                id
+            }
+            crate::Expression::Constant(constant) => self.lookup_constant[&constant],
+            crate::Expression::Compose { ty, ref components } => {
+                let type_id = self.get_type_id(ty, module)?;
+                let id = self.generate_id();
+                let mut operands = vec![type_id, id];
+                for &component in components {
+                    operands.push(self.write_expression(component, context, out)?);
+                }
+                push_instruction(out, spirv::Op::CompositeConstruct, &operands);
+                id
+            }
+            crate::Expression::FunctionParameter(index) => context.parameter_ids[index as usize],
+            crate::Expression::GlobalVariable(var) => {
+                let var_id = self.lookup_global_variable[&var];
+                let ty = module.global_variables[var].ty;
+                match module.types[ty].inner {
+                    crate::TypeInner::Pointer { .. } => var_id,
+                    _ => {
+                        let type_id = self.get_type_id(ty, module)?;
+                        let id = self.generate_id();
+                        push_instruction(out, spirv::Op::Load, &[type_id, id, var_id]);
+                        id
+                    }
+                }
+            }
+            crate::Expression::LocalVariable(var) => context.local_ids[var.index()],
+            crate::Expression::Load { pointer } => {
+                match *self.typifier.get(pointer, &module.types) {
+                    crate::TypeInner::Pointer { .. } => (),
+                    // value-typed globals are loaded already
+                    _ => return self.write_expression(pointer, context, out),
+                }
+                let type_id = self.get_expression_type_id(token, context)?;
+                let pointer_id = self.write_pointer(pointer, context, out)?;
+                let id = self.generate_id();
+                push_instruction(out, spirv::Op::Load, &[type_id, id, pointer_id]);
+                id
+            }
+            crate::Expression::ImageSample { image, sampler, coordinate } => {
+                let image_type_id = self.get_expression_type_id(image, context)?;
+                let sampled_image_type_id = self.declare_type(spirv::Op::TypeSampledImage, vec![image_type_id]);
+                let image_id = self.write_expression(image, context, out)?;
+                let sampler_id = self.write_expression(sampler, context, out)?;
+                let coordinate_id = self.write_expression(coordinate, context, out)?;
+                let sampled_image_id = self.generate_id();
+                push_instruction(out, spirv::Op::SampledImage, &[sampled_image_type_id, sampled_image_id, image_id, sampler_id]);
+                self.write_texel_access(token, spirv::Op::ImageSampleImplicitLod, &[sampled_image_id, coordinate_id], context, out)?
+            }
+            crate::Expression::ImageLoad { image, coordinate, index } => {
+                let flags = match *self.typifier.get(image, &module.types) {
+                    crate::TypeInner::Image { flags, .. } => flags,
+                    _ => return Err(Error::InvalidOperands(token)),
+                };
+                let image_id = self.write_expression(image, context, out)?;
+                let coordinate_id = self.write_expression(coordinate, context, out)?;
+                let mut operands = vec![image_id, coordinate_id];
+                if let Some(index) = index {
+                    let operand = if flags.contains(crate::ImageFlags::MULTISAMPLED) {
+                        spirv::ImageOperands::SAMPLE
+                    } else {
+                        spirv::ImageOperands::LOD
+                    };
+                    operands.push(operand.bits());
+                    operands.push(self.write_expression(index, context, out)?);
+                }
+                let op = if flags.contains(crate::ImageFlags::SAMPLED) {
+                    spirv::Op::ImageFetch
+                } else {
+                    // storage images are declared without a format
+                    self.require_capability(spirv::Capability::StorageImageReadWithoutFormat);
+                    spirv::Op::ImageRead
+                };
+                self.write_texel_access(token, op, &operands, context, out)?
+            }
+            crate::Expression::ImageDimensions { image, level } => {
+                let (dim, flags) = match *self.typifier.get(image, &module.types) {
+                    crate::TypeInner::Image { dim, flags, .. } => (dim, flags),
+                    _ => return Err(Error::InvalidOperands(token)),
+                };
+                self.require_capability(spirv::Capability::ImageQuery);
+                let image_id = self.write_expression(image, context, out)?;
+                let has_levels = flags.contains(crate::ImageFlags::SAMPLED) &&
+                    !flags.contains(crate::ImageFlags::MULTISAMPLED);
+                let mut operands = vec![image_id];
+                let op = if has_levels {
+                    // the level of detail is mandatory
+                    let level_id = match level {
+                        Some(level) => self.write_expression(level, context, out)?,
+                        None => self.get_uint_constant_id(0),
+                    };
+                    operands.push(level_id);
+                    spirv::Op::ImageQuerySizeLod
+                } else {
+                    spirv::Op::ImageQuerySize
+                };
+                let size = match dim {
+                    spirv::Dim::Dim1D => 1,
+                    spirv::Dim::Dim3D => 3,
+                    _ => 2,
+                };
+                let arrayed = flags.contains(crate::ImageFlags::ARRAYED);
+                // the array layers come last, and get dropped
+                let query_type_id = if arrayed {
+                    let scalar_id = self.declare_type(spirv::Op::TypeInt, vec![32, 1]);
+                    self.declare_type(spirv::Op::TypeVector, vec![scalar_id, size + 1])
+                } else {
+                    self.get_expression_type_id(token, context)?
+                };
+                let query_id = self.generate_id();
+                operands.splice(0 .. 0, vec![query_type_id, query_id]);
+                push_instruction(out, op, &operands);
+                if arrayed {
+                    let type_id = self.get_expression_type_id(token, context)?;
+                    let id = self.generate_id();
+                    if size == 1 {
+                        push_instruction(out, spirv::Op::CompositeExtract, &[type_id, id, query_id, 0]);
+                    } else {
+                        let mut operands = vec![type_id, id, query_id, query_id];
+                        operands.extend(0 .. size);
+                        push_instruction(out, spirv::Op::VectorShuffle, &operands);
+                    }
+                    id
+                } else {
+                    query_id
+                }
+            }
+            crate::Expression::Unary { op, expr } => {
+                let kind = match *self.typifier.get(expr, &module.types) {
+                    crate::TypeInner::Scalar { kind, .. } |
+                    crate::TypeInner::Vector { kind, .. } |
+                    crate::TypeInner::Matrix { kind, .. } => kind,
+                    _ => return Err(Error::InvalidOperands(token)),
+                };
+                let spirv_op = match op {
+                    crate::UnaryOperator::Negate if kind == crate::ScalarKind::Float => spirv::Op::FNegate,
+                    crate::UnaryOperator::Negate => spirv::Op::SNegate,
+                    crate::UnaryOperator::Not => spirv::Op::LogicalNot,
+                    crate::UnaryOperator::BitwiseNot => spirv::Op::Not,
+                };
+                let type_id = self.get_expression_type_id(token, context)?;
+                let expr_id = self.write_expression(expr, context, out)?;
+                let id = self.generate_id();
+                push_instruction(out, spirv_op, &[type_id, id, expr_id]);
+                id
+            }
+            crate::Expression::Binary { op, left, right } => {
+                self.write_binary(token, op, left, right, context, out)?
+            }
+            crate::Expression::Call { function, ref arguments } => {
+                let type_id = self.get_expression_type_id(token, context)?;
+                let id = self.generate_id();
+                let mut operands = vec![type_id, id, self.lookup_function[&function]];
+                for &argument in arguments {
+                    operands.push(self.write_expression(argument, context, out)?);
+                }
+                push_instruction(out, spirv::Op::FunctionCall, &operands);
+                id
+            }
+            crate::Expression::Math { fun, ref arguments } => {
+                let type_id = self.get_expression_type_id(token, context)?;
+                let set_id = self.get_glsl_id();
+                let id = self.generate_id();
+                let mut operands = vec![type_id, id, set_id, map_math_function(fun) as Word];
+                for &argument in arguments {
+                    operands.push(self.write_expression(argument, context, out)?);
+                }
+                push_instruction(out, spirv::Op::ExtInst, &operands);
+                id
+            }
+        };
+        Ok(id)
+    }
+
+    /// Write an instruction producing a texel, which has 4 components
+    /// even if the expression only takes the first one.
+    fn write_texel_access(
+        &mut self,
+        token: Token<crate::Expression>,
+        op: spirv::Op,
+        operands: &[Word],
+        context: &FunctionContext,
+        out: &mut Vec<Word>,
+    ) -> Result<Word, Error> {
+        let type_id = self.get_expression_type_id(token, context)?;
+        let (texel_type_id, is_scalar) = match *self.typifier.get(token, &context.module.types) {
+            crate::TypeInner::Scalar { kind, width } => {
+                let inner = crate::TypeInner::Vector { size: crate::VectorSize::Quad, kind, width };
+                (self.get_type_inner_id(&inner, context.module)?, true)
+            }
+            _ => (type_id, false),
+        };
+        let texel_id = self.generate_id();
+        let mut instruction = vec![texel_type_id, texel_id];
+        instruction.extend_from_slice(operands);
+        push_instruction(out, op, &instruction);
+        if !is_scalar {
+            return Ok(texel_id);
+        }
+        let id = self.generate_id();
+        push_instruction(out, spirv::Op::CompositeExtract, &[type_id, id, texel_id, 0]);
+        Ok(id)
+    }
+
+    /// Write an access into memory that is used as a value.
+    fn write_reference_value(
+        &mut self,
+        token: Token<crate::Expression>,
+        context: &FunctionContext,
+        out: &mut Vec<Word>,
+    ) -> Result<Word, Error> {
+        let pointer_id = self.write_pointer(token, context, out)?;
+        match *self.typifier.get(token, &context.module.types) {
+            crate::TypeInner::Pointer { .. } => Ok(pointer_id),
+            _ => {
+                let type_id = self.get_expression_type_id(token, context)?;
+                let id = self.generate_id();
+                push_instruction(out, spirv::Op::Load, &[type_id, id, pointer_id]);
+                Ok(id)
+            }
+        }
+    }
+
+    /// Write a block of statements, returning true if it ended with a terminator.
+    fn write_block(
+        &mut self,
+        block: &[crate::Statement],
+        context: &FunctionContext,
+        flow: FlowContext,
+        out: &mut Vec<Word>,
+    ) -> Result<bool, Error> {
+        for statement in block {
+            log::trace!("statement {:?}", statement);
+            match *statement {
+                crate::Statement::Block(ref inner) => {
+                    if self.write_block(inner, context, flow, out)? {
+                        return Ok(true);
+                    }
+                }
+                crate::Statement::If { condition, ref accept, ref reject } => {
+                    let condition_id = self.write_expression(condition, context, out)?;
+                    let merge_id = self.generate_id();
+                    let accept_id = self.generate_id();
+                    let reject_id = if reject.is_empty() { merge_id } else { self.generate_id() };
+                    push_instruction(out, spirv::Op::SelectionMerge, &[merge_id, spirv::SelectionControl::NONE.bits()]);
+                    push_instruction(out, spirv::Op::BranchConditional, &[condition_id, accept_id, reject_id]);
+
+                    push_instruction(out, spirv::Op::Label, &[accept_id]);
+                    if !self.write_block(accept, context, flow, out)? {
+                        push_instruction(out, spirv::Op::Branch, &[merge_id]);
+                    }
+                    if !reject.is_empty() {
+                        push_instruction(out, spirv::Op::Label, &[reject_id]);
+                        if !self.write_block(reject, context, flow, out)? {
+                            push_instruction(out, spirv::Op::Branch, &[merge_id]);
+                        }
+                    }
+                    push_instruction(out, spirv::Op::Label, &[merge_id]);
+                }
+                crate::Statement::Switch { selector, ref cases, ref default } => {
+                    let selector_id = self.write_expression(selector, context, out)?;
+                    let merge_id = self.generate_id();
+                    let default_id = self.generate_id();
+                    let case_ids = cases
+                        .iter()
+                        .map(|_| self.generate_id())
+                        .collect::<Vec<_>>();
+                    let mut operands = vec![selector_id, default_id];
+                    for (&(value, _, _), &case_id) in cases.iter().zip(case_ids.iter()) {
+                        operands.push(value as Word);
+                        operands.push(case_id);
+                    }
+                    push_instruction(out, spirv::Op::SelectionMerge, &[merge_id, spirv::SelectionControl::NONE.bits()]);
+                    push_instruction(out, spirv::Op::Switch, &operands);
+
+                    let case_flow = FlowContext {
+                        break_id: Some(merge_id),
+                        ..flow
+                    };
+                    for (i, (_, case, fall_through)) in cases.iter().enumerate() {
+                        push_instruction(out, spirv::Op::Label, &[case_ids[i]]);
+                        if !self.write_block(case, context, case_flow, out)? {
+                            let next_id = match *fall_through {
+                                Some(_) => case_ids.get(i + 1).cloned().unwrap_or(default_id),
+                                None => merge_id,
+                            };
+                            push_instruction(out, spirv::Op::Branch, &[next_id]);
+                        }
+                    }
+                    push_instruction(out, spirv::Op::Label, &[default_id]);
+                    if !self.write_block(default, context, case_flow, out)? {
+                        push_instruction(out, spirv::Op::Branch, &[merge_id]);
+                    }
+                    push_instruction(out, spirv::Op::Label, &[merge_id]);
+                }
+                crate::Statement::Loop { ref body, ref continuing } => {
+                    let header_id = self.generate_id();
+                    let body_id = self.generate_id();
+                    let continuing_id = self.generate_id();
+                    let merge_id = self.generate_id();
+                    push_instruction(out, spirv::Op::Branch, &[header_id]);
+                    push_instruction(out, spirv::Op::Label, &[header_id]);
+                    push_instruction(out, spirv::Op::LoopMerge, &[merge_id, continuing_id, spirv::LoopControl::NONE.bits()]);
+                    push_instruction(out, spirv::Op::Branch, &[body_id]);
+
+                    push_instruction(out, spirv::Op::Label, &[body_id]);
+                    let body_flow = FlowContext {
+                        break_id: Some(merge_id),
+                        continue_id: Some(continuing_id),
+                    };
+                    if !self.write_block(body, context, body_flow, out)? {
+                        push_instruction(out, spirv::Op::Branch, &[continuing_id]);
+                    }
+                    push_instruction(out, spirv::Op::Label, &[continuing_id]);
+                    let continuing_flow = FlowContext {
+                        break_id: Some(merge_id),
+                        continue_id: None,
+                    };
+                    if !self.write_block(continuing, context, continuing_flow, out)? {
+                        push_instruction(out, spirv::Op::Branch, &[header_id]);
+                    }
+                    push_instruction(out, spirv::Op::Label, &[merge_id]);
+                }
+                crate::Statement::Break => {
+                    let target_id = flow.break_id.ok_or(Error::BreakOutsideLoop)?;
+                    push_instruction(out, spirv::Op::Branch, &[target_id]);
+                    return Ok(true);
+                }
+                crate::Statement::Continue => {
+                    let target_id = flow.continue_id.ok_or(Error::ContinueOutsideLoop)?;
+                    push_instruction(out, spirv::Op::Branch, &[target_id]);
+                    return Ok(true);
+                }
+                crate::Statement::Return { value: Some(value) } => {
+                    let value_id = self.write_expression(value, context, out)?;
+                    push_instruction(out, spirv::Op::ReturnValue, &[value_id]);
+                    return Ok(true);
+                }
+                crate::Statement::Return { value: None } => {
+                    push_instruction(out, spirv::Op::Return, &[]);
+                    return Ok(true);
+                }
+                crate::Statement::Kill => {
+                    push_instruction(out, spirv::Op::Kill, &[]);
+                    return Ok(true);
+                }
+                crate::Statement::Store { pointer, value } => {
+                    let pointer_id = self.write_pointer(pointer, context, out)?;
+                    let value_id = self.write_expression(value, context, out)?;
+                    push_instruction(out, spirv::Op::Store, &[pointer_id, value_id]);
+                }
+                crate::Statement::ImageStore { image, coordinate, value } => {
+                    let image_id = self.write_expression(image, context, out)?;
+                    let coordinate_id = self.write_expression(coordinate, context, out)?;
+                    let value_id = self.write_expression(value, context, out)?;
+                    self.require_capability(spirv::Capability::StorageImageWriteWithoutFormat);
+                    push_instruction(out, spirv::Op::ImageWrite, &[image_id, coordinate_id, value_id]);
+                }
+                crate::Statement::Call { function, ref arguments } => {
+                    let type_id = self.get_void_type_id();
+                    let id = self.generate_id();
+                    let mut operands = vec![type_id, id, self.lookup_function[&function]];
+                    for &argument in arguments {
+                        operands.push(self.write_expression(argument, context, out)?);
+                    }
+                    push_instruction(out, spirv::Op::FunctionCall, &operands);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn write_function(
+        &mut self,
+        token: Token<crate::Function>,
+        module: &crate::Module,
+    ) -> Result<(), Error> {
+        let fun = &module.functions[token];
+        let id = self.lookup_function[&token];
+        let return_type_id = match fun.return_type {
+            Some(ty) => self.get_type_id(ty, module)?,
+            None => self.get_void_type_id(),
+        };
+        let mut parameter_type_ids = Vec::with_capacity(fun.parameter_types.len());
+        for &ty in fun.parameter_types.iter() {
+            parameter_type_ids.push(self.get_type_id(ty, module)?);
+        }
+        let mut function_type_operands = vec![return_type_id];
+        function_type_operands.extend_from_slice(&parameter_type_ids);
+        let function_type_id = self.declare_type(spirv::Op::TypeFunction, function_type_operands);
+        self.write_name(id, &fun.name);
+
+        let mut out = Vec::new();
+        push_instruction(&mut out, spirv::Op::Function, &[return_type_id, id, fun.control.bits(), function_type_id]);
+        let mut parameter_ids = Vec::with_capacity(parameter_type_ids.len());
+        for type_id in parameter_type_ids {
+            let parameter_id = self.generate_id();
+            push_instruction(&mut out, spirv::Op::FunctionParameter, &[type_id, parameter_id]);
+            parameter_ids.push(parameter_id);
+        }
+        let label_id = self.generate_id();
+        push_instruction(&mut out, spirv::Op::Label, &[label_id]);
+
+        // local variables have to be declared at the start of the first block
+        let mut local_ids = Vec::new();
+        for (_, var) in fun.local_variables.iter() {
+            let base_id = self.get_type_id(var.ty, module)?;
+            let pointer_type_id = self.get_pointer_type_id(base_id, spirv::StorageClass::Function);
+            let var_id = self.generate_id();
+            let mut operands = vec![pointer_type_id, var_id, spirv::StorageClass::Function as Word];
+            if let Some(init) = var.init {
+                operands.push(self.lookup_constant[&init]);
+            }
+            push_instruction(&mut out, spirv::Op::Variable, &operands);
+            self.write_name(var_id, &var.name);
+            local_ids.push(var_id);
+        }
+
+        self.typifier.resolve_all(&fun.expressions, &ResolveContext {
+            types: &module.types,
+            constants: &module.constants,
+            global_vars: &module.global_variables,
+            local_vars: &fun.local_variables,
+            functions: &module.functions,
+            parameter_types: &fun.parameter_types,
+        })?;
+        let context = FunctionContext {
+            function: fun,
+            module,
+            parameter_ids,
+            local_ids,
+        };
+        if !self.write_block(&fun.body, &context, FlowContext::default(), &mut out)? {
+            if fun.return_type.is_some() {
+                push_instruction(&mut out, spirv::Op::Unreachable, &[]);
+            } else {
+                push_instruction(&mut out, spirv::Op::Return, &[]);
+            }
+        }
+        push_instruction(&mut out, spirv::Op::FunctionEnd, &[]);
+        self.layout.functions.extend(out);
+        Ok(())
+    }
+
+    fn write_entry_point(&mut self, ep: &crate::EntryPoint, module: &crate::Module) {
+        let function_id = self.lookup_function[&ep.function];
+        // the interface has to list all the inputs and outputs used
+        let mut interface = ep.inputs
+            .iter()
+            .chain(ep.outputs.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        for (_, expression) in module.functions[ep.function].expressions.iter() {
+            if let crate::Expression::GlobalVariable(token) = *expression {
+                match module.global_variables[token].class {
+                    spirv::StorageClass::Input |
+                    spirv::StorageClass::Output if !interface.contains(&token) => interface.push(token),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut operands = vec![ep.exec_model as Word, function_id];
+        operands.extend(string_to_words(&ep.name));
+        operands.extend(interface.iter().map(|token| self.lookup_global_variable[token]));
+        push_instruction(&mut self.layout.entry_points, spirv::Op::EntryPoint, &operands);
+
+        match ep.exec_model {
+            spirv::ExecutionModel::Fragment => {
+                push_instruction(&mut self.layout.execution_modes, spirv::Op::ExecutionMode, &[
+                    function_id,
+                    spirv::ExecutionMode::OriginUpperLeft as Word,
+                ]);
+            }
+            spirv::ExecutionModel::GLCompute => {
+                // the IR doesn't carry the workgroup size yet
+                push_instruction(&mut self.layout.execution_modes, spirv::Op::ExecutionMode, &[
+                    function_id,
+                    spirv::ExecutionMode::LocalSize as Word,
+                    1,
+                    1,
+                    1,
+                ]);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn write(&mut self, module: &crate::Module) -> Result<Vec<Word>, Error> {
+        push_instruction(&mut self.layout.memory_model, spirv::Op::MemoryModel, &[
+            spirv::AddressingModel::Logical as Word,
+            spirv::MemoryModel::GLSL450 as Word,
+        ]);
+
+        for (token, _) in module.types.iter() {
+            self.get_type_id(token, module)?;
+        }
+        for (token, _) in module.constants.iter() {
+            self.write_constant(token, module)?;
+        }
+        for (token, _) in module.global_variables.iter() {
+            self.write_global_variable(token, module)?;
+        }
+        if module.global_variables.iter().any(|(_, var)| var.class == spirv::StorageClass::StorageBuffer) {
+            let operands = string_to_words("SPV_KHR_storage_buffer_storage_class");
+            push_instruction(&mut self.layout.extensions, spirv::Op::Extension, &operands);
+        }
+
+        // functions may be called before they are defined
+        for (token, _) in module.functions.iter() {
+            let id = self.generate_id();
+            self.lookup_function.insert(token, id);
+        }
+        for (token, _) in module.functions.iter() {
+            self.write_function(token, module)
+                .map_err(|e| Error::Function(token, Box::new(e)))?;
+        }
+        for ep in module.entry_points.iter() {
+            self.write_entry_point(ep, module);
+        }
+
+        for &capability in self.capabilities.iter() {
+            push_instruction(&mut self.layout.capabilities, spirv::Op::Capability, &[capability as Word]);
+        }
+
+        let (major, minor, _) = module.header.version;
+        let layout = std::mem::take(&mut self.layout);
+        let mut words = vec![
+            spirv::MAGIC_NUMBER,
+            ((major as Word) << 16) | ((minor as Word) << 8),
+            module.header.generator,
+            self.id_count + 1,
+            0,
+        ];
+        words.extend(layout.capabilities);
+        words.extend(layout.extensions);
+        words.extend(layout.ext_inst_imports);
+        words.extend(layout.memory_model);
+        words.extend(layout.entry_points);
+        words.extend(layout.execution_modes);
+        words.extend(layout.debugs);
+        words.extend(layout.annotations);
+        words.extend(layout.declarations);
+        words.extend(layout.functions);
+        Ok(words)
+    }
+}
+
+pub fn write_vec(module: &crate::Module, options: &Options) -> Result<Vec<Word>, Error> {
+    Writer::new(options).write(module)
+}
+
+#[cfg(test)]
+mod test {
+    const SOURCE: &str = "
+        [[location 0]] var<out> color : vec4<f32>;
+        fn main() -> void {
+            var a : f32 = 1.0;
+            a = a * 2.0;
+            color = vec4<f32>(a, a, a, 1.0);
+            return;
+        }
+        entry_point fragment as \"main\" = main;
+    ";
+
+    fn round_trip(options: &super::Options) -> crate::Module {
+        let module = crate::front::wgsl::parse_str(SOURCE).unwrap();
+        let words = super::write_vec(&module, options).unwrap();
+        assert_eq!(words[0], spirv::MAGIC_NUMBER);
+        crate::front::spirv::Parser::new(words.into_iter()).parse().unwrap()
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let module = round_trip(&super::Options { debug_names: true });
+        assert_eq!(module.entry_points.len(), 1);
+        let ep = &module.entry_points[0];
+        assert_eq!(ep.exec_model, spirv::ExecutionModel::Fragment);
+        assert_eq!(ep.name, "main");
+        assert_eq!(ep.outputs.len(), 1);
+        let fun = &module.functions[ep.function];
+        assert_eq!(fun.name.as_deref(), Some("main"));
+        assert_eq!(fun.local_variables.len(), 1);
+        assert_eq!(fun.body.len(), 3);
+        let (_, var) = module.global_variables.iter().next().unwrap();
+        assert_eq!(var.name.as_deref(), Some("color"));
+        assert_eq!(var.binding, Some(crate::Binding::Location(0)));
+    }
+
+    #[test]
+    fn write_buffer_layout() {
+        let module = crate::front::wgsl::parse_str("
+            type Globals = [[block]] struct {
+                [[offset 0]] color : vec4<f32>;
+                [[offset 16]] scale : f32;
+            };
+            [[set 1, binding 2]] var<uniform> globals : Globals;
+        ").unwrap();
+        let words = super::write_vec(&module, &super::Options::default()).unwrap();
+        let module = crate::front::spirv::Parser::new(words.into_iter()).parse().unwrap();
+        let (_, var) = module.global_variables.iter().next().unwrap();
+        assert_eq!(var.class, spirv::StorageClass::Uniform);
+        assert_eq!(var.binding, Some(crate::Binding::Descriptor { set: 1, binding: 2 }));
+        let base = match module.types[var.ty].inner {
+            crate::TypeInner::Pointer { base, .. } => base,
+            ref other => panic!("Unexpected type {:?}", other),
+        };
+        match module.types[base].inner {
+            crate::TypeInner::Struct { ref members } => {
+                let offsets = members.iter().map(|member| member.offset).collect::<Vec<_>>();
+                assert_eq!(offsets, vec![Some(0), Some(16)]);
+            }
+            ref other => panic!("Unexpected type {:?}", other),
+        }
+    }
+
+    /// Returns the decorations of the types, as the operands of the instructions.
+    fn type_decorations(words: &[u32]) -> Vec<Vec<u32>> {
+        let mut decorations = Vec::new();
+        let mut position = 5;
+        while position < words.len() {
+            let count = (words[position] >> 16) as usize;
+            let op = words[position] & 0xffff;
+            if op == spirv::Op::Decorate as u32 || op == spirv::Op::MemberDecorate as u32 {
+                decorations.push(words[position + 1 .. position + count].to_vec());
+            }
+            position += count;
+        }
+        decorations
+    }
+
+    #[test]
+    fn write_strides() {
+        let module = crate::front::wgsl::parse_str("
+            type Light = struct {
+                [[offset 0]] transform : mat4x3<f32>;
+                [[offset 64]] color : vec3<f32>;
+            };
+            type Lights = [[block]] struct {
+                [[offset 0]] lights : array<Light>;
+            };
+            type Params = [[block]] struct {
+                [[offset 0]] scales : array<f32, 4>;
+                [[offset 64]] rotation : mat2x2<f32>;
+            };
+            [[set 0, binding 0]] var<storage_buffer> lights : Lights;
+            [[set 0, binding 1]] var<uniform> params : Params;
+        ").unwrap();
+        let words = super::write_vec(&module, &super::Options::default()).unwrap();
+        let decorations = type_decorations(&words);
+        let find = |decoration: spirv::Decoration| {
+            decorations
+                .iter()
+                .filter(|operands| operands.len() > 2 && operands[operands.len() - 2] == decoration as u32)
+                .map(|operands| operands[operands.len() - 1])
+                .collect::<Vec<_>>()
+        };
+        // the storage array is as large as the structure, rounded to its alignment,
+        // and the uniform one aligns its elements to 16 bytes
+        assert_eq!(find(spirv::Decoration::ArrayStride), vec![80, 16]);
+        // only the columns of the uniform matrix are aligned to 16 bytes
+        assert_eq!(find(spirv::Decoration::MatrixStride), vec![16, 16]);
+        let col_major = decorations
+            .iter()
+            .filter(|operands| operands.last() == Some(&(spirv::Decoration::ColMajor as u32)))
+            .count();
+        assert_eq!(col_major, 2);
+    }
+
+    #[test]
+    fn round_trip_storage_buffer() {
+        let module = crate::front::wgsl::parse_str("
+            type Particles = [[block]] struct {
+                [[offset 0]] positions : array<vec4<f32>>;
+            };
+            [[set 0, binding 0]] var<storage_buffer> particles : Particles;
+            fn main() -> void {
+                particles.positions[0] = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+                return;
+            }
+            entry_point compute as \"main\" = main;
+        ").unwrap();
+        let words = super::write_vec(&module, &super::Options::default()).unwrap();
+        let module = crate::front::spirv::Parser::new(words.into_iter()).parse().unwrap();
+        let (_, var) = module.global_variables.iter().next().unwrap();
+        assert_eq!(var.class, spirv::StorageClass::StorageBuffer);
+    }
+
+    #[test]
+    fn write_missing_offset() {
+        let module = crate::front::wgsl::parse_str("
+            type Inner = struct {
+                scale : f32;
+            };
+            type Params = [[block]] struct {
+                [[offset 0]] inner : Inner;
+            };
+            [[set 0, binding 0]] var<uniform> params : Params;
+        ").unwrap();
+        match super::write_vec(&module, &super::Options::default()) {
+            Err(super::Error::MissingOffset(ty, 0)) => assert_eq!(module.types[ty].name.as_deref(), Some("Inner")),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn write_no_names() {
+        let module = round_trip(&super::Options::default());
+        let (_, fun) = module.functions.iter().next().unwrap();
+        assert_eq!(fun.name, None);
+    }
+
+    #[test]
+    fn write_unknown_structure() {
+        let module = crate::Module::generate_empty();
+        let mut writer = super::Writer::new(&super::Options::default());
+        let inner = crate::TypeInner::Struct { members: Vec::new() };
+        let result = writer.get_type_inner_id(&inner, &module);
+        assert!(matches!(result, Err(super::Error::UnknownStructure)));
+    }
+}
@@ -0,0 +1,291 @@
+/*! SPIR-V disassembler
+
+Prints the words of a SPIR-V module in the text format accepted by
+`front::spvasm`, so that it can be inspected, or edited and assembled back.
+The ids keep their numbers, the numbers are printed according to their
+types, and the instructions of "GLSL.std.450" are printed by name.
+!*/
+
+use crate::front::spvasm::grammar::{self, Kind, NumberType, Quantity, Types};
+
+use num_traits::FromPrimitive;
+use std::fmt::{Error as FmtError, Write};
+
+#[derive(Debug)]
+pub enum Error {
+    Format(FmtError),
+    InvalidHeader,
+    /// The instruction at the given word offset doesn't fit.
+    InvalidWordCount(usize),
+    UnknownInstruction(u16),
+    UnsupportedInstruction(spirv::Op),
+    MissingOperand(spirv::Op),
+    ExtraOperands(spirv::Op),
+    BadString(spirv::Op),
+}
+
+impl From<FmtError> for Error {
+    fn from(e: FmtError) -> Self {
+        Error::Format(e)
+    }
+}
+
+/// Prints a float so that it parses back to the same bits.
+///
+/// The infinities and the NaNs use the hexadecimal notation.
+macro_rules! float_text {
+    ($value:expr, $max_exponent:expr) => {{
+        let value = $value;
+        let sign = if value.is_sign_negative() { "-" } else { "" };
+        if value.is_nan() {
+            format!("{}0x1.8p+{}", sign, $max_exponent)
+        } else if value.is_infinite() {
+            format!("{}0x1p+{}", sign, $max_exponent)
+        } else {
+            format!("{:?}", value)
+        }
+    }};
+}
+
+fn string_text(string: &str) -> String {
+    let mut text = String::with_capacity(string.len() + 2);
+    text.push('"');
+    for c in string.chars() {
+        if c == '"' || c == '\\' {
+            text.push('\\');
+        }
+        text.push(c);
+    }
+    text.push('"');
+    text
+}
+
+/// Reads the operands of one instruction.
+struct Reader<'a> {
+    op: spirv::Op,
+    operands: &'a [spirv::Word],
+    position: usize,
+    types: &'a Types,
+    result: Option<spirv::Word>,
+    texts: Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn is_done(&self) -> bool {
+        self.position == self.operands.len()
+    }
+
+    fn next(&mut self) -> Result<spirv::Word, Error> {
+        let word = *self.operands.get(self.position).ok_or(Error::MissingOperand(self.op))?;
+        self.position += 1;
+        Ok(word)
+    }
+
+    fn number(&mut self, ty: Option<NumberType>) -> Result<(), Error> {
+        let low = self.next()?;
+        let text = match ty {
+            Some(NumberType::Float { width: 32 }) => float_text!(f32::from_bits(low), 128),
+            Some(NumberType::Float { width: 64 }) => {
+                let bits = ((self.next()? as u64) << 32) | low as u64;
+                float_text!(f64::from_bits(bits), 1024)
+            }
+            Some(NumberType::Int { width: 64, signed }) => {
+                let bits = ((self.next()? as u64) << 32) | low as u64;
+                if signed {
+                    (bits as i64).to_string()
+                } else {
+                    bits.to_string()
+                }
+            }
+            Some(NumberType::Int { signed: true, .. }) => (low as i32).to_string(),
+            _ => low.to_string(),
+        };
+        self.texts.push(text);
+        Ok(())
+    }
+
+    fn operand(&mut self, kind: Kind) -> Result<(), Error> {
+        match kind {
+            Kind::ResultType | Kind::Id => {
+                let id = self.next()?;
+                self.texts.push(format!("%{}", id));
+            }
+            Kind::Result => self.result = Some(self.next()?),
+            Kind::Literal => {
+                let value = self.next()?;
+                self.texts.push(value.to_string());
+            }
+            Kind::String => {
+                let (string, count) = grammar::decode_string(&self.operands[self.position ..])
+                    .ok_or(Error::BadString(self.op))?;
+                self.position += count;
+                self.texts.push(string_text(&string));
+            }
+            Kind::Number => {
+                let ty = self.types.number(self.op, &self.operands[.. self.position]);
+                self.number(ty)?;
+            }
+            Kind::ExtInst => {
+                let kind = self.types.ext_inst(&self.operands[.. self.position]);
+                self.operand(kind)?;
+            }
+            Kind::Enum(kind) => {
+                let value = self.next()?;
+                self.texts.push(kind.name(value).unwrap_or_else(|| value.to_string()));
+                for &parameter in kind.parameters(value) {
+                    self.operand(parameter)?;
+                }
+            }
+            Kind::Mask(kind) => {
+                let mask = self.next()?;
+                let bits = kind.bits().iter().filter(|&&(_, bit, _)| mask & bit != 0);
+                let known = bits.clone().fold(0, |known, &(_, bit, _)| known | bit);
+                let text = if mask == 0 {
+                    "None".to_owned()
+                } else if known == mask {
+                    bits.clone().map(|&(name, _, _)| name).collect::<Vec<_>>().join("|")
+                } else {
+                    format!("0x{:x}", mask)
+                };
+                self.texts.push(text);
+                for &(_, _, parameters) in bits {
+                    for &parameter in parameters {
+                        self.operand(parameter)?;
+                    }
+                }
+            }
+            Kind::Target => {
+                let ty = self.types.number(self.op, &self.operands[.. 1]);
+                self.number(ty)?;
+                self.operand(Kind::Id)?;
+            }
+            Kind::Incoming => {
+                self.operand(Kind::Id)?;
+                self.operand(Kind::Id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Disassembles the words of a SPIR-V module, header included.
+pub fn disassemble(words: &[spirv::Word]) -> Result<String, Error> {
+    let header = match words.get(.. 5) {
+        Some(header) if header[0] == spirv::MAGIC_NUMBER => header,
+        _ => return Err(Error::InvalidHeader),
+    };
+    let mut out = String::new();
+    writeln!(out, "; SPIR-V")?;
+    writeln!(out, "; Version: {}.{}", (header[1] >> 16) & 0xFF, (header[1] >> 8) & 0xFF)?;
+    writeln!(out, "; Generator: 0x{:08x}", header[2])?;
+    writeln!(out, "; Bound: {}", header[3])?;
+    writeln!(out, "; Schema: {}", header[4])?;
+
+    let mut types = Types::default();
+    let mut offset = header.len();
+    while offset < words.len() {
+        let count = (words[offset] >> 16) as usize;
+        if count == 0 || offset + count > words.len() {
+            return Err(Error::InvalidWordCount(offset));
+        }
+        let opcode = words[offset] & 0xFFFF;
+        let op = spirv::Op::from_u32(opcode).ok_or(Error::UnknownInstruction(opcode as u16))?;
+        let layout = grammar::operands(op).ok_or(Error::UnsupportedInstruction(op))?;
+        let operands = &words[offset + 1 .. offset + count];
+
+        let mut reader = Reader {
+            op,
+            operands,
+            position: 0,
+            types: &types,
+            result: None,
+            texts: Vec::new(),
+        };
+        for &(kind, quantity) in layout {
+            match quantity {
+                Quantity::One => reader.operand(kind)?,
+                Quantity::Optional => {
+                    if !reader.is_done() {
+                        reader.operand(kind)?;
+                    }
+                }
+                Quantity::Any => {
+                    while !reader.is_done() {
+                        reader.operand(kind)?;
+                    }
+                }
+            }
+        }
+        if !reader.is_done() {
+            return Err(Error::ExtraOperands(op));
+        }
+
+        match reader.result {
+            Some(id) => write!(out, "{:>12} = ", format!("%{}", id))?,
+            None => write!(out, "{:15}", "")?,
+        }
+        write!(out, "{}", grammar::instruction_name(op))?;
+        for text in reader.texts.iter() {
+            write!(out, " {}", text)?;
+        }
+        writeln!(out)?;
+
+        types.register(op, layout, operands);
+        if op == spirv::Op::ExtInstImport {
+            if let Some((name, _)) = grammar::decode_string(&operands[1 ..]) {
+                types.import(operands[0], &name);
+            }
+        }
+        offset += count;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn disassemble_round_trip() {
+        let module = crate::front::wgsl::parse_str("
+            import \"GLSL.std.450\" as std;
+            [[location 0]] var<in> v_uv : vec2<f32>;
+            [[location 0]] var<out> color : vec4<f32>;
+            fn main() -> void {
+                var a : f32 = -2.5;
+                a = std::sqrt(a * 2.0);
+                color = vec4<f32>(v_uv, a, 1.0);
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap();
+        let words = crate::back::spv::write_vec(&module, &crate::back::spv::Options { debug_names: true }).unwrap();
+        let text = super::disassemble(&words).unwrap();
+        assert!(text.contains("OpEntryPoint Fragment"));
+        assert!(text.contains("OpName"));
+        assert!(text.contains("OpExtInst"));
+        assert!(text.contains(" Sqrt "));
+        assert!(text.contains(" = OpConstant %1 2.5\n"));
+        assert_eq!(crate::front::spvasm::assemble(&text).unwrap(), words);
+    }
+
+    #[test]
+    fn disassemble_special_floats() {
+        let words = crate::front::spvasm::assemble("
+            %float = OpTypeFloat 32
+            %1 = OpConstant %float 0x1p+128
+            %2 = OpConstant %float -0x1p+128
+            %3 = OpConstant %float 1e-7
+        ").unwrap();
+        let text = super::disassemble(&words).unwrap();
+        assert!(text.contains("%1 = OpConstant %4 0x1p+128\n"));
+        assert!(text.contains("%2 = OpConstant %4 -0x1p+128\n"));
+        assert!(text.contains("%3 = OpConstant %4 1e-7\n"));
+        assert_eq!(crate::front::spvasm::assemble(&text).unwrap(), words);
+    }
+
+    #[test]
+    fn disassemble_errors() {
+        assert!(matches!(super::disassemble(&[0, 0, 0, 0, 0]), Err(super::Error::InvalidHeader)));
+        let words = [spirv::MAGIC_NUMBER, 0x0001_0000, 0, 1, 0, (3 << 16) | spirv::Op::TypeVoid as u32, 1];
+        assert!(matches!(super::disassemble(&words), Err(super::Error::InvalidWordCount(5))));
+    }
+}
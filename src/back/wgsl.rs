@@ -0,0 +1,720 @@
+/*! WebGPU Shading Language (WGSL) backend
+
+The output follows the dialect accepted by `front::wgsl`, so that a module
+can be printed and parsed back. All the named entities get unique identifiers
+that are valid in WGSL, anything unnamed gets one derived from its index.
+
+Constants are always printed inline as literals. Expressions are printed
+at the point of use, which means a value used in multiple places is
+re-evaluated in each of them.
+!*/
+
+use std::fmt::{
+    Display, Error as FmtError, Formatter, Write,
+};
+
+use crate::{
+    back::{assign_names, call_order, global_value_type, NameKey, Namer},
+    proc::{ResolveContext, ResolveError, Typifier},
+    storage::Token,
+    FastHashMap,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Format(FmtError),
+    UnsupportedExecutionModel(spirv::ExecutionModel),
+    UnsupportedStorageClass(spirv::StorageClass),
+    UnsupportedBuiltIn(spirv::BuiltIn),
+    UnsupportedScalar(crate::ScalarKind, crate::Bytes),
+    UnsupportedImageDim(spirv::Dim),
+    /// The array layer of a texel is passed separately, so the coordinates
+    /// of arrayed images need to be composed in place.
+    UnsupportedImageCoordinate(Token<crate::Expression>),
+    UnsupportedConstant(Token<crate::Constant>),
+    Type(ResolveError),
+}
+
+impl From<FmtError> for Error {
+    fn from(e: FmtError) -> Self {
+        Error::Format(e)
+    }
+}
+
+impl From<ResolveError> for Error {
+    fn from(e: ResolveError) -> Self {
+        Error::Type(e)
+    }
+}
+
+/// Namespace under which the GLSL.std.450 functions are imported.
+const NAME_STD: &str = "std";
+const COMPONENTS: &[char] = &['x', 'y', 'z', 'w'];
+
+const RESERVED_NAMES: &[&str] = &[
+    NAME_STD,
+    "array", "as", "binding", "block", "bool", "break", "builtin", "case",
+    "compute", "const", "continue", "continuing", "default", "discard",
+    "else", "elseif", "entry_point", "f32", "fallthrough", "false", "fn",
+    "fragment", "function", "i32", "if", "image", "import", "in", "location",
+    "loop", "mat2x2", "mat2x3", "mat2x4", "mat3x2", "mat3x3", "mat3x4",
+    "mat4x2", "mat4x3", "mat4x4", "offset", "out", "private", "ptr", "return",
+    "sampler", "set", "storage_buffer", "struct", "switch", "true", "type",
+    "u32", "uniform", "uniform_constant", "var", "vec2", "vec3", "vec4",
+    "vertex", "void", "workgroup",
+];
+
+#[derive(Clone, Copy, Debug)]
+struct Level(usize);
+impl Level {
+    fn next(&self) -> Self {
+        Level(self.0 + 1)
+    }
+}
+impl Display for Level {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
+        (0 .. self.0).try_for_each(|_| formatter.write_str("    "))
+    }
+}
+
+fn scalar_kind_string(kind: crate::ScalarKind, width: crate::Bytes) -> Result<&'static str, Error> {
+    match (kind, width) {
+        (crate::ScalarKind::Float, 32) => Ok("f32"),
+        (crate::ScalarKind::Sint, 32) => Ok("i32"),
+        (crate::ScalarKind::Uint, 32) => Ok("u32"),
+        (crate::ScalarKind::Bool, _) => Ok("bool"),
+        _ => Err(Error::UnsupportedScalar(kind, width)),
+    }
+}
+
+fn vector_size_string(size: crate::VectorSize) -> &'static str {
+    match size {
+        crate::VectorSize::Bi => "2",
+        crate::VectorSize::Tri => "3",
+        crate::VectorSize::Quad => "4",
+    }
+}
+
+fn storage_class_string(class: spirv::StorageClass) -> Result<&'static str, Error> {
+    match class {
+        spirv::StorageClass::Input => Ok("in"),
+        spirv::StorageClass::Output => Ok("out"),
+        spirv::StorageClass::Uniform => Ok("uniform"),
+        spirv::StorageClass::UniformConstant => Ok("uniform_constant"),
+        spirv::StorageClass::StorageBuffer => Ok("storage_buffer"),
+        spirv::StorageClass::Workgroup => Ok("workgroup"),
+        spirv::StorageClass::Image => Ok("image"),
+        spirv::StorageClass::Private => Ok("private"),
+        spirv::StorageClass::Function => Ok("function"),
+        other => Err(Error::UnsupportedStorageClass(other)),
+    }
+}
+
+fn built_in_string(built_in: spirv::BuiltIn) -> Result<&'static str, Error> {
+    Ok(match built_in {
+        spirv::BuiltIn::Position => "position",
+        spirv::BuiltIn::VertexIndex => "vertex_idx",
+        spirv::BuiltIn::InstanceIndex => "instance_idx",
+        spirv::BuiltIn::FrontFacing => "front_facing",
+        spirv::BuiltIn::FragCoord => "frag_coord",
+        spirv::BuiltIn::FragDepth => "frag_depth",
+        spirv::BuiltIn::LocalInvocationId => "local_invocation_id",
+        spirv::BuiltIn::LocalInvocationIndex => "local_invocation_idx",
+        spirv::BuiltIn::GlobalInvocationId => "global_invocation_id",
+        other => return Err(Error::UnsupportedBuiltIn(other)),
+    })
+}
+
+fn image_dim_string(dim: spirv::Dim) -> Result<&'static str, Error> {
+    match dim {
+        spirv::Dim::Dim1D => Ok("1d"),
+        spirv::Dim::Dim2D => Ok("2d"),
+        spirv::Dim::Dim3D => Ok("3d"),
+        spirv::Dim::DimCube => Ok("cube"),
+        other => Err(Error::UnsupportedImageDim(other)),
+    }
+}
+
+fn binary_operator_string(op: crate::BinaryOperator) -> &'static str {
+    match op {
+        crate::BinaryOperator::Add => "+",
+        crate::BinaryOperator::Subtract => "-",
+        crate::BinaryOperator::Multiply => "*",
+        crate::BinaryOperator::Divide => "/",
+        crate::BinaryOperator::Modulo => "%",
+        crate::BinaryOperator::Equal => "==",
+        crate::BinaryOperator::NotEqual => "!=",
+        crate::BinaryOperator::Less => "<",
+        crate::BinaryOperator::LessEqual => "<=",
+        crate::BinaryOperator::Greater => ">",
+        crate::BinaryOperator::GreaterEqual => ">=",
+        crate::BinaryOperator::And => "&",
+        crate::BinaryOperator::ExclusiveOr => "^",
+        crate::BinaryOperator::InclusiveOr => "|",
+        crate::BinaryOperator::LogicalAnd => "&&",
+        crate::BinaryOperator::LogicalOr => "||",
+        crate::BinaryOperator::ShiftLeftLogical => "<<",
+        crate::BinaryOperator::ShiftRightLogical => ">>",
+        crate::BinaryOperator::ShiftRightArithmetic => ">>>",
+    }
+}
+
+struct FunctionContext<'a> {
+    token: Token<crate::Function>,
+    function: &'a crate::Function,
+    module: &'a crate::Module,
+}
+
+pub struct Writer<W> {
+    out: W,
+    names: FastHashMap<NameKey, String>,
+    typifier: Typifier,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Writer {
+            out,
+            names: FastHashMap::default(),
+            typifier: Typifier::new(),
+        }
+    }
+
+    pub fn finish(self) -> W {
+        self.out
+    }
+
+    fn put_type(&mut self, ty: Token<crate::Type>, module: &crate::Module) -> Result<(), Error> {
+        match module.types[ty].inner {
+            crate::TypeInner::Scalar { kind, width } => {
+                write!(self.out, "{}", scalar_kind_string(kind, width)?)?;
+            }
+            crate::TypeInner::Vector { size, kind, width } => {
+                write!(self.out, "vec{}<{}>", vector_size_string(size), scalar_kind_string(kind, width)?)?;
+            }
+            crate::TypeInner::Matrix { columns, rows, kind, width } => {
+                write!(
+                    self.out,
+                    "mat{}x{}<{}>",
+                    vector_size_string(columns),
+                    vector_size_string(rows),
+                    scalar_kind_string(kind, width)?,
+                )?;
+            }
+            crate::TypeInner::Pointer { base, class } => {
+                write!(self.out, "ptr<{}, ", storage_class_string(class)?)?;
+                self.put_type(base, module)?;
+                write!(self.out, ">")?;
+            }
+            crate::TypeInner::Array { base, size } => {
+                write!(self.out, "array<")?;
+                self.put_type(base, module)?;
+                if let crate::ArraySize::Static(length) = size {
+                    write!(self.out, ", {}", length)?;
+                }
+                write!(self.out, ">")?;
+            }
+            crate::TypeInner::Struct { .. } => {
+                write!(self.out, "{}", self.names[&NameKey::Type(ty)])?;
+            }
+            crate::TypeInner::Image { base, dim, flags } => {
+                let dim_str = image_dim_string(dim)?;
+                let arrayed = if flags.contains(crate::ImageFlags::ARRAYED) { "_array" } else { "" };
+                // depth textures always hold floats
+                if flags.contains(crate::ImageFlags::DEPTH) {
+                    write!(self.out, "texture_depth_{}{}", dim_str, arrayed)?;
+                    return Ok(());
+                }
+                if flags.contains(crate::ImageFlags::SAMPLED) {
+                    let ms = if flags.contains(crate::ImageFlags::MULTISAMPLED) { "multisampled_" } else { "" };
+                    write!(self.out, "texture_{}{}", ms, dim_str)?;
+                } else {
+                    let access = if flags.contains(crate::ImageFlags::CAN_STORE) { "wo" } else { "ro" };
+                    write!(self.out, "texture_storage_{}_{}", access, dim_str)?;
+                }
+                write!(self.out, "{}<", arrayed)?;
+                self.put_type(base, module)?;
+                write!(self.out, ">")?;
+            }
+            crate::TypeInner::Sampler { comparison } => {
+                write!(self.out, "{}", if comparison { "sampler_comparison" } else { "sampler" })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_constant(&mut self, token: Token<crate::Constant>, module: &crate::Module) -> Result<(), Error> {
+        match module.constants[token].inner {
+            crate::ConstantInner::Sint(value) => write!(self.out, "{}", value)?,
+            crate::ConstantInner::Uint(value) => write!(self.out, "{}", value)?,
+            crate::ConstantInner::Float(value) => {
+                if !value.is_finite() {
+                    return Err(Error::UnsupportedConstant(token));
+                }
+                // float literals have no exponent, and need a dot
+                // to be told apart from the integer ones
+                let text = value.to_string();
+                if text.contains('.') {
+                    write!(self.out, "{}", text)?;
+                } else {
+                    write!(self.out, "{}.0", text)?;
+                }
+            }
+            crate::ConstantInner::Bool(value) => write!(self.out, "{}", value)?,
+        }
+        Ok(())
+    }
+
+    fn put_arguments(
+        &mut self,
+        arguments: &[Token<crate::Expression>],
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        write!(self.out, "(")?;
+        for (i, &argument) in arguments.iter().enumerate() {
+            if i != 0 {
+                write!(self.out, ", ")?;
+            }
+            self.put_expression(argument, context)?;
+        }
+        write!(self.out, ")")?;
+        Ok(())
+    }
+
+    /// Returns the image followed by the texel coordinate arguments.
+    fn texel_arguments(
+        &self,
+        image: Token<crate::Expression>,
+        coordinate: Token<crate::Expression>,
+        context: &FunctionContext,
+    ) -> Result<Vec<Token<crate::Expression>>, Error> {
+        let arrayed = match *self.typifier.get(image, &context.module.types) {
+            crate::TypeInner::Image { flags, .. } => flags.contains(crate::ImageFlags::ARRAYED),
+            _ => false,
+        };
+        let mut arguments = vec![image];
+        match context.function.expressions[coordinate] {
+            _ if !arrayed => arguments.push(coordinate),
+            crate::Expression::Compose { ref components, .. } => arguments.extend(components),
+            _ => return Err(Error::UnsupportedImageCoordinate(coordinate)),
+        }
+        Ok(arguments)
+    }
+
+    fn put_expression(
+        &mut self,
+        expr_token: Token<crate::Expression>,
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        let module = context.module;
+        let expression = &context.function.expressions[expr_token];
+        log::trace!("expression {:?}", expression);
+        match *expression {
+            crate::Expression::Access { base, index } => {
+                self.put_expression(base, context)?;
+                write!(self.out, "[")?;
+                self.put_expression(index, context)?;
+                write!(self.out, "]")?;
+            }
+            crate::Expression::AccessIndex { base, index } => {
+                self.put_expression(base, context)?;
+                let (base_ty, base_inner) = match *self.typifier.get(base, &module.types) {
+                    crate::TypeInner::Pointer { base, .. } => (Some(base), &module.types[base].inner),
+                    ref other => (self.typifier.get_handle(base), other),
+                };
+                match *base_inner {
+                    crate::TypeInner::Struct { .. } => {
+                        let ty = base_ty.ok_or(ResolveError::InvalidAccessIndex(index))?;
+                        write!(self.out, ".{}", self.names[&NameKey::StructMember(ty, index)])?;
+                    }
+                    crate::TypeInner::Vector { .. } => {
+                        write!(self.out, ".{}", COMPONENTS[index as usize])?;
+                    }
+                    _ => {
+                        write!(self.out, "[{}]", index)?;
+                    }
+                }
+            }
+            crate::Expression::Constant(token) => {
+                self.put_constant(token, module)?;
+            }
+            crate::Expression::Compose { ty, ref components } => {
+                self.put_type(ty, module)?;
+                self.put_arguments(components, context)?;
+            }
+            crate::Expression::FunctionParameter(index) => {
+                let key = NameKey::FunctionParameter(context.token, index);
+                write!(self.out, "{}", self.names[&key])?;
+            }
+            crate::Expression::GlobalVariable(token) => {
+                write!(self.out, "{}", self.names[&NameKey::GlobalVariable(token)])?;
+            }
+            crate::Expression::LocalVariable(token) => {
+                let key = NameKey::LocalVariable(context.token, token);
+                write!(self.out, "{}", self.names[&key])?;
+            }
+            crate::Expression::Load { pointer } => {
+                self.put_expression(pointer, context)?;
+            }
+            crate::Expression::ImageSample { image, sampler, coordinate } => {
+                write!(self.out, "textureSample(")?;
+                self.put_expression(image, context)?;
+                write!(self.out, ", ")?;
+                self.put_expression(sampler, context)?;
+                write!(self.out, ", ")?;
+                self.put_expression(coordinate, context)?;
+                write!(self.out, ")")?;
+            }
+            crate::Expression::ImageLoad { image, coordinate, index } => {
+                let mut arguments = self.texel_arguments(image, coordinate, context)?;
+                arguments.extend(index);
+                write!(self.out, "textureLoad")?;
+                self.put_arguments(&arguments, context)?;
+            }
+            crate::Expression::ImageDimensions { image, level } => {
+                let mut arguments = vec![image];
+                arguments.extend(level);
+                write!(self.out, "textureDimensions")?;
+                self.put_arguments(&arguments, context)?;
+            }
+            crate::Expression::Unary { op, expr } => {
+                let op_str = match op {
+                    crate::UnaryOperator::Negate => "-",
+                    crate::UnaryOperator::Not => "!",
+                    crate::UnaryOperator::BitwiseNot => "~",
+                };
+                write!(self.out, "({}", op_str)?;
+                self.put_expression(expr, context)?;
+                write!(self.out, ")")?;
+            }
+            crate::Expression::Binary { op, left, right } => {
+                write!(self.out, "(")?;
+                self.put_expression(left, context)?;
+                write!(self.out, " {} ", binary_operator_string(op))?;
+                self.put_expression(right, context)?;
+                write!(self.out, ")")?;
+            }
+            crate::Expression::Call { function, ref arguments } => {
+                write!(self.out, "{}", self.names[&NameKey::Function(function)])?;
+                self.put_arguments(arguments, context)?;
+            }
+            crate::Expression::Math { fun, ref arguments } => {
+                // the variants are named after the GLSL.std.450 instructions,
+                // which `front::wgsl` matches in lower case
+                let name = format!("{:?}", fun).to_lowercase();
+                write!(self.out, "{}::{}", NAME_STD, name)?;
+                self.put_arguments(arguments, context)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_block(
+        &mut self,
+        level: Level,
+        statements: &[crate::Statement],
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        for statement in statements {
+            self.put_statement(level, statement, context)?;
+        }
+        Ok(())
+    }
+
+    fn put_statement(
+        &mut self,
+        level: Level,
+        statement: &crate::Statement,
+        context: &FunctionContext,
+    ) -> Result<(), Error> {
+        log::trace!("statement[{}] {:?}", level.0, statement);
+        match *statement {
+            crate::Statement::Block(ref block) => {
+                // all the variables are declared upfront,
+                // so there is nothing to scope
+                self.put_block(level, block, context)?;
+            }
+            crate::Statement::If { condition, ref accept, ref reject } => {
+                write!(self.out, "{}if (", level)?;
+                self.put_expression(condition, context)?;
+                writeln!(self.out, ") {{")?;
+                self.put_block(level.next(), accept, context)?;
+                if !reject.is_empty() {
+                    writeln!(self.out, "{}}} else {{", level)?;
+                    self.put_block(level.next(), reject, context)?;
+                }
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Switch { selector, ref cases, ref default } => {
+                write!(self.out, "{}switch (", level)?;
+                self.put_expression(selector, context)?;
+                writeln!(self.out, ") {{")?;
+                let lcase = level.next();
+                for &(value, ref block, ref fall_through) in cases.iter() {
+                    writeln!(self.out, "{}case {}: {{", lcase, value)?;
+                    self.put_block(lcase.next(), block, context)?;
+                    if fall_through.is_some() {
+                        writeln!(self.out, "{}fallthrough;", lcase.next())?;
+                    }
+                    writeln!(self.out, "{}}}", lcase)?;
+                }
+                writeln!(self.out, "{}default: {{", lcase)?;
+                self.put_block(lcase.next(), default, context)?;
+                writeln!(self.out, "{}}}", lcase)?;
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Loop { ref body, ref continuing } => {
+                writeln!(self.out, "{}loop {{", level)?;
+                self.put_block(level.next(), body, context)?;
+                if !continuing.is_empty() {
+                    writeln!(self.out, "{}continuing {{", level.next())?;
+                    self.put_block(level.next().next(), continuing, context)?;
+                    writeln!(self.out, "{}}}", level.next())?;
+                }
+                writeln!(self.out, "{}}}", level)?;
+            }
+            crate::Statement::Break => {
+                writeln!(self.out, "{}break;", level)?;
+            }
+            crate::Statement::Continue => {
+                writeln!(self.out, "{}continue;", level)?;
+            }
+            crate::Statement::Return { value } => {
+                write!(self.out, "{}return", level)?;
+                if let Some(expr_token) = value {
+                    write!(self.out, " ")?;
+                    self.put_expression(expr_token, context)?;
+                }
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::Kill => {
+                writeln!(self.out, "{}discard;", level)?;
+            }
+            crate::Statement::Store { pointer, value } => {
+                write!(self.out, "{}", level)?;
+                self.put_expression(pointer, context)?;
+                write!(self.out, " = ")?;
+                self.put_expression(value, context)?;
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::ImageStore { image, coordinate, value } => {
+                write!(self.out, "{}textureStore", level)?;
+                let mut arguments = self.texel_arguments(image, coordinate, context)?;
+                arguments.push(value);
+                self.put_arguments(&arguments, context)?;
+                writeln!(self.out, ";")?;
+            }
+            crate::Statement::Call { function, ref arguments } => {
+                write!(self.out, "{}{}", level, self.names[&NameKey::Function(function)])?;
+                self.put_arguments(arguments, context)?;
+                writeln!(self.out, ";")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_function(&mut self, token: Token<crate::Function>, module: &crate::Module) -> Result<(), Error> {
+        let fun = &module.functions[token];
+        write!(self.out, "fn {}(", self.names[&NameKey::Function(token)])?;
+        for (index, &ty) in fun.parameter_types.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            let key = NameKey::FunctionParameter(token, index as u32);
+            write!(self.out, "{} : ", self.names[&key])?;
+            self.put_type(ty, module)?;
+        }
+        write!(self.out, ") -> ")?;
+        match fun.return_type {
+            Some(ty) => self.put_type(ty, module)?,
+            None => write!(self.out, "void")?,
+        }
+        writeln!(self.out, " {{")?;
+
+        self.typifier.resolve_all(&fun.expressions, &ResolveContext {
+            types: &module.types,
+            constants: &module.constants,
+            global_vars: &module.global_variables,
+            local_vars: &fun.local_variables,
+            functions: &module.functions,
+            parameter_types: &fun.parameter_types,
+        })?;
+        let level = Level(1);
+        for (local_token, local) in fun.local_variables.iter() {
+            let key = NameKey::LocalVariable(token, local_token);
+            write!(self.out, "{}var {} : ", level, self.names[&key])?;
+            self.put_type(local.ty, module)?;
+            if let Some(init) = local.init {
+                write!(self.out, " = ")?;
+                self.put_constant(init, module)?;
+            }
+            writeln!(self.out, ";")?;
+        }
+        let context = FunctionContext {
+            token,
+            function: fun,
+            module,
+        };
+        self.put_block(level, &fun.body, &context)?;
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    pub fn write(&mut self, module: &crate::Module) -> Result<(), Error> {
+        self.names = assign_names(module, &mut Namer::new(RESERVED_NAMES, &[]));
+
+        let uses_math = module.functions.iter().any(|(_, fun)| {
+            fun.expressions
+                .iter()
+                .any(|(_, expr)| matches!(*expr, crate::Expression::Math { .. }))
+        });
+        if uses_math {
+            writeln!(self.out, "import \"GLSL.std.450\" as {};", NAME_STD)?;
+            writeln!(self.out)?;
+        }
+
+        // the buffers are declared with the block structures
+        let block_types = module.global_variables
+            .iter()
+            .filter(|&(_, var)| matches!(var.class, spirv::StorageClass::Uniform | spirv::StorageClass::StorageBuffer))
+            .map(|(_, var)| global_value_type(var, module))
+            .collect::<Vec<_>>();
+
+        // structures are the only types that need declaring,
+        // everything else is spelled out at the point of use
+        for (token, ty) in module.types.iter() {
+            if let crate::TypeInner::Struct { ref members } = ty.inner {
+                write!(self.out, "type {} = ", self.names[&NameKey::Type(token)])?;
+                if block_types.contains(&token) {
+                    write!(self.out, "[[block]] ")?;
+                }
+                writeln!(self.out, "struct {{")?;
+                for (index, member) in members.iter().enumerate() {
+                    let key = NameKey::StructMember(token, index as u32);
+                    write!(self.out, "{}", Level(1))?;
+                    if let Some(offset) = member.offset {
+                        write!(self.out, "[[offset {}]] ", offset)?;
+                    }
+                    write!(self.out, "{} : ", self.names[&key])?;
+                    self.put_type(member.ty, module)?;
+                    writeln!(self.out, ";")?;
+                }
+                writeln!(self.out, "}};")?;
+                writeln!(self.out)?;
+            }
+        }
+
+        for (token, var) in module.global_variables.iter() {
+            match var.binding {
+                Some(crate::Binding::Location(location)) => {
+                    write!(self.out, "[[location {}]] ", location)?;
+                }
+                Some(crate::Binding::BuiltIn(built_in)) => {
+                    write!(self.out, "[[builtin {}]] ", built_in_string(built_in)?)?;
+                }
+                Some(crate::Binding::Descriptor { set, binding }) => {
+                    write!(self.out, "[[binding {}, set {}]] ", binding, set)?;
+                }
+                None => (),
+            }
+            write!(self.out, "var")?;
+            // private is the default class of global variables
+            if var.class != spirv::StorageClass::Private {
+                write!(self.out, "<{}>", storage_class_string(var.class)?)?;
+            }
+            write!(self.out, " {} : ", self.names[&NameKey::GlobalVariable(token)])?;
+            self.put_type(global_value_type(var, module), module)?;
+            writeln!(self.out, ";")?;
+        }
+        if !module.global_variables.is_empty() {
+            writeln!(self.out)?;
+        }
+
+        // functions need to be declared before they are called
+        let roots = module.functions.iter().map(|(token, _)| token);
+        for token in call_order(module, roots) {
+            self.put_function(token, module)?;
+            writeln!(self.out)?;
+        }
+
+        for ep in module.entry_points.iter() {
+            let stage = match ep.exec_model {
+                spirv::ExecutionModel::Vertex => "vertex",
+                spirv::ExecutionModel::Fragment => "fragment",
+                spirv::ExecutionModel::GLCompute => "compute",
+                other => return Err(Error::UnsupportedExecutionModel(other)),
+            };
+            writeln!(
+                self.out,
+                "entry_point {} as \"{}\" = {};",
+                stage,
+                ep.name,
+                self.names[&NameKey::Function(ep.function)],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn write_string(module: &crate::Module) -> Result<String, Error> {
+    let mut w = Writer::new(String::new());
+    w.write(module)?;
+    Ok(w.finish())
+}
+
+#[cfg(test)]
+mod test {
+    const SOURCE: &str = "
+        import \"GLSL.std.450\" as glsl;
+        [[location 0]] var<in> uv : vec2<f32>;
+        [[location 0]] var<out> color : vec4<f32>;
+        [[builtin frag_coord]] var<in> coord : vec4<f32>;
+        fn scale(value : f32) -> f32 {
+            return value * 0.5;
+        }
+        fn main() -> void {
+            var a : f32 = 1.0;
+            a = scale(glsl::fclamp(a, 0.0, 1.0)) - 2.5;
+            color = vec4<f32>(uv, a, 1.0);
+            return;
+        }
+        entry_point fragment as \"main\" = main;
+    ";
+
+    #[test]
+    fn write_round_trip() {
+        let module = crate::front::wgsl::parse_str(SOURCE).unwrap();
+        let text = super::write_string(&module).unwrap();
+        let module2 = crate::front::wgsl::parse_str(&text).unwrap();
+        assert_eq!(super::write_string(&module2).unwrap(), text);
+        assert_eq!(module2.global_variables.len(), 3);
+        assert_eq!(module2.functions.len(), 2);
+        let ep = &module2.entry_points[0];
+        assert_eq!(ep.name, "main");
+        assert_eq!(ep.exec_model, spirv::ExecutionModel::Fragment);
+    }
+
+    #[test]
+    fn write_from_spirv() {
+        let module = crate::front::wgsl::parse_str(SOURCE).unwrap();
+        let words = crate::back::spv::write_vec(&module, &crate::back::spv::Options { debug_names: true }).unwrap();
+        let module = crate::front::spirv::Parser::new(words.into_iter()).parse().unwrap();
+        let text = super::write_string(&module).unwrap();
+        let module2 = crate::front::wgsl::parse_str(&text).unwrap();
+        assert_eq!(module2.entry_points.len(), 1);
+        let (_, var) = module2.global_variables.iter().next().unwrap();
+        assert_eq!(var.class, spirv::StorageClass::Input);
+    }
+
+    #[test]
+    fn names() {
+        let mut namer = crate::back::Namer::new(super::RESERVED_NAMES, &[]);
+        assert_eq!(namer.call(Some("foo"), "x"), "foo");
+        assert_eq!(namer.call(Some("foo"), "x"), "foo_1");
+        assert_eq!(namer.call(Some("_in.put"), "x"), "in_put");
+        assert_eq!(namer.call(Some("loop"), "x"), "loop_1");
+        assert_eq!(namer.call(Some("a__b"), "x"), "a_b");
+        assert_eq!(namer.call(None, "global3"), "global3");
+    }
+}
@@ -0,0 +1,197 @@
+use super::{Error, ErrorKind};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Identifier(String),
+    IntLiteral(i64),
+    UintLiteral(u64),
+    FloatLiteral(f64),
+    Punct(&'static str),
+    /// End of a line, which terminates the preprocessor directives.
+    Newline,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lexeme {
+    pub token: Token,
+    pub line: u32,
+    /// Whether the token is separated from the previous one, which tells
+    /// the function-like macro definitions from the object-like ones.
+    pub leading_space: bool,
+}
+
+/// Punctuation, the longer sequences preceding their prefixes.
+const PUNCTUATION: &[&str] = &[
+    "<<=", ">>=",
+    "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "^^",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "##",
+    "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "|", "^",
+    "?", ":", ";", ",", ".", "(", ")", "[", "]", "{", "}", "#",
+];
+
+pub struct Lexer<'a> {
+    source: &'a str,
+    offset: usize,
+    line: u32,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Lexer {
+            source,
+            offset: 0,
+            line: 1,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.offset ..]
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error { kind, line: self.line }
+    }
+
+    /// Skips the whitespace and the comments, except for the line breaks.
+    ///
+    /// Returns true if anything was skipped.
+    fn skip_whitespace(&mut self) -> Result<bool, Error> {
+        let start = self.offset;
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("\\\n") {
+                // line continuation
+                self.offset += 2;
+                self.line += 1;
+            } else if rest.starts_with("\\\r\n") {
+                self.offset += 3;
+                self.line += 1;
+            } else if rest.starts_with("//") {
+                self.offset += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                let end = comment
+                    .find("*/")
+                    .ok_or_else(|| self.error(ErrorKind::UnterminatedComment))?;
+                self.line += comment[.. end].matches('\n').count() as u32;
+                self.offset += end + 4;
+            } else {
+                match rest.chars().next() {
+                    Some(c) if c != '\n' && c.is_whitespace() => self.offset += c.len_utf8(),
+                    _ => break,
+                }
+            }
+        }
+        Ok(self.offset != start)
+    }
+
+    fn number(&mut self) -> Result<Token, Error> {
+        let rest = self.rest();
+        if let Some(hex) = rest.strip_prefix("0x").or_else(|| rest.strip_prefix("0X")) {
+            let digits = hex.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(hex.len());
+            let value = u64::from_str_radix(&hex[.. digits], 16)
+                .map_err(|e| self.error(ErrorKind::BadInt(e)))?;
+            self.offset += 2 + digits;
+            return Ok(self.int_suffix(value));
+        }
+
+        let mut length = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let mut is_float = false;
+        if rest[length ..].starts_with('.') {
+            is_float = true;
+            length += 1;
+            length += rest[length ..].find(|c: char| !c.is_ascii_digit()).unwrap_or_else(|| rest.len() - length);
+        }
+        if rest[length ..].starts_with(['e', 'E']) {
+            let mut exponent = length + 1;
+            if rest[exponent ..].starts_with(['+', '-']) {
+                exponent += 1;
+            }
+            let digits = rest[exponent ..].find(|c: char| !c.is_ascii_digit()).unwrap_or_else(|| rest.len() - exponent);
+            if digits != 0 {
+                is_float = true;
+                length = exponent + digits;
+            }
+        }
+        let text = &rest[.. length];
+        self.offset += length;
+
+        if is_float {
+            let value = text.parse().map_err(|e| self.error(ErrorKind::BadFloat(e)))?;
+            for suffix in &["lf", "LF", "f", "F"] {
+                if self.rest().starts_with(suffix) {
+                    self.offset += suffix.len();
+                    break;
+                }
+            }
+            Ok(Token::FloatLiteral(value))
+        } else {
+            let (digits, radix) = if text.len() > 1 && text.starts_with('0') {
+                (&text[1 ..], 8)
+            } else {
+                (text, 10)
+            };
+            let value = u64::from_str_radix(digits, radix)
+                .map_err(|e| self.error(ErrorKind::BadInt(e)))?;
+            Ok(self.int_suffix(value))
+        }
+    }
+
+    fn int_suffix(&mut self, value: u64) -> Token {
+        if self.rest().starts_with(['u', 'U']) {
+            self.offset += 1;
+            Token::UintLiteral(value)
+        } else {
+            Token::IntLiteral(value as i64)
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, Error> {
+        let rest = self.rest();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let starts_number = c.is_ascii_digit() ||
+            (c == '.' && rest[1 ..].starts_with(|c: char| c.is_ascii_digit()));
+        if starts_number {
+            return self.number().map(Some);
+        }
+        if c == '\n' {
+            self.offset += 1;
+            return Ok(Some(Token::Newline));
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            self.offset += length;
+            return Ok(Some(Token::Identifier(rest[.. length].to_owned())));
+        }
+        match PUNCTUATION.iter().find(|&p| rest.starts_with(p)) {
+            Some(punct) => {
+                self.offset += punct.len();
+                Ok(Some(Token::Punct(punct)))
+            }
+            None => Err(self.error(ErrorKind::UnexpectedCharacter(c))),
+        }
+    }
+
+    /// Splits the whole source into tokens.
+    pub fn tokenize(mut self) -> Result<Vec<Lexeme>, Error> {
+        let mut lexemes = Vec::new();
+        loop {
+            let leading_space = self.skip_whitespace()?;
+            let line = self.line;
+            match self.next_token()? {
+                Some(token) => {
+                    if token == Token::Newline {
+                        self.line += 1;
+                    }
+                    lexemes.push(Lexeme { token, line, leading_space });
+                }
+                None => break,
+            }
+        }
+        Ok(lexemes)
+    }
+}
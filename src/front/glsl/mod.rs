@@ -0,0 +1,277 @@
+//! Front-end for the Vulkan flavor of GLSL, version 450.
+//!
+//! The source goes through the lexer and the preprocessor, which expands
+//! the macros and the conditional sections, before being parsed.
+//! One shader stage is parsed at a time: `main` becomes the entry point.
+
+mod lex;
+mod parser;
+mod preprocess;
+
+use crate::FastHashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnterminatedComment,
+    BadInt(std::num::ParseIntError),
+    BadFloat(std::num::ParseFloatError),
+    UnexpectedCharacter(char),
+    BadDirective(String),
+    BadMacroArguments(String),
+    BadTokenPaste,
+    UnbalancedConditional,
+    ErrorDirective(String),
+    MissingVersion,
+    UnsupportedVersion(i64, Option<String>),
+    UnexpectedToken { expected: &'static str, found: String },
+    UnexpectedEnd,
+    UnknownIdentifier(String),
+    UnknownType(String),
+    UnknownFunction(String),
+    UnknownField(String),
+    UnknownLayoutQualifier(String),
+    Redefinition(String),
+    UnsupportedType(String),
+    UnsupportedFeature(&'static str),
+    UnsupportedConversion,
+    InvalidOperands(&'static str),
+    InvalidArguments(String),
+    InvalidIndex(u32),
+    InvalidArraySize,
+    InvalidCaseLabel,
+    InvalidFallThrough,
+    InvalidInitializer(String),
+    TypeMismatch,
+    NotAssignable,
+    VoidValue,
+    MissingLocation(String),
+    MissingFunctionBody(String),
+    MissingEntryPoint,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    Resolve(crate::proc::ResolveError),
+}
+
+/// Error with the source line it occurred on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Stage of the shader, and the name given to its `main` function.
+    pub entry_point: (spirv::ExecutionModel, String),
+    /// Macros defined before the source, as with `-D` on the command line.
+    pub defines: FastHashMap<String, String>,
+}
+
+pub fn parse_str(source: &str, options: &Options) -> Result<crate::Module, Error> {
+    let lexemes = lex::Lexer::new(source).tokenize()?;
+    let mut preprocessor = preprocess::Preprocessor::new();
+    for (name, value) in options.defines.iter() {
+        preprocessor.define(name, value)?;
+    }
+    let output = preprocessor.process(lexemes)?;
+    match output.version {
+        Some((450, ref profile)) |
+        Some((460, ref profile)) if profile.as_deref().map_or(true, |p| p == "core") => (),
+        Some((version, profile)) => {
+            return Err(Error {
+                kind: ErrorKind::UnsupportedVersion(version, profile),
+                line: 1,
+            });
+        }
+        None => {
+            return Err(Error {
+                kind: ErrorKind::MissingVersion,
+                line: 1,
+            });
+        }
+    }
+    parser::Parser::new(output.tokens, options).parse()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_str, ErrorKind, Options};
+
+    fn options(model: spirv::ExecutionModel) -> Options {
+        Options {
+            entry_point: (model, "main".to_owned()),
+            defines: Default::default(),
+        }
+    }
+
+    fn parse_valid(source: &str, model: spirv::ExecutionModel) -> crate::Module {
+        let module = parse_str(source, &options(model)).unwrap();
+        assert_eq!(crate::valid::Validator::new().validate(&module), Ok(()));
+        module
+    }
+
+    #[test]
+    fn vertex_shader() {
+        let module = parse_valid("
+            #version 450
+            layout(set = 0, binding = 0) uniform Locals {
+                mat4 transform;
+                float scale;
+            };
+            layout(location = 0) in vec3 a_position;
+            layout(location = 1) in vec2 a_uv;
+            layout(location = 0) out vec2 v_uv;
+
+            void main() {
+                v_uv = a_uv;
+                gl_Position = transform * vec4(a_position * scale, 1.0);
+            }
+        ", spirv::ExecutionModel::Vertex);
+        let entry_point = &module.entry_points[0];
+        assert_eq!(entry_point.name, "main");
+        assert_eq!(entry_point.exec_model, spirv::ExecutionModel::Vertex);
+        // the inputs, the outputs and the position
+        assert_eq!(entry_point.inputs.len() + entry_point.outputs.len(), 4);
+        let locals = module.global_variables
+            .iter()
+            .find(|&(_, var)| var.class == spirv::StorageClass::Uniform)
+            .map(|(_, var)| var)
+            .unwrap();
+        assert_eq!(locals.binding, Some(crate::Binding::Descriptor { set: 0, binding: 0 }));
+        assert!(module.global_variables
+            .iter()
+            .any(|(_, var)| var.binding == Some(crate::Binding::BuiltIn(spirv::BuiltIn::Position))));
+    }
+
+    #[test]
+    fn fragment_shader() {
+        let module = parse_valid("
+            #version 450
+            layout(set = 0, binding = 1) uniform texture2D u_texture;
+            layout(set = 0, binding = 2) uniform sampler u_sampler;
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 0) out vec4 o_color;
+
+            vec4 tint(vec4 color, float amount) {
+                return mix(color, vec4(1.0, 0.0, 0.0, 1.0), amount);
+            }
+
+            void main() {
+                vec4 color = texture(sampler2D(u_texture, u_sampler), v_uv);
+                o_color = tint(color, 0.5);
+                if (o_color.a < 0.1) {
+                    discard;
+                }
+            }
+        ", spirv::ExecutionModel::Fragment);
+        assert_eq!(module.functions.len(), 2);
+        let (_, main) = module.functions
+            .iter()
+            .find(|&(_, fun)| fun.name.as_deref() == Some("main"))
+            .unwrap();
+        assert!(main.expressions
+            .iter()
+            .any(|(_, expr)| matches!(*expr, crate::Expression::ImageSample { .. })));
+    }
+
+    #[test]
+    fn control_flow() {
+        parse_valid("
+            #version 450
+            layout(location = 0) flat in int v_mode;
+            layout(location = 0) out vec4 o_color;
+
+            void accumulate(inout float total, float value) {
+                total += value;
+            }
+
+            void main() {
+                float total = 0.0;
+                for (int i = 0; i < 4; ++i) {
+                    if (i == v_mode) {
+                        continue;
+                    }
+                    accumulate(total, float(2));
+                }
+                int j = 0;
+                while (true) {
+                    j++;
+                    if (j > 10) break;
+                }
+                do {
+                    total *= 0.5;
+                } while (total > 1.0);
+                switch (v_mode) {
+                    case 0:
+                    case 1:
+                        total = 1.0;
+                        break;
+                    case 2:
+                        total = -total;
+                        break;
+                    default:
+                        total = total > 0.0 ? total : 0.0;
+                }
+                o_color = vec4(total);
+            }
+        ", spirv::ExecutionModel::Fragment);
+    }
+
+    #[test]
+    fn preprocessor() {
+        let source = "
+            #version 450
+            #define SCALE(x) ((x) * 2.0)
+            #ifdef USE_RED
+            #define COLOR vec4(SCALE(0.5), 0.0, 0.0, 1.0)
+            #elif defined(USE_GREEN) && USE_GREEN > 1
+            #define COLOR vec4(0.0, 1.0, 0.0, 1.0)
+            #else
+            #error no color
+            #endif
+            layout(location = 0) out vec4 o_color;
+            void main() {
+                o_color = COLOR;
+            }
+        ";
+        let mut options = options(spirv::ExecutionModel::Fragment);
+        let error = parse_str(source, &options).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ErrorDirective("no color".to_owned()));
+        assert_eq!(error.line, 9);
+
+        options.defines.insert("USE_GREEN".to_owned(), "2".to_owned());
+        parse_str(source, &options).unwrap();
+        options.defines.insert("USE_RED".to_owned(), String::new());
+        let module = parse_str(source, &options).unwrap();
+        assert_eq!(crate::valid::Validator::new().validate(&module), Ok(()));
+    }
+
+    #[test]
+    fn errors() {
+        let options = options(spirv::ExecutionModel::Fragment);
+        let error = parse_str("void main() {}", &options).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingVersion);
+
+        let error = parse_str("#version 330\nvoid main() {}", &options).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnsupportedVersion(330, None));
+
+        let error = parse_str("
+            #version 450
+            void main() {
+                float a = 1.0;
+                a = b;
+            }
+        ", &options).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownIdentifier("b".to_owned()));
+        assert_eq!(error.line, 5);
+
+        let error = parse_str("
+            #version 450
+            void main() {
+                vec2 v = vec2(1.0);
+                float c = v.z;
+            }
+        ", &options).unwrap_err();
+        assert_eq!(error.line, 5);
+    }
+}
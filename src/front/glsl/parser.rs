@@ -0,0 +1,2782 @@
+use super::{
+    lex::{self, Lexeme},
+    preprocess::{binary_precedence, token_text},
+    Error, ErrorKind, Options,
+};
+use crate::{
+    proc::{ResolveContext, Typifier},
+    storage::{Storage, Token},
+    FastHashMap,
+};
+
+/// Start of an expression, which decides how it's read and assigned to.
+#[derive(Clone, Copy, Debug)]
+enum Root {
+    /// Pointer to a function variable, which has to be loaded to be read.
+    Pointer(Token<crate::Expression>),
+    /// Global variable, which is both the value and the reference to it.
+    Reference(Token<crate::Expression>),
+    Value(Token<crate::Expression>),
+    /// Result of a call to a function not returning anything.
+    Void,
+}
+
+#[derive(Clone, Debug)]
+enum Access {
+    Index(u32),
+    Dynamic(Token<crate::Expression>),
+    /// Selection of several vector components, which is always the last access.
+    Swizzle(Vec<u32>),
+}
+
+/// Parsed expression, with the accesses applied lazily, so that
+/// the same syntax can be used for reading and for assigning.
+#[derive(Clone, Debug)]
+struct Place {
+    root: Root,
+    path: Vec<Access>,
+}
+
+impl Place {
+    fn value(expr: Token<crate::Expression>) -> Self {
+        Place {
+            root: Root::Value(expr),
+            path: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum GlobalSymbol {
+    Variable(Token<crate::GlobalVariable>),
+    /// Member of an interface block declared without an instance name.
+    Member(Token<crate::GlobalVariable>, u32),
+    Constant(Token<crate::Constant>),
+    /// Constant that isn't a literal, which is parsed again at each use.
+    Expression {
+        start: usize,
+        end: usize,
+        ty: Token<crate::Type>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ParameterQualifier {
+    In,
+    Out,
+    InOut,
+}
+
+#[derive(Debug)]
+struct FunctionInfo {
+    token: Token<crate::Function>,
+    parameters: Vec<(ParameterQualifier, Token<crate::Type>)>,
+    return_type: Option<Token<crate::Type>>,
+    defined: bool,
+    used: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StorageQualifier {
+    In,
+    Out,
+    InOut,
+    Uniform,
+    Buffer,
+    Shared,
+    Const,
+}
+
+#[derive(Debug, Default)]
+struct Layout {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    push_constant: bool,
+}
+
+#[derive(Debug, Default)]
+struct Qualifiers {
+    storage: Option<StorageQualifier>,
+    layout: Layout,
+    readonly: bool,
+    writeonly: bool,
+}
+
+enum BuiltinType {
+    Inner(crate::TypeInner),
+    Image {
+        kind: crate::ScalarKind,
+        dim: spirv::Dim,
+        arrayed: bool,
+        multisampled: bool,
+        sampled: bool,
+    },
+    /// Type that can't be represented in the IR.
+    Unsupported,
+}
+
+const ASSIGNMENT_OPERATORS: &[&str] = &[
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "|=", "^=",
+];
+
+/// Image formats of the storage images, which aren't represented in the IR.
+const IMAGE_FORMATS: &[&str] = &[
+    "rgba32f", "rgba16f", "rg32f", "rg16f", "r11f_g11f_b10f", "r32f", "r16f",
+    "rgba16", "rgb10_a2", "rgba8", "rg16", "rg8", "r16", "r8",
+    "rgba16_snorm", "rgba8_snorm", "rg16_snorm", "rg8_snorm", "r16_snorm", "r8_snorm",
+    "rgba32i", "rgba16i", "rgba8i", "rg32i", "rg16i", "rg8i", "r32i", "r16i", "r8i",
+    "rgba32ui", "rgba16ui", "rgb10_a2ui", "rgba8ui", "rg32ui", "rg16ui", "rg8ui", "r32ui", "r16ui", "r8ui",
+];
+
+/// Layout qualifiers that have no effect on the IR.
+const IGNORED_LAYOUT_QUALIFIERS: &[&str] = &[
+    "std140", "std430", "shared", "packed", "row_major", "column_major",
+    "origin_upper_left", "pixel_center_integer", "early_fragment_tests",
+    "offset", "align", "component", "index", "constant_id",
+    "local_size_x", "local_size_y", "local_size_z",
+];
+
+fn vector_size(text: &str) -> Option<crate::VectorSize> {
+    match text {
+        "2" => Some(crate::VectorSize::Bi),
+        "3" => Some(crate::VectorSize::Tri),
+        "4" => Some(crate::VectorSize::Quad),
+        _ => None,
+    }
+}
+
+fn vector_size_of(count: usize) -> Option<crate::VectorSize> {
+    match count {
+        2 => Some(crate::VectorSize::Bi),
+        3 => Some(crate::VectorSize::Tri),
+        4 => Some(crate::VectorSize::Quad),
+        _ => None,
+    }
+}
+
+fn scalar_width(kind: crate::ScalarKind) -> crate::Bytes {
+    match kind {
+        crate::ScalarKind::Bool => 1,
+        _ => 32,
+    }
+}
+
+/// Returns the dimension, arrayness and multisampling of an image type suffix.
+fn image_shape(shape: &str) -> Option<(spirv::Dim, bool, bool)> {
+    Some(match shape {
+        "1D" => (spirv::Dim::Dim1D, false, false),
+        "1DArray" => (spirv::Dim::Dim1D, true, false),
+        "2D" => (spirv::Dim::Dim2D, false, false),
+        "2DArray" => (spirv::Dim::Dim2D, true, false),
+        "2DMS" => (spirv::Dim::Dim2D, false, true),
+        "2DMSArray" => (spirv::Dim::Dim2D, true, true),
+        "2DRect" => (spirv::Dim::DimRect, false, false),
+        "3D" => (spirv::Dim::Dim3D, false, false),
+        "Cube" => (spirv::Dim::DimCube, false, false),
+        "CubeArray" => (spirv::Dim::DimCube, true, false),
+        "Buffer" => (spirv::Dim::DimBuffer, false, false),
+        _ => return None,
+    })
+}
+
+/// Checks if the name is one of the combined image sampler types, like `sampler2D`.
+fn is_combined_sampler(name: &str) -> bool {
+    let shape = name
+        .strip_prefix("sampler")
+        .or_else(|| name.strip_prefix("isampler"))
+        .or_else(|| name.strip_prefix("usampler"));
+    match shape {
+        Some(shape) => image_shape(shape.strip_suffix("Shadow").unwrap_or(shape)).is_some(),
+        None => false,
+    }
+}
+
+fn builtin_type(name: &str) -> Option<BuiltinType> {
+    use crate::ScalarKind as Sk;
+    let scalar = |kind| {
+        Some(BuiltinType::Inner(crate::TypeInner::Scalar {
+            kind,
+            width: scalar_width(kind),
+        }))
+    };
+    match name {
+        "float" => return scalar(Sk::Float),
+        "int" => return scalar(Sk::Sint),
+        "uint" => return scalar(Sk::Uint),
+        "bool" => return scalar(Sk::Bool),
+        "sampler" => return Some(BuiltinType::Inner(crate::TypeInner::Sampler { comparison: false })),
+        "samplerShadow" => return Some(BuiltinType::Inner(crate::TypeInner::Sampler { comparison: true })),
+        "double" | "atomic_uint" => return Some(BuiltinType::Unsupported),
+        _ => (),
+    }
+    for &(prefix, kind) in &[("vec", Sk::Float), ("ivec", Sk::Sint), ("uvec", Sk::Uint), ("bvec", Sk::Bool)] {
+        if let Some(size) = name.strip_prefix(prefix).and_then(vector_size) {
+            return Some(BuiltinType::Inner(crate::TypeInner::Vector {
+                size,
+                kind,
+                width: scalar_width(kind),
+            }));
+        }
+    }
+    if let Some(dims) = name.strip_prefix("mat") {
+        let (columns, rows) = match dims.split_once('x') {
+            Some((columns, rows)) => (vector_size(columns)?, vector_size(rows)?),
+            None => {
+                let size = vector_size(dims)?;
+                (size, size)
+            }
+        };
+        return Some(BuiltinType::Inner(crate::TypeInner::Matrix {
+            columns,
+            rows,
+            kind: Sk::Float,
+            width: 32,
+        }));
+    }
+    let prefixes = &[
+        ("texture", Sk::Float, true),
+        ("itexture", Sk::Sint, true),
+        ("utexture", Sk::Uint, true),
+        ("image", Sk::Float, false),
+        ("iimage", Sk::Sint, false),
+        ("uimage", Sk::Uint, false),
+    ];
+    for &(prefix, kind, sampled) in prefixes {
+        if let Some((dim, arrayed, multisampled)) = name.strip_prefix(prefix).and_then(image_shape) {
+            return Some(BuiltinType::Image { kind, dim, arrayed, multisampled, sampled });
+        }
+    }
+    let is_double = name.strip_prefix("dvec").and_then(vector_size).is_some() ||
+        name.strip_prefix("dmat").map_or(false, |dims| {
+            dims.split('x').all(|size| vector_size(size).is_some())
+        });
+    let is_subpass = ["subpassInput", "subpassInputMS"]
+        .iter()
+        .any(|&s| name.trim_start_matches(['i', 'u']) == s);
+    if is_double || is_subpass || is_combined_sampler(name) {
+        return Some(BuiltinType::Unsupported);
+    }
+    None
+}
+
+/// Maps a GLSL built-in function to a math function, depending on the kind of the arguments.
+fn math_function(name: &str, kind: crate::ScalarKind) -> Option<crate::MathFunction> {
+    use crate::{MathFunction as Mf, ScalarKind as Sk};
+    Some(match (name, kind) {
+        ("round", Sk::Float) => Mf::Round,
+        ("roundEven", Sk::Float) => Mf::RoundEven,
+        ("trunc", Sk::Float) => Mf::Trunc,
+        ("abs", Sk::Float) => Mf::FAbs,
+        ("abs", Sk::Sint) => Mf::SAbs,
+        ("sign", Sk::Float) => Mf::FSign,
+        ("sign", Sk::Sint) => Mf::SSign,
+        ("floor", Sk::Float) => Mf::Floor,
+        ("ceil", Sk::Float) => Mf::Ceil,
+        ("fract", Sk::Float) => Mf::Fract,
+        ("radians", Sk::Float) => Mf::Radians,
+        ("degrees", Sk::Float) => Mf::Degrees,
+        ("sin", Sk::Float) => Mf::Sin,
+        ("cos", Sk::Float) => Mf::Cos,
+        ("tan", Sk::Float) => Mf::Tan,
+        ("asin", Sk::Float) => Mf::Asin,
+        ("acos", Sk::Float) => Mf::Acos,
+        ("atan", Sk::Float) => Mf::Atan,
+        ("sinh", Sk::Float) => Mf::Sinh,
+        ("cosh", Sk::Float) => Mf::Cosh,
+        ("tanh", Sk::Float) => Mf::Tanh,
+        ("asinh", Sk::Float) => Mf::Asinh,
+        ("acosh", Sk::Float) => Mf::Acosh,
+        ("atanh", Sk::Float) => Mf::Atanh,
+        ("pow", Sk::Float) => Mf::Pow,
+        ("exp", Sk::Float) => Mf::Exp,
+        ("log", Sk::Float) => Mf::Log,
+        ("exp2", Sk::Float) => Mf::Exp2,
+        ("log2", Sk::Float) => Mf::Log2,
+        ("sqrt", Sk::Float) => Mf::Sqrt,
+        ("inversesqrt", Sk::Float) => Mf::InverseSqrt,
+        ("determinant", Sk::Float) => Mf::Determinant,
+        ("inverse", Sk::Float) => Mf::MatrixInverse,
+        ("min", Sk::Float) => Mf::FMin,
+        ("min", Sk::Sint) => Mf::SMin,
+        ("min", Sk::Uint) => Mf::UMin,
+        ("max", Sk::Float) => Mf::FMax,
+        ("max", Sk::Sint) => Mf::SMax,
+        ("max", Sk::Uint) => Mf::UMax,
+        ("clamp", Sk::Float) => Mf::FClamp,
+        ("clamp", Sk::Sint) => Mf::SClamp,
+        ("clamp", Sk::Uint) => Mf::UClamp,
+        ("mix", Sk::Float) => Mf::FMix,
+        ("step", Sk::Float) => Mf::Step,
+        ("smoothstep", Sk::Float) => Mf::SmoothStep,
+        ("fma", Sk::Float) => Mf::Fma,
+        ("ldexp", Sk::Float) => Mf::Ldexp,
+        ("packSnorm4x8", Sk::Float) => Mf::PackSnorm4x8,
+        ("packUnorm4x8", Sk::Float) => Mf::PackUnorm4x8,
+        ("packSnorm2x16", Sk::Float) => Mf::PackSnorm2x16,
+        ("packUnorm2x16", Sk::Float) => Mf::PackUnorm2x16,
+        ("packHalf2x16", Sk::Float) => Mf::PackHalf2x16,
+        ("unpackSnorm2x16", Sk::Uint) => Mf::UnpackSnorm2x16,
+        ("unpackUnorm2x16", Sk::Uint) => Mf::UnpackUnorm2x16,
+        ("unpackHalf2x16", Sk::Uint) => Mf::UnpackHalf2x16,
+        ("unpackSnorm4x8", Sk::Uint) => Mf::UnpackSnorm4x8,
+        ("unpackUnorm4x8", Sk::Uint) => Mf::UnpackUnorm4x8,
+        ("length", Sk::Float) => Mf::Length,
+        ("distance", Sk::Float) => Mf::Distance,
+        ("cross", Sk::Float) => Mf::Cross,
+        ("normalize", Sk::Float) => Mf::Normalize,
+        ("faceforward", Sk::Float) => Mf::FaceForward,
+        ("reflect", Sk::Float) => Mf::Reflect,
+        ("refract", Sk::Float) => Mf::Refract,
+        ("findLSB", Sk::Sint) |
+        ("findLSB", Sk::Uint) => Mf::FindILsb,
+        ("findMSB", Sk::Sint) => Mf::FindSMsb,
+        ("findMSB", Sk::Uint) => Mf::FindUMsb,
+        _ => return None,
+    })
+}
+
+fn scalar_kind(inner: &crate::TypeInner) -> Option<crate::ScalarKind> {
+    match *inner {
+        crate::TypeInner::Scalar { kind, .. } |
+        crate::TypeInner::Vector { kind, .. } |
+        crate::TypeInner::Matrix { kind, .. } => Some(kind),
+        _ => None,
+    }
+}
+
+/// Converts a constant to another scalar kind.
+///
+/// Implicit conversions only go from the signed integers to the unsigned
+/// ones and from the integers to the floats, as in GLSL.
+fn convert_constant(
+    inner: &crate::ConstantInner,
+    kind: crate::ScalarKind,
+    explicit: bool,
+) -> Option<crate::ConstantInner> {
+    use crate::{ConstantInner as Ci, ScalarKind as Sk};
+    Some(match (inner, kind) {
+        (&Ci::Sint(value), Sk::Uint) if value >= 0 || explicit => Ci::Uint(value as u64),
+        (&Ci::Sint(value), Sk::Float) => Ci::Float(value as f64),
+        (&Ci::Uint(value), Sk::Float) => Ci::Float(value as f64),
+        (&Ci::Uint(value), Sk::Sint) if explicit => Ci::Sint(value as i64),
+        (&Ci::Float(value), Sk::Sint) if explicit => Ci::Sint(value as i64),
+        (&Ci::Float(value), Sk::Uint) if explicit => Ci::Uint(value as u64),
+        (&Ci::Bool(value), Sk::Sint) if explicit => Ci::Sint(value as i64),
+        (&Ci::Bool(value), Sk::Uint) if explicit => Ci::Uint(value as u64),
+        (&Ci::Bool(value), Sk::Float) if explicit => Ci::Float(value as u8 as f64),
+        (&Ci::Sint(value), Sk::Bool) if explicit => Ci::Bool(value != 0),
+        (&Ci::Uint(value), Sk::Bool) if explicit => Ci::Bool(value != 0),
+        (&Ci::Float(value), Sk::Bool) if explicit => Ci::Bool(value != 0.0),
+        _ => return None,
+    })
+}
+
+fn same_constant(a: &crate::ConstantInner, b: &crate::ConstantInner) -> bool {
+    use crate::ConstantInner as Ci;
+    match (a, b) {
+        (&Ci::Sint(a), &Ci::Sint(b)) => a == b,
+        (&Ci::Uint(a), &Ci::Uint(b)) => a == b,
+        (&Ci::Float(a), &Ci::Float(b)) => a.to_bits() == b.to_bits(),
+        (&Ci::Bool(a), &Ci::Bool(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// State of the function being parsed.
+struct Context {
+    function: crate::Function,
+    typifier: Typifier,
+    /// Pointers to the local variables in the nested scopes.
+    scopes: Vec<FastHashMap<String, Token<crate::Expression>>>,
+    globals: FastHashMap<Token<crate::GlobalVariable>, Token<crate::Expression>>,
+    constants: FastHashMap<Token<crate::Constant>, Token<crate::Expression>>,
+    /// Statements produced by the expressions, which precede
+    /// the statement the expressions are parsed for.
+    pending: Vec<crate::Statement>,
+    in_loop: bool,
+    in_switch: bool,
+}
+
+impl Context {
+    fn new(function: crate::Function) -> Self {
+        Context {
+            function,
+            typifier: Typifier::new(),
+            scopes: vec![FastHashMap::default()],
+            globals: FastHashMap::default(),
+            constants: FastHashMap::default(),
+            pending: Vec::new(),
+            in_loop: false,
+            in_switch: false,
+        }
+    }
+
+    fn flush(&mut self, block: &mut crate::Block) {
+        block.append(&mut self.pending);
+    }
+
+    fn append(&mut self, expression: crate::Expression) -> Token<crate::Expression> {
+        self.function.expressions.append(expression)
+    }
+
+    /// Adds an unnamed local variable, returning the pointer to it.
+    fn temporary(&mut self, ty: Token<crate::Type>) -> Token<crate::Expression> {
+        let local = self.function.local_variables.append(crate::LocalVariable {
+            name: None,
+            ty,
+            init: None,
+        });
+        self.append(crate::Expression::LocalVariable(local))
+    }
+}
+
+pub struct Parser<'a> {
+    tokens: Vec<Lexeme>,
+    position: usize,
+    options: &'a Options,
+    module: crate::Module,
+    lookup_type: FastHashMap<String, Token<crate::Type>>,
+    lookup_global: FastHashMap<String, GlobalSymbol>,
+    lookup_function: FastHashMap<String, FunctionInfo>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Lexeme>, options: &'a Options) -> Self {
+        Parser {
+            tokens,
+            position: 0,
+            options,
+            module: crate::Module::generate_empty(),
+            lookup_type: FastHashMap::default(),
+            lookup_global: FastHashMap::default(),
+            lookup_function: FastHashMap::default(),
+        }
+    }
+
+    fn line(&self) -> u32 {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |lexeme| lexeme.line)
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error { kind, line: self.line() }
+    }
+
+    fn unexpected(&self, expected: &'static str) -> Error {
+        match self.tokens.get(self.position) {
+            Some(lexeme) => self.error(ErrorKind::UnexpectedToken {
+                expected,
+                found: token_text(&lexeme.token),
+            }),
+            None => self.error(ErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn peek(&self) -> Option<&lex::Token> {
+        self.tokens.get(self.position).map(|lexeme| &lexeme.token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&lex::Token> {
+        self.tokens.get(self.position + offset).map(|lexeme| &lexeme.token)
+    }
+
+    fn peek_identifier(&self) -> Option<&str> {
+        match self.peek() {
+            Some(lex::Token::Identifier(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(&lex::Token::Punct(p)) if p == punct)
+    }
+
+    fn consume(&mut self, punct: &str) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// Consumes one of the given punctuation tokens.
+    fn consume_any(&mut self, puncts: &[&'static str]) -> Option<&'static str> {
+        let punct = match self.peek() {
+            Some(&lex::Token::Punct(p)) if puncts.contains(&p) => p,
+            _ => return None,
+        };
+        self.position += 1;
+        Some(punct)
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_identifier() == Some(keyword);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<(), Error> {
+        if self.consume(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(punct))
+        }
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, Error> {
+        match self.peek_identifier() {
+            Some(name) => {
+                let name = name.to_owned();
+                self.position += 1;
+                Ok(name)
+            }
+            None => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    /// Skips the tokens up to the end of the expression, which
+    /// is a comma or a semicolon outside of any parentheses.
+    fn skip_expression(&mut self) -> Result<(), Error> {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return Err(self.error(ErrorKind::UnexpectedEnd)),
+                Some(&lex::Token::Punct(",")) |
+                Some(&lex::Token::Punct(";")) if depth == 0 => return Ok(()),
+                Some(&lex::Token::Punct("(")) |
+                Some(&lex::Token::Punct("[")) |
+                Some(&lex::Token::Punct("{")) => depth += 1,
+                Some(&lex::Token::Punct(")")) |
+                Some(&lex::Token::Punct("]")) |
+                Some(&lex::Token::Punct("}")) => depth -= 1,
+                Some(_) => (),
+            }
+            self.position += 1;
+        }
+    }
+
+    /// Skips a block in braces, with the declarators following it.
+    fn skip_block(&mut self) -> Result<(), Error> {
+        self.expect("{")?;
+        let mut depth = 1;
+        while depth != 0 {
+            match self.peek() {
+                None => return Err(self.error(ErrorKind::UnexpectedEnd)),
+                Some(&lex::Token::Punct("{")) => depth += 1,
+                Some(&lex::Token::Punct("}")) => depth -= 1,
+                Some(_) => (),
+            }
+            self.position += 1;
+        }
+        self.skip_expression()?;
+        self.expect(";")
+    }
+
+    fn ensure_type(&mut self, inner: crate::TypeInner) -> Token<crate::Type> {
+        let existing = self.module.types.iter().find(|&(_, ty)| ty.inner == inner).map(|(token, _)| token);
+        match existing {
+            Some(token) => token,
+            None => self.module.types.append(crate::Type { name: None, inner }),
+        }
+    }
+
+    fn is_type_name(&self, name: &str) -> bool {
+        name == "void" ||
+            name == "struct" ||
+            self.lookup_type.contains_key(name) ||
+            builtin_type(name).is_some()
+    }
+
+    fn lookup_type_name(&mut self, name: &str) -> Result<Token<crate::Type>, Error> {
+        if let Some(&ty) = self.lookup_type.get(name) {
+            return Ok(ty);
+        }
+        let inner = match builtin_type(name) {
+            Some(BuiltinType::Inner(inner)) => inner,
+            Some(BuiltinType::Image { kind, dim, arrayed, multisampled, sampled }) => {
+                let base = self.ensure_type(crate::TypeInner::Scalar { kind, width: 32 });
+                let mut flags = if sampled {
+                    crate::ImageFlags::SAMPLED
+                } else {
+                    crate::ImageFlags::CAN_LOAD | crate::ImageFlags::CAN_STORE
+                };
+                flags.set(crate::ImageFlags::ARRAYED, arrayed);
+                flags.set(crate::ImageFlags::MULTISAMPLED, multisampled);
+                crate::TypeInner::Image { base, dim, flags }
+            }
+            Some(BuiltinType::Unsupported) => {
+                return Err(self.error(ErrorKind::UnsupportedType(name.to_owned())));
+            }
+            None => return Err(self.error(ErrorKind::UnknownType(name.to_owned()))),
+        };
+        Ok(self.ensure_type(inner))
+    }
+
+    /// Parses a type, returning `None` for `void`.
+    fn parse_type_specifier(&mut self) -> Result<Option<Token<crate::Type>>, Error> {
+        let name = self.expect_identifier()?;
+        let ty = match name.as_str() {
+            "void" => return Ok(None),
+            "struct" => {
+                let struct_name = self.expect_identifier()?;
+                if self.lookup_type.contains_key(&struct_name) {
+                    return Err(self.error(ErrorKind::Redefinition(struct_name)));
+                }
+                self.expect("{")?;
+                let members = self.parse_struct_members()?;
+                let ty = self.module.types.append(crate::Type {
+                    name: Some(struct_name.clone()),
+                    inner: crate::TypeInner::Struct { members },
+                });
+                self.lookup_type.insert(struct_name, ty);
+                ty
+            }
+            _ => self.lookup_type_name(&name)?,
+        };
+        self.parse_array_suffix(ty).map(Some)
+    }
+
+    fn parse_struct_members(&mut self) -> Result<Vec<crate::StructMember>, Error> {
+        let mut members = Vec::new();
+        while !self.consume("}") {
+            // offsets and precisions of the members have no effect
+            let _ = self.parse_qualifiers()?;
+            let ty = self
+                .parse_type_specifier()?
+                .ok_or_else(|| self.error(ErrorKind::VoidValue))?;
+            loop {
+                let name = self.expect_identifier()?;
+                let ty = self.parse_array_suffix(ty)?;
+                members.push(crate::StructMember {
+                    name: Some(name),
+                    binding: None,
+                    offset: None,
+                    ty,
+                });
+                if !self.consume(",") {
+                    break;
+                }
+            }
+            self.expect(";")?;
+        }
+        Ok(members)
+    }
+
+    fn parse_array_size(&mut self) -> Result<u32, Error> {
+        let size = match self.peek() {
+            Some(&lex::Token::IntLiteral(value)) => value.max(0) as u64,
+            Some(&lex::Token::UintLiteral(value)) => value,
+            Some(lex::Token::Identifier(name)) => match self.lookup_global.get(name) {
+                Some(&GlobalSymbol::Constant(token)) => match self.module.constants[token].inner {
+                    crate::ConstantInner::Sint(value) => value.max(0) as u64,
+                    crate::ConstantInner::Uint(value) => value,
+                    _ => 0,
+                },
+                _ => 0,
+            },
+            _ => 0,
+        };
+        if size == 0 || size > u32::MAX as u64 {
+            return Err(self.error(ErrorKind::InvalidArraySize));
+        }
+        self.position += 1;
+        Ok(size as u32)
+    }
+
+    fn parse_array_suffix(&mut self, mut ty: Token<crate::Type>) -> Result<Token<crate::Type>, Error> {
+        let mut sizes = Vec::new();
+        while self.consume("[") {
+            if self.consume("]") {
+                sizes.push(crate::ArraySize::Dynamic);
+            } else {
+                sizes.push(crate::ArraySize::Static(self.parse_array_size()?));
+                self.expect("]")?;
+            }
+        }
+        // `float a[2][3]` is an array of 2 arrays of 3 floats
+        for size in sizes.into_iter().rev() {
+            ty = self.ensure_type(crate::TypeInner::Array { base: ty, size });
+        }
+        Ok(ty)
+    }
+
+    fn parse_layout(&mut self, layout: &mut Layout) -> Result<(), Error> {
+        self.expect("(")?;
+        loop {
+            let name = self.expect_identifier()?;
+            let value = if self.consume("=") {
+                match self.peek() {
+                    Some(&lex::Token::IntLiteral(value)) if value >= 0 => {
+                        self.position += 1;
+                        Some(value as u32)
+                    }
+                    Some(&lex::Token::UintLiteral(value)) => {
+                        self.position += 1;
+                        Some(value as u32)
+                    }
+                    _ => return Err(self.unexpected("an integer")),
+                }
+            } else {
+                None
+            };
+            match (name.as_str(), value) {
+                ("set", Some(value)) => layout.set = Some(value),
+                ("binding", Some(value)) => layout.binding = Some(value),
+                ("location", Some(value)) => layout.location = Some(value),
+                ("push_constant", None) => layout.push_constant = true,
+                (other, _) if IGNORED_LAYOUT_QUALIFIERS.contains(&other) => {
+                    log::debug!("Ignoring layout qualifier {}", other);
+                }
+                (other, None) if IMAGE_FORMATS.contains(&other) => (),
+                _ => return Err(self.error(ErrorKind::UnknownLayoutQualifier(name))),
+            }
+            if !self.consume(",") {
+                break;
+            }
+        }
+        self.expect(")")
+    }
+
+    fn parse_qualifiers(&mut self) -> Result<Qualifiers, Error> {
+        let mut qualifiers = Qualifiers::default();
+        loop {
+            let storage = match self.peek_identifier() {
+                Some("layout") => {
+                    self.position += 1;
+                    self.parse_layout(&mut qualifiers.layout)?;
+                    continue;
+                }
+                Some("readonly") => {
+                    self.position += 1;
+                    qualifiers.readonly = true;
+                    continue;
+                }
+                Some("writeonly") => {
+                    self.position += 1;
+                    qualifiers.writeonly = true;
+                    continue;
+                }
+                // precision, interpolation and the other memory
+                // qualifiers have no effect on the IR
+                Some("highp") | Some("mediump") | Some("lowp") |
+                Some("flat") | Some("smooth") | Some("noperspective") |
+                Some("centroid") | Some("sample") | Some("invariant") | Some("precise") |
+                Some("coherent") | Some("volatile") | Some("restrict") => {
+                    self.position += 1;
+                    continue;
+                }
+                Some("in") => StorageQualifier::In,
+                Some("out") => StorageQualifier::Out,
+                Some("inout") => StorageQualifier::InOut,
+                Some("uniform") => StorageQualifier::Uniform,
+                Some("buffer") => StorageQualifier::Buffer,
+                Some("shared") => StorageQualifier::Shared,
+                Some("const") => StorageQualifier::Const,
+                _ => break,
+            };
+            if qualifiers.storage.is_some() {
+                return Err(self.unexpected("a single storage qualifier"));
+            }
+            self.position += 1;
+            qualifiers.storage = Some(storage);
+        }
+        Ok(qualifiers)
+    }
+
+    fn storage_class(
+        &self,
+        qualifiers: &Qualifiers,
+        ty: Token<crate::Type>,
+    ) -> Result<spirv::StorageClass, Error> {
+        let is_opaque = matches!(
+            self.module.types[ty].inner,
+            crate::TypeInner::Image { .. } | crate::TypeInner::Sampler { .. }
+        );
+        Ok(match qualifiers.storage {
+            None => spirv::StorageClass::Private,
+            Some(StorageQualifier::In) => spirv::StorageClass::Input,
+            Some(StorageQualifier::Out) => spirv::StorageClass::Output,
+            Some(StorageQualifier::Uniform) if qualifiers.layout.push_constant => spirv::StorageClass::PushConstant,
+            Some(StorageQualifier::Uniform) if is_opaque => spirv::StorageClass::UniformConstant,
+            Some(StorageQualifier::Uniform) => spirv::StorageClass::Uniform,
+            Some(StorageQualifier::Buffer) => spirv::StorageClass::StorageBuffer,
+            Some(StorageQualifier::Shared) => spirv::StorageClass::Workgroup,
+            Some(StorageQualifier::InOut) |
+            Some(StorageQualifier::Const) => return Err(self.unexpected("a global storage qualifier")),
+        })
+    }
+
+    fn binding(
+        &self,
+        qualifiers: &Qualifiers,
+        class: spirv::StorageClass,
+        name: &str,
+    ) -> Result<Option<crate::Binding>, Error> {
+        let layout = &qualifiers.layout;
+        Ok(match class {
+            spirv::StorageClass::Input |
+            spirv::StorageClass::Output => match layout.location {
+                Some(location) => Some(crate::Binding::Location(location)),
+                None => return Err(self.error(ErrorKind::MissingLocation(name.to_owned()))),
+            },
+            spirv::StorageClass::Private |
+            spirv::StorageClass::Workgroup |
+            spirv::StorageClass::PushConstant => None,
+            _ => layout.binding.map(|binding| crate::Binding::Descriptor {
+                set: layout.set.unwrap_or(0),
+                binding,
+            }),
+        })
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub fn parse(mut self) -> Result<crate::Module, Error> {
+        while self.position < self.tokens.len() {
+            self.parse_external_declaration()?;
+        }
+        for (name, info) in self.lookup_function.iter() {
+            if info.used && !info.defined {
+                return Err(self.error(ErrorKind::MissingFunctionBody(name.clone())));
+            }
+        }
+        let function = match self.lookup_function.get("main") {
+            Some(info) if info.defined => info.token,
+            _ => return Err(self.error(ErrorKind::MissingEntryPoint)),
+        };
+        let interface = |class| {
+            self.module.global_variables
+                .iter()
+                .filter(|&(_, var)| var.class == class)
+                .map(|(token, _)| token)
+                .collect::<Vec<_>>()
+        };
+        let inputs = interface(spirv::StorageClass::Input);
+        let outputs = interface(spirv::StorageClass::Output);
+        let (exec_model, ref name) = self.options.entry_point;
+        self.module.entry_points.push(crate::EntryPoint {
+            exec_model,
+            name: name.clone(),
+            inputs,
+            outputs,
+            function,
+        });
+        Ok(self.module)
+    }
+
+    fn parse_external_declaration(&mut self) -> Result<(), Error> {
+        if self.consume(";") {
+            return Ok(());
+        }
+        if self.consume_keyword("precision") {
+            // default precisions have no effect on the IR
+            self.skip_expression()?;
+            return self.expect(";");
+        }
+        let qualifiers = self.parse_qualifiers()?;
+        // declarations like `layout(local_size_x = 64) in;`
+        if self.consume(";") {
+            return Ok(());
+        }
+        // redeclarations like `invariant gl_Position;`
+        if self.peek_identifier().map_or(false, |name| name.starts_with("gl_")) &&
+            self.peek_at(1) == Some(&lex::Token::Punct(";"))
+        {
+            self.position += 2;
+            return Ok(());
+        }
+        let is_block = self.peek_identifier().map_or(false, |name| name != "struct") &&
+            self.peek_at(1) == Some(&lex::Token::Punct("{"));
+        if is_block {
+            return self.parse_interface_block(&qualifiers);
+        }
+
+        let ty = self.parse_type_specifier()?;
+        if self.consume(";") {
+            return Ok(());
+        }
+        let mut name = self.expect_identifier()?;
+        if self.is_punct("(") {
+            return self.parse_function(&qualifiers, ty, name);
+        }
+        let ty = ty.ok_or_else(|| self.error(ErrorKind::VoidValue))?;
+        loop {
+            let var_ty = self.parse_array_suffix(ty)?;
+            self.parse_global_declarator(&qualifiers, var_ty, name)?;
+            if !self.consume(",") {
+                break;
+            }
+            name = self.expect_identifier()?;
+        }
+        self.expect(";")
+    }
+
+    fn parse_global_declarator(
+        &mut self,
+        qualifiers: &Qualifiers,
+        mut ty: Token<crate::Type>,
+        name: String,
+    ) -> Result<(), Error> {
+        if self.lookup_global.contains_key(&name) {
+            return Err(self.error(ErrorKind::Redefinition(name)));
+        }
+        if qualifiers.storage == Some(StorageQualifier::Const) {
+            self.expect("=")?;
+            let start = self.position;
+            self.skip_expression()?;
+            let end = self.position;
+            let symbol = match self.constant_literal(start, end, ty) {
+                Some(inner) => GlobalSymbol::Constant(self.module.constants.append(crate::Constant {
+                    name: Some(name.clone()),
+                    specialization: None,
+                    inner,
+                })),
+                None => GlobalSymbol::Expression { start, end, ty },
+            };
+            self.lookup_global.insert(name, symbol);
+            return Ok(());
+        }
+        if self.is_punct("=") {
+            return Err(self.error(ErrorKind::InvalidInitializer(name)));
+        }
+
+        // storage images are restricted by the memory qualifiers
+        if let crate::TypeInner::Image { base, dim, mut flags } = self.module.types[ty].inner {
+            if !flags.contains(crate::ImageFlags::SAMPLED) {
+                flags.set(crate::ImageFlags::CAN_LOAD, !qualifiers.writeonly);
+                flags.set(crate::ImageFlags::CAN_STORE, !qualifiers.readonly);
+                ty = self.ensure_type(crate::TypeInner::Image { base, dim, flags });
+            }
+        }
+        let class = self.storage_class(qualifiers, ty)?;
+        let binding = self.binding(qualifiers, class, &name)?;
+        let var = self.module.global_variables.append(crate::GlobalVariable {
+            name: Some(name.clone()),
+            class,
+            binding,
+            ty,
+        });
+        self.lookup_global.insert(name, GlobalSymbol::Variable(var));
+        Ok(())
+    }
+
+    /// Returns the value of a constant initializer made of a scalar literal.
+    fn constant_literal(
+        &self,
+        start: usize,
+        end: usize,
+        ty: Token<crate::Type>,
+    ) -> Option<crate::ConstantInner> {
+        let kind = match self.module.types[ty].inner {
+            crate::TypeInner::Scalar { kind, .. } => kind,
+            _ => return None,
+        };
+        let (negative, literal) = match self.tokens[start .. end] {
+            [ref literal] => (false, &literal.token),
+            [ref minus, ref literal] if minus.token == lex::Token::Punct("-") => (true, &literal.token),
+            _ => return None,
+        };
+        let inner = match (literal, negative) {
+            (&lex::Token::IntLiteral(value), false) => crate::ConstantInner::Sint(value),
+            (&lex::Token::IntLiteral(value), true) => crate::ConstantInner::Sint(-value),
+            (&lex::Token::UintLiteral(value), false) => crate::ConstantInner::Uint(value),
+            (&lex::Token::FloatLiteral(value), false) => crate::ConstantInner::Float(value),
+            (&lex::Token::FloatLiteral(value), true) => crate::ConstantInner::Float(-value),
+            (lex::Token::Identifier(name), false) if name == "true" || name == "false" => {
+                crate::ConstantInner::Bool(name == "true")
+            }
+            _ => return None,
+        };
+        match (&inner, kind) {
+            (&crate::ConstantInner::Sint(_), crate::ScalarKind::Sint) |
+            (&crate::ConstantInner::Uint(_), crate::ScalarKind::Uint) |
+            (&crate::ConstantInner::Float(_), crate::ScalarKind::Float) |
+            (&crate::ConstantInner::Bool(_), crate::ScalarKind::Bool) => Some(inner),
+            _ => convert_constant(&inner, kind, false),
+        }
+    }
+
+    fn parse_interface_block(&mut self, qualifiers: &Qualifiers) -> Result<(), Error> {
+        let block_name = self.expect_identifier()?;
+        // the built-in outputs are declared implicitly
+        if block_name == "gl_PerVertex" {
+            return self.skip_block();
+        }
+        match qualifiers.storage {
+            Some(StorageQualifier::In) |
+            Some(StorageQualifier::Out) |
+            Some(StorageQualifier::Uniform) |
+            Some(StorageQualifier::Buffer) => (),
+            _ => return Err(self.unexpected("a type")),
+        }
+        self.expect("{")?;
+        let members = self.parse_struct_members()?;
+        let member_names = members
+            .iter()
+            .map(|member| member.name.clone().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let mut ty = self.module.types.append(crate::Type {
+            name: Some(block_name.clone()),
+            inner: crate::TypeInner::Struct { members },
+        });
+        let instance = match self.peek_identifier() {
+            Some(name) => {
+                let name = name.to_owned();
+                self.position += 1;
+                ty = self.parse_array_suffix(ty)?;
+                Some(name)
+            }
+            None => None,
+        };
+        self.expect(";")?;
+
+        let name = instance.clone().unwrap_or(block_name);
+        let class = self.storage_class(qualifiers, ty)?;
+        let binding = self.binding(qualifiers, class, &name)?;
+        let var = self.module.global_variables.append(crate::GlobalVariable {
+            name: Some(name),
+            class,
+            binding,
+            ty,
+        });
+        match instance {
+            Some(name) => {
+                if self.lookup_global.contains_key(&name) {
+                    return Err(self.error(ErrorKind::Redefinition(name)));
+                }
+                self.lookup_global.insert(name, GlobalSymbol::Variable(var));
+            }
+            // members of the blocks without an instance name are global
+            None => {
+                for (index, name) in member_names.into_iter().enumerate() {
+                    if self.lookup_global.contains_key(&name) {
+                        return Err(self.error(ErrorKind::Redefinition(name)));
+                    }
+                    self.lookup_global.insert(name, GlobalSymbol::Member(var, index as u32));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_function(
+        &mut self,
+        qualifiers: &Qualifiers,
+        return_type: Option<Token<crate::Type>>,
+        name: String,
+    ) -> Result<(), Error> {
+        if qualifiers.storage.is_some() {
+            return Err(self.unexpected("a function return type"));
+        }
+        self.expect("(")?;
+        let mut parameters = Vec::new();
+        let is_empty = self.consume(")") || (
+            self.peek_identifier() == Some("void") &&
+            self.peek_at(1) == Some(&lex::Token::Punct(")")) && {
+                self.position += 2;
+                true
+            }
+        );
+        if !is_empty {
+            loop {
+                let qualifier = match self.parse_qualifiers()?.storage {
+                    None |
+                    Some(StorageQualifier::In) |
+                    Some(StorageQualifier::Const) => ParameterQualifier::In,
+                    Some(StorageQualifier::Out) => ParameterQualifier::Out,
+                    Some(StorageQualifier::InOut) => ParameterQualifier::InOut,
+                    Some(_) => return Err(self.unexpected("a parameter qualifier")),
+                };
+                let ty = self
+                    .parse_type_specifier()?
+                    .ok_or_else(|| self.error(ErrorKind::VoidValue))?;
+                let parameter_name = match self.peek_identifier() {
+                    Some(parameter_name) => {
+                        let parameter_name = parameter_name.to_owned();
+                        self.position += 1;
+                        Some(parameter_name)
+                    }
+                    None => None,
+                };
+                let ty = self.parse_array_suffix(ty)?;
+                parameters.push((qualifier, ty, parameter_name));
+                if !self.consume(",") {
+                    self.expect(")")?;
+                    break;
+                }
+            }
+        }
+
+        let signature = parameters
+            .iter()
+            .map(|&(qualifier, ty, _)| (qualifier, ty))
+            .collect::<Vec<_>>();
+        let existing = match self.lookup_function.get(&name) {
+            // overloads aren't supported, so the declarations have to match
+            Some(info) if info.parameters != signature || info.return_type != return_type => {
+                return Err(self.error(ErrorKind::Redefinition(name)));
+            }
+            Some(info) => Some((info.token, info.defined)),
+            None => None,
+        };
+        if name == "main" && (!signature.is_empty() || return_type.is_some()) {
+            return Err(self.error(ErrorKind::InvalidArguments(name)));
+        }
+
+        let mut parameter_types = Vec::with_capacity(signature.len());
+        for &(qualifier, ty) in signature.iter() {
+            parameter_types.push(match qualifier {
+                ParameterQualifier::In => ty,
+                // output parameters are passed as pointers
+                ParameterQualifier::Out |
+                ParameterQualifier::InOut => self.ensure_type(crate::TypeInner::Pointer {
+                    base: ty,
+                    class: spirv::StorageClass::Function,
+                }),
+            });
+        }
+        let function = crate::Function {
+            name: Some(name.clone()),
+            control: spirv::FunctionControl::NONE,
+            parameter_types,
+            return_type,
+            local_variables: Storage::new(),
+            expressions: Storage::new(),
+            body: Vec::new(),
+            spans: Default::default(),
+        };
+
+        if self.consume(";") {
+            if existing.is_none() {
+                let token = self.module.functions.append(function);
+                self.lookup_function.insert(name, FunctionInfo {
+                    token,
+                    parameters: signature,
+                    return_type,
+                    defined: false,
+                    used: false,
+                });
+            }
+            return Ok(());
+        }
+        if let Some((_, true)) = existing {
+            return Err(self.error(ErrorKind::Redefinition(name)));
+        }
+
+        let mut ctx = Context::new(function);
+        let mut body = Vec::new();
+        for (index, (qualifier, ty, parameter_name)) in parameters.into_iter().enumerate() {
+            let parameter_name = match parameter_name {
+                Some(parameter_name) => parameter_name,
+                None => continue,
+            };
+            let argument = ctx.append(crate::Expression::FunctionParameter(index as u32));
+            let pointer = match qualifier {
+                // input parameters are copied, since they may be assigned to
+                ParameterQualifier::In => {
+                    let local = ctx.function.local_variables.append(crate::LocalVariable {
+                        name: Some(parameter_name.clone()),
+                        ty,
+                        init: None,
+                    });
+                    let pointer = ctx.append(crate::Expression::LocalVariable(local));
+                    body.push(crate::Statement::Store { pointer, value: argument });
+                    pointer
+                }
+                ParameterQualifier::Out |
+                ParameterQualifier::InOut => argument,
+            };
+            ctx.scopes[0].insert(parameter_name, pointer);
+        }
+        self.expect("{")?;
+        self.parse_block(&mut ctx, &mut body)?;
+        if return_type.is_none() {
+            match body.last() {
+                Some(crate::Statement::Return { .. }) => (),
+                _ => body.push(crate::Statement::Return { value: None }),
+            }
+        }
+        ctx.function.body = body;
+
+        let token = match existing {
+            Some((token, _)) => {
+                self.module.functions[token] = ctx.function;
+                token
+            }
+            None => self.module.functions.append(ctx.function),
+        };
+        let used = self.lookup_function.get(&name).map_or(false, |info| info.used);
+        self.lookup_function.insert(name, FunctionInfo {
+            token,
+            parameters: signature,
+            return_type,
+            defined: true,
+            used,
+        });
+        Ok(())
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// Parses the statements up to the closing brace, in a new scope.
+    fn parse_block(&mut self, ctx: &mut Context, block: &mut crate::Block) -> Result<(), Error> {
+        ctx.scopes.push(FastHashMap::default());
+        while !self.consume("}") {
+            if self.peek().is_none() {
+                return Err(self.error(ErrorKind::UnexpectedEnd));
+            }
+            self.parse_statement(ctx, block)?;
+        }
+        ctx.scopes.pop();
+        Ok(())
+    }
+
+    fn parse_scoped_statement(&mut self, ctx: &mut Context, block: &mut crate::Block) -> Result<(), Error> {
+        ctx.scopes.push(FastHashMap::default());
+        self.parse_statement(ctx, block)?;
+        ctx.scopes.pop();
+        Ok(())
+    }
+
+    /// Parses the body of a loop, which allows `break` and `continue`.
+    fn parse_loop_body(&mut self, ctx: &mut Context, block: &mut crate::Block) -> Result<(), Error> {
+        let (in_loop, in_switch) = (ctx.in_loop, ctx.in_switch);
+        ctx.in_loop = true;
+        ctx.in_switch = false;
+        self.parse_scoped_statement(ctx, block)?;
+        ctx.in_loop = in_loop;
+        ctx.in_switch = in_switch;
+        Ok(())
+    }
+
+    fn parse_condition(&mut self, ctx: &mut Context) -> Result<Token<crate::Expression>, Error> {
+        let place = self.parse_conditional(ctx)?;
+        let condition = self.read(ctx, place)?;
+        match self.resolve(ctx, condition)? {
+            crate::TypeInner::Scalar { kind: crate::ScalarKind::Bool, .. } => Ok(condition),
+            _ => Err(self.error(ErrorKind::TypeMismatch)),
+        }
+    }
+
+    /// Returns the statement exiting the loop unless the condition holds.
+    fn loop_exit(condition: Token<crate::Expression>) -> crate::Statement {
+        crate::Statement::If {
+            condition,
+            accept: Vec::new(),
+            reject: vec![crate::Statement::Break],
+        }
+    }
+
+    /// Checks if a declaration starts at the current position.
+    fn is_declaration(&self) -> bool {
+        let name = match self.peek_identifier() {
+            Some(name) => name,
+            None => return false,
+        };
+        match name {
+            "const" | "highp" | "mediump" | "lowp" | "precise" | "struct" => return true,
+            _ if !self.is_type_name(name) => return false,
+            _ => (),
+        }
+        // skip the array sizes, which may also start a constructor
+        let mut offset = 1;
+        while self.peek_at(offset) == Some(&lex::Token::Punct("[")) {
+            while self.peek_at(offset).map_or(false, |t| *t != lex::Token::Punct("]")) {
+                offset += 1;
+            }
+            offset += 1;
+        }
+        matches!(self.peek_at(offset), Some(&lex::Token::Identifier(_)))
+    }
+
+    fn parse_statement(&mut self, ctx: &mut Context, block: &mut crate::Block) -> Result<(), Error> {
+        let keyword = self.peek_identifier().map(str::to_owned);
+        match keyword.as_deref() {
+            Some("if") => {
+                self.position += 1;
+                self.expect("(")?;
+                let condition = self.parse_condition(ctx)?;
+                self.expect(")")?;
+                ctx.flush(block);
+                let mut accept = Vec::new();
+                self.parse_scoped_statement(ctx, &mut accept)?;
+                let mut reject = Vec::new();
+                if self.consume_keyword("else") {
+                    self.parse_scoped_statement(ctx, &mut reject)?;
+                }
+                block.push(crate::Statement::If { condition, accept, reject });
+            }
+            Some("while") => {
+                self.position += 1;
+                self.expect("(")?;
+                let mut body = Vec::new();
+                let condition = self.parse_condition(ctx)?;
+                self.expect(")")?;
+                ctx.flush(&mut body);
+                body.push(Self::loop_exit(condition));
+                self.parse_loop_body(ctx, &mut body)?;
+                block.push(crate::Statement::Loop {
+                    body,
+                    continuing: Vec::new(),
+                });
+            }
+            Some("do") => {
+                self.position += 1;
+                let mut body = Vec::new();
+                self.parse_loop_body(ctx, &mut body)?;
+                if !self.consume_keyword("while") {
+                    return Err(self.unexpected("while"));
+                }
+                self.expect("(")?;
+                let condition = self.parse_condition(ctx)?;
+                self.expect(")")?;
+                self.expect(";")?;
+                // the condition is checked after the body and the `continue`
+                let mut continuing = Vec::new();
+                ctx.flush(&mut continuing);
+                continuing.push(Self::loop_exit(condition));
+                block.push(crate::Statement::Loop { body, continuing });
+            }
+            Some("for") => {
+                self.position += 1;
+                self.expect("(")?;
+                ctx.scopes.push(FastHashMap::default());
+                if self.is_declaration() {
+                    self.parse_local_declaration(ctx, block)?;
+                } else if !self.consume(";") {
+                    self.parse_expression_statement(ctx, block)?;
+                    self.expect(";")?;
+                }
+                let mut body = Vec::new();
+                if !self.consume(";") {
+                    let condition = self.parse_condition(ctx)?;
+                    self.expect(";")?;
+                    ctx.flush(&mut body);
+                    body.push(Self::loop_exit(condition));
+                }
+                let mut continuing = Vec::new();
+                if !self.consume(")") {
+                    self.parse_expression_statement(ctx, &mut continuing)?;
+                    self.expect(")")?;
+                }
+                self.parse_loop_body(ctx, &mut body)?;
+                ctx.scopes.pop();
+                block.push(crate::Statement::Loop { body, continuing });
+            }
+            Some("switch") => {
+                self.position += 1;
+                self.parse_switch(ctx, block)?;
+            }
+            Some("case") | Some("default") => {
+                return Err(self.error(ErrorKind::InvalidCaseLabel));
+            }
+            Some("break") => {
+                if !ctx.in_loop && !ctx.in_switch {
+                    return Err(self.error(ErrorKind::BreakOutsideLoop));
+                }
+                self.position += 1;
+                self.expect(";")?;
+                block.push(crate::Statement::Break);
+            }
+            Some("continue") => {
+                if !ctx.in_loop {
+                    return Err(self.error(ErrorKind::ContinueOutsideLoop));
+                }
+                self.position += 1;
+                self.expect(";")?;
+                block.push(crate::Statement::Continue);
+            }
+            Some("discard") => {
+                self.position += 1;
+                self.expect(";")?;
+                block.push(crate::Statement::Kill);
+            }
+            Some("return") => {
+                self.position += 1;
+                let value = if self.consume(";") {
+                    None
+                } else {
+                    let place = self.parse_conditional(ctx)?;
+                    self.expect(";")?;
+                    Some(self.read(ctx, place)?)
+                };
+                let value = match (value, ctx.function.return_type) {
+                    (Some(value), Some(ty)) => {
+                        let inner = self.module.types[ty].inner.clone();
+                        Some(self.coerce(ctx, value, &inner)?)
+                    }
+                    (None, None) => None,
+                    _ => return Err(self.error(ErrorKind::TypeMismatch)),
+                };
+                ctx.flush(block);
+                block.push(crate::Statement::Return { value });
+            }
+            _ if self.consume("{") => self.parse_block(ctx, block)?,
+            _ if self.consume(";") => (),
+            _ if self.is_declaration() => self.parse_local_declaration(ctx, block)?,
+            _ => {
+                self.parse_expression_statement(ctx, block)?;
+                self.expect(";")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_local_declaration(&mut self, ctx: &mut Context, block: &mut crate::Block) -> Result<(), Error> {
+        match self.parse_qualifiers()?.storage {
+            // constants are treated as variables that aren't assigned to
+            None | Some(StorageQualifier::Const) => (),
+            Some(_) => return Err(self.unexpected("a local variable")),
+        }
+        let base = self
+            .parse_type_specifier()?
+            .ok_or_else(|| self.error(ErrorKind::VoidValue))?;
+        // local structure declaration
+        if self.consume(";") {
+            return Ok(());
+        }
+        loop {
+            let name = self.expect_identifier()?;
+            if ctx.scopes.last().unwrap().contains_key(&name) {
+                return Err(self.error(ErrorKind::Redefinition(name)));
+            }
+            let ty = self.parse_array_suffix(base)?;
+            let local = ctx.function.local_variables.append(crate::LocalVariable {
+                name: Some(name.clone()),
+                ty,
+                init: None,
+            });
+            let pointer = ctx.append(crate::Expression::LocalVariable(local));
+            if self.consume("=") {
+                if self.is_punct("{") {
+                    return Err(self.error(ErrorKind::InvalidInitializer(name)));
+                }
+                let place = self.parse_conditional(ctx)?;
+                let value = self.read(ctx, place)?;
+                let inner = self.module.types[ty].inner.clone();
+                let value = self.coerce(ctx, value, &inner)?;
+                ctx.flush(block);
+                block.push(crate::Statement::Store { pointer, value });
+            }
+            // the variable is only visible after its initializer
+            ctx.scopes.last_mut().unwrap().insert(name, pointer);
+            if !self.consume(",") {
+                break;
+            }
+        }
+        self.expect(";")
+    }
+
+    /// Parses an assignment, an increment or a call, without the semicolon.
+    fn parse_expression_statement(&mut self, ctx: &mut Context, block: &mut crate::Block) -> Result<(), Error> {
+        if let Some(op) = self.consume_any(&["++", "--"]) {
+            let place = self.parse_unary(ctx)?;
+            self.increment(ctx, place, op)?;
+        } else {
+            let place = self.parse_unary(ctx)?;
+            if let Some(op) = self.consume_any(&["++", "--"]) {
+                self.increment(ctx, place, op)?;
+            } else if let Some(op) = self.consume_any(ASSIGNMENT_OPERATORS) {
+                let value = self.parse_conditional(ctx)?;
+                let mut value = self.read(ctx, value)?;
+                if op != "=" {
+                    let current = self.read(ctx, place.clone())?;
+                    value = self.binary(ctx, &op[.. op.len() - 1], current, value)?;
+                }
+                self.assign(ctx, place, value)?;
+            } else {
+                let place = self.parse_binary_rest(ctx, place, 0)?;
+                let place = self.parse_conditional_rest(ctx, place)?;
+                match place.root {
+                    // calls not returning anything are already pending
+                    Root::Void => (),
+                    Root::Value(expr) if place.path.is_empty() => {
+                        if let crate::Expression::Call { function, .. } = ctx.function.expressions[expr] {
+                            // the result is stored, so that the call isn't lost
+                            let ty = self.module.functions[function].return_type.unwrap();
+                            let pointer = ctx.temporary(ty);
+                            ctx.pending.push(crate::Statement::Store { pointer, value: expr });
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        ctx.flush(block);
+        Ok(())
+    }
+
+    fn increment(&mut self, ctx: &mut Context, place: Place, op: &str) -> Result<(), Error> {
+        let current = self.read(ctx, place.clone())?;
+        let one = self.literal(ctx, crate::ConstantInner::Sint(1));
+        let value = self.binary(ctx, &op[.. 1], current, one)?;
+        self.assign(ctx, place, value)
+    }
+
+    fn parse_case_value(&mut self) -> Result<i32, Error> {
+        let negative = self.consume("-");
+        let value = match self.peek() {
+            Some(&lex::Token::IntLiteral(value)) => value,
+            Some(&lex::Token::UintLiteral(value)) => value as i64,
+            Some(lex::Token::Identifier(name)) => match self.lookup_global.get(name) {
+                Some(&GlobalSymbol::Constant(token)) => match self.module.constants[token].inner {
+                    crate::ConstantInner::Sint(value) => value,
+                    crate::ConstantInner::Uint(value) => value as i64,
+                    _ => return Err(self.error(ErrorKind::InvalidCaseLabel)),
+                },
+                _ => return Err(self.error(ErrorKind::InvalidCaseLabel)),
+            },
+            _ => return Err(self.error(ErrorKind::InvalidCaseLabel)),
+        };
+        self.position += 1;
+        Ok(if negative { -value } else { value } as i32)
+    }
+
+    fn parse_switch(&mut self, ctx: &mut Context, block: &mut crate::Block) -> Result<(), Error> {
+        self.expect("(")?;
+        let place = self.parse_conditional(ctx)?;
+        let selector = self.read(ctx, place)?;
+        self.expect(")")?;
+        match self.resolve(ctx, selector)? {
+            crate::TypeInner::Scalar { kind: crate::ScalarKind::Sint, .. } |
+            crate::TypeInner::Scalar { kind: crate::ScalarKind::Uint, .. } => (),
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        }
+        ctx.flush(block);
+        self.expect("{")?;
+
+        // labels in the source order, with the statements following them
+        let mut labels: Vec<(Option<i32>, crate::Block)> = Vec::new();
+        let in_switch = ctx.in_switch;
+        ctx.in_switch = true;
+        ctx.scopes.push(FastHashMap::default());
+        while !self.consume("}") {
+            if self.consume_keyword("case") {
+                let value = self.parse_case_value()?;
+                self.expect(":")?;
+                labels.push((Some(value), Vec::new()));
+            } else if self.consume_keyword("default") {
+                self.expect(":")?;
+                labels.push((None, Vec::new()));
+            } else {
+                match labels.last_mut() {
+                    Some((_, body)) => self.parse_statement(ctx, body)?,
+                    None => return Err(self.error(ErrorKind::InvalidCaseLabel)),
+                }
+            }
+        }
+        ctx.scopes.pop();
+        ctx.in_switch = in_switch;
+
+        let mut cases: Vec<(i32, crate::Block, Option<crate::FallThrough>)> = Vec::new();
+        let mut default = Vec::new();
+        let mut labels = labels.into_iter().peekable();
+        while let Some((value, mut body)) = labels.next() {
+            let next_is_default = labels.peek().map(|&(next, _)| next.is_none());
+            let falls_through = match body.last() {
+                Some(crate::Statement::Break) => {
+                    body.pop();
+                    false
+                }
+                Some(crate::Statement::Return { .. }) |
+                Some(crate::Statement::Continue) |
+                Some(crate::Statement::Kill) => false,
+                _ => next_is_default.is_some(),
+            };
+            match value {
+                // only the empty cases may fall into the default,
+                // which handles their values anyway
+                Some(_) if falls_through && next_is_default == Some(true) => {
+                    if !body.is_empty() {
+                        return Err(self.error(ErrorKind::InvalidFallThrough));
+                    }
+                    while cases.last().map_or(false, |case| case.2.is_some()) {
+                        if !cases.pop().unwrap().1.is_empty() {
+                            return Err(self.error(ErrorKind::InvalidFallThrough));
+                        }
+                    }
+                }
+                Some(value) => {
+                    let fall_through = if falls_through { Some(crate::FallThrough) } else { None };
+                    cases.push((value, body, fall_through));
+                }
+                None if falls_through => return Err(self.error(ErrorKind::InvalidFallThrough)),
+                None => default = body,
+            }
+        }
+        block.push(crate::Statement::Switch { selector, cases, default });
+        Ok(())
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn resolve(&self, ctx: &mut Context, expr: Token<crate::Expression>) -> Result<crate::TypeInner, Error> {
+        ctx.typifier
+            .resolve_new(&ctx.function.expressions, &ResolveContext {
+                types: &self.module.types,
+                constants: &self.module.constants,
+                global_vars: &self.module.global_variables,
+                local_vars: &ctx.function.local_variables,
+                functions: &self.module.functions,
+                parameter_types: &ctx.function.parameter_types,
+            })
+            .map_err(|e| self.error(ErrorKind::Resolve(e)))?;
+        Ok(ctx.typifier.get(expr, &self.module.types).clone())
+    }
+
+    /// Returns the type of an expression, adding it to the module if needed.
+    fn resolve_handle(&mut self, ctx: &mut Context, expr: Token<crate::Expression>) -> Result<Token<crate::Type>, Error> {
+        let inner = self.resolve(ctx, expr)?;
+        Ok(match ctx.typifier.get_handle(expr) {
+            Some(ty) => ty,
+            None => self.ensure_type(inner),
+        })
+    }
+
+    fn constant(&mut self, ctx: &mut Context, token: Token<crate::Constant>) -> Token<crate::Expression> {
+        if let Some(&expr) = ctx.constants.get(&token) {
+            return expr;
+        }
+        let expr = ctx.append(crate::Expression::Constant(token));
+        ctx.constants.insert(token, expr);
+        expr
+    }
+
+    fn literal(&mut self, ctx: &mut Context, inner: crate::ConstantInner) -> Token<crate::Expression> {
+        let existing = self.module.constants
+            .iter()
+            .find(|&(_, c)| c.name.is_none() && c.specialization.is_none() && same_constant(&c.inner, &inner))
+            .map(|(token, _)| token);
+        let token = match existing {
+            Some(token) => token,
+            None => self.module.constants.append(crate::Constant {
+                name: None,
+                specialization: None,
+                inner,
+            }),
+        };
+        self.constant(ctx, token)
+    }
+
+    fn global(&mut self, ctx: &mut Context, var: Token<crate::GlobalVariable>) -> Token<crate::Expression> {
+        if let Some(&expr) = ctx.globals.get(&var) {
+            return expr;
+        }
+        let expr = ctx.append(crate::Expression::GlobalVariable(var));
+        ctx.globals.insert(var, expr);
+        expr
+    }
+
+    /// Converts a constant expression to another scalar kind.
+    fn convert(
+        &mut self,
+        ctx: &mut Context,
+        expr: Token<crate::Expression>,
+        kind: crate::ScalarKind,
+        explicit: bool,
+    ) -> Result<Token<crate::Expression>, Error> {
+        match self.resolve(ctx, expr)? {
+            crate::TypeInner::Scalar { kind: expr_kind, .. } if expr_kind == kind => return Ok(expr),
+            crate::TypeInner::Scalar { .. } => (),
+            _ => return Err(self.error(ErrorKind::UnsupportedConversion)),
+        }
+        let converted = match ctx.function.expressions[expr] {
+            crate::Expression::Constant(token) => convert_constant(&self.module.constants[token].inner, kind, explicit),
+            // the IR has no conversions of the runtime values
+            _ => None,
+        };
+        match converted {
+            Some(inner) => Ok(self.literal(ctx, inner)),
+            None => Err(self.error(ErrorKind::UnsupportedConversion)),
+        }
+    }
+
+    /// Makes an expression have the given type, converting the literals.
+    fn coerce(
+        &mut self,
+        ctx: &mut Context,
+        expr: Token<crate::Expression>,
+        target: &crate::TypeInner,
+    ) -> Result<Token<crate::Expression>, Error> {
+        let inner = self.resolve(ctx, expr)?;
+        if inner == *target {
+            return Ok(expr);
+        }
+        match (inner, target) {
+            (crate::TypeInner::Scalar { .. }, &crate::TypeInner::Scalar { kind, .. }) => {
+                self.convert(ctx, expr, kind, false).map_err(|_| self.error(ErrorKind::TypeMismatch))
+            }
+            _ => Err(self.error(ErrorKind::TypeMismatch)),
+        }
+    }
+
+    /// Converts the literals of the operands, so that they have the same scalar kind.
+    fn unify(
+        &mut self,
+        ctx: &mut Context,
+        left: Token<crate::Expression>,
+        right: Token<crate::Expression>,
+    ) -> Result<(Token<crate::Expression>, Token<crate::Expression>), Error> {
+        let left_kind = scalar_kind(&self.resolve(ctx, left)?);
+        let right_kind = scalar_kind(&self.resolve(ctx, right)?);
+        let rank = |kind| match kind {
+            Some(crate::ScalarKind::Sint) => 1,
+            Some(crate::ScalarKind::Uint) => 2,
+            Some(crate::ScalarKind::Float) => 3,
+            _ => 0,
+        };
+        Ok(match (left_kind, right_kind) {
+            (Some(l), Some(r)) if l != r && rank(left_kind) > 0 && rank(right_kind) > 0 => {
+                if rank(left_kind) < rank(right_kind) {
+                    (self.convert(ctx, left, r, false)?, right)
+                } else {
+                    (left, self.convert(ctx, right, l, false)?)
+                }
+            }
+            _ => (left, right),
+        })
+    }
+
+    fn splat(
+        &mut self,
+        ctx: &mut Context,
+        scalar: Token<crate::Expression>,
+        size: crate::VectorSize,
+    ) -> Result<Token<crate::Expression>, Error> {
+        let kind = match self.resolve(ctx, scalar)? {
+            crate::TypeInner::Scalar { kind, .. } => kind,
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        };
+        let ty = self.ensure_type(crate::TypeInner::Vector { size, kind, width: scalar_width(kind) });
+        Ok(ctx.append(crate::Expression::Compose {
+            ty,
+            components: vec![scalar; size as usize],
+        }))
+    }
+
+    fn access_type(&self, inner: &crate::TypeInner, access: &Access) -> Result<crate::TypeInner, Error> {
+        Ok(match (inner, access) {
+            (&crate::TypeInner::Vector { kind, width, .. }, Access::Swizzle(components)) => {
+                match vector_size_of(components.len()) {
+                    Some(size) => crate::TypeInner::Vector { size, kind, width },
+                    None => crate::TypeInner::Scalar { kind, width },
+                }
+            }
+            (_, &Access::Swizzle(_)) => return Err(self.error(ErrorKind::InvalidOperands("."))),
+            (&crate::TypeInner::Vector { kind, width, .. }, _) => crate::TypeInner::Scalar { kind, width },
+            (&crate::TypeInner::Matrix { rows, kind, width, .. }, _) => crate::TypeInner::Vector { size: rows, kind, width },
+            (&crate::TypeInner::Array { base, .. }, _) => self.module.types[base].inner.clone(),
+            (crate::TypeInner::Struct { members }, &Access::Index(index)) => {
+                self.module.types[members[index as usize].ty].inner.clone()
+            }
+            _ => return Err(self.error(ErrorKind::InvalidOperands("[]"))),
+        })
+    }
+
+    fn place_type(&self, ctx: &mut Context, place: &Place) -> Result<crate::TypeInner, Error> {
+        let mut inner = match place.root {
+            Root::Pointer(pointer) => match self.resolve(ctx, pointer)? {
+                crate::TypeInner::Pointer { base, .. } => self.module.types[base].inner.clone(),
+                other => other,
+            },
+            Root::Reference(expr) | Root::Value(expr) => self.resolve(ctx, expr)?,
+            Root::Void => return Err(self.error(ErrorKind::VoidValue)),
+        };
+        for access in place.path.iter() {
+            inner = self.access_type(&inner, access)?;
+        }
+        Ok(inner)
+    }
+
+    fn read(&mut self, ctx: &mut Context, place: Place) -> Result<Token<crate::Expression>, Error> {
+        let mut expr = match place.root {
+            Root::Pointer(pointer) => ctx.append(crate::Expression::Load { pointer }),
+            Root::Reference(expr) | Root::Value(expr) => expr,
+            Root::Void => return Err(self.error(ErrorKind::VoidValue)),
+        };
+        for access in place.path {
+            expr = match access {
+                Access::Index(index) => ctx.append(crate::Expression::AccessIndex { base: expr, index }),
+                Access::Dynamic(index) => ctx.append(crate::Expression::Access { base: expr, index }),
+                Access::Swizzle(components) => {
+                    let inner = self.resolve(ctx, expr)?;
+                    let inner = self.access_type(&inner, &Access::Swizzle(components.clone()))?;
+                    let ty = self.ensure_type(inner);
+                    let components = components
+                        .into_iter()
+                        .map(|index| ctx.append(crate::Expression::AccessIndex { base: expr, index }))
+                        .collect();
+                    ctx.append(crate::Expression::Compose { ty, components })
+                }
+            };
+        }
+        Ok(expr)
+    }
+
+    /// Adds the statements assigning the value to the place.
+    fn assign(&mut self, ctx: &mut Context, place: Place, value: Token<crate::Expression>) -> Result<(), Error> {
+        let target = self.place_type(ctx, &place)?;
+        let value = self.coerce(ctx, value, &target)?;
+        let (mut pointer, through_pointer) = match place.root {
+            Root::Pointer(pointer) => (pointer, true),
+            Root::Reference(expr) => (expr, false),
+            Root::Value(_) | Root::Void => return Err(self.error(ErrorKind::NotAssignable)),
+        };
+        let mut inner = self.place_type(ctx, &Place { root: place.root, path: Vec::new() })?;
+        let mut path = place.path;
+        let swizzle = match path.last() {
+            Some(Access::Swizzle(components)) => {
+                let components = components.clone();
+                path.pop();
+                Some(components)
+            }
+            _ => None,
+        };
+        for access in path {
+            let element = self.access_type(&inner, &access)?;
+            // accessing through a pointer needs the pointer type of the element
+            if through_pointer {
+                if let crate::TypeInner::Vector { .. } | crate::TypeInner::Matrix { .. } = inner {
+                    self.ensure_type(element.clone());
+                }
+            }
+            inner = element;
+            pointer = match access {
+                Access::Index(index) => ctx.append(crate::Expression::AccessIndex { base: pointer, index }),
+                Access::Dynamic(index) => ctx.append(crate::Expression::Access { base: pointer, index }),
+                Access::Swizzle(_) => unreachable!(),
+            };
+        }
+        let components = match swizzle {
+            Some(components) => components,
+            None => {
+                ctx.pending.push(crate::Statement::Store { pointer, value });
+                return Ok(());
+            }
+        };
+        if (1 .. components.len()).any(|i| components[.. i].contains(&components[i])) {
+            return Err(self.error(ErrorKind::NotAssignable));
+        }
+        if through_pointer {
+            self.access_type(&inner, &Access::Index(0)).map(|scalar| self.ensure_type(scalar))?;
+        }
+        // the value is kept aside, since it may depend on the components being assigned
+        let value_ty = self.resolve_handle(ctx, value)?;
+        let temporary = ctx.temporary(value_ty);
+        ctx.pending.push(crate::Statement::Store { pointer: temporary, value });
+        let value = ctx.append(crate::Expression::Load { pointer: temporary });
+        for (i, &component) in components.iter().enumerate() {
+            let target = ctx.append(crate::Expression::AccessIndex { base: pointer, index: component });
+            let part = ctx.append(crate::Expression::AccessIndex { base: value, index: i as u32 });
+            ctx.pending.push(crate::Statement::Store { pointer: target, value: part });
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn parse_conditional(&mut self, ctx: &mut Context) -> Result<Place, Error> {
+        let condition = self.parse_binary(ctx, 0)?;
+        self.parse_conditional_rest(ctx, condition)
+    }
+
+    fn parse_conditional_rest(&mut self, ctx: &mut Context, condition: Place) -> Result<Place, Error> {
+        if !self.consume("?") {
+            return Ok(condition);
+        }
+        let condition = self.read(ctx, condition)?;
+        match self.resolve(ctx, condition)? {
+            crate::TypeInner::Scalar { kind: crate::ScalarKind::Bool, .. } => (),
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        }
+        // only one of the branches is evaluated, so they are
+        // stored into a temporary by the conditional statement
+        let outer = std::mem::take(&mut ctx.pending);
+        let accept = self.parse_conditional(ctx)?;
+        let accept = self.read(ctx, accept)?;
+        let mut accept_block = std::mem::take(&mut ctx.pending);
+        self.expect(":")?;
+        let reject = self.parse_conditional(ctx)?;
+        let reject = self.read(ctx, reject)?;
+        let mut reject_block = std::mem::replace(&mut ctx.pending, outer);
+
+        let (accept, reject) = self.unify(ctx, accept, reject)?;
+        let ty = self.resolve_handle(ctx, accept)?;
+        if self.resolve(ctx, reject)? != self.module.types[ty].inner {
+            return Err(self.error(ErrorKind::TypeMismatch));
+        }
+        let pointer = ctx.temporary(ty);
+        accept_block.push(crate::Statement::Store { pointer, value: accept });
+        reject_block.push(crate::Statement::Store { pointer, value: reject });
+        ctx.pending.push(crate::Statement::If {
+            condition,
+            accept: accept_block,
+            reject: reject_block,
+        });
+        let value = ctx.append(crate::Expression::Load { pointer });
+        Ok(Place::value(value))
+    }
+
+    fn parse_binary(&mut self, ctx: &mut Context, min_precedence: u8) -> Result<Place, Error> {
+        let left = self.parse_unary(ctx)?;
+        self.parse_binary_rest(ctx, left, min_precedence)
+    }
+
+    /// Returns the next binary operator, if it binds at least as tight as `min_precedence`.
+    fn peek_operator(&self, min_precedence: u8) -> Option<(&'static str, u8)> {
+        match self.peek() {
+            Some(&lex::Token::Punct(op)) => binary_precedence(op)
+                .filter(|&precedence| precedence >= min_precedence)
+                .map(|precedence| (op, precedence)),
+            _ => None,
+        }
+    }
+
+    /// Parses the binary operators following an already parsed operand.
+    fn parse_binary_rest(&mut self, ctx: &mut Context, mut left: Place, min_precedence: u8) -> Result<Place, Error> {
+        while let Some((op, precedence)) = self.peek_operator(min_precedence) {
+            self.position += 1;
+            let left_value = self.read(ctx, left)?;
+            let right = self.parse_binary(ctx, precedence + 1)?;
+            let right_value = self.read(ctx, right)?;
+            left = Place::value(self.binary(ctx, op, left_value, right_value)?);
+        }
+        Ok(left)
+    }
+
+    /// Reduces the components of a boolean vector with a logical operator.
+    fn reduce(
+        &mut self,
+        ctx: &mut Context,
+        vector: Token<crate::Expression>,
+        op: crate::BinaryOperator,
+    ) -> Result<Token<crate::Expression>, Error> {
+        let size = match self.resolve(ctx, vector)? {
+            crate::TypeInner::Vector { size, kind: crate::ScalarKind::Bool, .. } => size as u32,
+            crate::TypeInner::Scalar { kind: crate::ScalarKind::Bool, .. } => return Ok(vector),
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        };
+        let mut result = ctx.append(crate::Expression::AccessIndex { base: vector, index: 0 });
+        for index in 1 .. size {
+            let right = ctx.append(crate::Expression::AccessIndex { base: vector, index });
+            result = ctx.append(crate::Expression::Binary { op, left: result, right });
+        }
+        Ok(result)
+    }
+
+    fn binary(
+        &mut self,
+        ctx: &mut Context,
+        op: &str,
+        left: Token<crate::Expression>,
+        right: Token<crate::Expression>,
+    ) -> Result<Token<crate::Expression>, Error> {
+        use crate::{BinaryOperator as Bo, TypeInner as Ti};
+        let is_shift = op == "<<" || op == ">>";
+        let (mut left, mut right) = if is_shift {
+            (left, right)
+        } else {
+            self.unify(ctx, left, right)?
+        };
+        let left_inner = self.resolve(ctx, left)?;
+        let right_inner = self.resolve(ctx, right)?;
+        let invalid = || self.error(ErrorKind::InvalidOperands(binary_name(op)));
+        let kind = scalar_kind(&left_inner).ok_or_else(invalid)?;
+        let right_kind = scalar_kind(&right_inner).ok_or_else(invalid)?;
+        let is_bool = kind == crate::ScalarKind::Bool;
+        let is_integer = kind == crate::ScalarKind::Sint || kind == crate::ScalarKind::Uint;
+        if !is_shift && kind != right_kind {
+            return Err(invalid());
+        }
+
+        let op = match op {
+            "&&" | "||" | "^^" => {
+                match (&left_inner, &right_inner) {
+                    (&Ti::Scalar { .. }, &Ti::Scalar { .. }) if is_bool => (),
+                    _ => return Err(invalid()),
+                }
+                match op {
+                    "&&" => Bo::LogicalAnd,
+                    "||" => Bo::LogicalOr,
+                    _ => Bo::NotEqual,
+                }
+            }
+            "<" | ">" | "<=" | ">=" => {
+                match (&left_inner, &right_inner) {
+                    (&Ti::Scalar { .. }, &Ti::Scalar { .. }) if !is_bool => (),
+                    _ => return Err(invalid()),
+                }
+                match op {
+                    "<" => Bo::Less,
+                    ">" => Bo::Greater,
+                    "<=" => Bo::LessEqual,
+                    _ => Bo::GreaterEqual,
+                }
+            }
+            "==" | "!=" => {
+                let (op, reduction) = if op == "==" {
+                    (Bo::Equal, Bo::LogicalAnd)
+                } else {
+                    (Bo::NotEqual, Bo::LogicalOr)
+                };
+                return match (&left_inner, &right_inner) {
+                    (&Ti::Scalar { .. }, &Ti::Scalar { .. }) => {
+                        Ok(ctx.append(crate::Expression::Binary { op, left, right }))
+                    }
+                    // vectors are equal if all the components are
+                    (&Ti::Vector { size, .. }, &Ti::Vector { size: right_size, .. }) if size == right_size => {
+                        let compare = ctx.append(crate::Expression::Binary { op, left, right });
+                        self.reduce(ctx, compare, reduction)
+                    }
+                    _ => Err(self.error(ErrorKind::UnsupportedFeature("comparison of composite values"))),
+                };
+            }
+            _ => {
+                let op = match op {
+                    "+" => Bo::Add,
+                    "-" => Bo::Subtract,
+                    "*" => Bo::Multiply,
+                    "/" => Bo::Divide,
+                    "%" => Bo::Modulo,
+                    "&" => Bo::And,
+                    "|" => Bo::InclusiveOr,
+                    "^" => Bo::ExclusiveOr,
+                    "<<" => Bo::ShiftLeftLogical,
+                    ">>" if kind == crate::ScalarKind::Sint => Bo::ShiftRightArithmetic,
+                    ">>" => Bo::ShiftRightLogical,
+                    _ => return Err(invalid()),
+                };
+                let needs_integer = !matches!(op, Bo::Add | Bo::Subtract | Bo::Multiply | Bo::Divide);
+                if is_bool || (needs_integer && !is_integer) || (is_shift && !is_integer_kind(right_kind))
+                {
+                    return Err(invalid());
+                }
+                match (&left_inner, &right_inner) {
+                    (&Ti::Scalar { .. }, &Ti::Scalar { .. }) => (),
+                    (&Ti::Vector { size, .. }, &Ti::Vector { size: right_size, .. }) if size == right_size => (),
+                    (&Ti::Matrix { .. }, &Ti::Matrix { .. }) if op == Bo::Add || op == Bo::Subtract || op == Bo::Multiply => (),
+                    (&Ti::Matrix { .. }, &Ti::Vector { .. }) |
+                    (&Ti::Vector { .. }, &Ti::Matrix { .. }) |
+                    (&Ti::Matrix { .. }, &Ti::Scalar { .. }) |
+                    (&Ti::Scalar { .. }, &Ti::Matrix { .. }) if op == Bo::Multiply => (),
+                    (&Ti::Vector { .. }, &Ti::Scalar { .. }) |
+                    (&Ti::Scalar { .. }, &Ti::Vector { .. }) if op == Bo::Multiply && !is_shift => (),
+                    // the other operators need the scalars to be turned into vectors
+                    (&Ti::Vector { size, .. }, &Ti::Scalar { .. }) => right = self.splat(ctx, right, size)?,
+                    (&Ti::Scalar { .. }, &Ti::Vector { size, .. }) if !is_shift => left = self.splat(ctx, left, size)?,
+                    _ => return Err(invalid()),
+                }
+                op
+            }
+        };
+        Ok(ctx.append(crate::Expression::Binary { op, left, right }))
+    }
+
+    fn parse_unary(&mut self, ctx: &mut Context) -> Result<Place, Error> {
+        let op = match self.peek() {
+            Some(&lex::Token::Punct(op @ "-")) |
+            Some(&lex::Token::Punct(op @ "+")) |
+            Some(&lex::Token::Punct(op @ "!")) |
+            Some(&lex::Token::Punct(op @ "~")) => op,
+            Some(&lex::Token::Punct("++")) |
+            Some(&lex::Token::Punct("--")) => {
+                return Err(self.error(ErrorKind::UnsupportedFeature("increments within expressions")));
+            }
+            _ => return self.parse_postfix(ctx),
+        };
+        self.position += 1;
+        let operand = self.parse_unary(ctx)?;
+        let expr = self.read(ctx, operand)?;
+        let kind = scalar_kind(&self.resolve(ctx, expr)?);
+        let op = match (op, kind) {
+            ("+", Some(kind)) if kind != crate::ScalarKind::Bool => return Ok(Place::value(expr)),
+            ("-", Some(kind)) if kind != crate::ScalarKind::Bool => {
+                // negative literals are folded
+                if let crate::Expression::Constant(token) = ctx.function.expressions[expr] {
+                    let negated = match self.module.constants[token].inner {
+                        crate::ConstantInner::Sint(value) => Some(crate::ConstantInner::Sint(-value)),
+                        crate::ConstantInner::Float(value) => Some(crate::ConstantInner::Float(-value)),
+                        _ => None,
+                    };
+                    if let Some(inner) = negated {
+                        return Ok(Place::value(self.literal(ctx, inner)));
+                    }
+                }
+                crate::UnaryOperator::Negate
+            }
+            ("!", Some(crate::ScalarKind::Bool)) => crate::UnaryOperator::Not,
+            ("~", Some(kind)) if is_integer_kind(kind) => crate::UnaryOperator::BitwiseNot,
+            _ => return Err(self.error(ErrorKind::InvalidOperands(binary_name(op)))),
+        };
+        Ok(Place::value(ctx.append(crate::Expression::Unary { op, expr })))
+    }
+
+    fn parse_postfix(&mut self, ctx: &mut Context) -> Result<Place, Error> {
+        let mut place = self.parse_primary(ctx)?;
+        loop {
+            if self.consume(".") {
+                let field = self.expect_identifier()?;
+                place = if field == "length" && self.is_punct("(") {
+                    self.expect("(")?;
+                    self.expect(")")?;
+                    let length = match self.place_type(ctx, &place)? {
+                        crate::TypeInner::Vector { size, .. } => size as i64,
+                        crate::TypeInner::Matrix { columns, .. } => columns as i64,
+                        crate::TypeInner::Array { size: crate::ArraySize::Static(length), .. } => length as i64,
+                        crate::TypeInner::Array { size: crate::ArraySize::Dynamic, .. } => {
+                            return Err(self.error(ErrorKind::UnsupportedFeature("length of runtime arrays")));
+                        }
+                        _ => return Err(self.error(ErrorKind::UnknownField(field))),
+                    };
+                    Place::value(self.literal(ctx, crate::ConstantInner::Sint(length)))
+                } else {
+                    self.member(ctx, place, field)?
+                };
+            } else if self.consume("[") {
+                let index = self.parse_conditional(ctx)?;
+                let index = self.read(ctx, index)?;
+                self.expect("]")?;
+                place = self.index(ctx, place, index)?;
+            } else {
+                return Ok(place);
+            }
+        }
+    }
+
+    fn member(&mut self, ctx: &mut Context, mut place: Place, field: String) -> Result<Place, Error> {
+        match self.place_type(ctx, &place)? {
+            crate::TypeInner::Struct { ref members } => {
+                match members.iter().position(|member| member.name.as_deref() == Some(field.as_str())) {
+                    Some(index) => place.path.push(Access::Index(index as u32)),
+                    None => return Err(self.error(ErrorKind::UnknownField(field))),
+                }
+            }
+            crate::TypeInner::Vector { size, .. } => {
+                let mut components = Vec::with_capacity(field.len());
+                for set in &["xyzw", "rgba", "stpq"] {
+                    components = field.chars().filter_map(|c| set.find(c)).map(|i| i as u32).collect();
+                    if components.len() == field.len() {
+                        break;
+                    }
+                }
+                if components.len() != field.len() || field.len() > 4 ||
+                    components.iter().any(|&c| c >= size as u32)
+                {
+                    return Err(self.error(ErrorKind::UnknownField(field)));
+                }
+                // swizzles of swizzles select from the original vector
+                if let Some(Access::Swizzle(previous)) = place.path.last() {
+                    components = components.iter().map(|&c| previous[c as usize]).collect();
+                    place.path.pop();
+                }
+                place.path.push(match components[..] {
+                    [component] => Access::Index(component),
+                    _ => Access::Swizzle(components),
+                });
+            }
+            _ => return Err(self.error(ErrorKind::UnknownField(field))),
+        }
+        Ok(place)
+    }
+
+    fn index(&mut self, ctx: &mut Context, mut place: Place, index: Token<crate::Expression>) -> Result<Place, Error> {
+        if let Some(&Access::Swizzle(_)) = place.path.last() {
+            place = Place::value(self.read(ctx, place)?);
+        }
+        let limit = match self.place_type(ctx, &place)? {
+            crate::TypeInner::Vector { size, .. } => size as u64,
+            crate::TypeInner::Matrix { columns, .. } => columns as u64,
+            crate::TypeInner::Array { size: crate::ArraySize::Static(length), .. } => length as u64,
+            crate::TypeInner::Array { size: crate::ArraySize::Dynamic, .. } => u64::MAX,
+            _ => return Err(self.error(ErrorKind::InvalidOperands("[]"))),
+        };
+        match self.resolve(ctx, index)? {
+            crate::TypeInner::Scalar { kind, .. } if is_integer_kind(kind) => (),
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        }
+        // constant indices are checked against the size
+        let constant = match ctx.function.expressions[index] {
+            crate::Expression::Constant(token) => match self.module.constants[token].inner {
+                crate::ConstantInner::Sint(value) => Some(value as u64),
+                crate::ConstantInner::Uint(value) => Some(value),
+                _ => None,
+            },
+            _ => None,
+        };
+        place.path.push(match constant {
+            Some(value) if value < limit => Access::Index(value as u32),
+            Some(value) => return Err(self.error(ErrorKind::InvalidIndex(value as u32))),
+            None => Access::Dynamic(index),
+        });
+        Ok(place)
+    }
+
+    fn parse_primary(&mut self, ctx: &mut Context) -> Result<Place, Error> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.error(ErrorKind::UnexpectedEnd)),
+        };
+        let inner = match token {
+            lex::Token::IntLiteral(value) => crate::ConstantInner::Sint(value),
+            lex::Token::UintLiteral(value) => crate::ConstantInner::Uint(value),
+            lex::Token::FloatLiteral(value) => crate::ConstantInner::Float(value),
+            lex::Token::Identifier(ref name) if name == "true" || name == "false" => {
+                crate::ConstantInner::Bool(name == "true")
+            }
+            lex::Token::Punct("(") => {
+                self.position += 1;
+                let place = self.parse_conditional(ctx)?;
+                self.expect(")")?;
+                return Ok(place);
+            }
+            lex::Token::Identifier(name) => {
+                self.position += 1;
+                return self.parse_identifier(ctx, name);
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.position += 1;
+        Ok(Place::value(self.literal(ctx, inner)))
+    }
+
+    fn parse_identifier(&mut self, ctx: &mut Context, name: String) -> Result<Place, Error> {
+        if self.is_punct("(") || (self.is_punct("[") && self.is_type_name(&name)) {
+            return self.parse_call(ctx, name);
+        }
+        for scope in ctx.scopes.iter().rev() {
+            if let Some(&pointer) = scope.get(&name) {
+                return Ok(Place {
+                    root: Root::Pointer(pointer),
+                    path: Vec::new(),
+                });
+            }
+        }
+        let symbol = match self.lookup_global.get(&name) {
+            Some(&symbol) => symbol,
+            None if name.starts_with("gl_") => GlobalSymbol::Variable(self.builtin_variable(&name)?),
+            None => return Err(self.error(ErrorKind::UnknownIdentifier(name))),
+        };
+        Ok(match symbol {
+            GlobalSymbol::Variable(var) => Place {
+                root: Root::Reference(self.global(ctx, var)),
+                path: Vec::new(),
+            },
+            GlobalSymbol::Member(var, index) => Place {
+                root: Root::Reference(self.global(ctx, var)),
+                path: vec![Access::Index(index)],
+            },
+            GlobalSymbol::Constant(token) => Place::value(self.constant(ctx, token)),
+            GlobalSymbol::Expression { start, end, ty } => {
+                let position = self.position;
+                self.position = start;
+                let place = self.parse_conditional(ctx)?;
+                if self.position != end {
+                    return Err(self.unexpected(";"));
+                }
+                self.position = position;
+                let value = self.read(ctx, place)?;
+                let inner = self.module.types[ty].inner.clone();
+                Place::value(self.coerce(ctx, value, &inner)?)
+            }
+        })
+    }
+
+    /// Declares a built-in variable of the shader stage on its first use.
+    fn builtin_variable(&mut self, name: &str) -> Result<Token<crate::GlobalVariable>, Error> {
+        use crate::{ScalarKind as Sk, TypeInner as Ti, VectorSize as Vs};
+        use spirv::{BuiltIn as Bi, ExecutionModel as Em, StorageClass as Sc};
+        let scalar = |kind| Ti::Scalar { kind, width: scalar_width(kind) };
+        let vector = |size, kind| Ti::Vector { size, kind, width: 32 };
+        let (built_in, class, inner) = match (self.options.entry_point.0, name) {
+            (Em::Vertex, "gl_Position") => (Bi::Position, Sc::Output, vector(Vs::Quad, Sk::Float)),
+            (Em::Vertex, "gl_PointSize") => (Bi::PointSize, Sc::Output, scalar(Sk::Float)),
+            (Em::Vertex, "gl_VertexIndex") => (Bi::VertexIndex, Sc::Input, scalar(Sk::Sint)),
+            (Em::Vertex, "gl_InstanceIndex") => (Bi::InstanceIndex, Sc::Input, scalar(Sk::Sint)),
+            (Em::Fragment, "gl_FragCoord") => (Bi::FragCoord, Sc::Input, vector(Vs::Quad, Sk::Float)),
+            (Em::Fragment, "gl_FrontFacing") => (Bi::FrontFacing, Sc::Input, scalar(Sk::Bool)),
+            (Em::Fragment, "gl_PointCoord") => (Bi::PointCoord, Sc::Input, vector(Vs::Bi, Sk::Float)),
+            (Em::Fragment, "gl_SampleID") => (Bi::SampleId, Sc::Input, scalar(Sk::Sint)),
+            (Em::Fragment, "gl_FragDepth") => (Bi::FragDepth, Sc::Output, scalar(Sk::Float)),
+            (Em::GLCompute, "gl_GlobalInvocationID") => (Bi::GlobalInvocationId, Sc::Input, vector(Vs::Tri, Sk::Uint)),
+            (Em::GLCompute, "gl_LocalInvocationID") => (Bi::LocalInvocationId, Sc::Input, vector(Vs::Tri, Sk::Uint)),
+            (Em::GLCompute, "gl_WorkGroupID") => (Bi::WorkgroupId, Sc::Input, vector(Vs::Tri, Sk::Uint)),
+            (Em::GLCompute, "gl_NumWorkGroups") => (Bi::NumWorkgroups, Sc::Input, vector(Vs::Tri, Sk::Uint)),
+            (Em::GLCompute, "gl_LocalInvocationIndex") => (Bi::LocalInvocationIndex, Sc::Input, scalar(Sk::Uint)),
+            _ => return Err(self.error(ErrorKind::UnknownIdentifier(name.to_owned()))),
+        };
+        let ty = self.ensure_type(inner);
+        let var = self.module.global_variables.append(crate::GlobalVariable {
+            name: Some(name.to_owned()),
+            class,
+            binding: Some(crate::Binding::BuiltIn(built_in)),
+            ty,
+        });
+        self.lookup_global.insert(name.to_owned(), GlobalSymbol::Variable(var));
+        Ok(var)
+    }
+}
+
+fn is_integer_kind(kind: crate::ScalarKind) -> bool {
+    kind == crate::ScalarKind::Sint || kind == crate::ScalarKind::Uint
+}
+
+/// Returns the static name of an operator, for the errors.
+fn binary_name(op: &str) -> &'static str {
+    [
+        "||", "^^", "&&", "|", "^", "&", "==", "!=", "<", ">", "<=", ">=",
+        "<<", ">>", "+", "-", "*", "/", "%", "!", "~",
+    ]
+    .iter()
+    .find(|&&name| name == op)
+    .copied()
+    .unwrap_or("?")
+}
+
+impl<'a> Parser<'a> {
+    /// Parses the arguments of a call, after the opening parenthesis.
+    fn parse_arguments(&mut self, ctx: &mut Context) -> Result<Vec<Place>, Error> {
+        let mut arguments = Vec::new();
+        if self.consume(")") {
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.parse_conditional(ctx)?);
+            if !self.consume(",") {
+                self.expect(")")?;
+                return Ok(arguments);
+            }
+        }
+    }
+
+    fn read_all(&mut self, ctx: &mut Context, places: Vec<Place>) -> Result<Vec<Token<crate::Expression>>, Error> {
+        places.into_iter().map(|place| self.read(ctx, place)).collect()
+    }
+
+    fn parse_call(&mut self, ctx: &mut Context, name: String) -> Result<Place, Error> {
+        // array constructors, like `float[3](a, b, c)`
+        if self.is_punct("[") {
+            let base = self.lookup_type_name(&name)?;
+            let ty = self.parse_array_suffix(base)?;
+            self.expect("(")?;
+            let arguments = self.parse_arguments(ctx)?;
+            let values = self.read_all(ctx, arguments)?;
+            return Ok(Place::value(self.construct(ctx, ty, &name, values)?));
+        }
+        if name == "texture" && !self.lookup_function.contains_key(&name) {
+            return self.parse_texture(ctx).map(Place::value);
+        }
+        self.expect("(")?;
+        let arguments = self.parse_arguments(ctx)?;
+        if self.lookup_function.contains_key(&name) {
+            return self.call_function(ctx, name, arguments);
+        }
+        let values = self.read_all(ctx, arguments)?;
+        if self.is_type_name(&name) {
+            let ty = self.lookup_type_name(&name)?;
+            return Ok(Place::value(self.construct(ctx, ty, &name, values)?));
+        }
+        self.builtin_function(ctx, name, values).map(Place::value)
+    }
+
+    fn call_function(&mut self, ctx: &mut Context, name: String, arguments: Vec<Place>) -> Result<Place, Error> {
+        let info = self.lookup_function.get_mut(&name).unwrap();
+        info.used = true;
+        let (function, parameters, return_type) = (info.token, info.parameters.clone(), info.return_type);
+        if parameters.len() != arguments.len() {
+            return Err(self.error(ErrorKind::InvalidArguments(name)));
+        }
+
+        let mut values = Vec::with_capacity(arguments.len());
+        // output arguments, with the temporaries they are copied from after the call
+        let mut copies = Vec::new();
+        for ((qualifier, ty), place) in parameters.into_iter().zip(arguments) {
+            let inner = self.module.types[ty].inner.clone();
+            if qualifier == ParameterQualifier::In {
+                let value = self.read(ctx, place)?;
+                values.push(self.coerce(ctx, value, &inner)?);
+                continue;
+            }
+            if self.place_type(ctx, &place)? != inner {
+                return Err(self.error(ErrorKind::TypeMismatch));
+            }
+            // local variables are passed directly, the rest goes through a temporary
+            if let Root::Pointer(pointer) = place.root {
+                if let crate::Expression::LocalVariable(local) = ctx.function.expressions[pointer] {
+                    if place.path.is_empty() && ctx.function.local_variables[local].ty == ty {
+                        values.push(pointer);
+                        continue;
+                    }
+                }
+            }
+            let temporary = ctx.temporary(ty);
+            if qualifier == ParameterQualifier::InOut {
+                let value = self.read(ctx, place.clone())?;
+                ctx.pending.push(crate::Statement::Store { pointer: temporary, value });
+            }
+            values.push(temporary);
+            copies.push((place, temporary));
+        }
+
+        let result = match return_type {
+            None => {
+                ctx.pending.push(crate::Statement::Call { function, arguments: values });
+                None
+            }
+            Some(ty) => {
+                let call = ctx.append(crate::Expression::Call { function, arguments: values });
+                if copies.is_empty() {
+                    return Ok(Place::value(call));
+                }
+                // the call has to be made before the outputs are copied
+                let pointer = ctx.temporary(ty);
+                ctx.pending.push(crate::Statement::Store { pointer, value: call });
+                Some(ctx.append(crate::Expression::Load { pointer }))
+            }
+        };
+        for (place, temporary) in copies {
+            let value = ctx.append(crate::Expression::Load { pointer: temporary });
+            self.assign(ctx, place, value)?;
+        }
+        Ok(match result {
+            Some(value) => Place::value(value),
+            None => Place { root: Root::Void, path: Vec::new() },
+        })
+    }
+
+    /// Parses `texture(samplerXX(image, sampler), coordinate)`.
+    fn parse_texture(&mut self, ctx: &mut Context) -> Result<Token<crate::Expression>, Error> {
+        self.expect("(")?;
+        let combined = match self.peek_identifier() {
+            Some(name) if is_combined_sampler(name) => name.to_owned(),
+            _ => return Err(self.error(ErrorKind::UnsupportedFeature("combined image samplers"))),
+        };
+        if combined.ends_with("Shadow") {
+            return Err(self.error(ErrorKind::UnsupportedFeature("depth comparison")));
+        }
+        self.position += 1;
+        self.expect("(")?;
+        let image = self.parse_conditional(ctx)?;
+        let image = self.read(ctx, image)?;
+        match self.resolve(ctx, image)? {
+            crate::TypeInner::Image { flags, .. } if flags.contains(crate::ImageFlags::SAMPLED) => (),
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        }
+        self.expect(",")?;
+        let sampler = self.parse_conditional(ctx)?;
+        let sampler = self.read(ctx, sampler)?;
+        match self.resolve(ctx, sampler)? {
+            crate::TypeInner::Sampler { .. } => (),
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        }
+        self.expect(")")?;
+        self.expect(",")?;
+        let coordinate = self.parse_conditional(ctx)?;
+        let coordinate = self.read(ctx, coordinate)?;
+        match self.resolve(ctx, coordinate)? {
+            crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, .. } |
+            crate::TypeInner::Vector { kind: crate::ScalarKind::Float, .. } => (),
+            _ => return Err(self.error(ErrorKind::TypeMismatch)),
+        }
+        if self.is_punct(",") {
+            return Err(self.error(ErrorKind::UnsupportedFeature("texture sampling bias")));
+        }
+        self.expect(")")?;
+        Ok(ctx.append(crate::Expression::ImageSample { image, sampler, coordinate }))
+    }
+
+    fn construct(
+        &mut self,
+        ctx: &mut Context,
+        ty: Token<crate::Type>,
+        name: &str,
+        values: Vec<Token<crate::Expression>>,
+    ) -> Result<Token<crate::Expression>, Error> {
+        use crate::TypeInner as Ti;
+        let invalid = |parser: &Self| parser.error(ErrorKind::InvalidArguments(name.to_owned()));
+        let mut ty = ty;
+        let components = match self.module.types[ty].inner.clone() {
+            Ti::Scalar { kind, .. } => {
+                let value = match values[..] {
+                    [value] => value,
+                    _ => return Err(invalid(self)),
+                };
+                return match self.resolve(ctx, value)? {
+                    Ti::Scalar { .. } => self.convert(ctx, value, kind, true),
+                    // the first component is taken
+                    Ti::Vector { kind: value_kind, .. } if value_kind == kind => {
+                        Ok(ctx.append(crate::Expression::AccessIndex { base: value, index: 0 }))
+                    }
+                    Ti::Matrix { kind: value_kind, .. } if value_kind == kind => {
+                        let column = ctx.append(crate::Expression::AccessIndex { base: value, index: 0 });
+                        Ok(ctx.append(crate::Expression::AccessIndex { base: column, index: 0 }))
+                    }
+                    _ => Err(self.error(ErrorKind::UnsupportedConversion)),
+                };
+            }
+            Ti::Vector { size, kind, .. } => {
+                if let [value] = values[..] {
+                    match self.resolve(ctx, value)? {
+                        Ti::Scalar { .. } => {
+                            let value = self.convert(ctx, value, kind, true)?;
+                            return self.splat(ctx, value, size);
+                        }
+                        Ti::Vector { size: value_size, kind: value_kind, .. } if value_kind == kind => {
+                            if value_size == size {
+                                return Ok(value);
+                            }
+                            // larger vectors are truncated
+                            if value_size as u8 > size as u8 {
+                                let components = (0 .. size as u32)
+                                    .map(|index| ctx.append(crate::Expression::AccessIndex { base: value, index }))
+                                    .collect();
+                                return Ok(ctx.append(crate::Expression::Compose { ty, components }));
+                            }
+                        }
+                        Ti::Vector { .. } => return Err(self.error(ErrorKind::UnsupportedConversion)),
+                        _ => (),
+                    }
+                }
+                let mut count = 0;
+                let mut components = Vec::with_capacity(values.len());
+                for value in values {
+                    match self.resolve(ctx, value)? {
+                        Ti::Scalar { .. } => {
+                            components.push(self.convert(ctx, value, kind, true)?);
+                            count += 1;
+                        }
+                        Ti::Vector { size: value_size, kind: value_kind, .. } if value_kind == kind => {
+                            components.push(value);
+                            count += value_size as usize;
+                        }
+                        _ => return Err(self.error(ErrorKind::UnsupportedConversion)),
+                    }
+                }
+                if count != size as usize {
+                    return Err(invalid(self));
+                }
+                components
+            }
+            Ti::Matrix { columns, rows, kind, width } => {
+                let column_ty = self.ensure_type(Ti::Vector { size: rows, kind, width });
+                let scalar_count = values.len();
+                let first = match values.first() {
+                    Some(&first) => self.resolve(ctx, first)?,
+                    None => return Err(invalid(self)),
+                };
+                match first {
+                    // a scalar makes a diagonal matrix
+                    Ti::Scalar { .. } if scalar_count == 1 => {
+                        let value = self.convert(ctx, values[0], kind, true)?;
+                        let zero = self.literal(ctx, crate::ConstantInner::Float(0.0));
+                        (0 .. columns as usize)
+                            .map(|column| {
+                                let components = (0 .. rows as usize)
+                                    .map(|row| if row == column { value } else { zero })
+                                    .collect();
+                                ctx.append(crate::Expression::Compose { ty: column_ty, components })
+                            })
+                            .collect()
+                    }
+                    Ti::Matrix { columns: c, rows: r, .. } if scalar_count == 1 => {
+                        if c == columns && r == rows {
+                            return Ok(values[0]);
+                        }
+                        return Err(self.error(ErrorKind::UnsupportedFeature("matrix resizing")));
+                    }
+                    Ti::Scalar { .. } if scalar_count == columns as usize * rows as usize => {
+                        let mut scalars = Vec::with_capacity(scalar_count);
+                        for value in values {
+                            scalars.push(self.convert(ctx, value, kind, true)?);
+                        }
+                        scalars
+                            .chunks(rows as usize)
+                            .map(|chunk| ctx.append(crate::Expression::Compose {
+                                ty: column_ty,
+                                components: chunk.to_vec(),
+                            }))
+                            .collect()
+                    }
+                    _ if values.len() == columns as usize => {
+                        let column_inner = self.module.types[column_ty].inner.clone();
+                        for &value in values.iter() {
+                            if self.resolve(ctx, value)? != column_inner {
+                                return Err(invalid(self));
+                            }
+                        }
+                        values
+                    }
+                    _ => return Err(invalid(self)),
+                }
+            }
+            Ti::Struct { members } => {
+                if members.len() != values.len() {
+                    return Err(invalid(self));
+                }
+                let mut components = Vec::with_capacity(values.len());
+                for (member, value) in members.iter().zip(values) {
+                    let inner = self.module.types[member.ty].inner.clone();
+                    components.push(self.coerce(ctx, value, &inner)?);
+                }
+                components
+            }
+            Ti::Array { base, size } => {
+                match size {
+                    crate::ArraySize::Static(length) if length as usize != values.len() => return Err(invalid(self)),
+                    crate::ArraySize::Static(_) => (),
+                    // the size is deduced from the arguments
+                    crate::ArraySize::Dynamic => {
+                        let size = crate::ArraySize::Static(values.len() as u32);
+                        ty = self.ensure_type(Ti::Array { base, size });
+                    }
+                }
+                let inner = self.module.types[base].inner.clone();
+                let mut components = Vec::with_capacity(values.len());
+                for value in values {
+                    components.push(self.coerce(ctx, value, &inner)?);
+                }
+                components
+            }
+            _ => return Err(self.error(ErrorKind::UnsupportedType(name.to_owned()))),
+        };
+        Ok(ctx.append(crate::Expression::Compose { ty, components }))
+    }
+
+    fn builtin_function(
+        &mut self,
+        ctx: &mut Context,
+        name: String,
+        mut values: Vec<Token<crate::Expression>>,
+    ) -> Result<Token<crate::Expression>, Error> {
+        use crate::{BinaryOperator as Bo, TypeInner as Ti};
+        let invalid = |parser: &Self| parser.error(ErrorKind::InvalidArguments(name.clone()));
+        let comparison = match name.as_str() {
+            "lessThan" => Some(Bo::Less),
+            "lessThanEqual" => Some(Bo::LessEqual),
+            "greaterThan" => Some(Bo::Greater),
+            "greaterThanEqual" => Some(Bo::GreaterEqual),
+            "equal" => Some(Bo::Equal),
+            "notEqual" => Some(Bo::NotEqual),
+            _ => None,
+        };
+        match (name.as_str(), &values[..]) {
+            ("dot", &[left, right]) => {
+                let (left, right) = self.unify(ctx, left, right)?;
+                let size = match (self.resolve(ctx, left)?, self.resolve(ctx, right)?) {
+                    (Ti::Vector { size, kind: crate::ScalarKind::Float, .. }, ref other)
+                        if *other == self.resolve(ctx, left)? => size as u32,
+                    _ => return Err(invalid(self)),
+                };
+                // the IR has no dot product, so the components are summed
+                let mut sum = None;
+                for index in 0 .. size {
+                    let a = ctx.append(crate::Expression::AccessIndex { base: left, index });
+                    let b = ctx.append(crate::Expression::AccessIndex { base: right, index });
+                    let product = ctx.append(crate::Expression::Binary { op: Bo::Multiply, left: a, right: b });
+                    sum = Some(match sum {
+                        Some(sum) => ctx.append(crate::Expression::Binary { op: Bo::Add, left: sum, right: product }),
+                        None => product,
+                    });
+                }
+                return Ok(sum.unwrap());
+            }
+            (_, &[left, right]) if comparison.is_some() => {
+                let (left, right) = self.unify(ctx, left, right)?;
+                let left_inner = self.resolve(ctx, left)?;
+                return match left_inner {
+                    Ti::Vector { .. } if left_inner == self.resolve(ctx, right)? => {
+                        let op = comparison.unwrap();
+                        Ok(ctx.append(crate::Expression::Binary { op, left, right }))
+                    }
+                    _ => Err(invalid(self)),
+                };
+            }
+            ("any", &[vector]) | ("all", &[vector]) => {
+                match self.resolve(ctx, vector)? {
+                    Ti::Vector { kind: crate::ScalarKind::Bool, .. } => (),
+                    _ => return Err(invalid(self)),
+                }
+                let op = if name == "any" { Bo::LogicalOr } else { Bo::LogicalAnd };
+                return self.reduce(ctx, vector, op);
+            }
+            ("not", &[vector]) => {
+                return match self.resolve(ctx, vector)? {
+                    Ti::Vector { kind: crate::ScalarKind::Bool, .. } => Ok(ctx.append(crate::Expression::Unary {
+                        op: crate::UnaryOperator::Not,
+                        expr: vector,
+                    })),
+                    _ => Err(invalid(self)),
+                };
+            }
+            // `mod` rounds towards the negative infinity, unlike the remainder
+            ("mod", &[x, y]) => {
+                let (x, y) = self.unify(ctx, x, y)?;
+                let quotient = self.binary(ctx, "/", x, y)?;
+                let floor = ctx.append(crate::Expression::Math {
+                    fun: crate::MathFunction::Floor,
+                    arguments: vec![quotient],
+                });
+                let product = self.binary(ctx, "*", y, floor)?;
+                return self.binary(ctx, "-", x, product);
+            }
+            _ => (),
+        }
+
+        let first = match values.first() {
+            Some(&first) => first,
+            None => return Err(self.error(ErrorKind::UnknownFunction(name))),
+        };
+        let first_inner = self.resolve(ctx, first)?;
+        let kind = scalar_kind(&first_inner);
+        let fun = match (name.as_str(), values.len()) {
+            ("atan", 2) => Some(crate::MathFunction::Atan2),
+            _ => kind.and_then(|kind| math_function(&name, kind)),
+        };
+        let fun = match fun {
+            Some(fun) => fun,
+            None => {
+                let kinds = [crate::ScalarKind::Float, crate::ScalarKind::Sint, crate::ScalarKind::Uint];
+                return Err(if kinds.iter().any(|&kind| math_function(&name, kind).is_some()) {
+                    invalid(self)
+                } else {
+                    self.error(ErrorKind::UnknownFunction(name))
+                });
+            }
+        };
+        if values.len() != fun.argument_count() {
+            return Err(invalid(self));
+        }
+        // the arguments follow the type of the first one, or the last
+        // one for the steps, and the scalars are turned into vectors
+        let shape = match fun {
+            crate::MathFunction::Step | crate::MathFunction::SmoothStep => *values.last().unwrap(),
+            _ => first,
+        };
+        let kind = kind.unwrap();
+        let shape_inner = self.resolve(ctx, shape)?;
+        for (index, value) in values.iter_mut().enumerate() {
+            let is_exempt = match fun {
+                crate::MathFunction::Ldexp => index == 1,
+                crate::MathFunction::Refract => index == 2,
+                _ => false,
+            };
+            if is_exempt || *value == shape {
+                continue;
+            }
+            let inner = self.resolve(ctx, *value)?;
+            if let Ti::Scalar { kind: value_kind, .. } = inner {
+                if value_kind != kind {
+                    *value = self.convert(ctx, *value, kind, false)?;
+                }
+                if let Ti::Vector { size, .. } = shape_inner {
+                    *value = self.splat(ctx, *value, size)?;
+                }
+            }
+        }
+        Ok(ctx.append(crate::Expression::Math { fun, arguments: values }))
+    }
+}
@@ -0,0 +1,528 @@
+use super::{
+    lex::{Lexeme, Lexer, Token},
+    Error, ErrorKind,
+};
+use crate::FastHashMap;
+
+#[derive(Clone, Debug)]
+struct Macro {
+    /// Parameters of a function-like macro.
+    params: Option<Vec<String>>,
+    body: Vec<Lexeme>,
+}
+
+/// State of a conditional section.
+#[derive(Clone, Copy, Debug)]
+struct Conditional {
+    /// Whether the enclosing section is included.
+    parent_active: bool,
+    /// Whether the current branch is included.
+    active: bool,
+    /// Whether any of the branches so far has been included.
+    taken: bool,
+    seen_else: bool,
+}
+
+pub struct Output {
+    pub tokens: Vec<Lexeme>,
+    /// Number and profile from the `#version` directive.
+    pub version: Option<(i64, Option<String>)>,
+}
+
+pub struct Preprocessor {
+    defines: FastHashMap<String, Macro>,
+    conditionals: Vec<Conditional>,
+    version: Option<(i64, Option<String>)>,
+}
+
+fn identifier(lexeme: &Lexeme) -> Option<&str> {
+    match lexeme.token {
+        Token::Identifier(ref name) => Some(name),
+        _ => None,
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        let mut preprocessor = Preprocessor {
+            defines: FastHashMap::default(),
+            conditionals: Vec::new(),
+            version: None,
+        };
+        for &(name, value) in &[("GL_core_profile", 1), ("VULKAN", 100)] {
+            preprocessor.define_value(name, Token::IntLiteral(value));
+        }
+        preprocessor
+    }
+
+    fn define_value(&mut self, name: &str, token: Token) {
+        let body = vec![Lexeme { token, line: 0, leading_space: false }];
+        self.defines.insert(name.to_owned(), Macro { params: None, body });
+    }
+
+    /// Defines an object-like macro, as if with a `#define` directive.
+    pub fn define(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let body = Lexer::new(value)
+            .tokenize()?
+            .into_iter()
+            .filter(|lexeme| lexeme.token != Token::Newline)
+            .collect();
+        self.defines.insert(name.to_owned(), Macro { params: None, body });
+        Ok(())
+    }
+
+    fn is_active(&self) -> bool {
+        self.conditionals.last().map_or(true, |c| c.active)
+    }
+
+    /// Collects the arguments of a macro invocation, starting after the
+    /// opening parenthesis, and returns them with the position after the closing one.
+    fn collect_arguments(
+        input: &[Lexeme],
+        mut position: usize,
+        name: &str,
+        line: u32,
+    ) -> Result<(Vec<Vec<Lexeme>>, usize), Error> {
+        let mut arguments = vec![Vec::new()];
+        let mut depth = 0;
+        loop {
+            let lexeme = input.get(position).ok_or_else(|| Error {
+                kind: ErrorKind::BadMacroArguments(name.to_owned()),
+                line,
+            })?;
+            position += 1;
+            match lexeme.token {
+                Token::Punct(")") if depth == 0 => break,
+                Token::Punct(",") if depth == 0 => {
+                    arguments.push(Vec::new());
+                    continue;
+                }
+                Token::Punct("(") => depth += 1,
+                Token::Punct(")") => depth -= 1,
+                Token::Newline => continue,
+                _ => (),
+            }
+            arguments.last_mut().unwrap().push(lexeme.clone());
+        }
+        Ok((arguments, position))
+    }
+
+    /// Joins the tokens around the `##` operators.
+    fn paste(body: Vec<Lexeme>) -> Result<Vec<Lexeme>, Error> {
+        let mut result: Vec<Lexeme> = Vec::new();
+        let mut lexemes = body.into_iter();
+        while let Some(lexeme) = lexemes.next() {
+            if lexeme.token != Token::Punct("##") {
+                result.push(lexeme);
+                continue;
+            }
+            let error = Error {
+                kind: ErrorKind::BadTokenPaste,
+                line: lexeme.line,
+            };
+            let left = result.pop().ok_or_else(|| error.clone())?;
+            let right = lexemes.next().ok_or(error)?;
+            let text = format!("{}{}", token_text(&left.token), token_text(&right.token));
+            for mut pasted in Lexer::new(&text).tokenize()? {
+                pasted.line = left.line;
+                result.push(pasted);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Replaces the macro invocations in the input, with the `disabled` macros
+    /// being the ones currently expanded, which aren't expanded again.
+    fn expand(
+        &self,
+        input: &[Lexeme],
+        disabled: &mut Vec<String>,
+        output: &mut Vec<Lexeme>,
+    ) -> Result<(), Error> {
+        let mut position = 0;
+        while position < input.len() {
+            let lexeme = &input[position];
+            position += 1;
+            let (name, mac) = match identifier(lexeme) {
+                Some(name) if !disabled.iter().any(|d| d == name) => match self.defines.get(name) {
+                    Some(mac) => (name, mac),
+                    None => {
+                        output.push(lexeme.clone());
+                        continue;
+                    }
+                },
+                _ => {
+                    output.push(lexeme.clone());
+                    continue;
+                }
+            };
+            let body = match mac.params {
+                None => mac.body.clone(),
+                Some(ref params) => {
+                    // the name of a function-like macro without arguments is left as is
+                    let next = input[position ..].iter().find(|l| l.token != Token::Newline);
+                    if next.map(|l| &l.token) != Some(&Token::Punct("(")) {
+                        output.push(lexeme.clone());
+                        continue;
+                    }
+                    while input[position].token == Token::Newline {
+                        position += 1;
+                    }
+                    let (mut arguments, next_position) =
+                        Self::collect_arguments(input, position + 1, name, lexeme.line)?;
+                    position = next_position;
+                    if params.is_empty() && arguments.len() == 1 && arguments[0].is_empty() {
+                        arguments.clear();
+                    }
+                    if arguments.len() != params.len() {
+                        return Err(Error {
+                            kind: ErrorKind::BadMacroArguments(name.to_owned()),
+                            line: lexeme.line,
+                        });
+                    }
+                    // arguments are expanded before the substitution
+                    let mut expanded_arguments = Vec::with_capacity(arguments.len());
+                    for argument in arguments {
+                        let mut expanded = Vec::new();
+                        self.expand(&argument, disabled, &mut expanded)?;
+                        expanded_arguments.push(expanded);
+                    }
+                    let mut body = Vec::new();
+                    for body_lexeme in mac.body.iter() {
+                        match identifier(body_lexeme).and_then(|id| params.iter().position(|p| p == id)) {
+                            Some(index) => body.extend(expanded_arguments[index].iter().cloned()),
+                            None => body.push(body_lexeme.clone()),
+                        }
+                    }
+                    body
+                }
+            };
+            let mut body = Self::paste(body)?;
+            for body_lexeme in body.iter_mut() {
+                body_lexeme.line = lexeme.line;
+            }
+            disabled.push(name.to_owned());
+            self.expand(&body, disabled, output)?;
+            disabled.pop();
+        }
+        Ok(())
+    }
+
+    fn directive_define(&mut self, args: &[Lexeme], line: u32) -> Result<(), Error> {
+        let name = args
+            .first()
+            .and_then(identifier)
+            .ok_or(Error { kind: ErrorKind::BadDirective("define".to_owned()), line })?
+            .to_owned();
+        let mut body = &args[1 ..];
+        // a function-like macro has the parenthesis right after the name
+        let params = match body.first() {
+            Some(lexeme) if lexeme.token == Token::Punct("(") && !lexeme.leading_space => {
+                let end = body
+                    .iter()
+                    .position(|l| l.token == Token::Punct(")"))
+                    .ok_or(Error { kind: ErrorKind::BadDirective("define".to_owned()), line })?;
+                let mut params = Vec::new();
+                for (i, lexeme) in body[1 .. end].iter().enumerate() {
+                    match (i % 2, &lexeme.token) {
+                        (0, Token::Identifier(param)) => params.push(param.clone()),
+                        (1, &Token::Punct(",")) => (),
+                        _ => return Err(Error { kind: ErrorKind::BadDirective("define".to_owned()), line }),
+                    }
+                }
+                body = &body[end + 1 ..];
+                Some(params)
+            }
+            _ => None,
+        };
+        self.defines.insert(name, Macro {
+            params,
+            body: body.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Evaluates the condition of an `#if` or `#elif` directive.
+    fn evaluate(&self, args: &[Lexeme], line: u32) -> Result<bool, Error> {
+        // `defined` has to be replaced before the macros are expanded
+        let mut replaced = Vec::new();
+        let mut position = 0;
+        while position < args.len() {
+            if identifier(&args[position]) != Some("defined") {
+                replaced.push(args[position].clone());
+                position += 1;
+                continue;
+            }
+            let parenthesized = args.get(position + 1).map(|l| &l.token) == Some(&Token::Punct("("));
+            let name_position = if parenthesized { position + 2 } else { position + 1 };
+            let name = args
+                .get(name_position)
+                .and_then(identifier)
+                .ok_or(Error { kind: ErrorKind::BadDirective("if".to_owned()), line })?;
+            position = name_position + 1;
+            if parenthesized {
+                if args.get(position).map(|l| &l.token) != Some(&Token::Punct(")")) {
+                    return Err(Error { kind: ErrorKind::BadDirective("if".to_owned()), line });
+                }
+                position += 1;
+            }
+            let value = self.defines.contains_key(name) as i64;
+            replaced.push(Lexeme { token: Token::IntLiteral(value), line, leading_space: true });
+        }
+        let mut expanded = Vec::new();
+        self.expand(&replaced, &mut Vec::new(), &mut expanded)?;
+        let tokens = expanded.into_iter().map(|l| l.token).collect::<Vec<_>>();
+        let mut evaluator = Evaluator { tokens: &tokens, position: 0 };
+        let value = evaluator.conditional();
+        match value {
+            Some(value) if evaluator.position == tokens.len() => Ok(value != 0),
+            _ => Err(Error { kind: ErrorKind::BadDirective("if".to_owned()), line }),
+        }
+    }
+
+    fn directive(&mut self, name: &str, args: &[Lexeme], line: u32) -> Result<(), Error> {
+        let active = self.is_active();
+        match name {
+            "ifdef" | "ifndef" | "if" => {
+                let value = if !active {
+                    false
+                } else if name == "if" {
+                    self.evaluate(args, line)?
+                } else {
+                    let macro_name = args
+                        .first()
+                        .and_then(identifier)
+                        .ok_or(Error { kind: ErrorKind::BadDirective(name.to_owned()), line })?;
+                    self.defines.contains_key(macro_name) == (name == "ifdef")
+                };
+                self.conditionals.push(Conditional {
+                    parent_active: active,
+                    active: value,
+                    taken: value,
+                    seen_else: false,
+                });
+            }
+            "elif" | "else" => {
+                let conditional = match self.conditionals.last() {
+                    Some(c) if !c.seen_else => *c,
+                    _ => return Err(Error { kind: ErrorKind::UnbalancedConditional, line }),
+                };
+                let value = if !conditional.parent_active || conditional.taken {
+                    false
+                } else if name == "elif" {
+                    self.evaluate(args, line)?
+                } else {
+                    true
+                };
+                *self.conditionals.last_mut().unwrap() = Conditional {
+                    active: value,
+                    taken: conditional.taken || value,
+                    seen_else: name == "else",
+                    ..conditional
+                };
+            }
+            "endif" => {
+                self.conditionals
+                    .pop()
+                    .ok_or(Error { kind: ErrorKind::UnbalancedConditional, line })?;
+            }
+            _ if !active => (),
+            "define" => self.directive_define(args, line)?,
+            "undef" => {
+                let macro_name = args
+                    .first()
+                    .and_then(identifier)
+                    .ok_or(Error { kind: ErrorKind::BadDirective(name.to_owned()), line })?;
+                self.defines.remove(macro_name);
+            }
+            "version" => {
+                let number = match args.first().map(|l| &l.token) {
+                    Some(&Token::IntLiteral(number)) => number,
+                    _ => return Err(Error { kind: ErrorKind::BadDirective(name.to_owned()), line }),
+                };
+                let profile = args.get(1).and_then(identifier).map(|p| p.to_owned());
+                self.version = Some((number, profile));
+                self.define_value("__VERSION__", Token::IntLiteral(number));
+            }
+            "error" => {
+                let message = args
+                    .iter()
+                    .map(|l| token_text(&l.token))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                return Err(Error { kind: ErrorKind::ErrorDirective(message), line });
+            }
+            "extension" | "pragma" | "line" => {
+                log::warn!("Ignoring #{} on line {}", name, line);
+            }
+            other => return Err(Error { kind: ErrorKind::BadDirective(other.to_owned()), line }),
+        }
+        Ok(())
+    }
+
+    /// Runs the directives and expands the macros.
+    pub fn process(mut self, input: Vec<Lexeme>) -> Result<Output, Error> {
+        let mut tokens = Vec::new();
+        // text between the directives, which may contain
+        // macro invocations spanning several lines
+        let mut text = Vec::new();
+        let mut position = 0;
+        let mut line_start = true;
+        while position < input.len() {
+            let lexeme = &input[position];
+            position += 1;
+            if line_start && lexeme.token == Token::Punct("#") {
+                let end = input[position ..]
+                    .iter()
+                    .position(|l| l.token == Token::Newline)
+                    .map_or(input.len(), |offset| position + offset);
+                let directive = &input[position .. end];
+                position = end;
+                self.expand(&text, &mut Vec::new(), &mut tokens)?;
+                text.clear();
+                if let Some((first, args)) = directive.split_first() {
+                    let name = identifier(first).ok_or(Error {
+                        kind: ErrorKind::BadDirective(token_text(&first.token)),
+                        line: first.line,
+                    })?;
+                    self.directive(name, args, first.line)?;
+                }
+                continue;
+            }
+            line_start = lexeme.token == Token::Newline;
+            if self.is_active() {
+                text.push(lexeme.clone());
+            }
+        }
+        if let Some(line) = input.last().map(|l| l.line) {
+            if !self.conditionals.is_empty() {
+                return Err(Error { kind: ErrorKind::UnbalancedConditional, line });
+            }
+        }
+        self.expand(&text, &mut Vec::new(), &mut tokens)?;
+        tokens.retain(|l| l.token != Token::Newline);
+        Ok(Output {
+            tokens,
+            version: self.version,
+        })
+    }
+}
+
+pub fn token_text(token: &Token) -> String {
+    match *token {
+        Token::Identifier(ref name) => name.clone(),
+        Token::IntLiteral(value) => value.to_string(),
+        Token::UintLiteral(value) => format!("{}u", value),
+        Token::FloatLiteral(value) => format!("{:?}", value),
+        Token::Punct(punct) => punct.to_owned(),
+        Token::Newline => "\n".to_owned(),
+    }
+}
+
+/// Integer expression of the `#if` directives.
+struct Evaluator<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Evaluator<'_> {
+    fn consume(&mut self, punct: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(&Token::Punct(p)) if p == punct => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn conditional(&mut self) -> Option<i64> {
+        let condition = self.binary(0)?;
+        if !self.consume("?") {
+            return Some(condition);
+        }
+        let accept = self.conditional()?;
+        if !self.consume(":") {
+            return None;
+        }
+        let reject = self.conditional()?;
+        Some(if condition != 0 { accept } else { reject })
+    }
+
+    fn operator(&self, min_precedence: u8) -> Option<(&'static str, u8)> {
+        match self.tokens.get(self.position) {
+            Some(&Token::Punct(op)) => binary_precedence(op)
+                .filter(|&precedence| precedence >= min_precedence)
+                .map(|precedence| (op, precedence)),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Option<i64> {
+        let mut left = self.unary()?;
+        while let Some((op, precedence)) = self.operator(min_precedence) {
+            self.position += 1;
+            let right = self.binary(precedence + 1)?;
+            left = match op {
+                "||" => ((left != 0) || (right != 0)) as i64,
+                "&&" => ((left != 0) && (right != 0)) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<<" => left.checked_shl(right as u32)?,
+                ">>" => left.checked_shr(right as u32)?,
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" => left.checked_div(right)?,
+                "%" => left.checked_rem(right)?,
+                _ => return None,
+            };
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        match *token {
+            Token::IntLiteral(value) => Some(value),
+            Token::UintLiteral(value) => Some(value as i64),
+            // identifiers that aren't macros evaluate to 0
+            Token::Identifier(_) => Some(0),
+            Token::Punct("(") => {
+                let value = self.conditional()?;
+                if self.consume(")") { Some(value) } else { None }
+            }
+            Token::Punct("-") => self.unary().map(|v| v.wrapping_neg()),
+            Token::Punct("+") => self.unary(),
+            Token::Punct("!") => self.unary().map(|v| (v == 0) as i64),
+            Token::Punct("~") => self.unary().map(|v| !v),
+            _ => None,
+        }
+    }
+}
+
+/// Precedence of a binary operator, shared with the parser.
+pub fn binary_precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "^^" => 2,
+        "&&" => 3,
+        "|" => 4,
+        "^" => 5,
+        "&" => 6,
+        "==" | "!=" => 7,
+        "<" | ">" | "<=" | ">=" => 8,
+        "<<" | ">>" => 9,
+        "+" | "-" => 10,
+        "*" | "/" | "%" => 11,
+        _ => return None,
+    })
+}
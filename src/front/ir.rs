@@ -0,0 +1,989 @@
+/*! Textual IR frontend
+
+Parses the text format printed by `back::ir` into a module. The types,
+constants, global variables and functions are declared in the order of
+their arenas, but their labels can be referred to before the declaration.
+Expressions are defined as `%N = ...`, where `N` has to be the index of
+the expression in its function.
+
+Comments start with `//` and run until the end of the line.
+
+The text doesn't carry spans, so the parsed module has none.
+!*/
+
+use crate::{
+    back::ir::{scalar_kind_name, BINARY_OPERATORS, IMAGE_FLAGS, MATH_FUNCTIONS, UNARY_OPERATORS},
+    front::spvasm::grammar::{EnumKind, MaskKind},
+    storage::{Storage, Token as Handle},
+    FastHashMap,
+};
+
+use num_traits::FromPrimitive;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    UnexpectedToken { expected: &'static str, found: String },
+    UnexpectedEnd,
+    BadNumber(String),
+    UnknownLabel(String),
+    DuplicateLabel(String),
+    UnknownName(String),
+    /// The expression defined is not the next one of the function.
+    UnexpectedExpression(String),
+    /// The expression referred to is not defined in the function.
+    UnknownExpression(u32),
+}
+
+/// Error with the source line it occurred on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Label(String),
+    String(String),
+    Word(String),
+    Punct(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match *self {
+            Token::Label(ref label) => format!("%{}", label),
+            Token::String(ref string) => format!("{:?}", string),
+            Token::Word(ref word) => word.clone(),
+            Token::Punct(punct) => punct.to_owned(),
+        }
+    }
+}
+
+struct Lexeme {
+    token: Token,
+    line: u32,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn tokenize(source: &str) -> Result<Vec<Lexeme>, Error> {
+    let mut lexemes = Vec::new();
+    let mut line = 1;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let error = move |kind| Error { kind, line };
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            _ if c.is_whitespace() => continue,
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().map_or(false, |&c| c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '-' if chars.peek() == Some(&'>') => {
+                chars.next();
+                Token::Punct("->")
+            }
+            '=' => Token::Punct("="),
+            '(' => Token::Punct("("),
+            ')' => Token::Punct(")"),
+            '{' => Token::Punct("{"),
+            '}' => Token::Punct("}"),
+            ',' => Token::Punct(","),
+            '|' => Token::Punct("|"),
+            '%' => {
+                let mut label = String::new();
+                while let Some(&c) = chars.peek().filter(|&&c| is_word_char(c)) {
+                    label.push(c);
+                    chars.next();
+                }
+                if label.is_empty() {
+                    return Err(error(ErrorKind::UnexpectedCharacter(c)));
+                }
+                Token::Label(label)
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => string.push(c),
+                            None => return Err(error(ErrorKind::UnterminatedString)),
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            string.push(c);
+                        }
+                        None => return Err(error(ErrorKind::UnterminatedString)),
+                    }
+                }
+                Token::String(string)
+            }
+            _ if is_word_char(c) || c == '-' || c == '+' => {
+                let numeric = !c.is_ascii_alphabetic();
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    let is_sign = numeric && (next == '-' || next == '+') && word.ends_with(['e', 'E']);
+                    if !is_word_char(next) && !is_sign {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+            _ => return Err(error(ErrorKind::UnexpectedCharacter(c))),
+        };
+        lexemes.push(Lexeme { token, line });
+    }
+    Ok(lexemes)
+}
+
+/// Labels of the declarations of one kind, with their indices.
+type Labels = FastHashMap<String, u32>;
+
+/// Names of the enumerants used in the text.
+struct Names {
+    storage_classes: FastHashMap<String, u32>,
+    built_ins: FastHashMap<String, u32>,
+    dims: FastHashMap<String, u32>,
+    exec_models: FastHashMap<String, u32>,
+}
+
+struct Parser<'a> {
+    lexemes: &'a [Lexeme],
+    position: usize,
+    names: Names,
+    types: Labels,
+    constants: Labels,
+    globals: Labels,
+    functions: Labels,
+    locals: Labels,
+    /// The highest expression referred to in the current function.
+    max_expression: Option<(u32, u32)>,
+}
+
+impl<'a> Parser<'a> {
+    fn line(&self) -> u32 {
+        match self.lexemes.get(self.position) {
+            Some(lexeme) => lexeme.line,
+            None => self.lexemes.last().map_or(1, |lexeme| lexeme.line),
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error { kind, line: self.line() }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.lexemes.get(self.position).map(|lexeme| &lexeme.token)
+    }
+
+    fn unexpected(&mut self, expected: &'static str) -> Error {
+        match self.peek() {
+            Some(token) => self.error(ErrorKind::UnexpectedToken { expected, found: token.describe() }),
+            None => self.error(ErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn skip_punct(&mut self, punct: &'static str) -> bool {
+        if self.peek() == Some(&Token::Punct(punct)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, punct: &'static str) -> Result<(), Error> {
+        if self.skip_punct(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(punct))
+        }
+    }
+
+    fn peek_word(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn skip_word(&mut self, word: &str) -> bool {
+        if self.peek_word() == Some(word) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_word(&mut self, word: &'static str) -> Result<(), Error> {
+        if self.skip_word(word) {
+            Ok(())
+        } else {
+            Err(self.unexpected(word))
+        }
+    }
+
+    fn word(&mut self) -> Result<&'a str, Error> {
+        match self.peek_word() {
+            Some(word) => {
+                self.position += 1;
+                Ok(word)
+            }
+            None => Err(self.unexpected("word")),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, Error> {
+        let word = self.word()?;
+        word.parse().map_err(|_| Error {
+            kind: ErrorKind::BadNumber(word.to_owned()),
+            line: self.lexemes[self.position - 1].line,
+        })
+    }
+
+    fn label(&mut self) -> Result<&'a str, Error> {
+        match self.peek() {
+            Some(Token::Label(label)) => {
+                self.position += 1;
+                Ok(label)
+            }
+            _ => Err(self.unexpected("label")),
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a str, Error> {
+        match self.peek() {
+            Some(Token::String(string)) => {
+                self.position += 1;
+                Ok(string)
+            }
+            _ => Err(self.unexpected("string")),
+        }
+    }
+
+    fn optional_string(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Token::String(string)) => {
+                self.position += 1;
+                Some(string.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn resolve<T>(&mut self, labels: fn(&Self) -> &Labels) -> Result<Handle<T>, Error> {
+        let label = self.label()?;
+        match labels(self).get(label) {
+            Some(&index) => Ok(Handle::new(index)),
+            None => Err(Error {
+                kind: ErrorKind::UnknownLabel(label.to_owned()),
+                line: self.lexemes[self.position - 1].line,
+            }),
+        }
+    }
+
+    fn ty(&mut self) -> Result<Handle<crate::Type>, Error> {
+        self.resolve(|parser| &parser.types)
+    }
+
+    fn expression(&mut self) -> Result<Handle<crate::Expression>, Error> {
+        let label = self.label()?;
+        let line = self.lexemes[self.position - 1].line;
+        let index = label.parse::<u32>().map_err(|_| Error {
+            kind: ErrorKind::UnknownLabel(label.to_owned()),
+            line,
+        })?;
+        if self.max_expression.map_or(true, |(max, _)| index > max) {
+            self.max_expression = Some((index, line));
+        }
+        Ok(Handle::new(index))
+    }
+
+    /// Parses an expression reference that may be omitted at the end of an instruction.
+    fn optional_expression(&mut self) -> Result<Option<Handle<crate::Expression>>, Error> {
+        // a label followed by `=` starts the next expression instead
+        let present = match (self.peek(), self.lexemes.get(self.position + 1)) {
+            (Some(&Token::Label(_)), Some(next)) => next.token != Token::Punct("="),
+            (Some(&Token::Label(_)), None) => true,
+            _ => false,
+        };
+        if present {
+            self.expression().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn expressions(&mut self) -> Result<Vec<Handle<crate::Expression>>, Error> {
+        let mut expressions = Vec::new();
+        self.expect_punct("(")?;
+        if !self.skip_punct(")") {
+            loop {
+                expressions.push(self.expression()?);
+                if self.skip_punct(")") {
+                    break;
+                }
+                self.expect_punct(",")?;
+            }
+        }
+        Ok(expressions)
+    }
+
+    fn lookup<T: Copy>(&mut self, pairs: impl IntoIterator<Item = (String, T)>) -> Result<T, Error> {
+        let word = self.word()?;
+        pairs
+            .into_iter()
+            .find(|(name, _)| name == word)
+            .map(|(_, value)| value)
+            .ok_or_else(|| Error {
+                kind: ErrorKind::UnknownName(word.to_owned()),
+                line: self.lexemes[self.position - 1].line,
+            })
+    }
+
+    fn enumerant<T: FromPrimitive>(&mut self, names: fn(&Names) -> &FastHashMap<String, u32>) -> Result<T, Error> {
+        let word = self.word()?;
+        match names(&self.names).get(word).and_then(|&value| T::from_u32(value)) {
+            Some(value) => Ok(value),
+            None => Err(Error {
+                kind: ErrorKind::UnknownName(word.to_owned()),
+                line: self.lexemes[self.position - 1].line,
+            }),
+        }
+    }
+
+    fn scalar_kind(&mut self) -> Result<crate::ScalarKind, Error> {
+        let kinds = [
+            crate::ScalarKind::Sint,
+            crate::ScalarKind::Uint,
+            crate::ScalarKind::Float,
+            crate::ScalarKind::Bool,
+        ];
+        self.lookup(kinds.iter().map(|&kind| (scalar_kind_name(kind).to_owned(), kind)))
+    }
+
+    fn vector_size(&mut self) -> Result<crate::VectorSize, Error> {
+        let sizes = [crate::VectorSize::Bi, crate::VectorSize::Tri, crate::VectorSize::Quad];
+        self.lookup(sizes.iter().map(|&size| ((size as u8).to_string(), size)))
+    }
+
+    fn binding(&mut self) -> Result<Option<crate::Binding>, Error> {
+        let binding = match self.peek_word() {
+            Some("builtin") => {
+                self.position += 1;
+                self.expect_punct("(")?;
+                crate::Binding::BuiltIn(self.enumerant(|names| &names.built_ins)?)
+            }
+            Some("location") => {
+                self.position += 1;
+                self.expect_punct("(")?;
+                crate::Binding::Location(self.number()?)
+            }
+            Some("descriptor") => {
+                self.position += 1;
+                self.expect_punct("(")?;
+                let set = self.number()?;
+                self.expect_punct(",")?;
+                crate::Binding::Descriptor { set, binding: self.number()? }
+            }
+            _ => return Ok(None),
+        };
+        self.expect_punct(")")?;
+        Ok(Some(binding))
+    }
+
+    fn type_inner(&mut self) -> Result<crate::TypeInner, Error> {
+        Ok(match self.word()? {
+            "scalar" => crate::TypeInner::Scalar {
+                kind: self.scalar_kind()?,
+                width: self.number()?,
+            },
+            "vector" => crate::TypeInner::Vector {
+                size: self.vector_size()?,
+                kind: self.scalar_kind()?,
+                width: self.number()?,
+            },
+            "matrix" => crate::TypeInner::Matrix {
+                columns: self.vector_size()?,
+                rows: self.vector_size()?,
+                kind: self.scalar_kind()?,
+                width: self.number()?,
+            },
+            "pointer" => {
+                let class = self.enumerant(|names| &names.storage_classes)?;
+                crate::TypeInner::Pointer { base: self.ty()?, class }
+            }
+            "array" => {
+                let base = self.ty()?;
+                let size = if self.skip_word("dynamic") {
+                    crate::ArraySize::Dynamic
+                } else {
+                    crate::ArraySize::Static(self.number()?)
+                };
+                crate::TypeInner::Array { base, size }
+            }
+            "struct" => {
+                self.expect_punct("{")?;
+                let mut members = Vec::new();
+                while !self.skip_punct("}") {
+                    let ty = self.ty()?;
+                    let name = self.optional_string();
+                    let binding = self.binding()?;
+                    let offset = if self.skip_word("offset") {
+                        self.expect_punct("(")?;
+                        let offset = self.number()?;
+                        self.expect_punct(")")?;
+                        Some(offset)
+                    } else {
+                        None
+                    };
+                    members.push(crate::StructMember { name, binding, offset, ty });
+                }
+                crate::TypeInner::Struct { members }
+            }
+            "image" => {
+                let base = self.ty()?;
+                let dim = self.enumerant(|names| &names.dims)?;
+                let mut flags = crate::ImageFlags::empty();
+                if !self.skip_word("none") {
+                    loop {
+                        flags |= self.lookup(IMAGE_FLAGS.iter().map(|&(name, flag)| (name.to_owned(), flag)))?;
+                        if !self.skip_punct("|") {
+                            break;
+                        }
+                    }
+                }
+                crate::TypeInner::Image { base, dim, flags }
+            }
+            "sampler" => crate::TypeInner::Sampler { comparison: self.skip_word("comparison") },
+            _ => {
+                self.position -= 1;
+                return Err(self.unexpected("type"));
+            }
+        })
+    }
+
+    fn constant_inner(&mut self) -> Result<crate::ConstantInner, Error> {
+        Ok(match self.word()? {
+            "Sint" => crate::ConstantInner::Sint(self.number()?),
+            "Uint" => crate::ConstantInner::Uint(self.number()?),
+            "Float" => crate::ConstantInner::Float(self.number()?),
+            "Bool" => crate::ConstantInner::Bool(self.number()?),
+            _ => {
+                self.position -= 1;
+                return Err(self.unexpected("constant kind"));
+            }
+        })
+    }
+
+    fn expression_inner(&mut self) -> Result<crate::Expression, Error> {
+        Ok(match self.word()? {
+            "access" => crate::Expression::Access {
+                base: self.expression()?,
+                index: self.expression()?,
+            },
+            "access_index" => crate::Expression::AccessIndex {
+                base: self.expression()?,
+                index: self.number()?,
+            },
+            "constant" => crate::Expression::Constant(self.resolve(|parser| &parser.constants)?),
+            "compose" => crate::Expression::Compose {
+                ty: self.ty()?,
+                components: self.expressions()?,
+            },
+            "parameter" => crate::Expression::FunctionParameter(self.number()?),
+            "global" => crate::Expression::GlobalVariable(self.resolve(|parser| &parser.globals)?),
+            "local" => crate::Expression::LocalVariable(self.resolve(|parser| &parser.locals)?),
+            "load" => crate::Expression::Load { pointer: self.expression()? },
+            "image_sample" => crate::Expression::ImageSample {
+                image: self.expression()?,
+                sampler: self.expression()?,
+                coordinate: self.expression()?,
+            },
+            "image_load" => crate::Expression::ImageLoad {
+                image: self.expression()?,
+                coordinate: self.expression()?,
+                index: self.optional_expression()?,
+            },
+            "image_dimensions" => crate::Expression::ImageDimensions {
+                image: self.expression()?,
+                level: self.optional_expression()?,
+            },
+            "unary" => crate::Expression::Unary {
+                op: self.lookup(UNARY_OPERATORS.iter().map(|&op| (format!("{:?}", op), op)))?,
+                expr: self.expression()?,
+            },
+            "binary" => crate::Expression::Binary {
+                op: self.lookup(BINARY_OPERATORS.iter().map(|&op| (format!("{:?}", op), op)))?,
+                left: self.expression()?,
+                right: self.expression()?,
+            },
+            "call" => crate::Expression::Call {
+                function: self.resolve(|parser| &parser.functions)?,
+                arguments: self.expressions()?,
+            },
+            "math" => crate::Expression::Math {
+                fun: self.lookup(MATH_FUNCTIONS.iter().map(|&fun| (format!("{:?}", fun), fun)))?,
+                arguments: self.expressions()?,
+            },
+            _ => {
+                self.position -= 1;
+                return Err(self.unexpected("expression"));
+            }
+        })
+    }
+
+    fn block(&mut self) -> Result<crate::Block, Error> {
+        self.expect_punct("{")?;
+        let mut block = Vec::new();
+        while !self.skip_punct("}") {
+            block.push(self.statement()?);
+        }
+        Ok(block)
+    }
+
+    fn statement(&mut self) -> Result<crate::Statement, Error> {
+        Ok(match self.word()? {
+            "block" => crate::Statement::Block(self.block()?),
+            "if" => {
+                let condition = self.expression()?;
+                let accept = self.block()?;
+                let reject = if self.skip_word("else") {
+                    self.block()?
+                } else {
+                    Vec::new()
+                };
+                crate::Statement::If { condition, accept, reject }
+            }
+            "switch" => {
+                let selector = self.expression()?;
+                let mut cases = Vec::new();
+                let mut default = Vec::new();
+                self.expect_punct("{")?;
+                while !self.skip_punct("}") {
+                    if self.skip_word("default") {
+                        default = self.block()?;
+                        continue;
+                    }
+                    self.expect_word("case")?;
+                    let value = self.number()?;
+                    let fall_through = if self.skip_word("fallthrough") {
+                        Some(crate::FallThrough)
+                    } else {
+                        None
+                    };
+                    cases.push((value, self.block()?, fall_through));
+                }
+                crate::Statement::Switch { selector, cases, default }
+            }
+            "loop" => {
+                let body = self.block()?;
+                let continuing = if self.skip_word("continuing") {
+                    self.block()?
+                } else {
+                    Vec::new()
+                };
+                crate::Statement::Loop { body, continuing }
+            }
+            "break" => crate::Statement::Break,
+            "continue" => crate::Statement::Continue,
+            "return" => crate::Statement::Return { value: self.optional_expression()? },
+            "kill" => crate::Statement::Kill,
+            "store" => crate::Statement::Store {
+                pointer: self.expression()?,
+                value: self.expression()?,
+            },
+            "image_store" => crate::Statement::ImageStore {
+                image: self.expression()?,
+                coordinate: self.expression()?,
+                value: self.expression()?,
+            },
+            "call" => crate::Statement::Call {
+                function: self.resolve(|parser| &parser.functions)?,
+                arguments: self.expressions()?,
+            },
+            _ => {
+                self.position -= 1;
+                return Err(self.unexpected("statement"));
+            }
+        })
+    }
+
+    fn function(&mut self) -> Result<crate::Function, Error> {
+        self.label()?;
+        let name = self.optional_string();
+        let mut parameter_types = Vec::new();
+        self.expect_punct("(")?;
+        if !self.skip_punct(")") {
+            loop {
+                parameter_types.push(self.ty()?);
+                if self.skip_punct(")") {
+                    break;
+                }
+                self.expect_punct(",")?;
+            }
+        }
+        self.expect_punct("->")?;
+        let return_type = if self.skip_word("void") { None } else { Some(self.ty()?) };
+        let mut control = spirv::FunctionControl::empty();
+        if self.skip_word("control") {
+            loop {
+                let bits = MaskKind::FunctionControl.bits();
+                let bit = self.lookup(bits.iter().map(|&(name, bit, _)| (name.to_owned(), bit)))?;
+                control |= spirv::FunctionControl::from_bits_truncate(bit);
+                if !self.skip_punct("|") {
+                    break;
+                }
+            }
+        }
+
+        let mut fun = crate::Function {
+            name,
+            control,
+            parameter_types,
+            return_type,
+            local_variables: Storage::new(),
+            expressions: Storage::new(),
+            body: Vec::new(),
+            spans: Default::default(),
+        };
+        self.locals.clear();
+        self.max_expression = None;
+        // the local variables are declared before they are used
+        self.expect_punct("{")?;
+        while !self.skip_punct("}") {
+            match self.peek() {
+                Some(Token::Word(word)) if word == "local" => {
+                    self.position += 1;
+                    let label = self.label()?;
+                    let index = fun.local_variables.len() as u32;
+                    if self.locals.insert(label.to_owned(), index).is_some() {
+                        return Err(Error {
+                            kind: ErrorKind::DuplicateLabel(label.to_owned()),
+                            line: self.lexemes[self.position - 1].line,
+                        });
+                    }
+                    let name = self.optional_string();
+                    let ty = self.ty()?;
+                    let init = if self.skip_punct("=") {
+                        Some(self.resolve(|parser| &parser.constants)?)
+                    } else {
+                        None
+                    };
+                    fun.local_variables.append(crate::LocalVariable { name, ty, init });
+                }
+                Some(Token::Label(label)) => {
+                    if label.parse() != Ok(fun.expressions.len()) {
+                        return Err(self.error(ErrorKind::UnexpectedExpression(label.clone())));
+                    }
+                    self.position += 1;
+                    self.expect_punct("=")?;
+                    let expr = self.expression_inner()?;
+                    fun.expressions.append(expr);
+                }
+                _ => {
+                    let statement = self.statement()?;
+                    fun.body.push(statement);
+                }
+            }
+        }
+        if let Some((index, line)) = self.max_expression {
+            if index as usize >= fun.expressions.len() {
+                return Err(Error { kind: ErrorKind::UnknownExpression(index), line });
+            }
+        }
+        Ok(fun)
+    }
+
+    /// Collects the labels of the declarations, so that they can be
+    /// referred to before being declared.
+    fn collect_labels(&mut self) -> Result<(), Error> {
+        let mut depth = 0;
+        let lexemes = self.lexemes;
+        for (index, lexeme) in lexemes.iter().enumerate() {
+            let labels = match lexeme.token {
+                Token::Punct("{") => {
+                    depth += 1;
+                    continue;
+                }
+                Token::Punct("}") => {
+                    depth -= 1;
+                    continue;
+                }
+                Token::Word(ref word) if depth == 0 => match word.as_str() {
+                    "type" => &mut self.types,
+                    "const" => &mut self.constants,
+                    "global" => &mut self.globals,
+                    "function" => &mut self.functions,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if let Some(next) = lexemes.get(index + 1) {
+                if let Token::Label(ref label) = next.token {
+                    let count = labels.len() as u32;
+                    if labels.insert(label.clone(), count).is_some() {
+                        return Err(Error {
+                            kind: ErrorKind::DuplicateLabel(label.clone()),
+                            line: next.line,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse(&mut self) -> Result<crate::Module, Error> {
+        self.collect_labels()?;
+        let mut module = crate::Module::generate_empty();
+        if self.skip_word("version") {
+            let word = self.word()?;
+            let mut parts = word.split('.').map(|part| part.parse::<u8>().ok());
+            module.header.version = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => (major, minor, patch),
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error(ErrorKind::BadNumber(word.to_owned())));
+                }
+            };
+        }
+        if self.skip_word("generator") {
+            module.header.generator = self.number()?;
+        }
+
+        while self.peek().is_some() {
+            match self.word()? {
+                "type" => {
+                    self.label()?;
+                    let name = self.optional_string();
+                    self.expect_punct("=")?;
+                    let inner = self.type_inner()?;
+                    module.types.append(crate::Type { name, inner });
+                }
+                "const" => {
+                    self.label()?;
+                    let name = self.optional_string();
+                    let specialization = if self.skip_word("spec") {
+                        self.expect_punct("(")?;
+                        let id = self.number()?;
+                        self.expect_punct(")")?;
+                        Some(id)
+                    } else {
+                        None
+                    };
+                    self.expect_punct("=")?;
+                    let inner = self.constant_inner()?;
+                    module.constants.append(crate::Constant { name, specialization, inner });
+                }
+                "global" => {
+                    self.label()?;
+                    let name = self.optional_string();
+                    let class = self.enumerant(|names| &names.storage_classes)?;
+                    let ty = self.ty()?;
+                    let binding = self.binding()?;
+                    module.global_variables.append(crate::GlobalVariable { name, class, binding, ty });
+                }
+                "function" => {
+                    let fun = self.function()?;
+                    module.functions.append(fun);
+                }
+                "entry_point" => {
+                    let exec_model = self.enumerant(|names| &names.exec_models)?;
+                    let name = self.string()?.to_owned();
+                    self.expect_punct("=")?;
+                    let function = self.resolve(|parser| &parser.functions)?;
+                    let mut vars = [Vec::new(), Vec::new()];
+                    for (&keyword, vars) in ["in", "out"].iter().zip(vars.iter_mut()) {
+                        self.expect_word(keyword)?;
+                        self.expect_punct("(")?;
+                        if !self.skip_punct(")") {
+                            loop {
+                                vars.push(self.resolve(|parser| &parser.globals)?);
+                                if self.skip_punct(")") {
+                                    break;
+                                }
+                                self.expect_punct(",")?;
+                            }
+                        }
+                    }
+                    let [inputs, outputs] = vars;
+                    module.entry_points.push(crate::EntryPoint { exec_model, name, inputs, outputs, function });
+                }
+                _ => {
+                    self.position -= 1;
+                    return Err(self.unexpected("declaration"));
+                }
+            }
+        }
+        Ok(module)
+    }
+}
+
+pub fn parse_str(source: &str) -> Result<crate::Module, Error> {
+    let lexemes = tokenize(source)?;
+    let mut parser = Parser {
+        lexemes: &lexemes,
+        position: 0,
+        names: Names {
+            storage_classes: EnumKind::StorageClass.values(),
+            built_ins: EnumKind::BuiltIn.values(),
+            dims: EnumKind::Dim.values(),
+            exec_models: EnumKind::ExecutionModel.values(),
+        },
+        types: Labels::default(),
+        constants: Labels::default(),
+        globals: Labels::default(),
+        functions: Labels::default(),
+        locals: Labels::default(),
+        max_expression: None,
+    };
+    parser.parse()
+}
+
+#[cfg(test)]
+mod test {
+    fn round_trip(module: &mut crate::Module) {
+        let text = crate::back::ir::write_string(module).unwrap();
+        let parsed = super::parse_str(&text).unwrap();
+        // the spans are left out of the format, so they are checked on their own
+        assert_eq!(format!("{:?}", parsed.spans), format!("{:?}", crate::ModuleSpans::default()));
+        module.spans = Default::default();
+        for ((_, fun), (_, parsed_fun)) in module.functions.iter_mut().zip(parsed.functions.iter()) {
+            assert_eq!(format!("{:?}", parsed_fun.spans), format!("{:?}", crate::FunctionSpans::default()));
+            fun.spans = Default::default();
+        }
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", module));
+        assert_eq!(crate::back::ir::write_string(&parsed).unwrap(), text);
+    }
+
+    #[test]
+    fn round_trip_wgsl() {
+        let mut module = crate::front::wgsl::parse_str("
+            import \"GLSL.std.450\" as std;
+            type Globals = [[block]] struct {
+                [[offset 0]] offset : vec2<f32>;
+                [[offset 8]] scale : f32;
+            };
+            [[binding 0, set 1]] var<uniform> globals : Globals;
+            [[location 0]] var<in> a_pos : vec2<f32>;
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            fn scale(x : f32) -> f32 {
+                return x * 2.0;
+            }
+            fn main() -> void {
+                var w : f32 = 1.0;
+                w = std::sqrt(scale(w));
+                o_position = vec4<f32>(a_pos, 0.0, w);
+                return;
+            }
+            entry_point vertex as \"main\" = main;
+        ").unwrap();
+        round_trip(&mut module);
+    }
+
+    #[test]
+    fn round_trip_glsl() {
+        let source = "
+            #version 450
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 0) out vec4 o_color;
+            layout(set = 0, binding = 0) uniform texture2D u_texture;
+            layout(set = 0, binding = 1) uniform sampler u_sampler;
+            void main() {
+                vec4 color = texture(sampler2D(u_texture, u_sampler), v_uv);
+                for (int i = 0; i < 4; i++) {
+                    if (color.w > 0.5) {
+                        break;
+                    }
+                    color = color * 2.0;
+                }
+                int mode = 1;
+                switch (mode) {
+                    case 0: color.y = 1.0;
+                    case 1: color.z = 1.0; break;
+                    default: discard;
+                }
+                o_color = color;
+            }
+        ";
+        let options = crate::front::glsl::Options {
+            entry_point: (spirv::ExecutionModel::Fragment, "main".to_owned()),
+            defines: Default::default(),
+        };
+        let mut module = crate::front::glsl::parse_str(source, &options).unwrap();
+        round_trip(&mut module);
+    }
+
+    #[test]
+    fn parse_hand_written() {
+        let mut module = super::parse_str("
+            version 1.0.0
+            generator 0
+
+            type %f32 = scalar Float 32
+            type %vec4 \"vec4\" = vector 4 Float 32
+            const %one = Float 1.0
+            global %color \"color\" Output %vec4 location(0)
+
+            // the body refers to the expressions by index
+            function %main \"main\"() -> void {
+                %0 = global %color
+                %1 = constant %one
+                %2 = compose %vec4(%1, %1, %1, %1)
+                store %0 %2
+                return
+            }
+
+            entry_point Fragment \"main\" = %main in() out(%color)
+        ").unwrap();
+        assert_eq!(module.types.len(), 2);
+        assert_eq!(module.entry_points[0].outputs.len(), 1);
+        let fun = module.functions.iter().next().unwrap().1;
+        assert_eq!(fun.expressions.len(), 3);
+        assert_eq!(fun.body.len(), 2);
+        crate::valid::Validator::new().validate(&module).unwrap();
+        round_trip(&mut module);
+    }
+
+    #[test]
+    fn parse_errors() {
+        use super::{Error, ErrorKind};
+        assert_eq!(
+            super::parse_str("type %a = scalar Float 32\ntype %a = sampler").unwrap_err(),
+            Error { kind: ErrorKind::DuplicateLabel("a".to_owned()), line: 2 },
+        );
+        assert_eq!(
+            super::parse_str("global %g Output %missing").unwrap_err(),
+            Error { kind: ErrorKind::UnknownLabel("missing".to_owned()), line: 1 },
+        );
+        assert_eq!(
+            super::parse_str("function %f() -> void {\n    %1 = parameter 0\n}").unwrap_err(),
+            Error { kind: ErrorKind::UnexpectedExpression("1".to_owned()), line: 2 },
+        );
+        assert_eq!(
+            super::parse_str("function %f() -> void {\n    %0 = load %3\n}").unwrap_err(),
+            Error { kind: ErrorKind::UnknownExpression(3), line: 2 },
+        );
+        assert_eq!(
+            super::parse_str("const %c = Float 1.0\nconst %d = Float one").unwrap_err(),
+            Error { kind: ErrorKind::BadNumber("one".to_owned()), line: 2 },
+        );
+    }
+}
@@ -1,4 +1,7 @@
+pub mod glsl;
+pub mod ir;
 pub mod spirv;
+pub mod spvasm;
 pub mod wgsl;
 
 use crate::storage::Storage;
@@ -14,10 +17,11 @@ impl crate::Module {
             global_variables: Storage::new(),
             functions: Storage::new(),
             entry_points: Vec::new(),
+            spans: Default::default(),
         }
     }
 
-    fn generate_empty() -> Self {
+    pub(crate) fn generate_empty() -> Self {
         Self::from_header(crate::Header {
             version: (1, 0, 0),
             generator: GENERATOR,
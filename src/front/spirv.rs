@@ -29,6 +29,7 @@ pub const SUPPORTED_CAPABILITIES: &[spirv::Capability] = &[
     spirv::Capability::Shader,
 ];
 pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "SPV_KHR_storage_buffer_storage_class",
 ];
 pub const SUPPORTED_EXT_SETS: &[&str] = &[
     "GLSL.std.450",
@@ -44,6 +45,7 @@ pub enum Error {
     UnsupportedCapability(spirv::Capability),
     UnsupportedExtension(String),
     UnsupportedExtSet(String),
+    UnsupportedExtInst(spirv::Word),
     UnsupportedType(Token<crate::Type>),
     UnsupportedExecutionModel(u32),
     UnsupportedStorageClass(u32),
@@ -64,6 +66,9 @@ pub enum Error {
     InvalidLoadType(spirv::Word),
     InvalidStoreType(spirv::Word),
     InvalidBinding(spirv::Word),
+    InvalidMerge(spirv::Word),
+    InvalidTerminator(spirv::Op),
+    InvalidFallThrough(spirv::Word),
     WrongFunctionResultType(spirv::Word),
     WrongFunctionParameterType(spirv::Word),
     BadString,
@@ -123,6 +128,14 @@ impl<T> LookupHelper for FastHashMap<spirv::Word, T> {
     }
 }
 
+/// Sets the span of an entity, which may be known before the entities preceding it.
+fn set_span(spans: &mut Vec<crate::Span>, index: usize, span: crate::Span) {
+    if spans.len() <= index {
+        spans.resize(index + 1, crate::Span::default());
+    }
+    spans[index] = span;
+}
+
 fn map_vector_size(word: spirv::Word) -> Result<crate::VectorSize, Error> {
     match word {
         2 => Ok(crate::VectorSize::Bi),
@@ -136,10 +149,102 @@ fn map_storage_class(word: spirv::Word) -> Result<spirv::StorageClass, Error> {
     if word > LAST_KNOWN_STORAGE_CLASS as u32 {
         Err(Error::UnsupportedStorageClass(word))
     } else {
-        Ok(unsafe { std::mem::transmute(word) })
+        Ok(unsafe { std::mem::transmute::<u32, spirv::StorageClass>(word) })
     }
 }
 
+fn map_math_function(raw: spirv::Word) -> Result<crate::MathFunction, Error> {
+    use spirv::GLOp as Gl;
+    use crate::MathFunction as Mf;
+    let op = match num_traits::FromPrimitive::from_u32(raw) {
+        Some(op) => op,
+        None => return Err(Error::UnsupportedExtInst(raw)),
+    };
+    Ok(match op {
+        Gl::Round => Mf::Round,
+        Gl::RoundEven => Mf::RoundEven,
+        Gl::Trunc => Mf::Trunc,
+        Gl::FAbs => Mf::FAbs,
+        Gl::SAbs => Mf::SAbs,
+        Gl::FSign => Mf::FSign,
+        Gl::SSign => Mf::SSign,
+        Gl::Floor => Mf::Floor,
+        Gl::Ceil => Mf::Ceil,
+        Gl::Fract => Mf::Fract,
+        Gl::Radians => Mf::Radians,
+        Gl::Degrees => Mf::Degrees,
+        Gl::Sin => Mf::Sin,
+        Gl::Cos => Mf::Cos,
+        Gl::Tan => Mf::Tan,
+        Gl::Asin => Mf::Asin,
+        Gl::Acos => Mf::Acos,
+        Gl::Atan => Mf::Atan,
+        Gl::Sinh => Mf::Sinh,
+        Gl::Cosh => Mf::Cosh,
+        Gl::Tanh => Mf::Tanh,
+        Gl::Asinh => Mf::Asinh,
+        Gl::Acosh => Mf::Acosh,
+        Gl::Atanh => Mf::Atanh,
+        Gl::Atan2 => Mf::Atan2,
+        Gl::Pow => Mf::Pow,
+        Gl::Exp => Mf::Exp,
+        Gl::Log => Mf::Log,
+        Gl::Exp2 => Mf::Exp2,
+        Gl::Log2 => Mf::Log2,
+        Gl::Sqrt => Mf::Sqrt,
+        Gl::InverseSqrt => Mf::InverseSqrt,
+        Gl::Determinant => Mf::Determinant,
+        Gl::MatrixInverse => Mf::MatrixInverse,
+        Gl::FMin => Mf::FMin,
+        Gl::UMin => Mf::UMin,
+        Gl::SMin => Mf::SMin,
+        Gl::FMax => Mf::FMax,
+        Gl::UMax => Mf::UMax,
+        Gl::SMax => Mf::SMax,
+        Gl::FClamp => Mf::FClamp,
+        Gl::UClamp => Mf::UClamp,
+        Gl::SClamp => Mf::SClamp,
+        Gl::FMix => Mf::FMix,
+        Gl::IMix => Mf::IMix,
+        Gl::Step => Mf::Step,
+        Gl::SmoothStep => Mf::SmoothStep,
+        Gl::Fma => Mf::Fma,
+        Gl::Ldexp => Mf::Ldexp,
+        Gl::PackSnorm4x8 => Mf::PackSnorm4x8,
+        Gl::PackUnorm4x8 => Mf::PackUnorm4x8,
+        Gl::PackSnorm2x16 => Mf::PackSnorm2x16,
+        Gl::PackUnorm2x16 => Mf::PackUnorm2x16,
+        Gl::PackHalf2x16 => Mf::PackHalf2x16,
+        Gl::UnpackSnorm2x16 => Mf::UnpackSnorm2x16,
+        Gl::UnpackUnorm2x16 => Mf::UnpackUnorm2x16,
+        Gl::UnpackHalf2x16 => Mf::UnpackHalf2x16,
+        Gl::UnpackSnorm4x8 => Mf::UnpackSnorm4x8,
+        Gl::UnpackUnorm4x8 => Mf::UnpackUnorm4x8,
+        Gl::Length => Mf::Length,
+        Gl::Distance => Mf::Distance,
+        Gl::Cross => Mf::Cross,
+        Gl::Normalize => Mf::Normalize,
+        Gl::FaceForward => Mf::FaceForward,
+        Gl::Reflect => Mf::Reflect,
+        Gl::Refract => Mf::Refract,
+        Gl::FindILsb => Mf::FindILsb,
+        Gl::FindSMsb => Mf::FindSMsb,
+        Gl::FindUMsb => Mf::FindUMsb,
+        Gl::NMin => Mf::NMin,
+        Gl::NMax => Mf::NMax,
+        Gl::NClamp => Mf::NClamp,
+        Gl::Modf |
+        Gl::ModfStruct |
+        Gl::Frexp |
+        Gl::FrexpStruct |
+        Gl::PackDouble2x32 |
+        Gl::UnpackDouble2x32 |
+        Gl::InterpolateAtCentroid |
+        Gl::InterpolateAtSample |
+        Gl::InterpolateAtOffset => return Err(Error::UnsupportedExtInst(raw)),
+    })
+}
+
 type MemberIndex = u32;
 
 #[derive(Debug, Default)]
@@ -149,6 +254,7 @@ struct Decoration {
     location: Option<spirv::Word>,
     desc_set: Option<spirv::Word>,
     desc_index: Option<spirv::Word>,
+    offset: Option<spirv::Word>,
 }
 
 impl Decoration {
@@ -181,7 +287,7 @@ impl Decoration {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct LookupFunctionType {
     parameter_type_ids: Vec<spirv::Word>,
     return_type_id: spirv::Word,
@@ -225,8 +331,104 @@ struct LookupSampledImage {
     sampler: Token<crate::Expression>,
 }
 
+/// The way a block passes control to the next one.
+#[derive(Debug)]
+enum Terminator {
+    Return {
+        value: Option<Token<crate::Expression>>,
+    },
+    Kill,
+    Unreachable,
+    Branch {
+        target_id: spirv::Word,
+    },
+    BranchConditional {
+        condition: Token<crate::Expression>,
+        true_id: spirv::Word,
+        false_id: spirv::Word,
+    },
+    Switch {
+        selector: Token<crate::Expression>,
+        default_id: spirv::Word,
+        targets: Vec<(i32, spirv::Word)>,
+    },
+}
+
+/// Structured control flow annotation of a header block.
+#[derive(Debug)]
+enum MergeInstruction {
+    Selection {
+        merge_id: spirv::Word,
+    },
+    Loop {
+        merge_id: spirv::Word,
+        continuing_id: spirv::Word,
+    },
+}
+
+/// A parsed SPIR-V block, before it gets linked into structured statements.
+#[derive(Debug)]
+struct FlowBlock {
+    body: crate::Block,
+    /// Spans of the statements in the body.
+    spans: Vec<crate::Span>,
+    merge: Option<MergeInstruction>,
+    terminator: Terminator,
+    /// Span of the merge instruction, if any, and the terminator.
+    control_span: crate::Span,
+}
+
+/// Structured statements, along with the spans of all the statements
+/// in the order of `FunctionSpans::statements`.
+#[derive(Clone, Debug, Default)]
+struct SpannedBlock {
+    block: crate::Block,
+    spans: Vec<crate::Span>,
+}
+
+impl SpannedBlock {
+    fn push(&mut self, statement: crate::Statement, span: crate::Span) {
+        self.block.push(statement);
+        self.spans.push(span);
+    }
+
+    fn push_if(
+        &mut self,
+        condition: Token<crate::Expression>,
+        accept: SpannedBlock,
+        reject: SpannedBlock,
+        span: crate::Span,
+    ) {
+        self.spans.push(span);
+        self.spans.extend(accept.spans);
+        self.spans.extend(reject.spans);
+        self.block.push(crate::Statement::If {
+            condition,
+            accept: accept.block,
+            reject: reject.block,
+        });
+    }
+}
+
+/// Targets that the branches resolve to within the construct being converted.
+#[derive(Clone, Copy, Debug)]
+struct FlowContext<'a> {
+    /// Block at which the conversion of the current construct stops.
+    stop_id: Option<spirv::Word>,
+    /// Merge block of the innermost loop or switch.
+    break_id: Option<spirv::Word>,
+    /// Continue target of the innermost loop.
+    continue_id: Option<spirv::Word>,
+    /// Case labels of the innermost switch, reaching one is a fall-through.
+    case_ids: &'a [spirv::Word],
+}
+
 pub struct Parser<I> {
     data: I,
+    /// Number of words read so far.
+    offset: usize,
+    /// Span of the last instruction read.
+    span: crate::Span,
     state: ModuleState,
     temp_bytes: Vec<u8>,
     future_decor: FastHashMap<spirv::Word, Decoration>,
@@ -240,12 +442,16 @@ pub struct Parser<I> {
     lookup_sampled_image: FastHashMap<spirv::Word, LookupSampledImage>,
     lookup_function_type: FastHashMap<spirv::Word, LookupFunctionType>,
     lookup_function: FastHashMap<spirv::Word, Token<crate::Function>>,
+    future_function_ids: FastHashSet<spirv::Word>,
+    ext_glsl_id: Option<spirv::Word>,
 }
 
 impl<I: Iterator<Item = u32>> Parser<I> {
     pub fn new(data: I) -> Self {
         Parser {
             data,
+            offset: 0,
+            span: crate::Span::default(),
             state: ModuleState::Empty,
             temp_bytes: Vec::new(),
             future_decor: FastHashMap::default(),
@@ -259,26 +465,32 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             lookup_sampled_image: FastHashMap::default(),
             lookup_function_type: FastHashMap::default(),
             lookup_function: FastHashMap::default(),
+            future_function_ids: FastHashSet::default(),
+            ext_glsl_id: None,
         }
     }
 
     fn next(&mut self) -> Result<u32, Error> {
-        self.data.next().ok_or(Error::IncompleteData)
+        let word = self.data.next().ok_or(Error::IncompleteData)?;
+        self.offset += 1;
+        Ok(word)
     }
 
     fn next_inst(&mut self) -> Result<Instruction, Error> {
+        let start = self.offset;
         let word = self.next()?;
         let (wc, opcode) = ((word >> 16) as u16, (word & 0xffff) as u16);
         if wc == 0 {
             return Err(Error::InvalidWordCount);
         }
+        self.span = crate::Span::new(start, start + wc as usize);
         if opcode > LAST_KNOWN_OPCODE as u16 {
             return Err(Error::UnknownInstruction(opcode));
         }
 
         Ok(Instruction {
             op: unsafe {
-                std::mem::transmute(opcode as u32)
+                std::mem::transmute::<u32, spirv::Op>(opcode as u32)
             },
             wc,
         })
@@ -314,7 +526,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             return Err(Error::InvalidDecoration(raw));
         }
         let dec_typed = unsafe {
-            std::mem::transmute::<_, spirv::Decoration>(raw)
+            std::mem::transmute::<u32, spirv::Decoration>(raw)
         };
         log::trace!("\t\t{:?}", dec_typed);
         match dec_typed {
@@ -325,7 +537,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     log::warn!("Unknown built in {:?}", raw);
                 } else {
                     dec.built_in = Some(unsafe {
-                        std::mem::transmute(raw)
+                        std::mem::transmute::<u32, spirv::BuiltIn>(raw)
                     });
                 }
             }
@@ -341,6 +553,10 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 inst.expect(base_words + 2)?;
                 dec.desc_index = Some(self.next()?);
             }
+            spirv::Decoration::Offset => {
+                inst.expect(base_words + 2)?;
+                dec.offset = Some(self.next()?);
+            }
             other => {
                 log::warn!("Unknown decoration {:?}", other);
                 for _ in base_words + 1 .. inst.wc {
@@ -351,14 +567,68 @@ impl<I: Iterator<Item = u32>> Parser<I> {
         Ok(())
     }
 
+    fn parse_expr_unary_op(
+        &mut self,
+        inst: Instruction,
+        fun: &mut crate::Function,
+        op: crate::UnaryOperator,
+    ) -> Result<(), Error> {
+        inst.expect(4)?;
+        let result_type_id = self.next()?;
+        let result_id = self.next()?;
+        let p_id = self.next()?;
+        let p_lexp = self.lookup_expression.lookup(p_id)?;
+        let expr = crate::Expression::Unary {
+            op,
+            expr: p_lexp.token,
+        };
+        self.lookup_expression.insert(result_id, LookupExpression {
+            token: fun.expressions.append(expr),
+            type_id: result_type_id,
+        });
+        Ok(())
+    }
+
+    fn parse_expr_binary_op(
+        &mut self,
+        inst: Instruction,
+        fun: &mut crate::Function,
+        op: crate::BinaryOperator,
+    ) -> Result<(), Error> {
+        inst.expect(5)?;
+        let result_type_id = self.next()?;
+        let result_id = self.next()?;
+        let p1_id = self.next()?;
+        let p2_id = self.next()?;
+        let p1_lexp = self.lookup_expression.lookup(p1_id)?;
+        let p2_lexp = self.lookup_expression.lookup(p2_id)?;
+        let expr = crate::Expression::Binary {
+            op,
+            left: p1_lexp.token,
+            right: p2_lexp.token,
+        };
+        self.lookup_expression.insert(result_id, LookupExpression {
+            token: fun.expressions.append(expr),
+            type_id: result_type_id,
+        });
+        Ok(())
+    }
+
     fn next_block(
         &mut self,
         fun: &mut crate::Function,
         type_store: &Storage<crate::Type>,
         const_store: &Storage<crate::Constant>,
-    ) -> Result<(), Error> {
-        loop {
+        fun_store: &mut Storage<crate::Function>,
+    ) -> Result<FlowBlock, Error> {
+        let mut body = Vec::new();
+        let mut spans = Vec::new();
+        let mut merge = None;
+        let mut merge_start = None;
+        let terminator = loop {
             use spirv::Op;
+            // the expressions added by the previous instruction come from it
+            fun.spans.expressions.resize(fun.expressions.len(), self.span);
             let inst = self.next_inst()?;
             log::debug!("\t\t{:?} [{}]", inst.op, inst.wc);
             match inst.op {
@@ -500,6 +770,36 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         type_id: result_type_id,
                     });
                 }
+                Op::Variable => {
+                    inst.expect_at_least(4)?;
+                    let result_type_id = self.next()?;
+                    let result_id = self.next()?;
+                    let storage = self.next()?;
+                    match map_storage_class(storage)? {
+                        spirv::StorageClass::Function => (),
+                        class => return Err(Error::InvalidVariableClass(class)),
+                    }
+                    let init = if inst.wc != 4 {
+                        inst.expect(5)?;
+                        let init_id = self.next()?;
+                        Some(self.lookup_constant.lookup(init_id)?.token)
+                    } else {
+                        None
+                    };
+                    let name = self.future_decor
+                        .remove(&result_id)
+                        .and_then(|dec| dec.name);
+                    let lookup_type = self.lookup_type.lookup(result_type_id)?;
+                    let ty = match type_store[lookup_type.token].inner {
+                        crate::TypeInner::Pointer { base, .. } => base,
+                        _ => return Err(Error::UnsupportedType(lookup_type.token)),
+                    };
+                    let token = fun.local_variables.append(crate::LocalVariable { name, ty, init });
+                    self.lookup_expression.insert(result_id, LookupExpression {
+                        token: fun.expressions.append(crate::Expression::LocalVariable(token)),
+                        type_id: result_type_id,
+                    });
+                }
                 Op::Load => {
                     inst.expect_at_least(4)?;
                     let result_type_id = self.next()?;
@@ -544,15 +844,144 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     if base_type.base_id != Some(value_expr.type_id) {
                         return Err(Error::InvalidStoreType(value_expr.type_id));
                     }
-                    fun.body.push(crate::Statement::Store {
+                    body.push(crate::Statement::Store {
                         pointer: base_expr.token,
                         value: value_expr.token,
-                    })
+                    });
+                    spans.push(self.span);
+                }
+                Op::FunctionCall => {
+                    inst.expect_at_least(4)?;
+                    let result_type_id = self.next()?;
+                    let result_id = self.next()?;
+                    let function_id = self.next()?;
+                    let mut arguments = Vec::with_capacity(inst.wc as usize - 4);
+                    for _ in 4 .. inst.wc {
+                        let arg_id = self.next()?;
+                        arguments.push(self.lookup_expression.lookup(arg_id)?.token);
+                    }
+                    let function = match self.lookup_function.get(&function_id) {
+                        Some(&token) => token,
+                        None => {
+                            // the function is defined later, reserve a slot for it
+                            let token = fun_store.append(crate::Function {
+                                name: None,
+                                control: spirv::FunctionControl::NONE,
+                                parameter_types: Vec::new(),
+                                return_type: None,
+                                local_variables: Storage::new(),
+                                expressions: Storage::new(),
+                                body: Vec::new(),
+                                spans: Default::default(),
+                            });
+                            self.lookup_function.insert(function_id, token);
+                            self.future_function_ids.insert(function_id);
+                            token
+                        }
+                    };
+                    if self.lookup_void_type.contains(&result_type_id) {
+                        body.push(crate::Statement::Call {
+                            function,
+                            arguments,
+                        });
+                        spans.push(self.span);
+                    } else {
+                        let expr = crate::Expression::Call {
+                            function,
+                            arguments,
+                        };
+                        self.lookup_expression.insert(result_id, LookupExpression {
+                            token: fun.expressions.append(expr),
+                            type_id: result_type_id,
+                        });
+                    }
+                }
+                Op::SelectionMerge => {
+                    inst.expect(3)?;
+                    let merge_id = self.next()?;
+                    let _selection_control = self.next()?;
+                    merge = Some(MergeInstruction::Selection { merge_id });
+                    merge_start = Some(self.span.start);
+                }
+                Op::LoopMerge => {
+                    inst.expect_at_least(4)?;
+                    let merge_id = self.next()?;
+                    let continuing_id = self.next()?;
+                    for _ in 3 .. inst.wc {
+                        let _loop_control = self.next()?;
+                    }
+                    merge = Some(MergeInstruction::Loop { merge_id, continuing_id });
+                    merge_start = Some(self.span.start);
                 }
                 Op::Return => {
                     inst.expect(1)?;
-                    fun.body.push(crate::Statement::Return { value: None });
-                    break
+                    break Terminator::Return { value: None };
+                }
+                Op::ReturnValue => {
+                    inst.expect(2)?;
+                    let value_id = self.next()?;
+                    let value_lexp = self.lookup_expression.lookup(value_id)?;
+                    break Terminator::Return { value: Some(value_lexp.token) };
+                }
+                Op::Kill => {
+                    inst.expect(1)?;
+                    break Terminator::Kill;
+                }
+                Op::Unreachable => {
+                    inst.expect(1)?;
+                    break Terminator::Unreachable;
+                }
+                Op::Branch => {
+                    inst.expect(2)?;
+                    let target_id = self.next()?;
+                    break Terminator::Branch { target_id };
+                }
+                Op::BranchConditional => {
+                    inst.expect_at_least(4)?;
+                    let condition_id = self.next()?;
+                    let true_id = self.next()?;
+                    let false_id = self.next()?;
+                    for _ in 4 .. inst.wc {
+                        let _weight = self.next()?;
+                    }
+                    let condition_lexp = self.lookup_expression.lookup(condition_id)?;
+                    let condition_type_lookup = self.lookup_type.lookup(condition_lexp.type_id)?;
+                    match type_store[condition_type_lookup.token].inner {
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Bool, .. } => (),
+                        _ => return Err(Error::UnsupportedType(condition_type_lookup.token)),
+                    }
+                    break Terminator::BranchConditional {
+                        condition: condition_lexp.token,
+                        true_id,
+                        false_id,
+                    };
+                }
+                Op::Switch => {
+                    inst.expect_at_least(3)?;
+                    let selector_id = self.next()?;
+                    let default_id = self.next()?;
+                    let selector_lexp = self.lookup_expression.lookup(selector_id)?.clone();
+                    let selector_type_lookup = self.lookup_type.lookup(selector_lexp.type_id)?;
+                    match type_store[selector_type_lookup.token].inner {
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Uint, width } |
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Sint, width } => {
+                            if width > 32 {
+                                return Err(Error::InvalidTypeWidth(width as u32));
+                            }
+                        }
+                        _ => return Err(Error::UnsupportedType(selector_type_lookup.token)),
+                    }
+                    let mut targets = Vec::with_capacity((inst.wc as usize - 3) / 2);
+                    for _ in 0 .. (inst.wc - 3) / 2 {
+                        let literal = self.next()?;
+                        let target_id = self.next()?;
+                        targets.push((literal as i32, target_id));
+                    }
+                    break Terminator::Switch {
+                        selector: selector_lexp.token,
+                        default_id,
+                        targets,
+                    };
                 }
                 Op::VectorTimesScalar => {
                     inst.expect(5)?;
@@ -577,7 +1006,11 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width } if width == res_width => (),
                         _ => return Err(Error::UnsupportedType(scalar_type_lookup.token)),
                     };
-                    let expr = crate::Expression::Mul(vector_lexp.token, scalar_lexp.token);
+                    let expr = crate::Expression::Binary {
+                        op: crate::BinaryOperator::Multiply,
+                        left: vector_lexp.token,
+                        right: scalar_lexp.token,
+                    };
                     self.lookup_expression.insert(result_id, LookupExpression {
                         token: fun.expressions.append(expr),
                         type_id: result_type_id,
@@ -606,12 +1039,130 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         crate::TypeInner::Vector { size, kind: crate::ScalarKind::Float, width } if size == columns && width == res_width => (),
                         _ => return Err(Error::UnsupportedType(vector_type_lookup.token)),
                     };
-                    let expr = crate::Expression::Mul(matrix_lexp.token, vector_lexp.token);
+                    let expr = crate::Expression::Binary {
+                        op: crate::BinaryOperator::Multiply,
+                        left: matrix_lexp.token,
+                        right: vector_lexp.token,
+                    };
+                    self.lookup_expression.insert(result_id, LookupExpression {
+                        token: fun.expressions.append(expr),
+                        type_id: result_type_id,
+                    });
+                }
+                Op::ExtInst => {
+                    inst.expect_at_least(5)?;
+                    let result_type_id = self.next()?;
+                    let result_id = self.next()?;
+                    let set_id = self.next()?;
+                    if Some(set_id) != self.ext_glsl_id {
+                        return Err(Error::InvalidId(set_id));
+                    }
+                    let fun_raw = self.next()?;
+                    let math_fun = map_math_function(fun_raw)?;
+                    inst.expect(5 + math_fun.argument_count() as u16)?;
+                    let mut arguments = Vec::with_capacity(math_fun.argument_count());
+                    for _ in 0 .. math_fun.argument_count() {
+                        let arg_id = self.next()?;
+                        arguments.push(self.lookup_expression.lookup(arg_id)?.token);
+                    }
+                    let expr = crate::Expression::Math {
+                        fun: math_fun,
+                        arguments,
+                    };
                     self.lookup_expression.insert(result_id, LookupExpression {
                         token: fun.expressions.append(expr),
                         type_id: result_type_id,
                     });
                 }
+                Op::SNegate |
+                Op::FNegate => {
+                    self.parse_expr_unary_op(inst, fun, crate::UnaryOperator::Negate)?;
+                }
+                Op::LogicalNot => {
+                    self.parse_expr_unary_op(inst, fun, crate::UnaryOperator::Not)?;
+                }
+                Op::Not => {
+                    self.parse_expr_unary_op(inst, fun, crate::UnaryOperator::BitwiseNot)?;
+                }
+                Op::VectorTimesMatrix |
+                Op::MatrixTimesMatrix |
+                Op::MatrixTimesScalar |
+                Op::IMul |
+                Op::FMul => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Multiply)?;
+                }
+                Op::IAdd |
+                Op::FAdd => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Add)?;
+                }
+                Op::ISub |
+                Op::FSub => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Subtract)?;
+                }
+                Op::UDiv |
+                Op::SDiv |
+                Op::FDiv => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Divide)?;
+                }
+                Op::UMod |
+                Op::SRem |
+                Op::FRem => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Modulo)?;
+                }
+                Op::IEqual |
+                Op::FOrdEqual |
+                Op::LogicalEqual => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Equal)?;
+                }
+                Op::INotEqual |
+                Op::FOrdNotEqual |
+                Op::LogicalNotEqual => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::NotEqual)?;
+                }
+                Op::ULessThan |
+                Op::SLessThan |
+                Op::FOrdLessThan => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Less)?;
+                }
+                Op::ULessThanEqual |
+                Op::SLessThanEqual |
+                Op::FOrdLessThanEqual => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::LessEqual)?;
+                }
+                Op::UGreaterThan |
+                Op::SGreaterThan |
+                Op::FOrdGreaterThan => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::Greater)?;
+                }
+                Op::UGreaterThanEqual |
+                Op::SGreaterThanEqual |
+                Op::FOrdGreaterThanEqual => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::GreaterEqual)?;
+                }
+                Op::BitwiseAnd => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::And)?;
+                }
+                Op::BitwiseXor => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::ExclusiveOr)?;
+                }
+                Op::BitwiseOr => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::InclusiveOr)?;
+                }
+                Op::LogicalAnd => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::LogicalAnd)?;
+                }
+                Op::LogicalOr => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::LogicalOr)?;
+                }
+                Op::ShiftLeftLogical => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::ShiftLeftLogical)?;
+                }
+                Op::ShiftRightLogical => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::ShiftRightLogical)?;
+                }
+                Op::ShiftRightArithmetic => {
+                    self.parse_expr_binary_op(inst, fun, crate::BinaryOperator::ShiftRightArithmetic)?;
+                }
                 Op::SampledImage => {
                     inst.expect(5)?;
                     let _result_type_id = self.next()?;
@@ -653,8 +1204,252 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 }
                 _ => return Err(Error::UnsupportedInstruction(self.state, inst.op)),
             }
+        };
+        fun.spans.expressions.resize(fun.expressions.len(), self.span);
+        Ok(FlowBlock {
+            body,
+            spans,
+            merge,
+            terminator,
+            control_span: crate::Span {
+                start: merge_start.unwrap_or(self.span.start),
+                end: self.span.end,
+            },
+        })
+    }
+
+    /// Convert the blocks reachable from `start_id` into a structured block,
+    /// resolving the branches according to the given flow context.
+    ///
+    /// Returns the statements together with the ID of the block at which
+    /// the conversion stopped, if it didn't end up with a terminator.
+    fn structurize(
+        blocks: &mut FastHashMap<spirv::Word, FlowBlock>,
+        start_id: spirv::Word,
+        context: &FlowContext,
+    ) -> Result<(SpannedBlock, Option<spirv::Word>), Error> {
+        let mut body = SpannedBlock::default();
+        let mut id = start_id;
+        loop {
+            if Some(id) == context.stop_id || (id != start_id && context.case_ids.contains(&id)) {
+                return Ok((body, Some(id)));
+            }
+            // the branch is already part of the terminator that leads here
+            if Some(id) == context.break_id {
+                body.push(crate::Statement::Break, crate::Span::default());
+                break
+            }
+            if Some(id) == context.continue_id {
+                body.push(crate::Statement::Continue, crate::Span::default());
+                break
+            }
+            let block = blocks
+                .remove(&id)
+                .ok_or(Error::InvalidId(id))?;
+            let next_id = match block.merge {
+                Some(MergeInstruction::Selection { merge_id }) => {
+                    body.block.extend(block.body);
+                    body.spans.extend(block.spans);
+                    let selection_context = FlowContext {
+                        stop_id: Some(merge_id),
+                        case_ids: &[],
+                        ..*context
+                    };
+                    match block.terminator {
+                        Terminator::BranchConditional { condition, true_id, false_id } => {
+                            let (accept, _) = Self::structurize(blocks, true_id, &selection_context)?;
+                            let (reject, _) = Self::structurize(blocks, false_id, &selection_context)?;
+                            body.push_if(condition, accept, reject, block.control_span);
+                        }
+                        Terminator::Switch { selector, default_id, targets } => {
+                            let switch = Self::structurize_switch(blocks, selector, default_id, targets, merge_id, context)?;
+                            body.spans.push(block.control_span);
+                            body.spans.extend(switch.spans);
+                            body.block.extend(switch.block);
+                        }
+                        _ => return Err(Error::InvalidMerge(merge_id)),
+                    }
+                    Some(merge_id)
+                }
+                Some(MergeInstruction::Loop { merge_id, continuing_id }) => {
+                    let body_context = FlowContext {
+                        stop_id: Some(continuing_id),
+                        break_id: Some(merge_id),
+                        continue_id: Some(continuing_id),
+                        case_ids: &[],
+                    };
+                    let mut loop_body = SpannedBlock {
+                        block: block.body,
+                        spans: block.spans,
+                    };
+                    let exit_id = Self::structurize_terminator(
+                        blocks,
+                        block.terminator,
+                        block.control_span,
+                        &body_context,
+                        &mut loop_body,
+                    )?;
+                    if let Some(exit_id) = exit_id {
+                        let (rest, _) = Self::structurize(blocks, exit_id, &body_context)?;
+                        loop_body.block.extend(rest.block);
+                        loop_body.spans.extend(rest.spans);
+                    }
+                    let continuing = if continuing_id == id {
+                        SpannedBlock::default()
+                    } else {
+                        // the back-edge leads to the header, which ends the construct
+                        let continuing_context = FlowContext {
+                            stop_id: Some(id),
+                            break_id: Some(merge_id),
+                            continue_id: None,
+                            case_ids: &[],
+                        };
+                        Self::structurize(blocks, continuing_id, &continuing_context)?.0
+                    };
+                    body.spans.push(block.control_span);
+                    body.spans.extend(loop_body.spans);
+                    body.spans.extend(continuing.spans);
+                    body.block.push(crate::Statement::Loop {
+                        body: loop_body.block,
+                        continuing: continuing.block,
+                    });
+                    Some(merge_id)
+                }
+                None => {
+                    body.block.extend(block.body);
+                    body.spans.extend(block.spans);
+                    Self::structurize_terminator(blocks, block.terminator, block.control_span, context, &mut body)?
+                }
+            };
+            match next_id {
+                Some(next_id) => id = next_id,
+                None => break,
+            }
         }
-        Ok(())
+        Ok((body, None))
+    }
+
+    /// Convert a terminator of a block that is not a selection header.
+    ///
+    /// Returns the ID of the block to proceed with, if any.
+    fn structurize_terminator(
+        blocks: &mut FastHashMap<spirv::Word, FlowBlock>,
+        terminator: Terminator,
+        span: crate::Span,
+        context: &FlowContext,
+        body: &mut SpannedBlock,
+    ) -> Result<Option<spirv::Word>, Error> {
+        match terminator {
+            Terminator::Return { value } => {
+                body.push(crate::Statement::Return { value }, span);
+                Ok(None)
+            }
+            Terminator::Kill => {
+                body.push(crate::Statement::Kill, span);
+                Ok(None)
+            }
+            Terminator::Unreachable => Ok(None),
+            Terminator::Branch { target_id } => Ok(Some(target_id)),
+            Terminator::BranchConditional { condition, true_id, false_id } => {
+                // Without a merge, both paths are expected to either
+                // leave the construct or converge at its end.
+                let (accept, accept_exit_id) = Self::structurize(blocks, true_id, context)?;
+                let (reject, reject_exit_id) = Self::structurize(blocks, false_id, context)?;
+                body.push_if(condition, accept, reject, span);
+                match (accept_exit_id, reject_exit_id) {
+                    (Some(a), Some(b)) if a != b => Err(Error::InvalidTerminator(spirv::Op::BranchConditional)),
+                    (a, b) => Ok(a.or(b)),
+                }
+            }
+            Terminator::Switch { .. } => Err(Error::InvalidTerminator(spirv::Op::Switch)),
+        }
+    }
+
+    /// Convert a switch construct, returning the statement along with
+    /// the spans of the statements nested in it.
+    fn structurize_switch(
+        blocks: &mut FastHashMap<spirv::Word, FlowBlock>,
+        selector: Token<crate::Expression>,
+        default_id: spirv::Word,
+        targets: Vec<(i32, spirv::Word)>,
+        merge_id: spirv::Word,
+        context: &FlowContext,
+    ) -> Result<SpannedBlock, Error> {
+        // group the literals by their target, preserving the order
+        let mut target_ids = Vec::<spirv::Word>::new();
+        for &(_, target_id) in targets.iter() {
+            if target_id != merge_id && !target_ids.contains(&target_id) {
+                target_ids.push(target_id);
+            }
+        }
+        // the last case may fall through into a default that has no literal
+        let mut case_ids = target_ids.clone();
+        if default_id != merge_id && !target_ids.contains(&default_id) {
+            case_ids.push(default_id);
+        }
+        let case_context = FlowContext {
+            stop_id: Some(merge_id),
+            break_id: Some(merge_id),
+            continue_id: context.continue_id,
+            case_ids: &case_ids,
+        };
+        // the literals branching to the merge do nothing, so they
+        // go first to keep the fall-through cases next to each other
+        let mut cases = targets
+            .iter()
+            .filter(|&&(_, target_id)| target_id == merge_id)
+            .map(|&(value, _)| (value, Vec::new(), None))
+            .collect::<Vec<_>>();
+        let mut spans = Vec::new();
+        let mut default = SpannedBlock::default();
+        for (i, &target_id) in target_ids.iter().enumerate() {
+            let (case_body, exit_id) = Self::structurize(blocks, target_id, &case_context)?;
+            let fall_through = match exit_id {
+                Some(next_id) if next_id != merge_id => {
+                    if case_ids.get(i + 1) != Some(&next_id) {
+                        return Err(Error::InvalidFallThrough(next_id));
+                    }
+                    Some(crate::FallThrough)
+                }
+                _ => None,
+            };
+            if target_id == default_id {
+                if fall_through.is_some() {
+                    return Err(Error::InvalidFallThrough(target_id));
+                }
+                default = case_body.clone();
+            }
+            let mut values = targets
+                .iter()
+                .filter(|&&(_, tid)| tid == target_id)
+                .map(|&(value, _)| value)
+                .peekable();
+            while let Some(value) = values.next() {
+                if values.peek().is_some() {
+                    cases.push((value, Vec::new(), Some(crate::FallThrough)));
+                } else {
+                    cases.push((value, case_body.block.clone(), fall_through));
+                    spans.extend_from_slice(&case_body.spans);
+                }
+            }
+        }
+        if !target_ids.contains(&default_id) {
+            let (default_body, exit_id) = Self::structurize(blocks, default_id, &case_context)?;
+            match exit_id {
+                Some(next_id) if next_id != merge_id => return Err(Error::InvalidFallThrough(next_id)),
+                _ => (),
+            }
+            default = default_body;
+        }
+        spans.extend(default.spans);
+        Ok(SpannedBlock {
+            block: vec![crate::Statement::Switch {
+                selector,
+                cases,
+                default: default.block,
+            }],
+            spans,
+        })
     }
 
     fn make_expression_storage(&mut self) -> Storage<crate::Expression> {
@@ -680,7 +1475,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
 
     fn switch(&mut self, state: ModuleState, op: spirv::Op) -> Result<(), Error> {
         if state < self.state {
-            return Err(Error::UnsupportedInstruction(self.state, op))
+            Err(Error::UnsupportedInstruction(self.state, op))
         } else {
             self.state = state;
             Ok(())
@@ -715,7 +1510,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         return Err(Error::UnknownCapability(capability));
                     }
                     let cap = unsafe {
-                        std::mem::transmute(capability)
+                        std::mem::transmute::<u32, spirv::Capability>(capability)
                     };
                     if !SUPPORTED_CAPABILITIES.contains(&cap) {
                         return Err(Error::UnsupportedCapability(cap));
@@ -735,7 +1530,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 Op::ExtInstImport => {
                     self.switch(ModuleState::Extension, inst.op)?;
                     inst.expect_at_least(3)?;
-                    let _result = self.next()?;
+                    let result_id = self.next()?;
                     let (name, left) = self.next_string(inst.wc - 2)?;
                     if left != 0 {
                         return Err(Error::InvalidOperand)
@@ -743,6 +1538,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     if !SUPPORTED_EXT_SETS.contains(&name.as_str()) {
                         return Err(Error::UnsupportedExtSet(name.to_owned()));
                     }
+                    self.ext_glsl_id = Some(result_id);
                 }
                 Op::MemoryModel => {
                     self.switch(ModuleState::MemoryModel, inst.op)?;
@@ -761,7 +1557,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     let (name, left) = self.next_string(inst.wc - 3)?;
                     let ep = EntryPoint {
                         exec_model: unsafe {
-                            std::mem::transmute(exec_model)
+                            std::mem::transmute::<u32, spirv::ExecutionModel>(exec_model)
                         },
                         name: name.to_owned(),
                         function_id,
@@ -846,6 +1642,24 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     let id = self.next()?;
                     self.lookup_void_type.insert(id);
                 }
+                Op::TypeBool => {
+                    self.switch(ModuleState::Type, inst.op)?;
+                    inst.expect(2)?;
+                    let id = self.next()?;
+                    let inner = crate::TypeInner::Scalar {
+                        kind: crate::ScalarKind::Bool,
+                        width: 1,
+                    };
+                    self.lookup_type.insert(id, LookupType {
+                        token: module.types.append(crate::Type {
+                            name: self.future_decor
+                                .remove(&id)
+                                .and_then(|dec| dec.name),
+                            inner,
+                        }),
+                        base_id: None,
+                    });
+                }
                 Op::TypeInt => {
                     self.switch(ModuleState::Type, inst.op)?;
                     inst.expect(4)?;
@@ -1001,7 +1815,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 }
                 Op::TypeRuntimeArray => {
                     self.switch(ModuleState::Type, inst.op)?;
-                    inst.expect(4)?;
+                    inst.expect(3)?;
                     let id = self.next()?;
                     let type_id = self.next()?;
                     let inner = crate::TypeInner::Array {
@@ -1034,6 +1848,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         members.push(crate::StructMember {
                             name: decor.name,
                             binding,
+                            offset: decor.offset,
                             ty,
                         });
                     }
@@ -1058,7 +1873,9 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     let sample_type_id = self.next()?;
                     let dim = self.next()?;
                     let mut flags = crate::ImageFlags::empty();
-                    let _is_depth = self.next()?;
+                    if self.next()? == 1 {
+                        flags |= crate::ImageFlags::DEPTH;
+                    }
                     if self.next()? != 0 {
                         flags |= crate::ImageFlags::ARRAYED;
                     }
@@ -1090,7 +1907,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         dim: if dim > LAST_KNOWN_DIM as u32 {
                             return Err(Error::UnsupportedDim(dim));
                         } else {
-                            unsafe { std::mem::transmute(dim) }
+                            unsafe { std::mem::transmute::<u32, spirv::Dim>(dim) }
                         },
                         flags,
                     };
@@ -1119,7 +1936,8 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     let decor = self.future_decor
                         .remove(&id)
                         .unwrap_or_default();
-                    let inner = crate::TypeInner::Sampler;
+                    // the comparison is only known from the instructions using the sampler
+                    let inner = crate::TypeInner::Sampler { comparison: false };
                     self.lookup_type.insert(id, LookupType {
                         token: module.types.append(crate::Type {
                             name: decor.name,
@@ -1148,17 +1966,17 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         }
                         crate::TypeInner::Scalar { kind: crate::ScalarKind::Sint, width } => {
                             let low = self.next()?;
-                            let high = if width < 32 {
+                            let value = if width < 32 {
                                 return Err(Error::InvalidTypeWidth(width as u32));
                             } else if width > 32 {
                                 inst.expect(4)?;
-                                self.next()?
+                                let high = self.next()?;
+                                (((high as u64) << 32) | low as u64) as i64
                             } else {
-                                !0
+                                // sign-extend the single word
+                                low as i32 as i64
                             };
-                            crate::ConstantInner::Sint(unsafe {
-                                std::mem::transmute(((high as u64) << 32) | low as u64)
-                            })
+                            crate::ConstantInner::Sint(value)
                         }
                         crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width } => {
                             let low = self.next()?;
@@ -1167,13 +1985,9 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                             } else if width > 32 {
                                 inst.expect(4)?;
                                 let high = self.next()?;
-                                unsafe {
-                                    std::mem::transmute(((high as u64) << 32) | low as u64)
-                                }
+                                f64::from_bits(((high as u64) << 32) | low as u64)
                             } else {
-                                unsafe {
-                                    std::mem::transmute::<_, f32>(low) as f64
-                                }
+                                f32::from_bits(low) as f64
                             };
                             crate::ConstantInner::Float(extended)
                         }
@@ -1190,6 +2004,28 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         type_id,
                     });
                 }
+                Op::ConstantTrue |
+                Op::ConstantFalse => {
+                    self.switch(ModuleState::Type, inst.op)?;
+                    inst.expect(3)?;
+                    let type_id = self.next()?;
+                    let id = self.next()?;
+                    let type_lookup = self.lookup_type.lookup(type_id)?;
+                    match module.types[type_lookup.token].inner {
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Bool, .. } => (),
+                        _ => return Err(Error::UnsupportedType(type_lookup.token)),
+                    }
+                    self.lookup_constant.insert(id, LookupConstant {
+                        token: module.constants.append(crate::Constant {
+                            name: self.future_decor
+                                .remove(&id)
+                                .and_then(|dec| dec.name),
+                            specialization: None,
+                            inner: crate::ConstantInner::Bool(inst.op == Op::ConstantTrue),
+                        }),
+                        type_id,
+                    });
+                }
                 Op::Variable => {
                     self.switch(ModuleState::Type, inst.op)?;
                     inst.expect_at_least(4)?;
@@ -1241,6 +2077,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         ty: lookup_type.token,
                     };
                     let token = module.global_variables.append(var);
+                    set_span(&mut module.spans.global_variables, token.index(), self.span);
                     self.lookup_variable.insert(id, LookupVariable {
                         token,
                         type_id,
@@ -1249,6 +2086,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 Op::Function => {
                     self.switch(ModuleState::Function, inst.op)?;
                     inst.expect(5)?;
+                    let start = self.span.start;
                     let result_type = self.next()?;
                     let fun_id = self.next()?;
                     let fun_control = self.next()?;
@@ -1270,16 +2108,20 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                             } else {
                                 Some(self.lookup_type.lookup(result_type)?.token)
                             },
+                            local_variables: Storage::new(),
                             expressions: self.make_expression_storage(),
                             body: Vec::new(),
+                            spans: Default::default(),
                         }
                     };
+                    // the global variables and constants are not located in the function
+                    fun.spans.expressions.resize(fun.expressions.len(), crate::Span::default());
                     // read parameters
                     for i in 0 .. fun.parameter_types.capacity() {
                         match self.next_inst()? {
                             Instruction { op: Op::FunctionParameter, wc: 3 } => {
                                 let type_id = self.next()?;
-                                let _id = self.next()?;
+                                let id = self.next()?;
                                 //Note: we redo the lookup in order to work around `self` borrowing
                                 if type_id != self.lookup_function_type
                                     .lookup(fun_type)?
@@ -1289,19 +2131,28 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                                 }
                                 let ty = self.lookup_type.lookup(type_id)?.token;
                                 fun.parameter_types.push(ty);
+                                self.lookup_expression.insert(id, LookupExpression {
+                                    token: fun.expressions.append(crate::Expression::FunctionParameter(i as u32)),
+                                    type_id,
+                                });
+                                fun.spans.expressions.resize(fun.expressions.len(), self.span);
                             }
                             Instruction { op, .. } => return Err(Error::InvalidParameter(op)),
                         }
                     }
                     // read body
+                    let mut blocks = FastHashMap::default();
+                    let mut entry_id = None;
                     loop {
                         let fun_inst = self.next_inst()?;
                         log::debug!("\t\t{:?}", fun_inst.op);
                         match fun_inst.op {
                             Op::Label => {
                                 fun_inst.expect(2)?;
-                                let _id = self.next()?;
-                                self.next_block(&mut fun, &module.types, &module.constants)?;
+                                let block_id = self.next()?;
+                                entry_id.get_or_insert(block_id);
+                                let block = self.next_block(&mut fun, &module.types, &module.constants, &mut module.functions)?;
+                                blocks.insert(block_id, block);
                             }
                             Op::FunctionEnd => {
                                 fun_inst.expect(1)?;
@@ -1310,9 +2161,33 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                             _ => return Err(Error::UnsupportedInstruction(self.state, fun_inst.op))
                         }
                     }
+                    // link the blocks into structured statements
+                    if let Some(entry_id) = entry_id {
+                        let context = FlowContext {
+                            stop_id: None,
+                            break_id: None,
+                            continue_id: None,
+                            case_ids: &[],
+                        };
+                        let (body, _) = Self::structurize(&mut blocks, entry_id, &context)?;
+                        fun.body = body.block;
+                        fun.spans.statements = body.spans;
+                    }
+                    if !blocks.is_empty() {
+                        log::warn!("Unreachable blocks: {:?}", blocks.keys());
+                    }
                     // done
-                    let token = module.functions.append(fun);
-                    self.lookup_function.insert(fun_id, token);
+                    let span = crate::Span { start, end: self.span.end };
+                    let token = if self.future_function_ids.remove(&fun_id) {
+                        let token = self.lookup_function[&fun_id];
+                        module.functions[token] = fun;
+                        token
+                    } else {
+                        let token = module.functions.append(fun);
+                        self.lookup_function.insert(fun_id, token);
+                        token
+                    };
+                    set_span(&mut module.spans.functions, token.index(), span);
                     self.lookup_expression.clear();
                     self.lookup_sampled_image.clear();
                 }
@@ -1321,6 +2196,9 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             }
         }
 
+        if let Some(&id) = self.future_function_ids.iter().next() {
+            return Err(Error::InvalidId(id));
+        }
         if !self.future_decor.is_empty() {
             log::warn!("Unused item decorations: {:?}", self.future_decor);
             self.future_decor.clear();
@@ -1383,4 +2261,423 @@ mod test {
         ];
         let _ = super::parse_u8_slice(&bin).unwrap();
     }
+
+    fn make_module(body: &[(spirv::Op, &[u32])]) -> Vec<u32> {
+        let mut words = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        for &(op, operands) in body {
+            words.push(((operands.len() as u32 + 1) << 16) | op as u32);
+            words.extend_from_slice(operands);
+        }
+        words
+    }
+
+    fn parse_single_function(body: &[(spirv::Op, &[u32])]) -> crate::Block {
+        let words = make_module(body);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        fun.body.clone()
+    }
+
+    #[test]
+    fn parse_if() {
+        use spirv::Op;
+        let body = parse_single_function(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeVoid, &[1]),
+            (Op::TypeBool, &[2]),
+            (Op::TypeFunction, &[3, 1]),
+            (Op::ConstantTrue, &[2, 4]),
+            (Op::Function, &[1, 5, 0, 3]),
+            (Op::Label, &[6]),
+            (Op::SelectionMerge, &[9, 0]),
+            (Op::BranchConditional, &[4, 7, 8]),
+            (Op::Label, &[7]),
+            (Op::Branch, &[9]),
+            (Op::Label, &[8]),
+            (Op::Kill, &[]),
+            (Op::Label, &[9]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        match body[..] {
+            [crate::Statement::If { ref accept, ref reject, .. }, crate::Statement::Return { value: None }] => {
+                assert!(accept.is_empty());
+                match reject[..] {
+                    [crate::Statement::Kill] => (),
+                    ref other => panic!("Unexpected reject {:?}", other),
+                }
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_spans() {
+        use spirv::Op;
+        let words = make_module(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeVoid, &[1]),
+            (Op::TypeBool, &[2]),
+            (Op::TypeFunction, &[3, 1]),
+            (Op::ConstantTrue, &[2, 4]),
+            (Op::Function, &[1, 5, 0, 3]),
+            (Op::Label, &[6]),
+            (Op::SelectionMerge, &[9, 0]),
+            (Op::BranchConditional, &[4, 7, 8]),
+            (Op::Label, &[7]),
+            (Op::Branch, &[9]),
+            (Op::Label, &[8]),
+            (Op::Kill, &[]),
+            (Op::Label, &[9]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let (token, fun) = module.functions.iter().next().unwrap();
+        // the spans are word offsets, counting the header
+        assert_eq!(module.spans.function(token), crate::Span { start: 20, end: 45 });
+        assert_eq!(fun.spans.statements, vec![
+            crate::Span { start: 27, end: 34 },
+            crate::Span { start: 40, end: 41 },
+            crate::Span { start: 43, end: 44 },
+        ]);
+    }
+
+    #[test]
+    fn parse_loop() {
+        use spirv::Op;
+        let body = parse_single_function(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeVoid, &[1]),
+            (Op::TypeBool, &[2]),
+            (Op::TypeFunction, &[3, 1]),
+            (Op::ConstantTrue, &[2, 4]),
+            (Op::Function, &[1, 5, 0, 3]),
+            (Op::Label, &[6]),
+            (Op::Branch, &[10]),
+            (Op::Label, &[10]),
+            (Op::LoopMerge, &[12, 11, 0]),
+            (Op::BranchConditional, &[4, 13, 12]),
+            (Op::Label, &[13]),
+            (Op::SelectionMerge, &[15, 0]),
+            (Op::BranchConditional, &[4, 14, 15]),
+            (Op::Label, &[14]),
+            (Op::Branch, &[12]),
+            (Op::Label, &[15]),
+            (Op::Branch, &[11]),
+            (Op::Label, &[11]),
+            (Op::Branch, &[10]),
+            (Op::Label, &[12]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let loop_body = match body[..] {
+            [crate::Statement::Loop { ref body, ref continuing }, crate::Statement::Return { value: None }] => {
+                assert!(continuing.is_empty());
+                body
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        };
+        match loop_body[..] {
+            [crate::Statement::If { ref accept, ref reject, .. }] => {
+                match accept[..] {
+                    [crate::Statement::If { ref accept, ref reject, .. }] => {
+                        match accept[..] {
+                            [crate::Statement::Break] => (),
+                            ref other => panic!("Unexpected inner accept {:?}", other),
+                        }
+                        assert!(reject.is_empty());
+                    }
+                    ref other => panic!("Unexpected accept {:?}", other),
+                }
+                match reject[..] {
+                    [crate::Statement::Break] => (),
+                    ref other => panic!("Unexpected reject {:?}", other),
+                }
+            }
+            ref other => panic!("Unexpected loop body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_switch() {
+        use spirv::Op;
+        let body = parse_single_function(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeVoid, &[1]),
+            (Op::TypeInt, &[2, 32, 0]),
+            (Op::TypeFunction, &[3, 1]),
+            (Op::Constant, &[2, 4, 7]),
+            (Op::Function, &[1, 5, 0, 3]),
+            (Op::Label, &[10]),
+            (Op::SelectionMerge, &[20, 0]),
+            (Op::Switch, &[4, 13, 1, 11, 2, 11, 3, 12]),
+            (Op::Label, &[11]),
+            (Op::Branch, &[12]),
+            (Op::Label, &[12]),
+            (Op::Branch, &[20]),
+            (Op::Label, &[13]),
+            (Op::Kill, &[]),
+            (Op::Label, &[20]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        match body[..] {
+            [crate::Statement::Switch { ref cases, ref default, .. }, crate::Statement::Return { value: None }] => {
+                let values = cases
+                    .iter()
+                    .map(|&(value, ref block, fall_through)| (value, block.len(), fall_through.is_some()))
+                    .collect::<Vec<_>>();
+                assert_eq!(values, [(1, 0, true), (2, 0, true), (3, 0, false)]);
+                match default[..] {
+                    [crate::Statement::Kill] => (),
+                    ref other => panic!("Unexpected default {:?}", other),
+                }
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_switch_merge_targets() {
+        use spirv::Op;
+        // the literal 5 goes straight to the merge, and the case of 1 falls into the default
+        let body = parse_single_function(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeVoid, &[1]),
+            (Op::TypeInt, &[2, 32, 0]),
+            (Op::TypeFunction, &[3, 1]),
+            (Op::Constant, &[2, 4, 7]),
+            (Op::Function, &[1, 5, 0, 3]),
+            (Op::Label, &[10]),
+            (Op::SelectionMerge, &[20, 0]),
+            (Op::Switch, &[4, 13, 5, 20, 1, 11]),
+            (Op::Label, &[11]),
+            (Op::Branch, &[13]),
+            (Op::Label, &[13]),
+            (Op::Kill, &[]),
+            (Op::Label, &[20]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        match body[..] {
+            [crate::Statement::Switch { ref cases, ref default, .. }, crate::Statement::Return { value: None }] => {
+                let values = cases
+                    .iter()
+                    .map(|&(value, ref block, fall_through)| (value, block.len(), fall_through.is_some()))
+                    .collect::<Vec<_>>();
+                assert_eq!(values, [(5, 0, false), (1, 0, true)]);
+                match default[..] {
+                    [crate::Statement::Kill] => (),
+                    ref other => panic!("Unexpected default {:?}", other),
+                }
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_forward_call() {
+        use spirv::Op;
+        let words = make_module(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeVoid, &[1]),
+            (Op::TypeFunction, &[3, 1]),
+            (Op::Function, &[1, 5, 0, 3]),
+            (Op::Label, &[10]),
+            (Op::FunctionCall, &[1, 11, 6]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+            (Op::Function, &[1, 6, 0, 3]),
+            (Op::Label, &[20]),
+            (Op::Kill, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        assert_eq!(module.functions.iter().count(), 2);
+        // the callee is reserved first, so look for the caller by its contents
+        let caller = module.functions
+            .iter()
+            .map(|(_, fun)| fun)
+            .find(|fun| fun.body.len() == 2)
+            .unwrap();
+        match caller.body[..] {
+            [crate::Statement::Call { function, ref arguments }, crate::Statement::Return { value: None }] => {
+                assert!(arguments.is_empty());
+                match module.functions[function].body[..] {
+                    [crate::Statement::Kill] => (),
+                    ref other => panic!("Unexpected callee body {:?}", other),
+                }
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_binary_op() {
+        use spirv::Op;
+        let words = make_module(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeFloat, &[2, 32]),
+            (Op::Constant, &[2, 3, 1.0f32.to_bits()]),
+            (Op::TypeFunction, &[5, 2]),
+            (Op::Function, &[2, 6, 0, 5]),
+            (Op::Label, &[10]),
+            (Op::FSub, &[2, 4, 3, 3]),
+            (Op::ReturnValue, &[4]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        match fun.body[..] {
+            [crate::Statement::Return { value: Some(token) }] => match fun.expressions[token] {
+                crate::Expression::Binary { op: crate::BinaryOperator::Subtract, left, right } => {
+                    assert_eq!(left, right);
+                }
+                ref other => panic!("Unexpected expression {:?}", other),
+            },
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_unary_ops() {
+        use spirv::Op;
+        let words = make_module(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeFloat, &[2, 32]),
+            (Op::Constant, &[2, 3, 1.0f32.to_bits()]),
+            (Op::TypeInt, &[7, 32, 1]),
+            (Op::Constant, &[7, 8, 5]),
+            (Op::TypeBool, &[12]),
+            (Op::ConstantTrue, &[12, 13]),
+            (Op::TypeFunction, &[5, 2]),
+            (Op::Function, &[2, 6, 0, 5]),
+            (Op::Label, &[10]),
+            (Op::FNegate, &[2, 4, 3]),
+            (Op::SNegate, &[7, 9, 8]),
+            (Op::Not, &[7, 11, 9]),
+            (Op::LogicalNot, &[12, 14, 13]),
+            (Op::ReturnValue, &[4]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        let ops = fun.expressions
+            .iter()
+            .filter_map(|(_, expression)| match *expression {
+                crate::Expression::Unary { op, expr } => Some((op, &fun.expressions[expr])),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        match ops[..] {
+            [
+                (crate::UnaryOperator::Negate, &crate::Expression::Constant(_)),
+                (crate::UnaryOperator::Negate, &crate::Expression::Constant(_)),
+                (crate::UnaryOperator::BitwiseNot, &crate::Expression::Unary { op: crate::UnaryOperator::Negate, .. }),
+                (crate::UnaryOperator::Not, &crate::Expression::Constant(_)),
+            ] => (),
+            ref other => panic!("Unexpected expressions {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_signed_constants() {
+        use spirv::Op;
+        let words = make_module(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeInt, &[2, 32, 1]),
+            (Op::Constant, &[2, 3, 7]),
+            (Op::Constant, &[2, 4, (-2i32) as u32]),
+        ]);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let values = module.constants
+            .iter()
+            .map(|(_, constant)| match constant.inner {
+                crate::ConstantInner::Sint(value) => value,
+                ref other => panic!("Unexpected constant {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![7, -2]);
+    }
+
+    #[test]
+    fn parse_ext_inst() {
+        use spirv::Op;
+        let mut set_name = [0u32; 4];
+        for (i, &byte) in b"GLSL.std.450".iter().enumerate() {
+            set_name[i / 4] |= (byte as u32) << ((i % 4) * 8);
+        }
+        let words = make_module(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::ExtInstImport, &[1, set_name[0], set_name[1], set_name[2], set_name[3]]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeFloat, &[2, 32]),
+            (Op::Constant, &[2, 3, 2.0f32.to_bits()]),
+            (Op::TypeFunction, &[5, 2]),
+            (Op::Function, &[2, 6, 0, 5]),
+            (Op::Label, &[10]),
+            (Op::ExtInst, &[2, 4, 1, spirv::GLOp::Pow as u32, 3, 3]),
+            (Op::ReturnValue, &[4]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        match fun.body[..] {
+            [crate::Statement::Return { value: Some(token) }] => match fun.expressions[token] {
+                crate::Expression::Math { fun: crate::MathFunction::Pow, ref arguments } => {
+                    assert_eq!(arguments.len(), 2);
+                }
+                ref other => panic!("Unexpected expression {:?}", other),
+            },
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_local_variable() {
+        use spirv::Op;
+        let words = make_module(&[
+            (Op::Capability, &[spirv::Capability::Shader as u32]),
+            (Op::MemoryModel, &[0, 1]),
+            (Op::TypeVoid, &[1]),
+            (Op::TypeFloat, &[2, 32]),
+            (Op::TypePointer, &[7, spirv::StorageClass::Function as u32, 2]),
+            (Op::Constant, &[2, 3, 1.0f32.to_bits()]),
+            (Op::TypeFunction, &[5, 1]),
+            (Op::Function, &[1, 6, 0, 5]),
+            (Op::Label, &[10]),
+            (Op::Variable, &[7, 8, spirv::StorageClass::Function as u32, 3]),
+            (Op::Load, &[2, 9, 8]),
+            (Op::Store, &[8, 9]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        let (var_token, var) = fun.local_variables.iter().next().unwrap();
+        assert!(var.init.is_some());
+        match fun.body[..] {
+            [crate::Statement::Store { pointer, value }, crate::Statement::Return { value: None }] => {
+                match fun.expressions[pointer] {
+                    crate::Expression::LocalVariable(token) => assert_eq!(token, var_token),
+                    ref other => panic!("Unexpected pointer {:?}", other),
+                }
+                match fun.expressions[value] {
+                    crate::Expression::Load { pointer: load_pointer } => assert_eq!(load_pointer, pointer),
+                    ref other => panic!("Unexpected value {:?}", other),
+                }
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
 }
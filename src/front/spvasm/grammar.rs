@@ -0,0 +1,680 @@
+//! Operand layouts of the SPIR-V instructions, shared with the disassembler.
+//!
+//! Only the instructions of the core specification that a shader may use
+//! are described, which covers everything the binary front-end and back-end
+//! deal with.
+
+use crate::FastHashMap;
+
+use num_traits::FromPrimitive;
+use std::fmt::Debug;
+
+/// Values above this are not searched when looking up enumerants by name.
+const ENUMERANT_LIMIT: u32 = 0x2000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Kind {
+    ResultType,
+    Result,
+    Id,
+    Literal,
+    String,
+    /// Number with the width and the kind of a type, like the constants.
+    Number,
+    /// Instruction of an extended instruction set.
+    ExtInst,
+    Enum(EnumKind),
+    Mask(MaskKind),
+    /// Literal and label pair of `OpSwitch`.
+    Target,
+    /// Value and parent block pair of `OpPhi`.
+    Incoming,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Quantity {
+    One,
+    Optional,
+    Any,
+}
+
+pub(crate) type Operand = (Kind, Quantity);
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub(crate) enum EnumKind {
+    SourceLanguage,
+    ExecutionModel,
+    AddressingModel,
+    MemoryModel,
+    ExecutionMode,
+    StorageClass,
+    Dim,
+    SamplerAddressingMode,
+    SamplerFilterMode,
+    ImageFormat,
+    AccessQualifier,
+    Decoration,
+    BuiltIn,
+    Capability,
+    /// Instructions of the "GLSL.std.450" set.
+    Glsl450,
+}
+
+fn debug_name<T: FromPrimitive + Debug>(value: u32) -> Option<String> {
+    T::from_u32(value).map(|v| format!("{:?}", v))
+}
+
+impl EnumKind {
+    /// Returns the name of an enumerant, as written in the assembly.
+    pub(crate) fn name(self, value: u32) -> Option<String> {
+        match self {
+            EnumKind::SourceLanguage => debug_name::<spirv::SourceLanguage>(value),
+            EnumKind::ExecutionModel => debug_name::<spirv::ExecutionModel>(value),
+            EnumKind::AddressingModel => debug_name::<spirv::AddressingModel>(value),
+            EnumKind::MemoryModel => debug_name::<spirv::MemoryModel>(value),
+            EnumKind::ExecutionMode => debug_name::<spirv::ExecutionMode>(value),
+            EnumKind::StorageClass => debug_name::<spirv::StorageClass>(value),
+            // the variants can't start with a digit, unlike the enumerants
+            EnumKind::Dim => debug_name::<spirv::Dim>(value).map(|name| name["Dim".len() ..].to_owned()),
+            EnumKind::SamplerAddressingMode => debug_name::<spirv::SamplerAddressingMode>(value),
+            EnumKind::SamplerFilterMode => debug_name::<spirv::SamplerFilterMode>(value),
+            EnumKind::ImageFormat => debug_name::<spirv::ImageFormat>(value),
+            EnumKind::AccessQualifier => debug_name::<spirv::AccessQualifier>(value),
+            EnumKind::Decoration => debug_name::<spirv::Decoration>(value),
+            EnumKind::BuiltIn => debug_name::<spirv::BuiltIn>(value),
+            EnumKind::Capability => debug_name::<spirv::Capability>(value),
+            EnumKind::Glsl450 => debug_name::<spirv::GLOp>(value),
+        }
+    }
+
+    /// Collects the values of all the enumerants, by name.
+    pub(crate) fn values(self) -> FastHashMap<String, u32> {
+        (0 .. ENUMERANT_LIMIT)
+            .filter_map(|value| self.name(value).map(|name| (name, value)))
+            .collect()
+    }
+
+    /// Returns the operands following an enumerant.
+    pub(crate) fn parameters(self, value: u32) -> &'static [Kind] {
+        match self {
+            EnumKind::Decoration => match spirv::Decoration::from_u32(value) {
+                Some(spirv::Decoration::SpecId) |
+                Some(spirv::Decoration::ArrayStride) |
+                Some(spirv::Decoration::MatrixStride) |
+                Some(spirv::Decoration::Stream) |
+                Some(spirv::Decoration::Location) |
+                Some(spirv::Decoration::Component) |
+                Some(spirv::Decoration::Index) |
+                Some(spirv::Decoration::Binding) |
+                Some(spirv::Decoration::DescriptorSet) |
+                Some(spirv::Decoration::Offset) |
+                Some(spirv::Decoration::XfbBuffer) |
+                Some(spirv::Decoration::XfbStride) |
+                Some(spirv::Decoration::FuncParamAttr) |
+                Some(spirv::Decoration::FPRoundingMode) |
+                Some(spirv::Decoration::FPFastMathMode) |
+                Some(spirv::Decoration::InputAttachmentIndex) |
+                Some(spirv::Decoration::Alignment) |
+                Some(spirv::Decoration::MaxByteOffset) |
+                Some(spirv::Decoration::SecondaryViewportRelativeNV) => &[Kind::Literal],
+                Some(spirv::Decoration::BuiltIn) => &[Kind::Enum(EnumKind::BuiltIn)],
+                Some(spirv::Decoration::LinkageAttributes) => &[Kind::String, Kind::Literal],
+                Some(spirv::Decoration::AlignmentId) |
+                Some(spirv::Decoration::MaxByteOffsetId) |
+                Some(spirv::Decoration::CounterBuffer) => &[Kind::Id],
+                Some(spirv::Decoration::UserSemantic) |
+                Some(spirv::Decoration::UserTypeGOOGLE) => &[Kind::String],
+                _ => &[],
+            },
+            EnumKind::ExecutionMode => match spirv::ExecutionMode::from_u32(value) {
+                Some(spirv::ExecutionMode::Invocations) |
+                Some(spirv::ExecutionMode::OutputVertices) |
+                Some(spirv::ExecutionMode::VecTypeHint) |
+                Some(spirv::ExecutionMode::SubgroupSize) |
+                Some(spirv::ExecutionMode::SubgroupsPerWorkgroup) |
+                Some(spirv::ExecutionMode::OutputPrimitivesNV) => &[Kind::Literal],
+                Some(spirv::ExecutionMode::LocalSize) |
+                Some(spirv::ExecutionMode::LocalSizeHint) => &[Kind::Literal, Kind::Literal, Kind::Literal],
+                Some(spirv::ExecutionMode::SubgroupsPerWorkgroupId) => &[Kind::Id],
+                Some(spirv::ExecutionMode::LocalSizeId) |
+                Some(spirv::ExecutionMode::LocalSizeHintId) => &[Kind::Id, Kind::Id, Kind::Id],
+                _ => &[],
+            },
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MaskKind {
+    FunctionControl,
+    SelectionControl,
+    LoopControl,
+    MemoryAccess,
+    ImageOperands,
+}
+
+/// Name of a mask bit, its value, and the operands it needs.
+pub(crate) type MaskBit = (&'static str, u32, &'static [Kind]);
+
+impl MaskKind {
+    /// Returns the bits in the order their operands come in.
+    pub(crate) fn bits(self) -> &'static [MaskBit] {
+        match self {
+            MaskKind::FunctionControl => &[
+                ("Inline", 0x1, &[]),
+                ("DontInline", 0x2, &[]),
+                ("Pure", 0x4, &[]),
+                ("Const", 0x8, &[]),
+            ],
+            MaskKind::SelectionControl => &[
+                ("Flatten", 0x1, &[]),
+                ("DontFlatten", 0x2, &[]),
+            ],
+            MaskKind::LoopControl => &[
+                ("Unroll", 0x1, &[]),
+                ("DontUnroll", 0x2, &[]),
+                ("DependencyInfinite", 0x4, &[]),
+                ("DependencyLength", 0x8, &[Kind::Literal]),
+                ("MinIterations", 0x10, &[Kind::Literal]),
+                ("MaxIterations", 0x20, &[Kind::Literal]),
+                ("IterationMultiple", 0x40, &[Kind::Literal]),
+                ("PeelCount", 0x80, &[Kind::Literal]),
+                ("PartialCount", 0x100, &[Kind::Literal]),
+            ],
+            MaskKind::MemoryAccess => &[
+                ("Volatile", 0x1, &[]),
+                ("Aligned", 0x2, &[Kind::Literal]),
+                ("Nontemporal", 0x4, &[]),
+                ("MakePointerAvailable", 0x8, &[Kind::Id]),
+                ("MakePointerVisible", 0x10, &[Kind::Id]),
+                ("NonPrivatePointer", 0x20, &[]),
+            ],
+            MaskKind::ImageOperands => &[
+                ("Bias", 0x1, &[Kind::Id]),
+                ("Lod", 0x2, &[Kind::Id]),
+                ("Grad", 0x4, &[Kind::Id, Kind::Id]),
+                ("ConstOffset", 0x8, &[Kind::Id]),
+                ("Offset", 0x10, &[Kind::Id]),
+                ("ConstOffsets", 0x20, &[Kind::Id]),
+                ("Sample", 0x40, &[Kind::Id]),
+                ("MinLod", 0x80, &[Kind::Id]),
+                ("MakeTexelAvailable", 0x100, &[Kind::Id]),
+                ("MakeTexelVisible", 0x200, &[Kind::Id]),
+                ("NonPrivateTexel", 0x400, &[]),
+                ("VolatileTexel", 0x800, &[]),
+                ("SignExtend", 0x1000, &[]),
+                ("ZeroExtend", 0x2000, &[]),
+            ],
+        }
+    }
+}
+
+/// Returns the name of an instruction, as written in the assembly.
+pub(crate) fn instruction_name(op: spirv::Op) -> String {
+    format!("Op{:?}", op)
+}
+
+/// Collects all the instructions, by name.
+pub(crate) fn instructions() -> FastHashMap<String, spirv::Op> {
+    (0 .. ENUMERANT_LIMIT)
+        .filter_map(spirv::Op::from_u32)
+        .map(|op| (instruction_name(op), op))
+        .collect()
+}
+
+const UNARY: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+];
+const BINARY: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+];
+const TERNARY: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+];
+const VALUE: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+];
+const COMPOSITE: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::Any),
+];
+const CONSTANT: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Number, Quantity::One),
+];
+const ACCESS_CHAIN: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::Any),
+];
+const IMAGE_ACCESS: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Mask(MaskKind::ImageOperands), Quantity::Optional),
+];
+const IMAGE_ACCESS_LOD: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Mask(MaskKind::ImageOperands), Quantity::One),
+];
+const IMAGE_ACCESS_DREF: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Mask(MaskKind::ImageOperands), Quantity::Optional),
+];
+const IMAGE_ACCESS_DREF_LOD: &[Operand] = &[
+    (Kind::ResultType, Quantity::One),
+    (Kind::Result, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Id, Quantity::One),
+    (Kind::Mask(MaskKind::ImageOperands), Quantity::One),
+];
+const TYPE: &[Operand] = &[(Kind::Result, Quantity::One)];
+const STRING: &[Operand] = &[(Kind::String, Quantity::One)];
+const NONE: &[Operand] = &[];
+
+/// Returns the operands of an instruction, or `None` if it's not supported.
+pub(crate) fn operands(op: spirv::Op) -> Option<&'static [Operand]> {
+    use self::{Kind as K, Quantity as Q};
+    use spirv::Op;
+
+    Some(match op {
+        Op::Nop |
+        Op::NoLine |
+        Op::FunctionEnd |
+        Op::Kill |
+        Op::Return |
+        Op::Unreachable |
+        Op::EmitVertex |
+        Op::EndPrimitive => NONE,
+        Op::Undef |
+        Op::ConstantTrue |
+        Op::ConstantFalse |
+        Op::ConstantNull |
+        Op::SpecConstantTrue |
+        Op::SpecConstantFalse |
+        Op::FunctionParameter => VALUE,
+        Op::SourceContinued |
+        Op::SourceExtension |
+        Op::ModuleProcessed |
+        Op::Extension => STRING,
+        Op::Source => &[
+            (K::Enum(EnumKind::SourceLanguage), Q::One),
+            (K::Literal, Q::One),
+            (K::Id, Q::Optional),
+            (K::String, Q::Optional),
+        ],
+        Op::Name => &[(K::Id, Q::One), (K::String, Q::One)],
+        Op::MemberName => &[(K::Id, Q::One), (K::Literal, Q::One), (K::String, Q::One)],
+        Op::String |
+        Op::ExtInstImport => &[(K::Result, Q::One), (K::String, Q::One)],
+        Op::Line => &[(K::Id, Q::One), (K::Literal, Q::One), (K::Literal, Q::One)],
+        Op::ExtInst => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::ExtInst, Q::One),
+            (K::Id, Q::Any),
+        ],
+        Op::MemoryModel => &[
+            (K::Enum(EnumKind::AddressingModel), Q::One),
+            (K::Enum(EnumKind::MemoryModel), Q::One),
+        ],
+        Op::EntryPoint => &[
+            (K::Enum(EnumKind::ExecutionModel), Q::One),
+            (K::Id, Q::One),
+            (K::String, Q::One),
+            (K::Id, Q::Any),
+        ],
+        Op::ExecutionMode => &[(K::Id, Q::One), (K::Enum(EnumKind::ExecutionMode), Q::One)],
+        Op::Capability => &[(K::Enum(EnumKind::Capability), Q::One)],
+        Op::TypeVoid |
+        Op::TypeBool |
+        Op::TypeSampler |
+        Op::DecorationGroup |
+        Op::Label => TYPE,
+        Op::TypeInt => &[(K::Result, Q::One), (K::Literal, Q::One), (K::Literal, Q::One)],
+        Op::TypeFloat => &[(K::Result, Q::One), (K::Literal, Q::One)],
+        Op::TypeVector |
+        Op::TypeMatrix => &[(K::Result, Q::One), (K::Id, Q::One), (K::Literal, Q::One)],
+        Op::TypeImage => &[
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::Enum(EnumKind::Dim), Q::One),
+            (K::Literal, Q::One),
+            (K::Literal, Q::One),
+            (K::Literal, Q::One),
+            (K::Literal, Q::One),
+            (K::Enum(EnumKind::ImageFormat), Q::One),
+            (K::Enum(EnumKind::AccessQualifier), Q::Optional),
+        ],
+        Op::TypeSampledImage |
+        Op::TypeRuntimeArray => &[(K::Result, Q::One), (K::Id, Q::One)],
+        Op::TypeArray => &[(K::Result, Q::One), (K::Id, Q::One), (K::Id, Q::One)],
+        Op::TypeStruct => &[(K::Result, Q::One), (K::Id, Q::Any)],
+        Op::TypeOpaque => &[(K::Result, Q::One), (K::String, Q::One)],
+        Op::TypePointer => &[
+            (K::Result, Q::One),
+            (K::Enum(EnumKind::StorageClass), Q::One),
+            (K::Id, Q::One),
+        ],
+        Op::TypeFunction => &[(K::Result, Q::One), (K::Id, Q::One), (K::Id, Q::Any)],
+        Op::Constant |
+        Op::SpecConstant => CONSTANT,
+        Op::ConstantComposite |
+        Op::SpecConstantComposite |
+        Op::CompositeConstruct => COMPOSITE,
+        Op::ConstantSampler => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Enum(EnumKind::SamplerAddressingMode), Q::One),
+            (K::Literal, Q::One),
+            (K::Enum(EnumKind::SamplerFilterMode), Q::One),
+        ],
+        Op::Function => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Mask(MaskKind::FunctionControl), Q::One),
+            (K::Id, Q::One),
+        ],
+        Op::FunctionCall |
+        Op::AccessChain |
+        Op::InBoundsAccessChain => ACCESS_CHAIN,
+        Op::PtrAccessChain |
+        Op::InBoundsPtrAccessChain => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Id, Q::Any),
+        ],
+        Op::Variable => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Enum(EnumKind::StorageClass), Q::One),
+            (K::Id, Q::Optional),
+        ],
+        Op::ImageTexelPointer |
+        Op::VectorInsertDynamic |
+        Op::Select => TERNARY,
+        Op::Load => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::Mask(MaskKind::MemoryAccess), Q::Optional),
+        ],
+        Op::Store |
+        Op::CopyMemory => &[
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Mask(MaskKind::MemoryAccess), Q::Optional),
+        ],
+        Op::ArrayLength => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::Literal, Q::One),
+        ],
+        Op::Decorate |
+        Op::DecorateId |
+        Op::DecorateString => &[(K::Id, Q::One), (K::Enum(EnumKind::Decoration), Q::One)],
+        Op::MemberDecorate |
+        Op::MemberDecorateString => &[
+            (K::Id, Q::One),
+            (K::Literal, Q::One),
+            (K::Enum(EnumKind::Decoration), Q::One),
+        ],
+        Op::GroupDecorate => &[(K::Id, Q::One), (K::Id, Q::Any)],
+        Op::VectorShuffle => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Literal, Q::Any),
+        ],
+        Op::CompositeExtract => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::Literal, Q::Any),
+        ],
+        Op::CompositeInsert => &[
+            (K::ResultType, Q::One),
+            (K::Result, Q::One),
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Literal, Q::Any),
+        ],
+        Op::ImageSampleImplicitLod |
+        Op::ImageSampleProjImplicitLod |
+        Op::ImageFetch |
+        Op::ImageRead => IMAGE_ACCESS,
+        Op::ImageSampleExplicitLod |
+        Op::ImageSampleProjExplicitLod => IMAGE_ACCESS_LOD,
+        Op::ImageSampleDrefImplicitLod |
+        Op::ImageSampleProjDrefImplicitLod |
+        Op::ImageGather |
+        Op::ImageDrefGather => IMAGE_ACCESS_DREF,
+        Op::ImageSampleDrefExplicitLod |
+        Op::ImageSampleProjDrefExplicitLod => IMAGE_ACCESS_DREF_LOD,
+        Op::ImageWrite => &[
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Mask(MaskKind::ImageOperands), Q::Optional),
+        ],
+        Op::CopyObject |
+        Op::Transpose |
+        Op::Image |
+        Op::ImageQuerySize |
+        Op::ImageQueryLevels |
+        Op::ImageQuerySamples |
+        Op::ConvertFToU |
+        Op::ConvertFToS |
+        Op::ConvertSToF |
+        Op::ConvertUToF |
+        Op::UConvert |
+        Op::SConvert |
+        Op::FConvert |
+        Op::QuantizeToF16 |
+        Op::Bitcast |
+        Op::SNegate |
+        Op::FNegate |
+        Op::Not |
+        Op::LogicalNot |
+        Op::IsNan |
+        Op::IsInf |
+        Op::Any |
+        Op::All |
+        Op::BitReverse |
+        Op::BitCount |
+        Op::DPdx |
+        Op::DPdy |
+        Op::Fwidth |
+        Op::DPdxFine |
+        Op::DPdyFine |
+        Op::FwidthFine |
+        Op::DPdxCoarse |
+        Op::DPdyCoarse |
+        Op::FwidthCoarse => UNARY,
+        Op::SampledImage |
+        Op::ImageQuerySizeLod |
+        Op::ImageQueryLod |
+        Op::VectorExtractDynamic |
+        Op::IAdd |
+        Op::FAdd |
+        Op::ISub |
+        Op::FSub |
+        Op::IMul |
+        Op::FMul |
+        Op::UDiv |
+        Op::SDiv |
+        Op::FDiv |
+        Op::UMod |
+        Op::SRem |
+        Op::SMod |
+        Op::FRem |
+        Op::FMod |
+        Op::VectorTimesScalar |
+        Op::MatrixTimesScalar |
+        Op::VectorTimesMatrix |
+        Op::MatrixTimesVector |
+        Op::MatrixTimesMatrix |
+        Op::OuterProduct |
+        Op::Dot |
+        Op::LogicalEqual |
+        Op::LogicalNotEqual |
+        Op::LogicalOr |
+        Op::LogicalAnd |
+        Op::IEqual |
+        Op::INotEqual |
+        Op::UGreaterThan |
+        Op::SGreaterThan |
+        Op::UGreaterThanEqual |
+        Op::SGreaterThanEqual |
+        Op::ULessThan |
+        Op::SLessThan |
+        Op::ULessThanEqual |
+        Op::SLessThanEqual |
+        Op::FOrdEqual |
+        Op::FUnordEqual |
+        Op::FOrdNotEqual |
+        Op::FUnordNotEqual |
+        Op::FOrdLessThan |
+        Op::FUnordLessThan |
+        Op::FOrdGreaterThan |
+        Op::FUnordGreaterThan |
+        Op::FOrdLessThanEqual |
+        Op::FUnordLessThanEqual |
+        Op::FOrdGreaterThanEqual |
+        Op::FUnordGreaterThanEqual |
+        Op::ShiftRightLogical |
+        Op::ShiftRightArithmetic |
+        Op::ShiftLeftLogical |
+        Op::BitwiseOr |
+        Op::BitwiseXor |
+        Op::BitwiseAnd => BINARY,
+        Op::Phi => &[(K::ResultType, Q::One), (K::Result, Q::One), (K::Incoming, Q::Any)],
+        Op::LoopMerge => &[
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Mask(MaskKind::LoopControl), Q::One),
+        ],
+        Op::SelectionMerge => &[(K::Id, Q::One), (K::Mask(MaskKind::SelectionControl), Q::One)],
+        Op::Branch |
+        Op::ReturnValue => &[(K::Id, Q::One)],
+        Op::BranchConditional => &[
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Id, Q::One),
+            (K::Literal, Q::Any),
+        ],
+        Op::Switch => &[(K::Id, Q::One), (K::Id, Q::One), (K::Target, Q::Any)],
+        Op::ControlBarrier => &[(K::Id, Q::One), (K::Id, Q::One), (K::Id, Q::One)],
+        Op::MemoryBarrier => &[(K::Id, Q::One), (K::Id, Q::One)],
+        _ => return None,
+    })
+}
+
+/// Type of the numbers, as far as their encoding is concerned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum NumberType {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+}
+
+/// Keeps track of the types needed to encode the numbers of an instruction
+/// stream, and of the imported instruction sets.
+#[derive(Default)]
+pub(crate) struct Types {
+    numbers: FastHashMap<spirv::Word, NumberType>,
+    values: FastHashMap<spirv::Word, spirv::Word>,
+    glsl_sets: Vec<spirv::Word>,
+}
+
+impl Types {
+    /// Records what an instruction defines, given all its operand words.
+    pub(crate) fn register(&mut self, op: spirv::Op, layout: &[Operand], operands: &[spirv::Word]) {
+        match (op, operands) {
+            (spirv::Op::TypeInt, &[id, width, signed]) => {
+                self.numbers.insert(id, NumberType::Int { width, signed: signed != 0 });
+            }
+            (spirv::Op::TypeFloat, &[id, width]) => {
+                self.numbers.insert(id, NumberType::Float { width });
+            }
+            _ => (),
+        }
+        if let [(Kind::ResultType, _), (Kind::Result, _), ..] = *layout {
+            self.values.insert(operands[1], operands[0]);
+        }
+    }
+
+    pub(crate) fn import(&mut self, id: spirv::Word, name: &str) {
+        if name == "GLSL.std.450" {
+            self.glsl_sets.push(id);
+        }
+    }
+
+    /// Returns the type of the numbers following the given operands.
+    pub(crate) fn number(&self, op: spirv::Op, operands: &[spirv::Word]) -> Option<NumberType> {
+        let ty = match op {
+            spirv::Op::Switch => *self.values.get(operands.first()?)?,
+            _ => *operands.first()?,
+        };
+        self.numbers.get(&ty).cloned()
+    }
+
+    /// Returns the kind of the instruction number of `OpExtInst`.
+    pub(crate) fn ext_inst(&self, operands: &[spirv::Word]) -> Kind {
+        match operands.get(2) {
+            Some(set) if self.glsl_sets.contains(set) => Kind::Enum(EnumKind::Glsl450),
+            _ => Kind::Literal,
+        }
+    }
+}
+
+/// Appends a literal string, nul-terminated and padded to whole words.
+pub(crate) fn encode_string(string: &str, words: &mut Vec<spirv::Word>) {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.push(0);
+    while bytes.len() % 4 != 0 {
+        bytes.push(0);
+    }
+    words.extend(bytes.chunks(4).map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])));
+}
+
+/// Reads a literal string, returning it with the number of words it takes.
+pub(crate) fn decode_string(words: &[spirv::Word]) -> Option<(String, usize)> {
+    let mut bytes = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        for &byte in word.to_le_bytes().iter() {
+            if byte == 0 {
+                return String::from_utf8(bytes).ok().map(|string| (string, index + 1));
+            }
+            bytes.push(byte);
+        }
+    }
+    None
+}
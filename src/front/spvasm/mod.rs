@@ -0,0 +1,693 @@
+/*! SPIR-V assembler
+
+Turns the text format of SPIR-V, as printed by `spirv-dis` and by
+`back::spvasm`, into the words consumed by `front::spirv::Parser`:
+
+```text
+               OpCapability Shader
+               OpMemoryModel Logical GLSL450
+       %void = OpTypeVoid
+      %float = OpTypeFloat 32
+  %float_one = OpConstant %float 1.0
+```
+
+Numbered ids (`%5`) keep their number, while the named ones get
+the first numbers not used anywhere in the source. The version and the
+generator of the header can be given by the `; Version: 1.3` and
+`; Generator: 7` comments.
+!*/
+
+pub(crate) mod grammar;
+
+use self::grammar::{EnumKind, Kind, MaskKind, NumberType, Quantity, Types};
+use crate::FastHashMap;
+
+use std::convert::TryFrom;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    BadNumber(String),
+    BadHeader(String),
+    UnexpectedToken { expected: &'static str, found: String },
+    UnexpectedEnd,
+    InvalidId(String),
+    UnknownInstruction(String),
+    UnsupportedInstruction(spirv::Op),
+    UnknownEnumerant(String),
+    MissingResult(spirv::Op),
+    UnexpectedResult(spirv::Op),
+    UnknownNumberType(spirv::Op),
+    UnsupportedNumberWidth(u32),
+}
+
+/// Error with the source line it occurred on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Id(String),
+    Equals,
+    Bar,
+    String(String),
+    Number(String),
+    Word(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match *self {
+            Token::Id(ref name) => format!("%{}", name),
+            Token::Equals => "=".to_owned(),
+            Token::Bar => "|".to_owned(),
+            Token::String(ref string) => format!("{:?}", string),
+            Token::Number(ref text) | Token::Word(ref text) => text.clone(),
+        }
+    }
+}
+
+struct Lexeme {
+    token: Token,
+    line: u32,
+}
+
+/// Version and generator of the module.
+struct Header {
+    version: spirv::Word,
+    generator: spirv::Word,
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Splits the source into tokens, reading the header from the comments.
+fn tokenize(source: &str) -> Result<(Vec<Lexeme>, Header), Error> {
+    let mut lexemes = Vec::new();
+    let mut header = Header {
+        version: 0x0001_0000,
+        generator: crate::front::GENERATOR,
+    };
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let error = move |kind| Error { kind, line };
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            _ if c.is_whitespace() => continue,
+            ';' => {
+                let end = source[start ..].find('\n').map_or(source.len(), |end| start + end);
+                while chars.peek().map_or(false, |&(offset, _)| offset < end) {
+                    chars.next();
+                }
+                let comment = source[start + 1 .. end].trim();
+                if let Some(version) = comment.strip_prefix("Version:") {
+                    header.version = parse_version(version.trim())
+                        .ok_or_else(|| error(ErrorKind::BadHeader(comment.to_owned())))?;
+                } else if let Some(generator) = comment.strip_prefix("Generator:") {
+                    header.generator = parse_integer(generator.trim())
+                        .and_then(|value| u32::try_from(value).ok())
+                        .ok_or_else(|| error(ErrorKind::BadHeader(comment.to_owned())))?;
+                }
+                continue;
+            }
+            '=' => Token::Equals,
+            '|' => Token::Bar,
+            '%' => {
+                let mut name = String::new();
+                while let Some(&(_, c)) = chars.peek().filter(|&&(_, c)| is_id_char(c)) {
+                    name.push(c);
+                    chars.next();
+                }
+                if name.is_empty() {
+                    return Err(error(ErrorKind::InvalidId(name)));
+                }
+                Token::Id(name)
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => string.push(c),
+                            None => return Err(error(ErrorKind::UnterminatedString)),
+                        },
+                        Some((_, c)) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            string.push(c);
+                        }
+                        None => return Err(error(ErrorKind::UnterminatedString)),
+                    }
+                }
+                Token::String(string)
+            }
+            _ if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let mut text = c.to_string();
+                while let Some(&(_, next)) = chars.peek() {
+                    // the exponent of the hexadecimal floats starts with a `p`
+                    let is_hex = text.contains(['x', 'X']);
+                    let is_sign = (next == '-' || next == '+') &&
+                        text.ends_with(|last: char| if is_hex { last == 'p' || last == 'P' } else { last == 'e' || last == 'E' });
+                    if !next.is_ascii_alphanumeric() && next != '.' && !is_sign {
+                        break;
+                    }
+                    text.push(next);
+                    chars.next();
+                }
+                Token::Number(text)
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&(_, c)) = chars.peek().filter(|&&(_, c)| c.is_ascii_alphanumeric() || c == '_') {
+                    word.push(c);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+            _ => return Err(error(ErrorKind::UnexpectedCharacter(c))),
+        };
+        lexemes.push(Lexeme { token, line });
+    }
+    Ok((lexemes, header))
+}
+
+fn parse_version(text: &str) -> Option<spirv::Word> {
+    let mut parts = text.splitn(2, '.');
+    let major: u8 = parts.next()?.parse().ok()?;
+    let minor: u8 = parts.next()?.parse().ok()?;
+    Some(((major as u32) << 16) | ((minor as u32) << 8))
+}
+
+/// Parses a decimal or hexadecimal integer, with an optional sign.
+fn parse_integer(text: &str) -> Option<i128> {
+    let (negative, rest) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = match rest.strip_prefix("0x").or_else(|| rest.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None if rest.starts_with(|c: char| c.is_ascii_digit()) => rest.parse().ok()?,
+        None => return None,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Parses a hexadecimal float, like `-0x1.8p+3`.
+///
+/// An exponent of `max_exponent` denotes the infinities and the NaNs.
+fn parse_hex_float(text: &str, max_exponent: i32) -> Option<f64> {
+    let (negative, rest) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let rest = rest.strip_prefix("0x").or_else(|| rest.strip_prefix("0X"))?;
+    let (mantissa, exponent) = match rest.find(['p', 'P']) {
+        Some(position) => (&rest[.. position], rest[position + 1 ..].parse::<i32>().ok()?),
+        None => (rest, 0),
+    };
+    let (integer, fraction) = match mantissa.find('.') {
+        Some(position) => (&mantissa[.. position], &mantissa[position + 1 ..]),
+        None => (mantissa, ""),
+    };
+    let digits = format!("{}{}", integer, fraction);
+    if digits.is_empty() || digits.len() > 15 {
+        return None;
+    }
+    let value = u64::from_str_radix(&digits, 16).ok()?;
+    let magnitude = if exponent >= max_exponent {
+        if value >> (4 * fraction.len()) != 1 {
+            return None;
+        }
+        if value == 1 << (4 * fraction.len()) {
+            f64::INFINITY
+        } else {
+            f64::NAN
+        }
+    } else {
+        value as f64 * 2f64.powi(exponent - 4 * fraction.len() as i32)
+    };
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+/// Encodes a number of the given type, the wider ones taking two words.
+fn encode_number(text: &str, ty: NumberType, words: &mut Vec<spirv::Word>) -> Result<(), ErrorKind> {
+    let bad_number = || ErrorKind::BadNumber(text.to_owned());
+    let is_hex = text.trim_start_matches(['-', '+']).starts_with("0x");
+    match ty {
+        NumberType::Float { width: 32 } => {
+            let value = if is_hex {
+                parse_hex_float(text, 128).map(|value| value as f32)
+            } else {
+                text.parse::<f32>().ok()
+            };
+            words.push(value.ok_or_else(bad_number)?.to_bits());
+        }
+        NumberType::Float { width: 64 } => {
+            let value = if is_hex {
+                parse_hex_float(text, 1024)
+            } else {
+                text.parse::<f64>().ok()
+            };
+            let bits = value.ok_or_else(bad_number)?.to_bits();
+            words.push(bits as u32);
+            words.push((bits >> 32) as u32);
+        }
+        NumberType::Float { width } => return Err(ErrorKind::UnsupportedNumberWidth(width)),
+        NumberType::Int { width, signed } => {
+            if width == 0 || width > 64 {
+                return Err(ErrorKind::UnsupportedNumberWidth(width));
+            }
+            let value = parse_integer(text).ok_or_else(bad_number)?;
+            let (min, max) = if signed {
+                (-(1i128 << (width - 1)), (1i128 << (width - 1)) - 1)
+            } else {
+                (0, (1i128 << width) - 1)
+            };
+            if value < min || value > max {
+                return Err(bad_number());
+            }
+            // the narrow signed types are sign-extended to a whole word
+            let bits = value as i64 as u64;
+            words.push(bits as u32);
+            if width > 32 {
+                words.push((bits >> 32) as u32);
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Assembler {
+    lexemes: Vec<Lexeme>,
+    position: usize,
+    instructions: FastHashMap<String, spirv::Op>,
+    enumerants: FastHashMap<EnumKind, FastHashMap<String, u32>>,
+    ids: FastHashMap<String, spirv::Word>,
+    next_id: spirv::Word,
+    types: Types,
+}
+
+impl Assembler {
+    fn new(lexemes: Vec<Lexeme>) -> Result<Self, Error> {
+        // the named ids come after all the numbered ones
+        let mut max_id = 0;
+        for lexeme in lexemes.iter() {
+            if let Token::Id(ref name) = lexeme.token {
+                if name.starts_with(|c: char| c.is_ascii_digit()) {
+                    match name.parse::<spirv::Word>() {
+                        Ok(id) if id != 0 => max_id = max_id.max(id),
+                        _ => {
+                            return Err(Error {
+                                kind: ErrorKind::InvalidId(name.clone()),
+                                line: lexeme.line,
+                            })
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Assembler {
+            lexemes,
+            position: 0,
+            instructions: grammar::instructions(),
+            enumerants: FastHashMap::default(),
+            ids: FastHashMap::default(),
+            next_id: max_id + 1,
+            types: Types::default(),
+        })
+    }
+
+    fn line(&self) -> u32 {
+        match self.lexemes.get(self.position).or_else(|| self.lexemes.last()) {
+            Some(lexeme) => lexeme.line,
+            None => 1,
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error { kind, line: self.line() }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.lexemes.get(self.position).map(|lexeme| &lexeme.token)
+    }
+
+    fn unexpected(&self, expected: &'static str) -> Error {
+        match self.peek() {
+            Some(token) => self.error(ErrorKind::UnexpectedToken {
+                expected,
+                found: token.describe(),
+            }),
+            None => self.error(ErrorKind::UnexpectedEnd),
+        }
+    }
+
+    /// Returns true if the next token starts a new instruction, or there is none.
+    fn at_instruction_start(&self) -> bool {
+        match self.peek() {
+            None => true,
+            Some(Token::Id(_)) => self.lexemes.get(self.position + 1).map(|lexeme| &lexeme.token) == Some(&Token::Equals),
+            Some(Token::Word(word)) => self.instructions.contains_key(word),
+            Some(_) => false,
+        }
+    }
+
+    fn starts_operand(&self, kind: Kind) -> bool {
+        if self.at_instruction_start() {
+            return false;
+        }
+        matches!(
+            (kind, self.peek()),
+            (Kind::ResultType, Some(Token::Id(_))) |
+            (Kind::Id, Some(Token::Id(_))) |
+            (Kind::Incoming, Some(Token::Id(_))) |
+            (Kind::Literal, Some(Token::Number(_))) |
+            (Kind::Number, Some(Token::Number(_))) |
+            (Kind::Target, Some(Token::Number(_))) |
+            (Kind::String, Some(Token::String(_))) |
+            (Kind::Enum(_), Some(Token::Word(_))) |
+            (Kind::Enum(_), Some(Token::Number(_))) |
+            (Kind::Mask(_), Some(Token::Word(_))) |
+            (Kind::Mask(_), Some(Token::Number(_))) |
+            (Kind::ExtInst, Some(Token::Word(_))) |
+            (Kind::ExtInst, Some(Token::Number(_)))
+        )
+    }
+
+    fn id(&mut self, name: &str) -> spirv::Word {
+        if let Ok(id) = name.parse() {
+            return id;
+        }
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.ids.insert(name.to_owned(), id);
+        id
+    }
+
+    fn expect_id(&mut self) -> Result<spirv::Word, Error> {
+        match self.peek() {
+            Some(Token::Id(name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(self.id(&name))
+            }
+            _ => Err(self.unexpected("id")),
+        }
+    }
+
+    fn expect_number(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Number(text)) => {
+                let text = text.clone();
+                self.position += 1;
+                Ok(text)
+            }
+            _ => Err(self.unexpected("number")),
+        }
+    }
+
+    fn expect_literal(&mut self) -> Result<spirv::Word, Error> {
+        let text = self.expect_number()?;
+        parse_integer(&text)
+            .and_then(|value| u32::try_from(value).ok())
+            .ok_or_else(|| self.error(ErrorKind::BadNumber(text)))
+    }
+
+    fn number(&mut self, op: spirv::Op, operands: &mut Vec<spirv::Word>) -> Result<(), Error> {
+        let ty = self.types
+            .number(op, operands)
+            .ok_or_else(|| self.error(ErrorKind::UnknownNumberType(op)))?;
+        let text = self.expect_number()?;
+        encode_number(&text, ty, operands).map_err(|kind| self.error(kind))
+    }
+
+    fn enumerant(&mut self, kind: EnumKind) -> Result<u32, Error> {
+        let name = match self.peek() {
+            Some(Token::Number(_)) => return self.expect_literal(),
+            Some(Token::Word(name)) => name.clone(),
+            _ => return Err(self.unexpected("enumerant")),
+        };
+        let value = self.enumerants
+            .entry(kind)
+            .or_insert_with(|| kind.values())
+            .get(&name)
+            .cloned();
+        match value {
+            Some(value) => {
+                self.position += 1;
+                Ok(value)
+            }
+            None => Err(self.error(ErrorKind::UnknownEnumerant(name))),
+        }
+    }
+
+    fn mask(&mut self, kind: MaskKind) -> Result<u32, Error> {
+        let mut mask = 0;
+        loop {
+            mask |= match self.peek() {
+                Some(Token::Number(_)) => self.expect_literal()?,
+                Some(Token::Word(name)) if name == "None" => {
+                    self.position += 1;
+                    0
+                }
+                Some(Token::Word(name)) => match kind.bits().iter().find(|&&(bit_name, _, _)| bit_name == name) {
+                    Some(&(_, bit, _)) => {
+                        self.position += 1;
+                        bit
+                    }
+                    None => return Err(self.error(ErrorKind::UnknownEnumerant(name.clone()))),
+                },
+                _ => return Err(self.unexpected("mask")),
+            };
+            if self.peek() != Some(&Token::Bar) {
+                return Ok(mask);
+            }
+            self.position += 1;
+        }
+    }
+
+    fn operand(
+        &mut self,
+        op: spirv::Op,
+        kind: Kind,
+        result: Option<spirv::Word>,
+        operands: &mut Vec<spirv::Word>,
+    ) -> Result<(), Error> {
+        match kind {
+            Kind::ResultType | Kind::Id => operands.push(self.expect_id()?),
+            Kind::Result => match result {
+                Some(id) => operands.push(id),
+                None => return Err(self.error(ErrorKind::MissingResult(op))),
+            },
+            Kind::Literal => operands.push(self.expect_literal()?),
+            Kind::String => match self.peek() {
+                Some(Token::String(string)) => {
+                    grammar::encode_string(string, operands);
+                    self.position += 1;
+                }
+                _ => return Err(self.unexpected("string")),
+            },
+            Kind::Number => self.number(op, operands)?,
+            Kind::ExtInst => {
+                let kind = self.types.ext_inst(operands);
+                self.operand(op, kind, result, operands)?;
+            }
+            Kind::Enum(kind) => {
+                let value = self.enumerant(kind)?;
+                operands.push(value);
+                for &parameter in kind.parameters(value) {
+                    self.operand(op, parameter, result, operands)?;
+                }
+            }
+            Kind::Mask(kind) => {
+                let mask = self.mask(kind)?;
+                operands.push(mask);
+                for &(_, bit, parameters) in kind.bits() {
+                    if mask & bit != 0 {
+                        for &parameter in parameters {
+                            self.operand(op, parameter, result, operands)?;
+                        }
+                    }
+                }
+            }
+            Kind::Target => {
+                self.number(op, operands)?;
+                operands.push(self.expect_id()?);
+            }
+            Kind::Incoming => {
+                operands.push(self.expect_id()?);
+                operands.push(self.expect_id()?);
+            }
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, words: &mut Vec<spirv::Word>) -> Result<(), Error> {
+        let result = match self.peek() {
+            Some(Token::Id(_)) => {
+                let id = self.expect_id()?;
+                if self.peek() != Some(&Token::Equals) {
+                    return Err(self.unexpected("="));
+                }
+                self.position += 1;
+                Some(id)
+            }
+            _ => None,
+        };
+        let op = match self.peek() {
+            Some(Token::Word(name)) => match self.instructions.get(name) {
+                Some(&op) => op,
+                None => return Err(self.error(ErrorKind::UnknownInstruction(name.clone()))),
+            },
+            _ => return Err(self.unexpected("instruction")),
+        };
+        self.position += 1;
+        let layout = grammar::operands(op).ok_or_else(|| self.error(ErrorKind::UnsupportedInstruction(op)))?;
+        if result.is_some() && !layout.iter().any(|&(kind, _)| kind == Kind::Result) {
+            return Err(self.error(ErrorKind::UnexpectedResult(op)));
+        }
+
+        let mut operands = Vec::new();
+        for &(kind, quantity) in layout {
+            match quantity {
+                Quantity::One => self.operand(op, kind, result, &mut operands)?,
+                Quantity::Optional => {
+                    if self.starts_operand(kind) {
+                        self.operand(op, kind, result, &mut operands)?;
+                    }
+                }
+                Quantity::Any => {
+                    while self.starts_operand(kind) {
+                        self.operand(op, kind, result, &mut operands)?;
+                    }
+                }
+            }
+        }
+        if !self.at_instruction_start() {
+            return Err(self.unexpected("instruction"));
+        }
+
+        self.types.register(op, layout, &operands);
+        if op == spirv::Op::ExtInstImport {
+            if let Some((name, _)) = grammar::decode_string(&operands[1 ..]) {
+                self.types.import(operands[0], &name);
+            }
+        }
+        words.push(((operands.len() as u32 + 1) << 16) | op as u32);
+        words.extend(operands);
+        Ok(())
+    }
+}
+
+/// Assembles the text into the words of a SPIR-V module, header included.
+pub fn assemble(source: &str) -> Result<Vec<spirv::Word>, Error> {
+    let (lexemes, header) = tokenize(source)?;
+    let mut assembler = Assembler::new(lexemes)?;
+    let mut words = vec![spirv::MAGIC_NUMBER, header.version, header.generator, 0, 0];
+    while assembler.position < assembler.lexemes.len() {
+        assembler.instruction(&mut words)?;
+    }
+    words[3] = assembler.next_id;
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assemble, ErrorKind};
+
+    const SOURCE: &str = "
+        ; Version: 1.3
+               OpCapability Shader
+        %glsl = OpExtInstImport \"GLSL.std.450\"
+               OpMemoryModel Logical GLSL450
+               OpEntryPoint Fragment %main \"main\" %color
+               OpExecutionMode %main OriginUpperLeft
+               OpDecorate %color Location 0
+       %void = OpTypeVoid
+      %float = OpTypeFloat 32
+    %v4float = OpTypeVector %float 4
+  %ptr_color = OpTypePointer Output %v4float
+      %color = OpVariable %ptr_color Output
+    %fn_void = OpTypeFunction %void
+       %half = OpConstant %float 0.5
+       %main = OpFunction %void None %fn_void
+      %entry = OpLabel
+      %white = OpCompositeConstruct %v4float %half %half %half %half
+       %sqrt = OpExtInst %v4float %glsl Sqrt %white
+               OpStore %color %sqrt
+               OpReturn
+               OpFunctionEnd
+    ";
+
+    #[test]
+    fn assemble_header() {
+        let words = assemble(SOURCE).unwrap();
+        assert_eq!(words[.. 5], [spirv::MAGIC_NUMBER, 0x0001_0300, 0, 13, 0]);
+        // OpCapability Shader
+        assert_eq!(words[5 .. 7], [(2 << 16) | spirv::Op::Capability as u32, spirv::Capability::Shader as u32]);
+    }
+
+    #[test]
+    fn assemble_parse() {
+        let words = assemble(SOURCE).unwrap();
+        let module = crate::front::spirv::Parser::new(words.into_iter()).parse().unwrap();
+        assert_eq!(module.entry_points.len(), 1);
+        assert_eq!(module.entry_points[0].name, "main");
+        let fun = &module.functions[module.entry_points[0].function];
+        assert!(fun.expressions.iter().any(|(_, expr)| matches!(
+            *expr,
+            crate::Expression::Math { fun: crate::MathFunction::Sqrt, .. }
+        )));
+    }
+
+    #[test]
+    fn assemble_numbers() {
+        let words = assemble("
+            %int = OpTypeInt 32 1
+            %uint = OpTypeInt 64 0
+            %double = OpTypeFloat 64
+            %1 = OpConstant %int -2
+            %2 = OpConstant %uint 0x100000001
+            %3 = OpConstant %double -0x1p+1024
+        ").unwrap();
+        assert_eq!(words[16 ..], [
+            (4 << 16) | spirv::Op::Constant as u32, 4, 1, -2i32 as u32,
+            (5 << 16) | spirv::Op::Constant as u32, 5, 2, 1, 1,
+            (5 << 16) | spirv::Op::Constant as u32, 6, 3, 0, 0xFFF0_0000,
+        ]);
+    }
+
+    #[test]
+    fn assemble_errors() {
+        let error = assemble("OpCapability Shader\nOpTypeVoid").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingResult(spirv::Op::TypeVoid));
+        assert_eq!(error.line, 2);
+
+        let error = assemble("%1 = OpTypeFloat 32\n%2 = OpConstant %1 1.0 2.0").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnexpectedToken {
+            expected: "instruction",
+            found: "2.0".to_owned(),
+        });
+
+        let error = assemble("OpMemoryModel Logical Unknown").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownEnumerant("Unknown".to_owned()));
+        let error = assemble("%1 = OpConstant %2 1").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownNumberType(spirv::Op::Constant));
+        let error = assemble("%1 = OpTypeInt 8 0\n%2 = OpConstant %1 256").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::BadNumber("256".to_owned()));
+    }
+}
@@ -0,0 +1,302 @@
+use super::{Error, ErrorKind};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token<'a> {
+    Word(&'a str),
+    /// Integer or float literal, without the sign.
+    Number(&'a str),
+    /// Contents of a string literal, where `""` stands for a quote.
+    String(&'a str),
+    Punct(&'static str),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Lexeme<'a> {
+    pub token: Token<'a>,
+    pub span: crate::Span,
+}
+
+/// Punctuation, the longer sequences preceding their prefixes.
+///
+/// The closing angle brackets and square brackets are always split,
+/// so that the types and the decorations can be nested. The parser
+/// joins the adjacent `>` back into the shift operators.
+const PUNCTUATION: &[&str] = &[
+    "->", "::", "<<", "<=", ">=", "==", "!=", "&&", "||",
+    "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "|", "^",
+    ":", ";", ",", ".", "(", ")", "[", "]", "{", "}",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Lexeme<'_>>, Error> {
+    let mut lexemes = Vec::new();
+    let mut offset = 0;
+    loop {
+        let rest = &source[offset ..];
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => break,
+        };
+        if c.is_whitespace() {
+            offset += c.len_utf8();
+            continue;
+        }
+        if c == '#' || rest.starts_with("//") {
+            offset += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+
+        let (token, length) = if c.is_ascii_digit() {
+            let length = match rest.strip_prefix("0x") {
+                Some(hex) => 2 + hex.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(hex.len()),
+                None => {
+                    let integer = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                    match rest[integer ..].strip_prefix('.') {
+                        Some(fraction) => {
+                            integer + 1 + fraction.find(|c: char| !c.is_ascii_digit()).unwrap_or(fraction.len())
+                        }
+                        None => integer,
+                    }
+                }
+            };
+            (Token::Number(&rest[.. length]), length)
+        } else if c.is_ascii_alphabetic() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (Token::Word(&rest[.. length]), length)
+        } else if c == '"' {
+            let mut end = 1;
+            loop {
+                match rest[end ..].find('"') {
+                    Some(pos) if rest[end + pos + 1 ..].starts_with('"') => end += pos + 2,
+                    Some(pos) => {
+                        end += pos;
+                        break;
+                    }
+                    None => {
+                        return Err(Error {
+                            kind: ErrorKind::UnterminatedString,
+                            span: crate::Span::new(offset, source.len()),
+                        })
+                    }
+                }
+            }
+            (Token::String(&rest[1 .. end]), end + 1)
+        } else {
+            match PUNCTUATION.iter().find(|&p| rest.starts_with(p)) {
+                Some(&punct) => (Token::Punct(punct), punct.len()),
+                None => {
+                    return Err(Error {
+                        kind: ErrorKind::UnexpectedCharacter(c),
+                        span: crate::Span::new(offset, offset + c.len_utf8()),
+                    })
+                }
+            }
+        };
+        lexemes.push(Lexeme {
+            token,
+            span: crate::Span::new(offset, offset + length),
+        });
+        offset += length;
+    }
+    Ok(lexemes)
+}
+
+/// Describes a token in the errors.
+fn token_text(token: Token) -> String {
+    match token {
+        Token::Word(word) => format!("`{}`", word),
+        Token::Number(number) => format!("number {}", number),
+        Token::String(string) => format!("string \"{}\"", string),
+        Token::Punct(punct) => format!("`{}`", punct),
+    }
+}
+
+/// Cursor over the tokens of the source.
+pub struct Lexer<'a> {
+    lexemes: Vec<Lexeme<'a>>,
+    position: usize,
+    source_length: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Result<Self, Error> {
+        Ok(Lexer {
+            lexemes: tokenize(source)?,
+            position: 0,
+            source_length: source.len(),
+        })
+    }
+
+    pub fn peek(&self) -> Option<Token<'a>> {
+        self.peek_at(0)
+    }
+
+    pub fn peek_at(&self, offset: usize) -> Option<Token<'a>> {
+        self.lexemes.get(self.position + offset).map(|lexeme| lexeme.token)
+    }
+
+    pub fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek()?;
+        self.position += 1;
+        Some(token)
+    }
+
+    /// Returns the span of the next token, or an empty span at the end of the source.
+    pub fn span(&self) -> crate::Span {
+        match self.lexemes.get(self.position) {
+            Some(lexeme) => lexeme.span,
+            None => crate::Span::new(self.source_length, self.source_length),
+        }
+    }
+
+    /// Returns the span of the last token taken.
+    pub fn last_span(&self) -> crate::Span {
+        match self.position.checked_sub(1) {
+            Some(index) => self.lexemes[index].span,
+            None => crate::Span::default(),
+        }
+    }
+
+    /// Returns the span from the given start to the end of the last token taken.
+    pub fn span_from(&self, start: u32) -> crate::Span {
+        crate::Span {
+            start,
+            end: self.last_span().end.max(start),
+        }
+    }
+
+    pub fn error(&self, kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            span: self.last_span(),
+        }
+    }
+
+    /// Makes an error about the next token not being what was expected.
+    pub fn unexpected(&self, expected: &'static str) -> Error {
+        let kind = match self.peek() {
+            Some(token) => ErrorKind::UnexpectedToken {
+                expected,
+                found: token_text(token),
+            },
+            None => ErrorKind::UnexpectedEnd,
+        };
+        Error {
+            kind,
+            span: self.span(),
+        }
+    }
+
+    pub fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if p == punct)
+    }
+
+    /// Counts the next tokens that are the given punctuation, with nothing in between.
+    pub fn joined_count(&self, punct: &str) -> usize {
+        let mut count = 0;
+        let mut end = None;
+        for lexeme in self.lexemes[self.position ..].iter() {
+            let is_punct = matches!(lexeme.token, Token::Punct(p) if p == punct);
+            if !is_punct || end.map_or(false, |end| end != lexeme.span.start) {
+                break;
+            }
+            end = Some(lexeme.span.end);
+            count += 1;
+        }
+        count
+    }
+
+    pub fn skip(&mut self, punct: &str) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    pub fn expect(&mut self, punct: &'static str) -> Result<(), Error> {
+        if self.skip(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(punct))
+        }
+    }
+
+    pub fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    pub fn skip_word(&mut self, word: &str) -> bool {
+        let found = self.is_word(word);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    pub fn expect_word(&mut self, word: &'static str) -> Result<(), Error> {
+        if self.skip_word(word) {
+            Ok(())
+        } else {
+            Err(self.unexpected(word))
+        }
+    }
+
+    pub fn next_word(&mut self, expected: &'static str) -> Result<&'a str, Error> {
+        match self.peek() {
+            Some(Token::Word(word)) => {
+                self.position += 1;
+                Ok(word)
+            }
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    pub fn next_string(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::String(string)) => {
+                self.position += 1;
+                Ok(string.replace("\"\"", "\""))
+            }
+            _ => Err(self.unexpected("a string")),
+        }
+    }
+
+    pub fn next_number(&mut self) -> Result<&'a str, Error> {
+        match self.peek() {
+            Some(Token::Number(number)) => {
+                self.position += 1;
+                Ok(number)
+            }
+            _ => Err(self.unexpected("a number")),
+        }
+    }
+
+    pub fn next_uint(&mut self) -> Result<u32, Error> {
+        let text = self.next_number()?;
+        match text.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => text.parse(),
+        }.map_err(|e| self.error(ErrorKind::BadInt(e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tokenize, Token};
+
+    #[test]
+    fn tokens() {
+        let source = "var<in> a:vec2<vec2<f32>>; # comment\n[[location 0x1]] \"a\"\"b\" 1.5 >=";
+        let tokens = tokenize(source).unwrap().into_iter().map(|l| l.token).collect::<Vec<_>>();
+        assert_eq!(tokens, vec![
+            Token::Word("var"), Token::Punct("<"), Token::Word("in"), Token::Punct(">"),
+            Token::Word("a"), Token::Punct(":"), Token::Word("vec2"), Token::Punct("<"),
+            Token::Word("vec2"), Token::Punct("<"), Token::Word("f32"), Token::Punct(">"),
+            Token::Punct(">"), Token::Punct(";"), Token::Punct("["), Token::Punct("["),
+            Token::Word("location"), Token::Number("0x1"), Token::Punct("]"), Token::Punct("]"),
+            Token::String("a\"\"b"), Token::Number("1.5"), Token::Punct(">="),
+        ]);
+    }
+}
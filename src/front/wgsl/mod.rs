@@ -0,0 +1,2318 @@
+//! Front-end for WGSL.
+//!
+//! The lexer splits the source into tokens, and the recursive descent
+//! parser builds the IR from them directly, keeping track of the spans.
+
+mod lex;
+
+use crate::{
+    proc::{ResolveContext, ResolveError, Typifier},
+    storage::{Storage, Token},
+    FastHashMap, FastHashSet,
+};
+
+use self::lex::{Lexer, Token as LexToken};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    UnexpectedToken { expected: &'static str, found: String },
+    UnexpectedEnd,
+    BadInt(std::num::ParseIntError),
+    BadFloat(std::num::ParseFloatError),
+    BadStorageClass(String),
+    BadDecoration(String),
+    MissingDecoration(&'static str),
+    UnknownIdent(String),
+    UnknownType(String),
+    UnknownFunction(String),
+    UnknownMember(String),
+    IndexOutOfBounds(i64),
+    Resolve(ResolveError),
+    UnsupportedFeature(&'static str),
+    InvalidCaseSelector,
+    DuplicateCaseSelector(i32),
+    DuplicateDefault,
+    WrongArgumentCount(crate::MathFunction, usize),
+    NotAnImage(&'static str),
+    WrongImageArgumentCount(&'static str, usize),
+    InvalidVariableClass(spirv::StorageClass),
+    NotABlock(spirv::StorageClass),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::UnexpectedCharacter(c) => write!(formatter, "unexpected character {:?}", c),
+            ErrorKind::UnterminatedString => write!(formatter, "unterminated string"),
+            ErrorKind::UnexpectedToken { expected, ref found } => {
+                write!(formatter, "expected {}, found {}", expected, found)
+            }
+            ErrorKind::UnexpectedEnd => write!(formatter, "unexpected end of the source"),
+            ErrorKind::BadInt(ref error) => write!(formatter, "invalid integer: {}", error),
+            ErrorKind::BadFloat(ref error) => write!(formatter, "invalid float: {}", error),
+            ErrorKind::BadStorageClass(ref name) => write!(formatter, "unknown storage class `{}`", name),
+            ErrorKind::BadDecoration(ref name) => write!(formatter, "unknown decoration `{}`", name),
+            ErrorKind::MissingDecoration(name) => write!(formatter, "the `{}` decoration is missing", name),
+            ErrorKind::UnknownIdent(ref name) => write!(formatter, "unknown identifier `{}`", name),
+            ErrorKind::UnknownType(ref name) => write!(formatter, "unknown type `{}`", name),
+            ErrorKind::UnknownFunction(ref name) => write!(formatter, "unknown function `{}`", name),
+            ErrorKind::UnknownMember(ref name) => write!(formatter, "no member named `{}`", name),
+            ErrorKind::IndexOutOfBounds(index) => write!(formatter, "index {} is out of bounds", index),
+            ErrorKind::Resolve(ref error) => write!(formatter, "invalid expression: {:?}", error),
+            ErrorKind::UnsupportedFeature(feature) => write!(formatter, "{} are not supported", feature),
+            ErrorKind::InvalidCaseSelector => write!(formatter, "case selectors must be integers"),
+            ErrorKind::DuplicateCaseSelector(value) => write!(formatter, "case {} is already handled", value),
+            ErrorKind::DuplicateDefault => write!(formatter, "the default case is already handled"),
+            ErrorKind::WrongArgumentCount(fun, count) => write!(
+                formatter,
+                "{:?} takes {} arguments, {} given",
+                fun,
+                fun.argument_count(),
+                count,
+            ),
+            ErrorKind::NotAnImage(fun) => write!(formatter, "`{}` takes a texture as the first argument", fun),
+            ErrorKind::WrongImageArgumentCount(fun, count) => {
+                write!(formatter, "`{}` doesn't take {} arguments with this texture", fun, count)
+            }
+            ErrorKind::InvalidVariableClass(class) => write!(formatter, "variables can't be in the {:?} class", class),
+            ErrorKind::NotABlock(class) => {
+                write!(formatter, "variables in the {:?} class have to be `[[block]]` structures", class)
+            }
+        }
+    }
+}
+
+/// Error with the part of the source it occurred at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Byte range of the source.
+    pub span: crate::Span,
+}
+
+impl Error {
+    /// Returns the line and the column of the start of the error, counting from 1.
+    ///
+    /// The columns are counted in characters.
+    pub fn location(&self, source: &str) -> (usize, usize) {
+        let start = (self.span.start as usize).min(source.len());
+        let line_start = source[.. start].rfind('\n').map_or(0, |pos| pos + 1);
+        let line = source[.. line_start].matches('\n').count() + 1;
+        (line, source[line_start .. start].chars().count() + 1)
+    }
+
+    /// Renders the error message along with the source line it occurred on,
+    /// with the span of the error underlined.
+    pub fn render(&self, source: &str) -> String {
+        let (line, column) = self.location(source);
+        let start = (self.span.start as usize).min(source.len());
+        let line_start = source[.. start].rfind('\n').map_or(0, |pos| pos + 1);
+        let line_end = source[start ..].find('\n').map_or(source.len(), |pos| start + pos);
+        let line_text = &source[line_start .. line_end];
+        // spans over several lines are underlined up to the end of the first one
+        let end = (self.span.end as usize).max(start).min(line_end);
+        let underline = source[start .. end].chars().count().max(1);
+
+        let number = line.to_string();
+        let margin = " ".repeat(number.len());
+        let mut text = String::new();
+        text += &format!("error: {}\n", self.kind);
+        text += &format!("{}--> {}:{}\n", margin, line, column);
+        text += &format!("{} |\n", margin);
+        text += &format!("{} | {}\n", number, line_text);
+        text += &format!("{} | {}{}\n", margin, " ".repeat(column - 1), "^".repeat(underline));
+        text
+    }
+}
+
+type ExpressionResult = Result<Token<crate::Expression>, Error>;
+
+struct ExpressionContext<'a> {
+    function: &'a mut crate::Function,
+    lookup_ident: &'a mut FastHashMap<String, Token<crate::Expression>>,
+    types: &'a mut Storage<crate::Type>,
+    constants: &'a mut Storage<crate::Constant>,
+    global_vars: &'a Storage<crate::GlobalVariable>,
+    functions: &'a Storage<crate::Function>,
+    typifier: &'a mut Typifier,
+    /// Tells if the statements are directly in the body of the function,
+    /// where they are only executed once.
+    top_level: bool,
+}
+
+impl<'a> ExpressionContext<'a> {
+    fn reborrow(&mut self) -> ExpressionContext<'_> {
+        ExpressionContext {
+            function: self.function,
+            lookup_ident: self.lookup_ident,
+            types: self.types,
+            constants: self.constants,
+            global_vars: self.global_vars,
+            functions: self.functions,
+            typifier: self.typifier,
+            top_level: self.top_level,
+        }
+    }
+
+    /// Returns the type of an expression, resolving the ones appended since the last call.
+    fn resolve_type(&mut self, token: Token<crate::Expression>, span: crate::Span) -> Result<crate::TypeInner, Error> {
+        self.typifier
+            .resolve_new(&self.function.expressions, &ResolveContext {
+                types: self.types,
+                constants: self.constants,
+                global_vars: self.global_vars,
+                local_vars: &self.function.local_variables,
+                functions: self.functions,
+                parameter_types: &self.function.parameter_types,
+            })
+            .map_err(|e| Error {
+                kind: ErrorKind::Resolve(e),
+                span,
+            })?;
+        Ok(self.typifier.get(token, self.types).clone())
+    }
+
+    /// Returns the dimension and the flags of the image an expression refers to.
+    fn image_info(&self, token: Token<crate::Expression>) -> Option<(spirv::Dim, crate::ImageFlags)> {
+        let ty = match self.function.expressions[token] {
+            crate::Expression::GlobalVariable(var) => self.global_vars[var].ty,
+            crate::Expression::FunctionParameter(index) => self.function.parameter_types[index as usize],
+            _ => return None,
+        };
+        match self.types[ty].inner {
+            crate::TypeInner::Image { dim, flags, .. } => Some((dim, flags)),
+            _ => None,
+        }
+    }
+
+    fn append(&mut self, expression: crate::Expression, span: crate::Span) -> Token<crate::Expression> {
+        append_expression(self.function, expression, span)
+    }
+}
+
+/// Appends an expression to the function, along with its span.
+fn append_expression(
+    fun: &mut crate::Function,
+    expression: crate::Expression,
+    span: crate::Span,
+) -> Token<crate::Expression> {
+    let token = fun.expressions.append(expression);
+    fun.spans.expressions.resize(token.index(), crate::Span::default());
+    fun.spans.expressions.push(span);
+    token
+}
+
+/// Returns the binary operator at the start of the tokens, along with
+/// its precedence and the number of tokens it is made of.
+fn peek_binary_operator(lexer: &Lexer) -> Option<(crate::BinaryOperator, u8, usize)> {
+    use crate::BinaryOperator as Bo;
+    let (op, count) = match lexer.peek() {
+        // the closing angle brackets are split by the lexer
+        Some(LexToken::Punct(">")) => match lexer.joined_count(">") {
+            1 => (">", 1),
+            2 => (">>", 2),
+            _ => (">>>", 3),
+        },
+        Some(LexToken::Punct(op)) => (op, 1),
+        _ => return None,
+    };
+    let (op, precedence) = match op {
+        "||" => (Bo::LogicalOr, 1),
+        "&&" => (Bo::LogicalAnd, 2),
+        "|" => (Bo::InclusiveOr, 3),
+        "^" => (Bo::ExclusiveOr, 4),
+        "&" => (Bo::And, 5),
+        "==" => (Bo::Equal, 6),
+        "!=" => (Bo::NotEqual, 6),
+        "<" => (Bo::Less, 7),
+        "<=" => (Bo::LessEqual, 7),
+        ">" => (Bo::Greater, 7),
+        ">=" => (Bo::GreaterEqual, 7),
+        "<<" => (Bo::ShiftLeftLogical, 8),
+        ">>" => (Bo::ShiftRightLogical, 8),
+        ">>>" => (Bo::ShiftRightArithmetic, 8),
+        "+" => (Bo::Add, 9),
+        "-" => (Bo::Subtract, 9),
+        "*" => (Bo::Multiply, 10),
+        "/" => (Bo::Divide, 10),
+        "%" => (Bo::Modulo, 10),
+        _ => return None,
+    };
+    Some((op, precedence, count))
+}
+
+fn is_type_keyword(word: &str) -> bool {
+    matches!(word, "f32" | "i32" | "u32" | "bool" | "vec2" | "vec3" | "vec4" | "array" | "ptr") ||
+        get_matrix_size(word).is_some()
+}
+
+fn get_vector_size(digit: u8) -> Option<crate::VectorSize> {
+    match digit {
+        b'2' => Some(crate::VectorSize::Bi),
+        b'3' => Some(crate::VectorSize::Tri),
+        b'4' => Some(crate::VectorSize::Quad),
+        _ => None,
+    }
+}
+
+/// Returns the number of columns and rows of a `matCxR` type name.
+fn get_matrix_size(name: &str) -> Option<(crate::VectorSize, crate::VectorSize)> {
+    match *name.strip_prefix("mat")?.as_bytes() {
+        [columns, b'x', rows] => Some((get_vector_size(columns)?, get_vector_size(rows)?)),
+        _ => None,
+    }
+}
+
+/// Returns the component indices of a swizzle of a vector with the given size.
+///
+/// The components are named either `xyzw` or `rgba`, without mixing the two.
+fn parse_swizzle(name: &str, size: crate::VectorSize) -> Option<Vec<u32>> {
+    let set = ["xyzw", "rgba"]
+        .iter()
+        .find(|set| name.starts_with(|c| set.contains(c)))?;
+    if name.len() > 4 {
+        return None;
+    }
+    name.chars()
+        .map(|c| match set.find(c) {
+            Some(index) if index < size as usize => Some(index as u32),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Maps the name of a texture type to its dimension and flags, telling
+/// if the type of the texels follows in angle brackets.
+fn get_texture_type(name: &str) -> Option<(spirv::Dim, crate::ImageFlags, bool)> {
+    use crate::ImageFlags as If;
+    let rest = name.strip_prefix("texture_")?;
+    let (rest, arrayed) = match rest.strip_suffix("_array") {
+        Some(rest) => (rest, true),
+        None => (rest, false),
+    };
+    let (dim, mut flags, has_base) = if let Some(dim) = rest.strip_prefix("multisampled_") {
+        (dim, If::SAMPLED | If::MULTISAMPLED, true)
+    } else if let Some(dim) = rest.strip_prefix("depth_") {
+        (dim, If::SAMPLED | If::DEPTH, false)
+    } else if let Some(dim) = rest.strip_prefix("storage_ro_") {
+        (dim, If::CAN_LOAD, true)
+    } else if let Some(dim) = rest.strip_prefix("storage_wo_") {
+        (dim, If::CAN_STORE, true)
+    } else {
+        (rest, If::SAMPLED, true)
+    };
+    flags.set(If::ARRAYED, arrayed);
+    let (dim, valid) = match dim {
+        "1d" => (spirv::Dim::Dim1D, !flags.intersects(If::MULTISAMPLED | If::DEPTH)),
+        "2d" => (spirv::Dim::Dim2D, true),
+        "3d" => (spirv::Dim::Dim3D, !flags.intersects(If::ARRAYED | If::MULTISAMPLED | If::DEPTH)),
+        "cube" => (spirv::Dim::DimCube, flags.contains(If::SAMPLED) && !flags.contains(If::MULTISAMPLED)),
+        _ => return None,
+    };
+    if valid {
+        Some((dim, flags, has_base))
+    } else {
+        None
+    }
+}
+
+/// Maps the name of a GLSL.std.450 instruction, in lower case, to a math function.
+fn get_math_function(name: &str) -> Option<crate::MathFunction> {
+    Some(match name {
+        "round" => crate::MathFunction::Round,
+        "roundeven" => crate::MathFunction::RoundEven,
+        "trunc" => crate::MathFunction::Trunc,
+        "fabs" => crate::MathFunction::FAbs,
+        "sabs" => crate::MathFunction::SAbs,
+        "fsign" => crate::MathFunction::FSign,
+        "ssign" => crate::MathFunction::SSign,
+        "floor" => crate::MathFunction::Floor,
+        "ceil" => crate::MathFunction::Ceil,
+        "fract" => crate::MathFunction::Fract,
+        "radians" => crate::MathFunction::Radians,
+        "degrees" => crate::MathFunction::Degrees,
+        "sin" => crate::MathFunction::Sin,
+        "cos" => crate::MathFunction::Cos,
+        "tan" => crate::MathFunction::Tan,
+        "asin" => crate::MathFunction::Asin,
+        "acos" => crate::MathFunction::Acos,
+        "atan" => crate::MathFunction::Atan,
+        "sinh" => crate::MathFunction::Sinh,
+        "cosh" => crate::MathFunction::Cosh,
+        "tanh" => crate::MathFunction::Tanh,
+        "asinh" => crate::MathFunction::Asinh,
+        "acosh" => crate::MathFunction::Acosh,
+        "atanh" => crate::MathFunction::Atanh,
+        "atan2" => crate::MathFunction::Atan2,
+        "pow" => crate::MathFunction::Pow,
+        "exp" => crate::MathFunction::Exp,
+        "log" => crate::MathFunction::Log,
+        "exp2" => crate::MathFunction::Exp2,
+        "log2" => crate::MathFunction::Log2,
+        "sqrt" => crate::MathFunction::Sqrt,
+        "inversesqrt" => crate::MathFunction::InverseSqrt,
+        "determinant" => crate::MathFunction::Determinant,
+        "matrixinverse" => crate::MathFunction::MatrixInverse,
+        "fmin" => crate::MathFunction::FMin,
+        "umin" => crate::MathFunction::UMin,
+        "smin" => crate::MathFunction::SMin,
+        "fmax" => crate::MathFunction::FMax,
+        "umax" => crate::MathFunction::UMax,
+        "smax" => crate::MathFunction::SMax,
+        "fclamp" => crate::MathFunction::FClamp,
+        "uclamp" => crate::MathFunction::UClamp,
+        "sclamp" => crate::MathFunction::SClamp,
+        "fmix" => crate::MathFunction::FMix,
+        "imix" => crate::MathFunction::IMix,
+        "step" => crate::MathFunction::Step,
+        "smoothstep" => crate::MathFunction::SmoothStep,
+        "fma" => crate::MathFunction::Fma,
+        "ldexp" => crate::MathFunction::Ldexp,
+        "packsnorm4x8" => crate::MathFunction::PackSnorm4x8,
+        "packunorm4x8" => crate::MathFunction::PackUnorm4x8,
+        "packsnorm2x16" => crate::MathFunction::PackSnorm2x16,
+        "packunorm2x16" => crate::MathFunction::PackUnorm2x16,
+        "packhalf2x16" => crate::MathFunction::PackHalf2x16,
+        "unpacksnorm2x16" => crate::MathFunction::UnpackSnorm2x16,
+        "unpackunorm2x16" => crate::MathFunction::UnpackUnorm2x16,
+        "unpackhalf2x16" => crate::MathFunction::UnpackHalf2x16,
+        "unpacksnorm4x8" => crate::MathFunction::UnpackSnorm4x8,
+        "unpackunorm4x8" => crate::MathFunction::UnpackUnorm4x8,
+        "length" => crate::MathFunction::Length,
+        "distance" => crate::MathFunction::Distance,
+        "cross" => crate::MathFunction::Cross,
+        "normalize" => crate::MathFunction::Normalize,
+        "faceforward" => crate::MathFunction::FaceForward,
+        "reflect" => crate::MathFunction::Reflect,
+        "refract" => crate::MathFunction::Refract,
+        "findilsb" => crate::MathFunction::FindILsb,
+        "findsmsb" => crate::MathFunction::FindSMsb,
+        "findumsb" => crate::MathFunction::FindUMsb,
+        "nmin" => crate::MathFunction::NMin,
+        "nmax" => crate::MathFunction::NMax,
+        "nclamp" => crate::MathFunction::NClamp,
+        _ => return None,
+    })
+}
+
+/// Decorations of a structure and its members.
+#[derive(Default)]
+struct LayoutDecorations {
+    block: bool,
+    offset: Option<spirv::Word>,
+}
+
+pub struct Parser {
+    lookup_type: FastHashMap<String, Token<crate::Type>>,
+    block_types: FastHashSet<Token<crate::Type>>,
+    lookup_function: FastHashMap<String, Token<crate::Function>>,
+    std_namespace: Option<String>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser {
+            lookup_type: FastHashMap::default(),
+            block_types: FastHashSet::default(),
+            lookup_function: FastHashMap::default(),
+            std_namespace: None,
+        }
+    }
+
+    fn lookup_math_function(&self, name: &str) -> Option<crate::MathFunction> {
+        match name.rfind("::") {
+            Some(pos) if self.std_namespace.as_deref() == Some(&name[.. pos]) => {
+                get_math_function(&name[pos + 2 ..])
+            }
+            Some(_) => None,
+            // user functions shadow the built-in ones
+            None if self.lookup_function.contains_key(name) => None,
+            None => get_math_function(name),
+        }
+    }
+
+    /// Parses a name that may be qualified by a namespace, like `std::sin`.
+    fn parse_path(lexer: &mut Lexer) -> Result<String, Error> {
+        let mut path = lexer.next_word("an identifier")?.to_owned();
+        while lexer.skip("::") {
+            path += "::";
+            path += lexer.next_word("an identifier")?;
+        }
+        Ok(path)
+    }
+
+    fn parse_decoration_list(lexer: &mut Lexer) -> Result<Option<crate::Binding>, Error> {
+        let start = lexer.span().start;
+        lexer.expect("[")?;
+        lexer.expect("[")?;
+        let mut binding = None;
+        let mut set = None;
+        let mut index = None;
+        loop {
+            match lexer.next_word("a decoration")? {
+                "location" => binding = Some(crate::Binding::Location(lexer.next_uint()?)),
+                "builtin" => {
+                    let builtin = match lexer.next_word("a built-in")? {
+                        "position" => spirv::BuiltIn::Position,
+                        "vertex_idx" => spirv::BuiltIn::VertexIndex,
+                        "instance_idx" => spirv::BuiltIn::InstanceIndex,
+                        "front_facing" => spirv::BuiltIn::FrontFacing,
+                        "frag_coord" => spirv::BuiltIn::FragCoord,
+                        "frag_depth" => spirv::BuiltIn::FragDepth,
+                        "local_invocation_idx" => spirv::BuiltIn::LocalInvocationIndex,
+                        "local_invocation_id" => spirv::BuiltIn::LocalInvocationId,
+                        "global_invocation_id" => spirv::BuiltIn::GlobalInvocationId,
+                        other => return Err(lexer.error(ErrorKind::BadDecoration(other.to_owned()))),
+                    };
+                    binding = Some(crate::Binding::BuiltIn(builtin));
+                }
+                "set" => set = Some(lexer.next_uint()?),
+                "binding" => index = Some(lexer.next_uint()?),
+                other => return Err(lexer.error(ErrorKind::BadDecoration(other.to_owned()))),
+            }
+            if !lexer.skip(",") {
+                break;
+            }
+        }
+        lexer.expect("]")?;
+        lexer.expect("]")?;
+        // the set and the binding are given by separate decorations
+        let missing = match (set, index) {
+            (Some(set), Some(binding_index)) => {
+                binding = Some(crate::Binding::Descriptor { set, binding: binding_index });
+                None
+            }
+            (Some(_), None) => Some("binding"),
+            (None, Some(_)) => Some("set"),
+            (None, None) => None,
+        };
+        match missing {
+            Some(name) => Err(Error {
+                kind: ErrorKind::MissingDecoration(name),
+                span: lexer.span_from(start),
+            }),
+            None => Ok(binding),
+        }
+    }
+
+    /// Parses the decorations of a structure or of its member.
+    fn parse_layout_decoration_list(
+        lexer: &mut Lexer,
+        allowed: &[&'static str],
+    ) -> Result<LayoutDecorations, Error> {
+        lexer.expect("[")?;
+        lexer.expect("[")?;
+        let mut decorations = LayoutDecorations::default();
+        loop {
+            let name = lexer.next_word("a decoration")?;
+            if !allowed.contains(&name) {
+                return Err(lexer.error(ErrorKind::BadDecoration(name.to_owned())));
+            }
+            match name {
+                "block" => decorations.block = true,
+                _ => decorations.offset = Some(lexer.next_uint()?),
+            }
+            if !lexer.skip(",") {
+                break;
+            }
+        }
+        lexer.expect("]")?;
+        lexer.expect("]")?;
+        Ok(decorations)
+    }
+
+    fn parse_storage_class(lexer: &mut Lexer) -> Result<spirv::StorageClass, Error> {
+        match lexer.next_word("a storage class")? {
+            "in" => Ok(spirv::StorageClass::Input),
+            "out" => Ok(spirv::StorageClass::Output),
+            "uniform" => Ok(spirv::StorageClass::Uniform),
+            "uniform_constant" => Ok(spirv::StorageClass::UniformConstant),
+            "storage_buffer" => Ok(spirv::StorageClass::StorageBuffer),
+            "workgroup" => Ok(spirv::StorageClass::Workgroup),
+            "image" => Ok(spirv::StorageClass::Image),
+            "private" => Ok(spirv::StorageClass::Private),
+            "function" => Ok(spirv::StorageClass::Function),
+            other => Err(lexer.error(ErrorKind::BadStorageClass(other.to_owned()))),
+        }
+    }
+
+    fn parse_type_decl(
+        &self,
+        lexer: &mut Lexer,
+        type_store: &mut Storage<crate::Type>,
+    ) -> Result<Token<crate::Type>, Error> {
+        let start = lexer.span().start;
+        let inner = match lexer.next_word("a type")? {
+            "f32" => crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 },
+            "i32" => crate::TypeInner::Scalar { kind: crate::ScalarKind::Sint, width: 32 },
+            "u32" => crate::TypeInner::Scalar { kind: crate::ScalarKind::Uint, width: 32 },
+            "bool" => crate::TypeInner::Scalar { kind: crate::ScalarKind::Bool, width: 1 },
+            "sampler" => crate::TypeInner::Sampler { comparison: false },
+            "sampler_comparison" => crate::TypeInner::Sampler { comparison: true },
+            word if word.starts_with("texture_") => {
+                let (dim, flags, has_base) = get_texture_type(word)
+                    .ok_or_else(|| lexer.error(ErrorKind::UnknownType(word.to_owned())))?;
+                let base = if has_base {
+                    lexer.expect("<")?;
+                    let base = self.parse_type_decl(lexer, type_store)?;
+                    lexer.expect(">")?;
+                    base
+                } else {
+                    let inner = crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 };
+                    Self::register_type(type_store, inner)
+                };
+                crate::TypeInner::Image { base, dim, flags }
+            }
+            "array" => {
+                lexer.expect("<")?;
+                let base = self.parse_type_decl(lexer, type_store)?;
+                let size = if lexer.skip(",") {
+                    crate::ArraySize::Static(lexer.next_uint()?)
+                } else {
+                    crate::ArraySize::Dynamic
+                };
+                lexer.expect(">")?;
+                crate::TypeInner::Array { base, size }
+            }
+            "ptr" => {
+                lexer.expect("<")?;
+                let class = Self::parse_storage_class(lexer)?;
+                lexer.expect(",")?;
+                let base = self.parse_type_decl(lexer, type_store)?;
+                lexer.expect(">")?;
+                crate::TypeInner::Pointer { base, class }
+            }
+            word @ "vec2" | word @ "vec3" | word @ "vec4" => {
+                let size = match word {
+                    "vec2" => crate::VectorSize::Bi,
+                    "vec3" => crate::VectorSize::Tri,
+                    _ => crate::VectorSize::Quad,
+                };
+                lexer.expect("<")?;
+                let base = self.parse_type_decl(lexer, type_store)?;
+                lexer.expect(">")?;
+                match type_store[base].inner {
+                    crate::TypeInner::Scalar { kind, width } => crate::TypeInner::Vector { size, kind, width },
+                    _ => {
+                        return Err(Error {
+                            kind: ErrorKind::UnknownType(word.to_owned()),
+                            span: lexer.span_from(start),
+                        })
+                    }
+                }
+            }
+            name => match get_matrix_size(name) {
+                Some((columns, rows)) => {
+                    lexer.expect("<")?;
+                    let base = self.parse_type_decl(lexer, type_store)?;
+                    lexer.expect(">")?;
+                    // only floating-point matrices exist
+                    match type_store[base].inner {
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width } => {
+                            crate::TypeInner::Matrix { columns, rows, kind: crate::ScalarKind::Float, width }
+                        }
+                        _ => {
+                            return Err(Error {
+                                kind: ErrorKind::UnknownType(name.to_owned()),
+                                span: lexer.span_from(start),
+                            })
+                        }
+                    }
+                }
+                None => {
+                    return self.lookup_type
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| lexer.error(ErrorKind::UnknownType(name.to_owned())));
+                }
+            },
+        };
+        Ok(Self::register_type(type_store, inner))
+    }
+
+    /// Returns the type with the given contents, adding it if there is none yet.
+    fn register_type(type_store: &mut Storage<crate::Type>, inner: crate::TypeInner) -> Token<crate::Type> {
+        if let Some((token, _)) = type_store
+            .iter()
+            .find(|(_, ty)| ty.inner == inner)
+        {
+            return token;
+        }
+        type_store.append(crate::Type {
+            name: None,
+            inner,
+        })
+    }
+
+    fn parse_variable_ident_decl<'a>(
+        &self,
+        lexer: &mut Lexer<'a>,
+        type_store: &mut Storage<crate::Type>,
+    ) -> Result<(&'a str, Token<crate::Type>), Error> {
+        let name = lexer.next_word("a variable name")?;
+        lexer.expect(":")?;
+        let ty = self.parse_type_decl(lexer, type_store)?;
+        Ok((name, ty))
+    }
+
+    fn parse_variable_decl<'a>(
+        &self,
+        lexer: &mut Lexer<'a>,
+        type_store: &mut Storage<crate::Type>,
+    ) -> Result<(&'a str, Option<spirv::StorageClass>, Token<crate::Type>), Error> {
+        lexer.expect_word("var")?;
+        let class = if lexer.skip("<") {
+            let class = Self::parse_storage_class(lexer)?;
+            lexer.expect(">")?;
+            Some(class)
+        } else {
+            None
+        };
+        let (name, ty) = self.parse_variable_ident_decl(lexer, type_store)?;
+        Ok((name, class, ty))
+    }
+
+    /// Parses a structure, telling if it's a `[[block]]` one.
+    fn parse_struct_decl(
+        &self,
+        lexer: &mut Lexer,
+        type_store: &mut Storage<crate::Type>,
+    ) -> Result<(crate::TypeInner, bool), Error> {
+        let block = if lexer.is_punct("[") {
+            Self::parse_layout_decoration_list(lexer, &["block"])?.block
+        } else {
+            false
+        };
+        lexer.expect_word("struct")?;
+        lexer.expect("{")?;
+        let mut members = Vec::new();
+        while !lexer.skip("}") {
+            let start = lexer.span().start;
+            let offset = if lexer.is_punct("[") {
+                Self::parse_layout_decoration_list(lexer, &["offset"])?.offset
+            } else {
+                None
+            };
+            let (member_name, ty) = self.parse_variable_ident_decl(lexer, type_store)?;
+            lexer.expect(";")?;
+            // the layout of the buffers is explicit
+            if block && offset.is_none() {
+                return Err(Error {
+                    kind: ErrorKind::MissingDecoration("offset"),
+                    span: lexer.span_from(start),
+                });
+            }
+            members.push(crate::StructMember {
+                name: Some(member_name.to_owned()),
+                binding: None,
+                offset,
+                ty,
+            });
+        }
+        Ok((crate::TypeInner::Struct { members }, block))
+    }
+
+    /// Parses a literal, which is negated if it follows a minus sign.
+    fn parse_const_literal(
+        lexer: &mut Lexer,
+        negative: bool,
+    ) -> Result<crate::ConstantInner, Error> {
+        let sign = if negative { "-" } else { "" };
+        let inner = match lexer.peek() {
+            Some(LexToken::Word("true")) if !negative => crate::ConstantInner::Bool(true),
+            Some(LexToken::Word("false")) if !negative => crate::ConstantInner::Bool(false),
+            Some(LexToken::Number(text)) if text.contains('.') => {
+                let value = format!("{}{}", sign, text)
+                    .parse::<f32>()
+                    .map_err(|e| Error { kind: ErrorKind::BadFloat(e), span: lexer.span() })?;
+                crate::ConstantInner::Float(value as f64)
+            }
+            Some(LexToken::Number(text)) => {
+                let value = match text.strip_prefix("0x") {
+                    Some(hex) => i32::from_str_radix(&format!("{}{}", sign, hex), 16),
+                    None => format!("{}{}", sign, text).parse(),
+                }.map_err(|e| Error { kind: ErrorKind::BadInt(e), span: lexer.span() })?;
+                crate::ConstantInner::Sint(value as i64)
+            }
+            _ => return Err(lexer.unexpected("a literal")),
+        };
+        lexer.next();
+        Ok(inner)
+    }
+
+    fn parse_const_expression(lexer: &mut Lexer) -> Result<crate::ConstantInner, Error> {
+        if let Some(LexToken::Word(word)) = lexer.peek() {
+            if word != "true" && word != "false" {
+                return Err(Error {
+                    kind: ErrorKind::UnsupportedFeature("composite constants"),
+                    span: lexer.span(),
+                });
+            }
+        }
+        let negative = lexer.skip("-");
+        Self::parse_const_literal(lexer, negative)
+    }
+
+    /// Parses the parenthesized arguments of a call or a constructor.
+    fn parse_arguments(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<Vec<Token<crate::Expression>>, Error> {
+        lexer.expect("(")?;
+        let mut arguments = Vec::new();
+        if lexer.skip(")") {
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.parse_expression(lexer, ctx.reborrow())?);
+            if !lexer.skip(",") {
+                break;
+            }
+        }
+        lexer.expect(")")?;
+        Ok(arguments)
+    }
+
+    fn parse_function_call(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<(Token<crate::Function>, Vec<Token<crate::Expression>>), Error> {
+        let name = Self::parse_path(lexer)?;
+        let function = match self.lookup_function.get(&name) {
+            Some(&function) => function,
+            None => return Err(lexer.error(ErrorKind::UnknownFunction(name))),
+        };
+        let arguments = self.parse_arguments(lexer, ctx.reborrow())?;
+        Ok((function, arguments))
+    }
+
+    /// Finds the image that the texture function is called on.
+    fn image_argument(
+        fun: &'static str,
+        arguments: &[Token<crate::Expression>],
+        ctx: &ExpressionContext,
+        span: crate::Span,
+    ) -> Result<(Token<crate::Expression>, (spirv::Dim, crate::ImageFlags)), Error> {
+        arguments
+            .first()
+            .and_then(|&image| Some((image, ctx.image_info(image)?)))
+            .ok_or(Error {
+                kind: ErrorKind::NotAnImage(fun),
+                span,
+            })
+    }
+
+    /// Makes the integer coordinate of a texel, which includes
+    /// the array layer of the arrayed images.
+    fn image_coordinate(
+        arguments: &[Token<crate::Expression>],
+        (dim, flags): (spirv::Dim, crate::ImageFlags),
+        mut ctx: ExpressionContext,
+        span: crate::Span,
+    ) -> Token<crate::Expression> {
+        if !flags.contains(crate::ImageFlags::ARRAYED) {
+            return arguments[0];
+        }
+        let size = match dim {
+            spirv::Dim::Dim1D => crate::VectorSize::Bi,
+            _ => crate::VectorSize::Tri,
+        };
+        let inner = crate::TypeInner::Vector { size, kind: crate::ScalarKind::Sint, width: 32 };
+        let ty = Self::register_type(ctx.types, inner);
+        ctx.append(crate::Expression::Compose { ty, components: arguments.to_vec() }, span)
+    }
+
+    /// Parses the arguments of a texture function, unless the name refers to something else.
+    fn parse_image_function(
+        &self,
+        name: &str,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<Option<crate::Expression>, Error> {
+        // user functions shadow the built-in ones
+        if self.lookup_function.contains_key(name) {
+            return Ok(None);
+        }
+        let fun = match name {
+            "textureSample" => "textureSample",
+            "textureLoad" => "textureLoad",
+            "textureDimensions" => "textureDimensions",
+            _ => return Ok(None),
+        };
+        let start = lexer.span().start;
+        let arguments = self.parse_arguments(lexer, ctx.reborrow())?;
+        let span = lexer.span_from(start);
+        let (image, info) = Self::image_argument(fun, &arguments, &ctx, span)?;
+        let (_, flags) = info;
+        let arrayed = flags.contains(crate::ImageFlags::ARRAYED) as usize;
+        let sampled = flags.contains(crate::ImageFlags::SAMPLED);
+        let has_levels = sampled && !flags.contains(crate::ImageFlags::MULTISAMPLED);
+        let count = arguments.len();
+        let valid = match fun {
+            "textureSample" => sampled && count == 3,
+            "textureLoad" => {
+                let readable = sampled || flags.contains(crate::ImageFlags::CAN_LOAD);
+                readable && count == 2 + arrayed + sampled as usize
+            }
+            _ => count == 1 || (has_levels && count == 2),
+        };
+        if !valid {
+            if fun == "textureSample" && arrayed != 0 {
+                // the array layer would have to be converted to a float
+                return Err(Error {
+                    kind: ErrorKind::UnsupportedFeature("sampled texture arrays"),
+                    span,
+                });
+            }
+            return Err(Error {
+                kind: ErrorKind::WrongImageArgumentCount(fun, count),
+                span,
+            });
+        }
+        Ok(Some(match fun {
+            "textureSample" => crate::Expression::ImageSample {
+                image,
+                sampler: arguments[1],
+                coordinate: arguments[2],
+            },
+            "textureLoad" => crate::Expression::ImageLoad {
+                image,
+                coordinate: Self::image_coordinate(&arguments[1 .. 2 + arrayed], info, ctx.reborrow(), span),
+                index: arguments.get(2 + arrayed).cloned(),
+            },
+            _ => crate::Expression::ImageDimensions {
+                image,
+                level: arguments.get(1).cloned(),
+            },
+        }))
+    }
+
+    fn parse_primary_expression(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> ExpressionResult {
+        let start = lexer.span().start;
+        match lexer.peek() {
+            Some(LexToken::Punct("(")) => {
+                lexer.next();
+                let expression = self.parse_expression(lexer, ctx)?;
+                lexer.expect(")")?;
+                Ok(expression)
+            }
+            Some(LexToken::Number(_)) |
+            Some(LexToken::Word("true")) |
+            Some(LexToken::Word("false")) => {
+                let inner = Self::parse_const_literal(lexer, false)?;
+                let token = ctx.constants.append(crate::Constant {
+                    name: None,
+                    specialization: None,
+                    inner,
+                });
+                Ok(ctx.append(crate::Expression::Constant(token), lexer.span_from(start)))
+            }
+            Some(LexToken::Word(word)) if is_type_keyword(word) => {
+                let ty = self.parse_type_decl(lexer, ctx.types)?;
+                let components = self.parse_arguments(lexer, ctx.reborrow())?;
+                let expression = crate::Expression::Compose { ty, components };
+                Ok(ctx.append(expression, lexer.span_from(start)))
+            }
+            Some(LexToken::Word(_)) => {
+                let name = Self::parse_path(lexer)?;
+                let name_span = lexer.span_from(start);
+                if !lexer.is_punct("(") {
+                    // local variables are loaded after the postfix expressions
+                    return match ctx.lookup_ident.get(&name) {
+                        Some(&token) => Ok(token),
+                        None => Err(Error {
+                            kind: ErrorKind::UnknownIdent(name),
+                            span: name_span,
+                        }),
+                    };
+                }
+                let expression = if let Some(&ty) = self.lookup_type.get(&name) {
+                    // a constructor of an aliased type
+                    let components = self.parse_arguments(lexer, ctx.reborrow())?;
+                    crate::Expression::Compose { ty, components }
+                } else if let Some(fun) = self.lookup_math_function(&name) {
+                    let arguments = self.parse_arguments(lexer, ctx.reborrow())?;
+                    if arguments.len() != fun.argument_count() {
+                        return Err(Error {
+                            kind: ErrorKind::WrongArgumentCount(fun, arguments.len()),
+                            span: lexer.span_from(start),
+                        });
+                    }
+                    crate::Expression::Math { fun, arguments }
+                } else if let Some(expression) = self.parse_image_function(&name, lexer, ctx.reborrow())? {
+                    expression
+                } else {
+                    let function = match self.lookup_function.get(&name) {
+                        Some(&function) => function,
+                        None => {
+                            return Err(Error {
+                                kind: ErrorKind::UnknownFunction(name),
+                                span: name_span,
+                            })
+                        }
+                    };
+                    let arguments = self.parse_arguments(lexer, ctx.reborrow())?;
+                    crate::Expression::Call { function, arguments }
+                };
+                Ok(ctx.append(expression, lexer.span_from(start)))
+            }
+            _ => Err(lexer.unexpected("an expression")),
+        }
+    }
+
+    /// Parses the member accesses, swizzles and indices following the given expression.
+    ///
+    /// The result refers to memory if the base expression does.
+    fn parse_postfix(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+        start: u32,
+        mut base: Token<crate::Expression>,
+    ) -> ExpressionResult {
+        loop {
+            let base_span = lexer.span_from(start);
+            let (pointer, inner) = match ctx.resolve_type(base, base_span)? {
+                crate::TypeInner::Pointer { base, .. } => (true, ctx.types[base].inner.clone()),
+                other => (false, other),
+            };
+            let (expression, element) = if lexer.skip(".") {
+                let name = lexer.next_word("a member name")?;
+                let unknown_member = || Error {
+                    kind: ErrorKind::UnknownMember(name.to_owned()),
+                    span: lexer.last_span(),
+                };
+                match inner {
+                    crate::TypeInner::Struct { ref members } => {
+                        let index = members
+                            .iter()
+                            .position(|member| member.name.as_deref() == Some(name))
+                            .ok_or_else(unknown_member)?;
+                        let expression = crate::Expression::AccessIndex { base, index: index as u32 };
+                        (expression, ctx.types[members[index].ty].inner.clone())
+                    }
+                    crate::TypeInner::Vector { size, kind, width } => {
+                        let indices = parse_swizzle(name, size).ok_or_else(unknown_member)?;
+                        let scalar = crate::TypeInner::Scalar { kind, width };
+                        if let [index] = indices[..] {
+                            (crate::Expression::AccessIndex { base, index }, scalar)
+                        } else {
+                            // the components are taken from the vector value
+                            if pointer {
+                                base = ctx.append(crate::Expression::Load { pointer: base }, base_span);
+                            }
+                            let components = indices
+                                .iter()
+                                .map(|&index| ctx.append(crate::Expression::AccessIndex { base, index }, lexer.span_from(start)))
+                                .collect();
+                            let size = match indices.len() {
+                                2 => crate::VectorSize::Bi,
+                                3 => crate::VectorSize::Tri,
+                                _ => crate::VectorSize::Quad,
+                            };
+                            let ty = Self::register_type(ctx.types, crate::TypeInner::Vector { size, kind, width });
+                            base = ctx.append(crate::Expression::Compose { ty, components }, lexer.span_from(start));
+                            continue;
+                        }
+                    }
+                    _ => return Err(unknown_member()),
+                }
+            } else if lexer.skip("[") {
+                let index = self.parse_expression(lexer, ctx.reborrow())?;
+                lexer.expect("]")?;
+                let (element, count) = match inner {
+                    crate::TypeInner::Vector { size, kind, width } => {
+                        (crate::TypeInner::Scalar { kind, width }, Some(size as u32))
+                    }
+                    crate::TypeInner::Matrix { columns, rows, kind, width } => {
+                        (crate::TypeInner::Vector { size: rows, kind, width }, Some(columns as u32))
+                    }
+                    crate::TypeInner::Array { base, size } => {
+                        let count = match size {
+                            crate::ArraySize::Static(count) => Some(count),
+                            crate::ArraySize::Dynamic => None,
+                        };
+                        (ctx.types[base].inner.clone(), count)
+                    }
+                    _ => {
+                        return Err(Error {
+                            kind: ErrorKind::Resolve(ResolveError::InvalidAccess(base)),
+                            span: lexer.span_from(start),
+                        })
+                    }
+                };
+                // constant indices are checked, and don't need to be evaluated
+                let constant_value = |expr: Token<crate::Expression>| match ctx.function.expressions[expr] {
+                    crate::Expression::Constant(token) => match ctx.constants[token].inner {
+                        crate::ConstantInner::Sint(value) => Some(value),
+                        crate::ConstantInner::Uint(value) => Some(value as i64),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let constant_index = match ctx.function.expressions[index] {
+                    crate::Expression::Unary { op: crate::UnaryOperator::Negate, expr } => {
+                        constant_value(expr).map(|value| -value)
+                    }
+                    _ => constant_value(index),
+                };
+                let expression = match constant_index {
+                    Some(value) if value < 0 || count.map_or(false, |count| value >= count as i64) => {
+                        return Err(Error {
+                            kind: ErrorKind::IndexOutOfBounds(value),
+                            span: ctx.function.spans.expression(index),
+                        });
+                    }
+                    Some(value) => crate::Expression::AccessIndex { base, index: value as u32 },
+                    None => crate::Expression::Access { base, index },
+                };
+                (expression, element)
+            } else {
+                return Ok(base);
+            };
+            // pointers to the elements need their types in the module
+            if pointer {
+                Self::register_type(ctx.types, element);
+            }
+            base = ctx.append(expression, lexer.span_from(start));
+        }
+    }
+
+    /// Parses a primary expression with its postfix, loading the value
+    /// if it refers to a local variable.
+    fn parse_postfix_expression(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> ExpressionResult {
+        let start = lexer.span().start;
+        let base = self.parse_primary_expression(lexer, ctx.reborrow())?;
+        let token = self.parse_postfix(lexer, ctx.reborrow(), start, base)?;
+        let span = lexer.span_from(start);
+        match ctx.resolve_type(token, span)? {
+            crate::TypeInner::Pointer { .. } => Ok(ctx.append(crate::Expression::Load { pointer: token }, span)),
+            _ => Ok(token),
+        }
+    }
+
+    fn parse_unary_expression(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> ExpressionResult {
+        let start = lexer.span().start;
+        let op = match lexer.peek() {
+            Some(LexToken::Punct("-")) => crate::UnaryOperator::Negate,
+            Some(LexToken::Punct("!")) => crate::UnaryOperator::Not,
+            Some(LexToken::Punct("~")) => crate::UnaryOperator::BitwiseNot,
+            _ => return self.parse_postfix_expression(lexer, ctx),
+        };
+        lexer.next();
+        let expr = self.parse_unary_expression(lexer, ctx.reborrow())?;
+        let expression = crate::Expression::Unary { op, expr };
+        Ok(ctx.append(expression, lexer.span_from(start)))
+    }
+
+    /// Parses the binary operators that bind at least as tight as the given precedence.
+    fn parse_binary_expression(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+        min_precedence: u8,
+    ) -> ExpressionResult {
+        let start = lexer.span().start;
+        let mut left = self.parse_unary_expression(lexer, ctx.reborrow())?;
+        while let Some((op, precedence, count)) = peek_binary_operator(lexer) {
+            if precedence < min_precedence {
+                break;
+            }
+            for _ in 0 .. count {
+                lexer.next();
+            }
+            let right = self.parse_binary_expression(lexer, ctx.reborrow(), precedence + 1)?;
+            let expression = crate::Expression::Binary { op, left, right };
+            let span = lexer.span_from(start);
+            left = ctx.append(expression, span);
+            // the operands of the matrix products have to match
+            ctx.resolve_type(left, span)?;
+        }
+        Ok(left)
+    }
+
+    fn parse_expression(
+        &self,
+        lexer: &mut Lexer,
+        ctx: ExpressionContext,
+    ) -> ExpressionResult {
+        self.parse_binary_expression(lexer, ctx, 1)
+    }
+
+    /// Parses a statement into the given block.
+    ///
+    /// Declarations don't produce statements, unless they store a value.
+    fn parse_statement(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+        block: &mut crate::Block,
+    ) -> Result<(), Error> {
+        let start = lexer.span().start;
+        // the statement comes before the ones nested in it
+        let span_index = ctx.function.spans.statements.len();
+        let top_level = std::mem::replace(&mut ctx.top_level, false);
+        let statement = match lexer.peek() {
+            Some(LexToken::Punct(";")) => {
+                lexer.next();
+                return Ok(());
+            }
+            Some(LexToken::Word("if")) => {
+                lexer.next();
+                self.parse_if(lexer, ctx.reborrow())?
+            }
+            Some(LexToken::Word("switch")) => {
+                lexer.next();
+                self.parse_switch(lexer, ctx.reborrow())?
+            }
+            Some(LexToken::Word("loop")) => {
+                lexer.next();
+                self.parse_loop(lexer, ctx.reborrow())?
+            }
+            Some(LexToken::Word("break")) => {
+                lexer.next();
+                lexer.expect(";")?;
+                crate::Statement::Break
+            }
+            Some(LexToken::Word("continue")) => {
+                lexer.next();
+                lexer.expect(";")?;
+                crate::Statement::Continue
+            }
+            Some(LexToken::Word("discard")) => {
+                lexer.next();
+                lexer.expect(";")?;
+                crate::Statement::Kill
+            }
+            Some(LexToken::Punct("{")) => {
+                let block = self.parse_block(lexer, ctx.reborrow())?;
+                crate::Statement::Block(block)
+            }
+            Some(LexToken::Word("return")) => {
+                lexer.next();
+                let value = if lexer.is_punct(";") {
+                    None
+                } else {
+                    Some(self.parse_expression(lexer, ctx.reborrow())?)
+                };
+                lexer.expect(";")?;
+                crate::Statement::Return { value }
+            }
+            Some(LexToken::Word("var")) => {
+                let (name, class, ty) = self.parse_variable_decl(lexer, ctx.types)?;
+                if let Some(class) = class {
+                    return Err(Error {
+                        kind: ErrorKind::InvalidVariableClass(class),
+                        span: lexer.span_from(start),
+                    });
+                }
+                let value = if lexer.skip("=") {
+                    Some(self.parse_expression(lexer, ctx.reborrow())?)
+                } else {
+                    None
+                };
+                lexer.expect(";")?;
+                // constant initializers of the variables declared at the top level
+                // are attached to them, anything else is stored at the point of
+                // declaration, as nested blocks may run several times
+                let init = value.filter(|_| top_level).and_then(|token| match ctx.function.expressions[token] {
+                    crate::Expression::Constant(constant) => Some(constant),
+                    _ => None,
+                });
+                let var_token = ctx.function.local_variables.append(crate::LocalVariable {
+                    name: Some(name.to_owned()),
+                    ty,
+                    init,
+                });
+                let pointer = ctx.append(crate::Expression::LocalVariable(var_token), lexer.span_from(start));
+                ctx.lookup_ident.insert(name.to_owned(), pointer);
+                match value {
+                    Some(value) if init.is_none() => crate::Statement::Store { pointer, value },
+                    _ => return Ok(()),
+                }
+            }
+            Some(LexToken::Word("const")) => {
+                lexer.next();
+                let (name, _ty) = self.parse_variable_ident_decl(lexer, ctx.types)?;
+                lexer.expect("=")?;
+                let value = self.parse_expression(lexer, ctx.reborrow())?;
+                lexer.expect(";")?;
+                ctx.lookup_ident.insert(name.to_owned(), value);
+                return Ok(());
+            }
+            Some(LexToken::Word(_)) if matches!(lexer.peek_at(1), Some(LexToken::Punct("=")) | Some(LexToken::Punct(".")) | Some(LexToken::Punct("["))) => {
+                let name = lexer.next_word("a variable name")?;
+                let base = match ctx.lookup_ident.get(name) {
+                    Some(&token) => token,
+                    None => return Err(lexer.error(ErrorKind::UnknownIdent(name.to_owned()))),
+                };
+                let pointer = self.parse_postfix(lexer, ctx.reborrow(), start, base)?;
+                if let crate::Expression::Compose { .. } = ctx.function.expressions[pointer] {
+                    return Err(Error {
+                        kind: ErrorKind::UnsupportedFeature("assignments to swizzles"),
+                        span: lexer.span_from(start),
+                    });
+                }
+                lexer.expect("=")?;
+                let value = self.parse_expression(lexer, ctx.reborrow())?;
+                lexer.expect(";")?;
+                crate::Statement::Store { pointer, value }
+            }
+            Some(LexToken::Word("textureStore")) if !self.lookup_function.contains_key("textureStore") => {
+                lexer.next();
+                let arguments = self.parse_arguments(lexer, ctx.reborrow())?;
+                lexer.expect(";")?;
+                let span = lexer.span_from(start);
+                let (image, info) = Self::image_argument("textureStore", &arguments, &ctx, span)?;
+                let (_, flags) = info;
+                let arrayed = flags.contains(crate::ImageFlags::ARRAYED);
+                if !flags.contains(crate::ImageFlags::CAN_STORE) || arguments.len() != 3 + arrayed as usize {
+                    return Err(Error {
+                        kind: ErrorKind::WrongImageArgumentCount("textureStore", arguments.len()),
+                        span,
+                    });
+                }
+                let coordinate = Self::image_coordinate(&arguments[1 .. arguments.len() - 1], info, ctx.reborrow(), span);
+                let value = arguments[arguments.len() - 1];
+                crate::Statement::ImageStore { image, coordinate, value }
+            }
+            Some(LexToken::Word(_)) if matches!(lexer.peek_at(1), Some(LexToken::Punct("(")) | Some(LexToken::Punct("::"))) => {
+                let (function, arguments) = self.parse_function_call(lexer, ctx.reborrow())?;
+                lexer.expect(";")?;
+                crate::Statement::Call { function, arguments }
+            }
+            _ => return Err(lexer.unexpected("a statement")),
+        };
+        block.push(statement);
+        ctx.function.spans.statements.insert(span_index, lexer.span_from(start));
+        Ok(())
+    }
+
+    /// Parses a block, with the variables declared in it going out of scope at its end.
+    fn parse_block(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<crate::Block, Error> {
+        lexer.expect("{")?;
+        let outer_scope = ctx.lookup_ident.clone();
+        let mut block = Vec::new();
+        while !lexer.skip("}") {
+            self.parse_statement(lexer, ctx.reborrow(), &mut block)?;
+        }
+        *ctx.lookup_ident = outer_scope;
+        Ok(block)
+    }
+
+    /// Parses an `if` statement, after its keyword.
+    fn parse_if(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<crate::Statement, Error> {
+        lexer.expect("(")?;
+        let condition = self.parse_expression(lexer, ctx.reborrow())?;
+        lexer.expect(")")?;
+        let accept = self.parse_block(lexer, ctx.reborrow())?;
+        let mut reject = Vec::new();
+        let is_else_if = lexer.is_word("elseif") ||
+            (lexer.is_word("else") && lexer.peek_at(1) == Some(LexToken::Word("if")));
+        if is_else_if {
+            // the chain continues in a nested statement
+            let start = lexer.span().start;
+            let span_index = ctx.function.spans.statements.len();
+            if !lexer.skip_word("elseif") {
+                lexer.next();
+                lexer.next();
+            }
+            let statement = self.parse_if(lexer, ctx.reborrow())?;
+            reject.push(statement);
+            ctx.function.spans.statements.insert(span_index, lexer.span_from(start));
+        } else if lexer.skip_word("else") {
+            reject = self.parse_block(lexer, ctx.reborrow())?;
+        }
+        Ok(crate::Statement::If { condition, accept, reject })
+    }
+
+    /// Parses a `switch` statement, after its keyword.
+    ///
+    /// The cases with several selectors fall through from the first value to the last.
+    fn parse_switch(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<crate::Statement, Error> {
+        lexer.expect("(")?;
+        let selector = self.parse_expression(lexer, ctx.reborrow())?;
+        lexer.expect(")")?;
+        lexer.expect("{")?;
+        let mut cases = Vec::new();
+        let mut default = None;
+        let mut default_spans = Vec::new();
+        while !lexer.skip("}") {
+            if lexer.skip_word("default") {
+                if default.is_some() {
+                    return Err(lexer.error(ErrorKind::DuplicateDefault));
+                }
+                lexer.expect(":")?;
+                let first_span = ctx.function.spans.statements.len();
+                default = Some(self.parse_block(lexer, ctx.reborrow())?);
+                // the default block comes after the cases in the IR
+                default_spans = ctx.function.spans.statements.drain(first_span ..).collect();
+                continue;
+            }
+            lexer.expect_word("case")?;
+            loop {
+                let negative = lexer.skip("-");
+                let value = match Self::parse_const_literal(lexer, negative)? {
+                    crate::ConstantInner::Sint(value) => value as i32,
+                    _ => return Err(lexer.error(ErrorKind::InvalidCaseSelector)),
+                };
+                if cases.iter().any(|&(v, _, _)| v == value) {
+                    return Err(lexer.error(ErrorKind::DuplicateCaseSelector(value)));
+                }
+                cases.push((value, Vec::new(), Some(crate::FallThrough)));
+                if !lexer.skip(",") {
+                    break;
+                }
+            }
+            lexer.expect(":")?;
+            let (body, fall_through) = self.parse_case_body(lexer, ctx.reborrow())?;
+            let last = cases.last_mut().unwrap();
+            last.1 = body;
+            last.2 = fall_through;
+        }
+        ctx.function.spans.statements.extend(default_spans);
+        Ok(crate::Statement::Switch {
+            selector,
+            cases,
+            default: default.unwrap_or_default(),
+        })
+    }
+
+    /// Parses the block of a case, which may end with a `fallthrough` statement.
+    fn parse_case_body(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<(crate::Block, Option<crate::FallThrough>), Error> {
+        lexer.expect("{")?;
+        let outer_scope = ctx.lookup_ident.clone();
+        let mut block = Vec::new();
+        let mut fall_through = None;
+        while !lexer.skip("}") {
+            if lexer.skip_word("fallthrough") {
+                lexer.expect(";")?;
+                fall_through = Some(crate::FallThrough);
+                lexer.expect("}")?;
+                break;
+            }
+            self.parse_statement(lexer, ctx.reborrow(), &mut block)?;
+        }
+        *ctx.lookup_ident = outer_scope;
+        Ok((block, fall_through))
+    }
+
+    /// Parses a `loop` statement, after its keyword.
+    ///
+    /// The `continuing` block comes last, and sees the variables of the body.
+    fn parse_loop(
+        &self,
+        lexer: &mut Lexer,
+        mut ctx: ExpressionContext,
+    ) -> Result<crate::Statement, Error> {
+        lexer.expect("{")?;
+        let outer_scope = ctx.lookup_ident.clone();
+        let mut body = Vec::new();
+        let mut continuing = Vec::new();
+        while !lexer.skip("}") {
+            if lexer.skip_word("continuing") {
+                continuing = self.parse_block(lexer, ctx.reborrow())?;
+                lexer.expect("}")?;
+                break;
+            }
+            self.parse_statement(lexer, ctx.reborrow(), &mut body)?;
+        }
+        *ctx.lookup_ident = outer_scope;
+        Ok(crate::Statement::Loop { body, continuing })
+    }
+
+    fn parse_function_decl(
+        &self,
+        lexer: &mut Lexer,
+        module: &mut crate::Module,
+    ) -> Result<Token<crate::Function>, Error> {
+        let start = lexer.span().start;
+        lexer.expect_word("fn")?;
+        let fun_name = lexer.next_word("a function name")?;
+        let mut lookup_ident = FastHashMap::default();
+        let mut fun = crate::Function {
+            name: Some(fun_name.to_owned()),
+            control: spirv::FunctionControl::empty(),
+            parameter_types: Vec::new(),
+            return_type: None,
+            local_variables: Storage::new(),
+            expressions: Storage::new(),
+            body: Vec::new(),
+            spans: Default::default(),
+        };
+        for (const_token, constant) in module.constants.iter() {
+            if let Some(ref name) = constant.name {
+                let expr_token = fun.expressions.append(crate::Expression::Constant(const_token));
+                lookup_ident.insert(name.clone(), expr_token);
+            }
+        }
+        for (var_token, variable) in module.global_variables.iter() {
+            if let Some(ref name) = variable.name {
+                let expr_token = fun.expressions.append(crate::Expression::GlobalVariable(var_token));
+                lookup_ident.insert(name.clone(), expr_token);
+            }
+        }
+
+        lexer.expect("(")?;
+        if !lexer.skip(")") {
+            loop {
+                let param_start = lexer.span().start;
+                let (param_name, ty) = self.parse_variable_ident_decl(lexer, &mut module.types)?;
+                let index = fun.parameter_types.len() as u32;
+                let span = lexer.span_from(param_start);
+                let expression_token = append_expression(&mut fun, crate::Expression::FunctionParameter(index), span);
+                lookup_ident.insert(param_name.to_owned(), expression_token);
+                fun.parameter_types.push(ty);
+                if !lexer.skip(",") {
+                    break;
+                }
+            }
+            lexer.expect(")")?;
+        }
+        lexer.expect("->")?;
+        if !lexer.skip_word("void") {
+            fun.return_type = Some(self.parse_type_decl(lexer, &mut module.types)?);
+        }
+
+        let ctx = ExpressionContext {
+            function: &mut fun,
+            lookup_ident: &mut lookup_ident,
+            types: &mut module.types,
+            constants: &mut module.constants,
+            global_vars: &module.global_variables,
+            functions: &module.functions,
+            typifier: &mut Typifier::new(),
+            top_level: true,
+        };
+        fun.body = self.parse_block(lexer, ctx)?;
+        let token = module.functions.append(fun);
+        module.spans.functions.push(lexer.span_from(start));
+        Ok(token)
+    }
+
+    fn parse_global_decl(
+        &mut self,
+        lexer: &mut Lexer,
+        module: &mut crate::Module,
+    ) -> Result<(), Error> {
+        let start = lexer.span().start;
+        match lexer.peek() {
+            Some(LexToken::Punct(";")) => {
+                lexer.next();
+            }
+            Some(LexToken::Word("import")) => {
+                lexer.next();
+                let path = lexer.next_string()?;
+                lexer.expect_word("as")?;
+                let namespace = Self::parse_path(lexer)?;
+                lexer.expect(";")?;
+                if path == "GLSL.std.450" {
+                    self.std_namespace = Some(namespace);
+                } else {
+                    log::warn!("Ignoring import {:?}", path);
+                }
+            }
+            Some(LexToken::Punct("[")) |
+            Some(LexToken::Word("var")) => {
+                let binding = if lexer.is_punct("[") {
+                    Self::parse_decoration_list(lexer)?
+                } else {
+                    None
+                };
+                let (name, class, ty) = self.parse_variable_decl(lexer, &mut module.types)?;
+                if lexer.skip("=") {
+                    //TODO: initializers of the global variables
+                    Self::parse_const_expression(lexer)?;
+                }
+                lexer.expect(";")?;
+                let class = class.unwrap_or_else(|| match module.types[ty].inner {
+                    crate::TypeInner::Image { .. } |
+                    crate::TypeInner::Sampler { .. } => spirv::StorageClass::UniformConstant,
+                    _ => spirv::StorageClass::Private,
+                });
+                let span = lexer.span_from(start);
+                let kind = match class {
+                    spirv::StorageClass::Function => Some(ErrorKind::InvalidVariableClass(class)),
+                    // buffers are described by the block structures
+                    spirv::StorageClass::Uniform |
+                    spirv::StorageClass::StorageBuffer if !self.block_types.contains(&ty) => {
+                        Some(ErrorKind::NotABlock(class))
+                    }
+                    _ => None,
+                };
+                if let Some(kind) = kind {
+                    return Err(Error { kind, span });
+                }
+                module.global_variables.append(crate::GlobalVariable {
+                    name: Some(name.to_owned()),
+                    class,
+                    binding,
+                    ty,
+                });
+                module.spans.global_variables.push(span);
+            }
+            Some(LexToken::Word("const")) => {
+                lexer.next();
+                let (name, _ty) = self.parse_variable_ident_decl(lexer, &mut module.types)?;
+                lexer.expect("=")?;
+                let inner = Self::parse_const_expression(lexer)?;
+                lexer.expect(";")?;
+                module.constants.append(crate::Constant {
+                    name: Some(name.to_owned()),
+                    specialization: None,
+                    inner,
+                });
+            }
+            Some(LexToken::Word("type")) => {
+                lexer.next();
+                let name = lexer.next_word("a type name")?;
+                lexer.expect("=")?;
+                let token = if lexer.is_punct("[") || lexer.is_word("struct") {
+                    let (inner, block) = self.parse_struct_decl(lexer, &mut module.types)?;
+                    let token = module.types.append(crate::Type {
+                        name: Some(name.to_owned()),
+                        inner,
+                    });
+                    if block {
+                        self.block_types.insert(token);
+                    }
+                    token
+                } else {
+                    self.parse_type_decl(lexer, &mut module.types)?
+                };
+                self.lookup_type.insert(name.to_owned(), token);
+                lexer.expect(";")?;
+            }
+            Some(LexToken::Word("fn")) => {
+                let token = self.parse_function_decl(lexer, module)?;
+                if let Some(ref name) = module.functions[token].name {
+                    self.lookup_function.insert(name.clone(), token);
+                }
+            }
+            Some(LexToken::Word("entry_point")) => {
+                lexer.next();
+                let exec_model = match lexer.peek() {
+                    Some(LexToken::Word("vertex")) => spirv::ExecutionModel::Vertex,
+                    Some(LexToken::Word("fragment")) => spirv::ExecutionModel::Fragment,
+                    Some(LexToken::Word("compute")) => spirv::ExecutionModel::GLCompute,
+                    _ => return Err(lexer.unexpected("a pipeline stage")),
+                };
+                lexer.next();
+                let name = if lexer.skip_word("as") {
+                    Some(lexer.next_string()?)
+                } else {
+                    None
+                };
+                lexer.expect("=")?;
+                let fun_ident = lexer.next_word("a function name")?;
+                let function = match self.lookup_function.get(fun_ident) {
+                    Some(&function) => function,
+                    None => return Err(lexer.error(ErrorKind::UnknownFunction(fun_ident.to_owned()))),
+                };
+                lexer.expect(";")?;
+                module.entry_points.push(crate::EntryPoint {
+                    exec_model,
+                    name: name.unwrap_or_else(|| fun_ident.to_owned()),
+                    inputs: Vec::new(), //TODO
+                    outputs: Vec::new(), //TODO
+                    function,
+                });
+            }
+            _ => return Err(lexer.unexpected("a global declaration")),
+        }
+        Ok(())
+    }
+
+    pub fn parse(&mut self, source: &str) -> Result<crate::Module, Error> {
+        let mut lexer = Lexer::new(source)?;
+        let mut module = crate::Module::generate_empty();
+        while lexer.peek().is_some() {
+            self.parse_global_decl(&mut lexer, &mut module)?;
+        }
+        Ok(module)
+    }
+}
+
+pub fn parse_str(source: &str) -> Result<crate::Module, Error> {
+    Parser::new().parse(source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::ErrorKind;
+
+    #[test]
+    fn error_location() {
+        let source = "fn main() -> void {\n    var a : f32 = 1.0;\n    a = oops;\n    return;\n}\n";
+        let error = super::parse_str(source).unwrap_err();
+        match error.kind {
+            ErrorKind::UnknownIdent(ref name) => assert_eq!(name, "oops"),
+            ref other => panic!("Unexpected error {:?}", other),
+        }
+        assert_eq!(error.location(source), (3, 9));
+        assert_eq!(
+            error.render(source),
+            "error: unknown identifier `oops`\n \
+             --> 3:9\n  \
+             |\n\
+             3 |     a = oops;\n  \
+             |         ^^^^\n",
+        );
+    }
+
+    #[test]
+    fn error_syntax() {
+        let source = "fn main() -> void {\n    return\n}\n";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnexpectedToken { expected: "an expression", found: "`}`".to_owned() });
+        assert_eq!(error.location(source), (3, 1));
+        assert!(error.render(source).contains("3 | }\n"));
+        let error = super::parse_str("fn main() -> void {\n    var a : f32 = 1.0 $ 2.0;\n").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnexpectedCharacter('$'));
+        let error = super::parse_str("fn main() -> void {\n    return;\n").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnexpectedEnd);
+    }
+
+    #[test]
+    fn parse_operators() {
+        let source = "
+            var<out> o : array<vec2<i32>>;
+            fn main(a : i32, b : i32) -> i32 {
+                return a >> b + a * -b >>> 2 < a;
+            }
+        ";
+        let module = super::parse_str(source).unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        let value = match fun.body[..] {
+            [crate::Statement::Return { value: Some(value) }] => value,
+            ref other => panic!("Unexpected body {:?}", other),
+        };
+        assert_eq!(&source[fun.spans.expression(value).to_range()], "a >> b + a * -b >>> 2 < a");
+        let shift = match fun.expressions[value] {
+            crate::Expression::Binary { op: crate::BinaryOperator::Less, left, .. } => left,
+            ref other => panic!("Unexpected expression {:?}", other),
+        };
+        assert_eq!(&source[fun.spans.expression(shift).to_range()], "a >> b + a * -b >>> 2");
+        match fun.expressions[shift] {
+            crate::Expression::Binary { op: crate::BinaryOperator::ShiftRightArithmetic, left, .. } => {
+                assert_eq!(&source[fun.spans.expression(left).to_range()], "a >> b + a * -b");
+            }
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_int_literals() {
+        let module = super::parse_str("
+            const a : i32 = 0x1f;
+            const b : i32 = -0x80000000;
+            const c : i32 = -2147483648;
+            const d : i32 = 0x7fffffff;
+        ").unwrap();
+        let values = module.constants
+            .iter()
+            .map(|(_, constant)| match constant.inner {
+                crate::ConstantInner::Sint(value) => value,
+                ref other => panic!("Unexpected constant {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0x1f, i32::MIN as i64, i32::MIN as i64, i32::MAX as i64]);
+        // without a sign, the literal is out of the range of `i32`
+        let error = super::parse_str("const a : i32 = 0x80000000;").unwrap_err();
+        assert!(matches!(error.kind, ErrorKind::BadInt(_)));
+    }
+
+    #[test]
+    fn parse_unary_operators() {
+        let source = "
+            fn main(a : i32) -> bool {
+                return !(-a == ~-a);
+            }
+        ";
+        let module = super::parse_str(source).unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        let value = match fun.body[..] {
+            [crate::Statement::Return { value: Some(value) }] => value,
+            ref other => panic!("Unexpected body {:?}", other),
+        };
+        assert_eq!(&source[fun.spans.expression(value).to_range()], "!(-a == ~-a)");
+        let (left, right) = match fun.expressions[value] {
+            crate::Expression::Unary { op: crate::UnaryOperator::Not, expr } => match fun.expressions[expr] {
+                crate::Expression::Binary { op: crate::BinaryOperator::Equal, left, right } => (left, right),
+                ref other => panic!("Unexpected expression {:?}", other),
+            },
+            ref other => panic!("Unexpected expression {:?}", other),
+        };
+        assert_eq!(&source[fun.spans.expression(left).to_range()], "-a");
+        assert!(matches!(fun.expressions[left], crate::Expression::Unary { op: crate::UnaryOperator::Negate, .. }));
+        assert_eq!(&source[fun.spans.expression(right).to_range()], "~-a");
+        match fun.expressions[right] {
+            crate::Expression::Unary { op: crate::UnaryOperator::BitwiseNot, expr } => {
+                assert!(matches!(fun.expressions[expr], crate::Expression::Unary { op: crate::UnaryOperator::Negate, .. }));
+            }
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_control_flow() {
+        let source = "
+            [[location 0]] var<in> alpha : f32;
+            [[location 0]] var<out> color : vec4<f32>;
+            fn main() -> void {
+                var mode : i32 = 2;
+                var sum : f32 = 0.0;
+                if (alpha < 0.5) {
+                    discard;
+                } elseif (alpha < 0.75) {
+                    sum = 0.5;
+                } else {
+                    sum = 1.0;
+                }
+                switch (mode) {
+                    default: {
+                        sum = sum + 3.0;
+                    }
+                    case 1, 2: {
+                        sum = sum + 1.0;
+                        fallthrough;
+                    }
+                    case -1: {
+                        break;
+                    }
+                }
+                loop {
+                    var step : f32 = 0.25;
+                    if (sum > 4.0) {
+                        break;
+                    }
+                    continuing {
+                        sum = sum + step;
+                    }
+                }
+                color = vec4<f32>(sum, sum, sum, 1.0);
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ";
+        let module = super::parse_str(source).unwrap();
+        crate::valid::Validator::new().validate(&module).unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        let (cases, default) = match fun.body[..] {
+            [
+                crate::Statement::If { ref reject, .. },
+                crate::Statement::Switch { ref cases, ref default, .. },
+                crate::Statement::Loop { ref continuing, .. },
+                crate::Statement::Store { .. },
+                crate::Statement::Return { value: None },
+            ] => {
+                assert!(matches!(reject[..], [crate::Statement::If { .. }]));
+                assert_eq!(continuing.len(), 1);
+                (cases, default)
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        };
+        assert_eq!(cases.iter().map(|&(value, _, fall_through)| (value, fall_through.is_some())).collect::<Vec<_>>(),
+            vec![(1, true), (2, true), (-1, false)]);
+        assert!(cases[0].1.is_empty());
+        assert_eq!(default.len(), 1);
+
+        // the spans follow the statements in the order of the IR
+        let statement_texts = fun.spans.statements
+            .iter()
+            .map(|span| source[span.to_range()].split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>();
+        assert_eq!(statement_texts[.. 5], [
+            "if (alpha < 0.5) { discard; } elseif (alpha < 0.75) { sum = 0.5; } else { sum = 1.0; }",
+            "discard;",
+            "elseif (alpha < 0.75) { sum = 0.5; } else { sum = 1.0; }",
+            "sum = 0.5;",
+            "sum = 1.0;",
+        ]);
+        assert_eq!(statement_texts[6 .. 9], ["sum = sum + 1.0;", "break;", "sum = sum + 3.0;"]);
+
+        let text = crate::back::wgsl::write_string(&module).unwrap();
+        let module2 = super::parse_str(&text).unwrap();
+        assert_eq!(crate::back::wgsl::write_string(&module2).unwrap(), text);
+    }
+
+    #[test]
+    fn parse_nested_initializers() {
+        let source = "
+            fn main() -> void {
+                var total : i32 = 0;
+                loop {
+                    var i : i32 = 5;
+                    i = i + 1;
+                    if (i > 6) {
+                        break;
+                    }
+                }
+                return;
+            }
+        ";
+        let module = super::parse_str(source).unwrap();
+        crate::valid::Validator::new().validate(&module).unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        // only the variables of the function body are initialized once
+        let inits = fun.local_variables
+            .iter()
+            .map(|(_, var)| match var.init.map(|constant| &module.constants[constant].inner) {
+                Some(&crate::ConstantInner::Sint(value)) => Some(value),
+                None => None,
+                ref other => panic!("Unexpected initializer {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(inits, vec![Some(0), None]);
+        let body = match fun.body[..] {
+            [crate::Statement::Loop { ref body, .. }, crate::Statement::Return { value: None }] => body,
+            ref other => panic!("Unexpected body {:?}", other),
+        };
+        match body[..] {
+            [crate::Statement::Store { value, .. }, crate::Statement::Store { .. }, crate::Statement::If { .. }] => {
+                assert_eq!(&source[fun.spans.expression(value).to_range()], "5");
+            }
+            ref other => panic!("Unexpected loop body {:?}", other),
+        }
+        assert_eq!(&source[fun.spans.statements[1].to_range()], "var i : i32 = 5;");
+    }
+
+    #[test]
+    fn control_flow_errors() {
+        let error = super::parse_str("
+            fn main() -> void {
+                switch (1) {
+                    case 1: {}
+                    case 1: {}
+                }
+            }
+        ").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::DuplicateCaseSelector(1));
+        let error = super::parse_str("
+            fn main() -> void {
+                loop {
+                    var a : i32 = 0;
+                }
+                a = 1;
+            }
+        ").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownIdent("a".to_owned()));
+    }
+
+    #[test]
+    fn parse_resource_bindings() {
+        let source = "
+            type Params = [[block]] struct {
+                [[offset 0]] scale : f32;
+                [[offset 16]] offset : vec4<f32>;
+            };
+            type Particles = [[block]] struct {
+                [[offset 0]] positions : array<vec4<f32>>;
+            };
+            [[binding 0, set 0]] var<uniform> params : Params;
+            [[set 1, binding 3]] var<storage_buffer> particles : Particles;
+        ";
+        let module = super::parse_str(source).unwrap();
+        crate::valid::Validator::new().validate(&module).unwrap();
+        let globals = module.global_variables
+            .iter()
+            .map(|(_, var)| (var.class, var.binding.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(globals, vec![
+            (spirv::StorageClass::Uniform, Some(crate::Binding::Descriptor { set: 0, binding: 0 })),
+            (spirv::StorageClass::StorageBuffer, Some(crate::Binding::Descriptor { set: 1, binding: 3 })),
+        ]);
+        let (_, params) = module.global_variables.iter().next().unwrap();
+        match module.types[params.ty].inner {
+            crate::TypeInner::Struct { ref members } => {
+                let offsets = members.iter().map(|member| member.offset).collect::<Vec<_>>();
+                assert_eq!(offsets, vec![Some(0), Some(16)]);
+            }
+            ref other => panic!("Unexpected type {:?}", other),
+        }
+
+        let text = crate::back::wgsl::write_string(&module).unwrap();
+        let module2 = super::parse_str(&text).unwrap();
+        assert_eq!(crate::back::wgsl::write_string(&module2).unwrap(), text);
+    }
+
+    #[test]
+    fn resource_binding_errors() {
+        let source = "[[set 0]] var<uniform> a : f32;";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingDecoration("binding"));
+        assert_eq!(&source[error.span.to_range()], "[[set 0]]");
+        let source = "
+            type Params = struct {
+                [[offset 0]] scale : f32;
+            };
+            [[binding 0, set 0]] var<uniform> params : Params;
+        ";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NotABlock(spirv::StorageClass::Uniform));
+        assert_eq!(&source[error.span.to_range()], "[[binding 0, set 0]] var<uniform> params : Params;");
+        let error = super::parse_str("type Params = [[offset 0]] struct {};").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::BadDecoration("offset".to_owned()));
+        let source = "type Params = [[block]] struct { scale : f32; };";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingDecoration("offset"));
+        assert_eq!(&source[error.span.to_range()], "scale : f32;");
+    }
+
+    #[test]
+    fn parse_textures() {
+        let source = "
+            [[set 0, binding 0]] var color_map : texture_2d<f32>;
+            [[set 0, binding 1]] var linear : sampler;
+            [[set 0, binding 2]] var shadow_map : texture_depth_2d;
+            [[set 0, binding 3]] var layers : texture_2d_array<f32>;
+            [[set 1, binding 0]] var<uniform_constant> output : texture_storage_wo_2d<f32>;
+            fn main() -> void {
+                var size : vec2<i32> = textureDimensions(output);
+                var texel : vec4<f32> = textureLoad(layers, size, 2, 0);
+                var depth : f32 = textureLoad(shadow_map, size, 1);
+                texel = texel + textureSample(color_map, linear, vec2<f32>(0.5, 0.5));
+                textureStore(output, size, texel * depth);
+                return;
+            }
+            entry_point compute as \"main\" = main;
+        ";
+        let module = super::parse_str(source).unwrap();
+        crate::valid::Validator::new().validate(&module).unwrap();
+        for (_, var) in module.global_variables.iter() {
+            assert_eq!(var.class, spirv::StorageClass::UniformConstant);
+        }
+        let types = module.global_variables
+            .iter()
+            .map(|(_, var)| module.types[var.ty].inner.clone())
+            .collect::<Vec<_>>();
+        let (base, flags) = match types[..] {
+            [
+                crate::TypeInner::Image { base, dim: spirv::Dim::Dim2D, flags },
+                crate::TypeInner::Sampler { comparison: false },
+                crate::TypeInner::Image { base: depth_base, flags: depth_flags, .. },
+                crate::TypeInner::Image { flags: layer_flags, .. },
+                crate::TypeInner::Image { flags: storage_flags, .. },
+            ] => {
+                assert_eq!(depth_base, base);
+                assert_eq!(depth_flags, crate::ImageFlags::SAMPLED | crate::ImageFlags::DEPTH);
+                assert_eq!(layer_flags, crate::ImageFlags::SAMPLED | crate::ImageFlags::ARRAYED);
+                assert_eq!(storage_flags, crate::ImageFlags::CAN_STORE);
+                (base, flags)
+            }
+            ref other => panic!("Unexpected types {:?}", other),
+        };
+        assert_eq!(module.types[base].inner, crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 });
+        assert_eq!(flags, crate::ImageFlags::SAMPLED);
+
+        let (_, fun) = module.functions.iter().next().unwrap();
+        match fun.body[..] {
+            [.., crate::Statement::ImageStore { coordinate, .. }, crate::Statement::Return { value: None }] => {
+                assert_eq!(fun.body.len(), 6);
+                assert!(matches!(fun.expressions[coordinate], crate::Expression::Load { .. }));
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+        // the array layer joins the coordinate of the texel
+        let layer_coordinate = fun.expressions
+            .iter()
+            .find_map(|(_, expression)| match *expression {
+                crate::Expression::ImageLoad { coordinate, index: Some(_), .. } => Some(coordinate),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&source[fun.spans.expression(layer_coordinate).to_range()], "(layers, size, 2, 0)");
+        match fun.expressions[layer_coordinate] {
+            crate::Expression::Compose { ty, ref components } => {
+                assert_eq!(components.len(), 2);
+                assert_eq!(module.types[ty].inner, crate::TypeInner::Vector {
+                    size: crate::VectorSize::Tri,
+                    kind: crate::ScalarKind::Sint,
+                    width: 32,
+                });
+            }
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+
+        let text = crate::back::wgsl::write_string(&module).unwrap();
+        let module2 = super::parse_str(&text).unwrap();
+        assert_eq!(crate::back::wgsl::write_string(&module2).unwrap(), text);
+        crate::back::spv::write_vec(&module, &Default::default()).unwrap();
+    }
+
+    #[test]
+    fn texture_errors() {
+        let error = super::parse_str("var t : texture_multisampled_3d<f32>;").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownType("texture_multisampled_3d".to_owned()));
+        let source = "
+            [[set 0, binding 0]] var t : texture_storage_ro_2d<f32>;
+            fn main(a : vec2<i32>) -> vec4<f32> {
+                return textureLoad(t, a, 0);
+            }
+        ";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::WrongImageArgumentCount("textureLoad", 3));
+        assert_eq!(&source[error.span.to_range()], "(t, a, 0)");
+        let error = super::parse_str("
+            fn main(a : vec2<i32>) -> vec4<f32> {
+                return textureLoad(a, a);
+            }
+        ").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NotAnImage("textureLoad"));
+    }
+
+    #[test]
+    fn parse_postfix() {
+        let source = "
+            type Light = struct {
+                [[offset 0]] position : vec4<f32>;
+                [[offset 16]] color : vec4<f32>;
+            };
+            type Lights = [[block]] struct {
+                [[offset 0]] count : u32;
+                [[offset 16]] data : array<Light>;
+            };
+            [[set 0, binding 0]] var<storage_buffer> lights : Lights;
+            [[location 0]] var<in> index : u32;
+            [[location 0]] var<out> color : vec4<f32>;
+            fn main() -> void {
+                var sum : vec4<f32> = lights.data[index].color;
+                sum.w = lights.data[0].position.x;
+                color = vec4<f32>(sum.rgb, sum[3]);
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ";
+        let module = super::parse_str(source).unwrap();
+        crate::valid::Validator::new().validate(&module).unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        let (first, second, third) = match fun.body[..] {
+            [
+                crate::Statement::Store { value: first, .. },
+                crate::Statement::Store { pointer: second, .. },
+                crate::Statement::Store { value: third, .. },
+                crate::Statement::Return { value: None },
+            ] => (first, second, third),
+            ref other => panic!("Unexpected body {:?}", other),
+        };
+        // struct members and constant indices are accessed directly
+        match fun.expressions[first] {
+            crate::Expression::AccessIndex { base, index: 1 } => match fun.expressions[base] {
+                crate::Expression::Access { base, .. } => {
+                    assert_eq!(&source[fun.spans.expression(base).to_range()], "lights.data");
+                }
+                ref other => panic!("Unexpected expression {:?}", other),
+            },
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+        match fun.expressions[second] {
+            crate::Expression::AccessIndex { base, index: 3 } => {
+                assert!(matches!(fun.expressions[base], crate::Expression::LocalVariable(_)));
+            }
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+        let swizzle = match fun.expressions[third] {
+            crate::Expression::Compose { ref components, .. } => components[0],
+            ref other => panic!("Unexpected expression {:?}", other),
+        };
+        assert_eq!(&source[fun.spans.expression(swizzle).to_range()], "sum.rgb");
+        match fun.expressions[swizzle] {
+            crate::Expression::Compose { ty, ref components } => {
+                assert_eq!(module.types[ty].inner, crate::TypeInner::Vector {
+                    size: crate::VectorSize::Tri,
+                    kind: crate::ScalarKind::Float,
+                    width: 32,
+                });
+                let indices = components
+                    .iter()
+                    .map(|&component| match fun.expressions[component] {
+                        crate::Expression::AccessIndex { index, .. } => index,
+                        ref other => panic!("Unexpected expression {:?}", other),
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(indices, vec![0, 1, 2]);
+            }
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+
+        let text = crate::back::wgsl::write_string(&module).unwrap();
+        let module2 = super::parse_str(&text).unwrap();
+        assert_eq!(crate::back::wgsl::write_string(&module2).unwrap(), text);
+        crate::back::spv::write_vec(&module, &Default::default()).unwrap();
+    }
+
+    #[test]
+    fn postfix_errors() {
+        let source = "
+            fn main(a : vec2<f32>) -> f32 {
+                return a.xz;
+            }
+        ";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownMember("xz".to_owned()));
+        assert_eq!(&source[error.span.to_range()], "xz");
+        let error = super::parse_str("
+            type Pair = struct {
+                [[offset 0]] first : f32;
+            };
+            fn main(a : Pair) -> f32 {
+                return a.second;
+            }
+        ").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownMember("second".to_owned()));
+        let source = "
+            fn main() -> f32 {
+                var a : array<f32, 4>;
+                return a[4];
+            }
+        ";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::IndexOutOfBounds(4));
+        assert_eq!(&source[error.span.to_range()], "4");
+        let source = "
+            fn main() -> f32 {
+                var a : array<f32, 4>;
+                return a[-1];
+            }
+        ";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::IndexOutOfBounds(-1));
+        assert_eq!(&source[error.span.to_range()], "-1");
+        let error = super::parse_str("
+            fn main() -> void {
+                var a : vec4<f32>;
+                a.xy = vec2<f32>(1.0, 2.0);
+            }
+        ").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnsupportedFeature("assignments to swizzles"));
+    }
+
+    #[test]
+    fn parse_matrices() {
+        let source = "
+            type Transforms = [[block]] struct {
+                [[offset 0]] view : mat4x4<f32>;
+                [[offset 64]] projection : mat4x4<f32>;
+                [[offset 128]] normal : mat3x2<f32>;
+            };
+            [[set 0, binding 0]] var<uniform> transforms : Transforms;
+            [[location 0]] var<in> position : vec4<f32>;
+            [[location 1]] var<in> normal : vec3<f32>;
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            fn main() -> void {
+                var scale : f32 = 2.0;
+                var view_proj : mat4x4<f32> = transforms.projection * transforms.view;
+                var flat : vec2<f32> = transforms.normal * normal;
+                var row : vec3<f32> = vec2<f32>(1.0, 0.0) * transforms.normal;
+                o_position = view_proj * scale * position;
+                return;
+            }
+            entry_point vertex as \"main\" = main;
+        ";
+        let module = super::parse_str(source).unwrap();
+        crate::valid::Validator::new().validate(&module).unwrap();
+        let (_, fun) = module.functions.iter().next().unwrap();
+        let mut typifier = crate::proc::Typifier::new();
+        typifier.resolve_all(&fun.expressions, &crate::proc::ResolveContext {
+            types: &module.types,
+            constants: &module.constants,
+            global_vars: &module.global_variables,
+            local_vars: &fun.local_variables,
+            functions: &module.functions,
+            parameter_types: &fun.parameter_types,
+        }).unwrap();
+        let products = fun.expressions
+            .iter()
+            .filter(|&(_, expression)| matches!(*expression, crate::Expression::Binary { .. }))
+            .map(|(token, _)| (
+                &source[fun.spans.expression(token).to_range()],
+                typifier.get(token, &module.types).clone(),
+            ))
+            .collect::<Vec<_>>();
+        let vector = |size| crate::TypeInner::Vector { size, kind: crate::ScalarKind::Float, width: 32 };
+        let mat4x4 = crate::TypeInner::Matrix {
+            columns: crate::VectorSize::Quad,
+            rows: crate::VectorSize::Quad,
+            kind: crate::ScalarKind::Float,
+            width: 32,
+        };
+        assert_eq!(products, vec![
+            ("transforms.projection * transforms.view", mat4x4.clone()),
+            ("transforms.normal * normal", vector(crate::VectorSize::Bi)),
+            ("vec2<f32>(1.0, 0.0) * transforms.normal", vector(crate::VectorSize::Tri)),
+            ("view_proj * scale", mat4x4),
+            ("view_proj * scale * position", vector(crate::VectorSize::Quad)),
+        ]);
+
+        let text = crate::back::wgsl::write_string(&module).unwrap();
+        let module2 = super::parse_str(&text).unwrap();
+        assert_eq!(crate::back::wgsl::write_string(&module2).unwrap(), text);
+        crate::back::spv::write_vec(&module, &Default::default()).unwrap();
+    }
+
+    #[test]
+    fn matrix_errors() {
+        let error = super::parse_str("var<private> m : mat2x2<i32>;").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownType("mat2x2".to_owned()));
+        let source = "
+            fn main(m : mat2x3<f32>, v : vec3<f32>) -> vec3<f32> {
+                return m * v;
+            }
+        ";
+        let error = super::parse_str(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Resolve(crate::proc::ResolveError::IncompatibleOperands(crate::BinaryOperator::Multiply)));
+        assert_eq!(&source[error.span.to_range()], "m * v");
+    }
+
+    #[test]
+    fn parse_samples() {
+        let sources = [
+            include_str!("../../../test-data/boids.wgsl"),
+            include_str!("../../../test-data/quad.wgsl"),
+        ];
+        for source in sources.iter() {
+            if let Err(error) = super::parse_str(source) {
+                panic!("{}", error.render(source));
+            }
+        }
+    }
+}
@@ -1,10 +1,10 @@
-#[macro_use]
-extern crate pest_derive;
 extern crate spirv_headers as spirv;
 
 pub mod back;
 pub mod front;
+pub mod proc;
 mod storage;
+pub mod valid;
 
 use crate::storage::{Storage, Token};
 
@@ -18,6 +18,8 @@ type FastHashMap<K, T> = HashMap<K, T, BuildHasherDefault<fxhash::FxHasher>>;
 type FastHashSet<K> = HashSet<K, BuildHasherDefault<fxhash::FxHasher>>;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct Header {
     pub version: (u8, u8, u8),
     pub generator: u32,
@@ -25,8 +27,40 @@ pub struct Header {
 
 pub type Bytes = u8;
 
+/// Range of the source that an entity was parsed from.
+///
+/// The offsets are in bytes for the text formats, and in words for SPIR-V.
+/// The default, empty span is used when the location is unknown.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span {
+            start: start as u32,
+            end: end as u32,
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        *self == Span::default()
+    }
+
+    /// Returns the range of the source covered by the span.
+    pub fn to_range(self) -> std::ops::Range<usize> {
+        self.start as usize .. self.end as usize
+    }
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum VectorSize {
     Bi = 2,
     Tri = 3,
@@ -35,6 +69,8 @@ pub enum VectorSize {
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum ScalarKind {
     Sint,
     Uint,
@@ -44,35 +80,49 @@ pub enum ScalarKind {
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum ArraySize {
     Static(spirv::Word),
     Dynamic,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct StructMember {
     pub name: Option<String>,
     pub binding: Option<Binding>,
+    /// Byte offset of the member, if the layout of the structure is explicit.
+    pub offset: Option<spirv::Word>,
     pub ty: Token<Type>,
 }
 
 bitflags::bitflags! {
+    #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+    #[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
     pub struct ImageFlags: u32 {
         const ARRAYED = 0x1;
         const MULTISAMPLED = 0x2;
         const SAMPLED = 0x4;
+        /// Image of depth values, which are read as scalars.
+        const DEPTH = 0x8;
         const CAN_LOAD = 0x10;
         const CAN_STORE = 0x20;
     }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct Type {
     pub name: Option<String>,
     pub inner: TypeInner,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum TypeInner {
     Scalar { kind: ScalarKind, width: Bytes },
     Vector { size: VectorSize, kind: ScalarKind, width: Bytes },
@@ -81,10 +131,12 @@ pub enum TypeInner {
     Array { base: Token<Type>, size: ArraySize },
     Struct { members: Vec<StructMember> },
     Image { base: Token<Type>, dim: spirv::Dim, flags: ImageFlags },
-    Sampler,
+    Sampler { comparison: bool },
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct Constant {
     pub name: Option<String>,
     pub specialization: Option<spirv::Word>,
@@ -92,6 +144,8 @@ pub struct Constant {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum ConstantInner {
     Sint(i64),
     Uint(u64),
@@ -100,6 +154,8 @@ pub enum ConstantInner {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum Binding {
     BuiltIn(spirv::BuiltIn),
     Location(spirv::Word),
@@ -107,6 +163,8 @@ pub enum Binding {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct GlobalVariable {
     pub name: Option<String>,
     pub class: spirv::StorageClass,
@@ -114,19 +172,160 @@ pub struct GlobalVariable {
     pub ty: Token<Type>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    BitwiseNot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum BinaryOperator {
-    Multiply,
     Add,
-    Equals,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     And,
     ExclusiveOr,
     InclusiveOr,
     LogicalAnd,
     LogicalOr,
+    ShiftLeftLogical,
+    ShiftRightLogical,
+    ShiftRightArithmetic,
+}
+
+/// Built-in math function, following the GLSL.std.450 extended instruction set.
+///
+/// Instructions that write through pointers or return structures
+/// (`Modf`, `Frexp` and friends), the double-precision packing and the
+/// interpolation functions are not represented.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub enum MathFunction {
+    Round,
+    RoundEven,
+    Trunc,
+    FAbs,
+    SAbs,
+    FSign,
+    SSign,
+    Floor,
+    Ceil,
+    Fract,
+    Radians,
+    Degrees,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Asinh,
+    Acosh,
+    Atanh,
+    Atan2,
+    Pow,
+    Exp,
+    Log,
+    Exp2,
+    Log2,
+    Sqrt,
+    InverseSqrt,
+    Determinant,
+    MatrixInverse,
+    FMin,
+    UMin,
+    SMin,
+    FMax,
+    UMax,
+    SMax,
+    FClamp,
+    UClamp,
+    SClamp,
+    FMix,
+    IMix,
+    Step,
+    SmoothStep,
+    Fma,
+    Ldexp,
+    PackSnorm4x8,
+    PackUnorm4x8,
+    PackSnorm2x16,
+    PackUnorm2x16,
+    PackHalf2x16,
+    UnpackSnorm2x16,
+    UnpackUnorm2x16,
+    UnpackHalf2x16,
+    UnpackSnorm4x8,
+    UnpackUnorm4x8,
+    Length,
+    Distance,
+    Cross,
+    Normalize,
+    FaceForward,
+    Reflect,
+    Refract,
+    FindILsb,
+    FindSMsb,
+    FindUMsb,
+    NMin,
+    NMax,
+    NClamp,
+}
+
+impl MathFunction {
+    /// Number of arguments the function expects.
+    pub fn argument_count(&self) -> usize {
+        match *self {
+            MathFunction::Atan2 |
+            MathFunction::Pow |
+            MathFunction::FMin |
+            MathFunction::UMin |
+            MathFunction::SMin |
+            MathFunction::FMax |
+            MathFunction::UMax |
+            MathFunction::SMax |
+            MathFunction::Step |
+            MathFunction::Ldexp |
+            MathFunction::Distance |
+            MathFunction::Cross |
+            MathFunction::Reflect |
+            MathFunction::NMin |
+            MathFunction::NMax => 2,
+            MathFunction::FClamp |
+            MathFunction::UClamp |
+            MathFunction::SClamp |
+            MathFunction::FMix |
+            MathFunction::IMix |
+            MathFunction::SmoothStep |
+            MathFunction::Fma |
+            MathFunction::FaceForward |
+            MathFunction::Refract |
+            MathFunction::NClamp => 3,
+            _ => 1,
+        }
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum Expression {
     Access {
         base: Token<Expression>,
@@ -143,34 +342,58 @@ pub enum Expression {
     },
     FunctionParameter(u32),
     GlobalVariable(Token<GlobalVariable>),
+    LocalVariable(Token<LocalVariable>),
     Load {
         pointer: Token<Expression>,
     },
-    Mul(Token<Expression>, Token<Expression>),
     ImageSample {
         image: Token<Expression>,
         sampler: Token<Expression>,
         coordinate: Token<Expression>,
     },
+    /// Read of a single texel, without filtering.
+    ImageLoad {
+        image: Token<Expression>,
+        coordinate: Token<Expression>, //int
+        /// Level of detail of sampled images, or the sample of multisampled ones.
+        /// Storage images have no index.
+        index: Option<Token<Expression>>, //int
+    },
+    /// Size of a level of detail of the image, not counting the array layers.
+    ImageDimensions {
+        image: Token<Expression>,
+        level: Option<Token<Expression>>, //int
+    },
+    Unary {
+        op: UnaryOperator,
+        expr: Token<Expression>,
+    },
     Binary {
         op: BinaryOperator,
         left: Token<Expression>,
         right: Token<Expression>,
     },
+    Call {
+        function: Token<Function>,
+        arguments: Vec<Token<Expression>>,
+    },
+    Math {
+        fun: MathFunction,
+        arguments: Vec<Token<Expression>>,
+    },
 }
 
 pub type Block = Vec<Statement>;
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct FallThrough;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub enum Statement {
     Block(Block),
-    VariableDeclaration {
-        name: String,
-        ty: Token<Type>,
-        value: Option<Token<Expression>>,
-    },
     If {
         condition: Token<Expression>, //bool
         accept: Block,
@@ -178,9 +401,17 @@ pub enum Statement {
     },
     Switch {
         selector: Token<Expression>, //int
-        cases: FastHashMap<i32, (Block, Option<FallThrough>)>,
+        /// Cases in their original order, so that a fall-through
+        /// proceeds to the next one in the list.
+        cases: Vec<(i32, Block, Option<FallThrough>)>,
         default: Block,
     },
+    Loop {
+        body: Block,
+        continuing: Block,
+    },
+    Break,
+    Continue,
     Return {
         value: Option<Token<Expression>>,
     },
@@ -189,19 +420,92 @@ pub enum Statement {
         pointer: Token<Expression>,
         value: Token<Expression>,
     },
+    ImageStore {
+        image: Token<Expression>,
+        coordinate: Token<Expression>, //int
+        value: Token<Expression>,
+    },
+    /// Call of a function that doesn't return a value.
+    Call {
+        function: Token<Function>,
+        arguments: Vec<Token<Expression>>,
+    },
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct LocalVariable {
+    pub name: Option<String>,
+    /// Type of the stored value, as opposed to the pointer to it.
+    pub ty: Token<Type>,
+    pub init: Option<Token<Constant>>,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct Function {
     pub name: Option<String>,
     pub control: spirv::FunctionControl,
     pub parameter_types: Vec<Token<Type>>,
     pub return_type: Option<Token<Type>>,
+    pub local_variables: Storage<LocalVariable>,
     pub expressions: Storage<Expression>,
     pub body: Block,
+    pub spans: FunctionSpans,
+}
+
+/// Spans of the entities of a function.
+///
+/// Frontends that can't locate an entity leave its span unknown,
+/// so the tables may be shorter than what they describe.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct FunctionSpans {
+    /// Spans of the expressions, by their index.
+    pub expressions: Vec<Span>,
+    /// Spans of the statements, in the order of a depth-first walk of the body,
+    /// where a statement comes before the statements nested in it, and the
+    /// nested blocks are walked in the order of the fields of the statement.
+    pub statements: Vec<Span>,
+}
+
+impl FunctionSpans {
+    pub fn expression(&self, token: Token<Expression>) -> Span {
+        self.expressions.get(token.index()).cloned().unwrap_or_default()
+    }
+
+    pub fn statement(&self, index: usize) -> Span {
+        self.statements.get(index).cloned().unwrap_or_default()
+    }
+}
+
+/// Spans of the entities of a module, see `FunctionSpans`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct ModuleSpans {
+    /// Spans of the global variables, by their index.
+    pub global_variables: Vec<Span>,
+    /// Spans of the functions, by their index.
+    pub functions: Vec<Span>,
+}
+
+impl ModuleSpans {
+    pub fn global_variable(&self, token: Token<GlobalVariable>) -> Span {
+        self.global_variables.get(token.index()).cloned().unwrap_or_default()
+    }
+
+    pub fn function(&self, token: Token<Function>) -> Span {
+        self.functions.get(token.index()).cloned().unwrap_or_default()
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct EntryPoint {
     pub exec_model: spirv::ExecutionModel,
     pub name: String,
@@ -211,6 +515,8 @@ pub struct EntryPoint {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct Module {
     pub header: Header,
     pub types: Storage<Type>,
@@ -218,4 +524,5 @@ pub struct Module {
     pub global_variables: Storage<GlobalVariable>,
     pub functions: Storage<Function>,
     pub entry_points: Vec<EntryPoint>,
+    pub spans: ModuleSpans,
 }
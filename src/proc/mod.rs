@@ -0,0 +1,5 @@
+//! Module processing functionality.
+
+mod typifier;
+
+pub use typifier::{ResolveContext, ResolveError, Resolution, Typifier};
@@ -0,0 +1,525 @@
+use crate::{
+    storage::{Storage, Token},
+};
+
+/// Resolved type of an expression.
+#[derive(Clone, Debug)]
+pub enum Resolution {
+    /// Type that is present in the module type arena.
+    Handle(Token<crate::Type>),
+    /// Type that only exists as a result of an expression.
+    Value(crate::TypeInner),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResolveError {
+    InvalidExpression(Token<crate::Expression>),
+    InvalidAccess(Token<crate::Expression>),
+    InvalidAccessIndex(u32),
+    InvalidParameter(u32),
+    InvalidPointer(Token<crate::Expression>),
+    InvalidImage(Token<crate::Expression>),
+    InvalidMathArgument(Token<crate::Expression>),
+    MissingPointerType(Token<crate::Expression>),
+    IncompatibleOperands(crate::BinaryOperator),
+    FunctionReturnsVoid(Token<crate::Function>),
+    InvalidConstant(Token<crate::Constant>),
+    InvalidGlobalVariable(Token<crate::GlobalVariable>),
+    InvalidLocalVariable(Token<crate::LocalVariable>),
+    InvalidFunction(Token<crate::Function>),
+    InvalidType(Token<crate::Type>),
+}
+
+fn lookup_type(
+    ty: Token<crate::Type>,
+    types: &Storage<crate::Type>,
+) -> Result<&crate::TypeInner, ResolveError> {
+    types
+        .get(ty)
+        .map(|ty| &ty.inner)
+        .ok_or(ResolveError::InvalidType(ty))
+}
+
+/// Everything an expression may refer to, outside of its own storage.
+pub struct ResolveContext<'a> {
+    pub types: &'a Storage<crate::Type>,
+    pub constants: &'a Storage<crate::Constant>,
+    pub global_vars: &'a Storage<crate::GlobalVariable>,
+    pub local_vars: &'a Storage<crate::LocalVariable>,
+    pub functions: &'a Storage<crate::Function>,
+    pub parameter_types: &'a [Token<crate::Type>],
+}
+
+/// Type resolution of all the expressions of a function.
+///
+/// Expressions are expected to only refer to the ones preceding them in the storage.
+#[derive(Debug, Default)]
+pub struct Typifier {
+    resolutions: Vec<Resolution>,
+}
+
+impl Typifier {
+    pub fn new() -> Self {
+        Typifier {
+            resolutions: Vec::new(),
+        }
+    }
+
+    /// Returns the type of a resolved expression.
+    ///
+    /// The type tokens are checked during the resolution, so `types`
+    /// has to be the storage the expressions were resolved against.
+    pub fn get<'a>(
+        &'a self,
+        expr_token: Token<crate::Expression>,
+        types: &'a Storage<crate::Type>,
+    ) -> &'a crate::TypeInner {
+        match self.resolutions[expr_token.index()] {
+            Resolution::Handle(ty) => &types[ty].inner,
+            Resolution::Value(ref inner) => inner,
+        }
+    }
+
+    /// Returns the type token of an expression, unless its type
+    /// only exists as a result of the expression.
+    pub fn get_handle(
+        &self,
+        expr_token: Token<crate::Expression>,
+    ) -> Option<Token<crate::Type>> {
+        match self.resolutions[expr_token.index()] {
+            Resolution::Handle(ty) => Some(ty),
+            Resolution::Value(_) => None,
+        }
+    }
+
+    pub fn resolve_all(
+        &mut self,
+        expressions: &Storage<crate::Expression>,
+        context: &ResolveContext,
+    ) -> Result<(), ResolveError> {
+        self.resolutions.clear();
+        self.resolve_new(expressions, context)
+    }
+
+    /// Resolves the expressions appended to the storage since the last resolution.
+    ///
+    /// This allows the types to be known while the expressions are being built.
+    pub fn resolve_new(
+        &mut self,
+        expressions: &Storage<crate::Expression>,
+        context: &ResolveContext,
+    ) -> Result<(), ResolveError> {
+        for (token, expression) in expressions.iter().skip(self.resolutions.len()) {
+            let resolution = self.resolve(token, expression, context)?;
+            if let Resolution::Handle(ty) = resolution {
+                lookup_type(ty, context.types)?;
+            }
+            self.resolutions.push(resolution);
+        }
+        Ok(())
+    }
+
+    fn lookup<'a>(
+        &'a self,
+        expr_token: Token<crate::Expression>,
+        current: Token<crate::Expression>,
+        types: &'a Storage<crate::Type>,
+    ) -> Result<&'a crate::TypeInner, ResolveError> {
+        match self.resolutions.get(expr_token.index()) {
+            Some(Resolution::Handle(ty)) => lookup_type(*ty, types),
+            Some(Resolution::Value(inner)) => Ok(inner),
+            None => Err(ResolveError::InvalidExpression(current)),
+        }
+    }
+
+    fn resolve_element(
+        base_inner: &crate::TypeInner,
+        index: Option<u32>,
+        current: Token<crate::Expression>,
+    ) -> Result<Resolution, ResolveError> {
+        Ok(match *base_inner {
+            crate::TypeInner::Vector { kind, width, .. } => {
+                Resolution::Value(crate::TypeInner::Scalar { kind, width })
+            }
+            crate::TypeInner::Matrix { rows, kind, width, .. } => {
+                Resolution::Value(crate::TypeInner::Vector { size: rows, kind, width })
+            }
+            crate::TypeInner::Array { base, .. } => Resolution::Handle(base),
+            crate::TypeInner::Struct { ref members } => match index {
+                Some(index) => {
+                    let member = members
+                        .get(index as usize)
+                        .ok_or(ResolveError::InvalidAccessIndex(index))?;
+                    Resolution::Handle(member.ty)
+                }
+                None => return Err(ResolveError::InvalidAccess(current)),
+            },
+            _ => return Err(ResolveError::InvalidAccess(current)),
+        })
+    }
+
+    fn resolve_access(
+        &self,
+        base: Token<crate::Expression>,
+        index: Option<u32>,
+        current: Token<crate::Expression>,
+        types: &Storage<crate::Type>,
+    ) -> Result<Resolution, ResolveError> {
+        match *self.lookup(base, current, types)? {
+            crate::TypeInner::Pointer { base: pointee, class } => {
+                // accessing through a pointer produces a pointer to the element,
+                // which has to point to a type known to the module
+                let element = match Self::resolve_element(lookup_type(pointee, types)?, index, current)? {
+                    Resolution::Handle(ty) => ty,
+                    Resolution::Value(inner) => types
+                        .iter()
+                        .find(|&(_, ty)| ty.inner == inner)
+                        .map(|(ty, _)| ty)
+                        .ok_or(ResolveError::MissingPointerType(current))?,
+                };
+                Ok(Resolution::Value(crate::TypeInner::Pointer { base: element, class }))
+            }
+            ref other => Self::resolve_element(other, index, current),
+        }
+    }
+
+    fn resolve(
+        &self,
+        current: Token<crate::Expression>,
+        expression: &crate::Expression,
+        context: &ResolveContext,
+    ) -> Result<Resolution, ResolveError> {
+        let types = context.types;
+        Ok(match *expression {
+            crate::Expression::Access { base, .. } => {
+                self.resolve_access(base, None, current, types)?
+            }
+            crate::Expression::AccessIndex { base, index } => {
+                self.resolve_access(base, Some(index), current, types)?
+            }
+            crate::Expression::Constant(token) => {
+                let constant = context.constants
+                    .get(token)
+                    .ok_or(ResolveError::InvalidConstant(token))?;
+                let (kind, width) = match constant.inner {
+                    crate::ConstantInner::Sint(_) => (crate::ScalarKind::Sint, 32),
+                    crate::ConstantInner::Uint(_) => (crate::ScalarKind::Uint, 32),
+                    crate::ConstantInner::Float(_) => (crate::ScalarKind::Float, 32),
+                    crate::ConstantInner::Bool(_) => (crate::ScalarKind::Bool, 1),
+                };
+                Resolution::Value(crate::TypeInner::Scalar { kind, width })
+            }
+            crate::Expression::Compose { ty, .. } => Resolution::Handle(ty),
+            crate::Expression::FunctionParameter(index) => {
+                let ty = context.parameter_types
+                    .get(index as usize)
+                    .ok_or(ResolveError::InvalidParameter(index))?;
+                Resolution::Handle(*ty)
+            }
+            crate::Expression::GlobalVariable(token) => {
+                let var = context.global_vars
+                    .get(token)
+                    .ok_or(ResolveError::InvalidGlobalVariable(token))?;
+                Resolution::Handle(var.ty)
+            }
+            crate::Expression::LocalVariable(token) => {
+                let var = context.local_vars
+                    .get(token)
+                    .ok_or(ResolveError::InvalidLocalVariable(token))?;
+                Resolution::Value(crate::TypeInner::Pointer {
+                    base: var.ty,
+                    class: spirv::StorageClass::Function,
+                })
+            }
+            crate::Expression::Load { pointer } => match *self.lookup(pointer, current, types)? {
+                crate::TypeInner::Pointer { base, .. } => Resolution::Handle(base),
+                _ => return Err(ResolveError::InvalidPointer(pointer)),
+            },
+            crate::Expression::ImageSample { image, .. } |
+            crate::Expression::ImageLoad { image, .. } => match *self.lookup(image, current, types)? {
+                // depth images produce a single value
+                crate::TypeInner::Image { base, flags, .. } if flags.contains(crate::ImageFlags::DEPTH) => {
+                    Resolution::Handle(base)
+                }
+                // others always produce 4 components of the sampled type
+                crate::TypeInner::Image { base, .. } => match *lookup_type(base, types)? {
+                    crate::TypeInner::Scalar { kind, width } => Resolution::Value(crate::TypeInner::Vector {
+                        size: crate::VectorSize::Quad,
+                        kind,
+                        width,
+                    }),
+                    _ => Resolution::Handle(base),
+                },
+                _ => return Err(ResolveError::InvalidImage(image)),
+            },
+            crate::Expression::ImageDimensions { image, .. } => match *self.lookup(image, current, types)? {
+                crate::TypeInner::Image { dim, .. } => {
+                    let kind = crate::ScalarKind::Sint;
+                    let width = 32;
+                    Resolution::Value(match dim {
+                        spirv::Dim::Dim1D => crate::TypeInner::Scalar { kind, width },
+                        spirv::Dim::Dim3D => crate::TypeInner::Vector { size: crate::VectorSize::Tri, kind, width },
+                        _ => crate::TypeInner::Vector { size: crate::VectorSize::Bi, kind, width },
+                    })
+                }
+                _ => return Err(ResolveError::InvalidImage(image)),
+            },
+            crate::Expression::Unary { expr, .. } => self.clone_resolution(expr, current)?,
+            crate::Expression::Binary { op, left, right } => {
+                let left_inner = self.lookup(left, current, types)?;
+                let right_inner = self.lookup(right, current, types)?;
+                match op {
+                    crate::BinaryOperator::Equal |
+                    crate::BinaryOperator::NotEqual |
+                    crate::BinaryOperator::Less |
+                    crate::BinaryOperator::LessEqual |
+                    crate::BinaryOperator::Greater |
+                    crate::BinaryOperator::GreaterEqual |
+                    crate::BinaryOperator::LogicalAnd |
+                    crate::BinaryOperator::LogicalOr => {
+                        let kind = crate::ScalarKind::Bool;
+                        let width = 1;
+                        Resolution::Value(match *left_inner {
+                            crate::TypeInner::Vector { size, .. } => crate::TypeInner::Vector { size, kind, width },
+                            _ => crate::TypeInner::Scalar { kind, width },
+                        })
+                    }
+                    crate::BinaryOperator::Multiply => match (left_inner, right_inner) {
+                        (&crate::TypeInner::Scalar { .. }, &crate::TypeInner::Vector { .. }) |
+                        (&crate::TypeInner::Scalar { .. }, &crate::TypeInner::Matrix { .. }) => {
+                            self.clone_resolution(right, current)?
+                        }
+                        (&crate::TypeInner::Matrix { columns, rows, kind, width }, &crate::TypeInner::Vector { size, .. }) => {
+                            if columns != size {
+                                return Err(ResolveError::IncompatibleOperands(op));
+                            }
+                            Resolution::Value(crate::TypeInner::Vector { size: rows, kind, width })
+                        }
+                        (&crate::TypeInner::Vector { size, .. }, &crate::TypeInner::Matrix { columns, rows, kind, width }) => {
+                            if rows != size {
+                                return Err(ResolveError::IncompatibleOperands(op));
+                            }
+                            Resolution::Value(crate::TypeInner::Vector { size: columns, kind, width })
+                        }
+                        (&crate::TypeInner::Matrix { columns: left_columns, rows, kind, width },
+                         &crate::TypeInner::Matrix { columns, rows: right_rows, .. }) => {
+                            if left_columns != right_rows {
+                                return Err(ResolveError::IncompatibleOperands(op));
+                            }
+                            Resolution::Value(crate::TypeInner::Matrix { columns, rows, kind, width })
+                        }
+                        _ => self.clone_resolution(left, current)?,
+                    },
+                    _ => self.clone_resolution(left, current)?,
+                }
+            }
+            crate::Expression::Call { function, .. } => {
+                let fun = context.functions
+                    .get(function)
+                    .ok_or(ResolveError::InvalidFunction(function))?;
+                match fun.return_type {
+                    Some(ty) => Resolution::Handle(ty),
+                    None => return Err(ResolveError::FunctionReturnsVoid(function)),
+                }
+            }
+            crate::Expression::Math { fun, ref arguments } => {
+                let first = *arguments
+                    .first()
+                    .ok_or(ResolveError::InvalidExpression(current))?;
+                match fun {
+                    crate::MathFunction::Length |
+                    crate::MathFunction::Distance |
+                    crate::MathFunction::Determinant => match *self.lookup(first, current, types)? {
+                        crate::TypeInner::Scalar { kind, width } |
+                        crate::TypeInner::Vector { kind, width, .. } |
+                        crate::TypeInner::Matrix { kind, width, .. } => {
+                            Resolution::Value(crate::TypeInner::Scalar { kind, width })
+                        }
+                        _ => return Err(ResolveError::InvalidMathArgument(first)),
+                    },
+                    crate::MathFunction::PackSnorm4x8 |
+                    crate::MathFunction::PackUnorm4x8 |
+                    crate::MathFunction::PackSnorm2x16 |
+                    crate::MathFunction::PackUnorm2x16 |
+                    crate::MathFunction::PackHalf2x16 => Resolution::Value(crate::TypeInner::Scalar {
+                        kind: crate::ScalarKind::Uint,
+                        width: 32,
+                    }),
+                    crate::MathFunction::UnpackSnorm2x16 |
+                    crate::MathFunction::UnpackUnorm2x16 |
+                    crate::MathFunction::UnpackHalf2x16 => Resolution::Value(crate::TypeInner::Vector {
+                        size: crate::VectorSize::Bi,
+                        kind: crate::ScalarKind::Float,
+                        width: 32,
+                    }),
+                    crate::MathFunction::UnpackSnorm4x8 |
+                    crate::MathFunction::UnpackUnorm4x8 => Resolution::Value(crate::TypeInner::Vector {
+                        size: crate::VectorSize::Quad,
+                        kind: crate::ScalarKind::Float,
+                        width: 32,
+                    }),
+                    // the edges may be scalars, the last argument defines the shape
+                    crate::MathFunction::Step |
+                    crate::MathFunction::SmoothStep => {
+                        self.clone_resolution(*arguments.last().unwrap(), current)?
+                    }
+                    _ => self.clone_resolution(first, current)?,
+                }
+            }
+        })
+    }
+
+    fn clone_resolution(
+        &self,
+        expr_token: Token<crate::Expression>,
+        current: Token<crate::Expression>,
+    ) -> Result<Resolution, ResolveError> {
+        self.resolutions
+            .get(expr_token.index())
+            .cloned()
+            .ok_or(ResolveError::InvalidExpression(current))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_binary() {
+        let mut types = Storage::new();
+        let vec2 = types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Vector {
+                size: crate::VectorSize::Bi,
+                kind: crate::ScalarKind::Float,
+                width: 32,
+            },
+        });
+        let mat3x2 = types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Matrix {
+                columns: crate::VectorSize::Tri,
+                rows: crate::VectorSize::Bi,
+                kind: crate::ScalarKind::Float,
+                width: 32,
+            },
+        });
+        let mut constants = Storage::new();
+        let one = constants.append(crate::Constant {
+            name: None,
+            specialization: None,
+            inner: crate::ConstantInner::Float(1.0),
+        });
+        let parameter_types = [vec2, mat3x2];
+        let context = ResolveContext {
+            types: &types,
+            constants: &constants,
+            global_vars: &Storage::new(),
+            local_vars: &Storage::new(),
+            functions: &Storage::new(),
+            parameter_types: &parameter_types,
+        };
+
+        let mut expressions = Storage::new();
+        let vector = expressions.append(crate::Expression::FunctionParameter(0));
+        let matrix = expressions.append(crate::Expression::FunctionParameter(1));
+        let scalar = expressions.append(crate::Expression::Constant(one));
+        let scaled = expressions.append(crate::Expression::Binary {
+            op: crate::BinaryOperator::Multiply,
+            left: scalar,
+            right: vector,
+        });
+        let product = expressions.append(crate::Expression::Binary {
+            op: crate::BinaryOperator::Multiply,
+            left: vector,
+            right: matrix,
+        });
+        let compare = expressions.append(crate::Expression::Binary {
+            op: crate::BinaryOperator::Less,
+            left: vector,
+            right: scaled,
+        });
+        let mut typifier = Typifier::new();
+        typifier.resolve_all(&expressions, &context).unwrap();
+        assert_eq!(typifier.get(scaled, &types), &types[vec2].inner);
+        assert_eq!(typifier.get(product, &types), &crate::TypeInner::Vector {
+            size: crate::VectorSize::Tri,
+            kind: crate::ScalarKind::Float,
+            width: 32,
+        });
+        assert_eq!(typifier.get(compare, &types), &crate::TypeInner::Vector {
+            size: crate::VectorSize::Bi,
+            kind: crate::ScalarKind::Bool,
+            width: 1,
+        });
+
+        expressions.append(crate::Expression::Binary {
+            op: crate::BinaryOperator::Multiply,
+            left: matrix,
+            right: matrix,
+        });
+        assert_eq!(
+            typifier.resolve_all(&expressions, &context),
+            Err(ResolveError::IncompatibleOperands(crate::BinaryOperator::Multiply)),
+        );
+    }
+
+    #[test]
+    fn resolve_invalid_references() {
+        let types = Storage::new();
+        let context = ResolveContext {
+            types: &types,
+            constants: &Storage::new(),
+            global_vars: &Storage::new(),
+            local_vars: &Storage::new(),
+            functions: &Storage::new(),
+            parameter_types: &[],
+        };
+        // tokens of another module, which has more entities
+        let mut constants = Storage::new();
+        let constant = constants.append(crate::Constant {
+            name: None,
+            specialization: None,
+            inner: crate::ConstantInner::Bool(true),
+        });
+        let mut functions = Storage::new();
+        let function = functions.append(crate::Function {
+            name: None,
+            control: spirv::FunctionControl::empty(),
+            parameter_types: Vec::new(),
+            return_type: None,
+            local_variables: Storage::new(),
+            expressions: Storage::new(),
+            body: Vec::new(),
+            spans: Default::default(),
+        });
+
+        let mut expressions = Storage::new();
+        expressions.append(crate::Expression::Constant(constant));
+        let mut typifier = Typifier::new();
+        assert_eq!(
+            typifier.resolve_all(&expressions, &context),
+            Err(ResolveError::InvalidConstant(constant)),
+        );
+        let mut expressions = Storage::new();
+        expressions.append(crate::Expression::Call { function, arguments: Vec::new() });
+        assert_eq!(
+            typifier.resolve_all(&expressions, &context),
+            Err(ResolveError::InvalidFunction(function)),
+        );
+
+        // a type token of another module
+        let mut other_types = Storage::new();
+        let dangling = other_types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 },
+        });
+        let mut expressions = Storage::new();
+        expressions.append(crate::Expression::Compose { ty: dangling, components: Vec::new() });
+        assert_eq!(
+            typifier.resolve_all(&expressions, &context),
+            Err(ResolveError::InvalidType(dangling)),
+        );
+    }
+}
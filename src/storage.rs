@@ -13,17 +13,18 @@ use std::{
 type Index = u32;
 
 /// A strongly typed reference to a SPIR-V element.
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(any(feature = "serialize", feature = "deserialize"), serde(transparent))]
 pub struct Token<T> {
     index: Index,
+    #[cfg_attr(any(feature = "serialize", feature = "deserialize"), serde(skip))]
     marker: PhantomData<T>,
 }
 
 impl<T> Clone for Token<T> {
     fn clone(&self) -> Self {
-        Token {
-            index: self.index,
-            marker: self.marker,
-        }
+        *self
     }
 }
 impl<T> Copy for Token<T> {}
@@ -66,6 +67,9 @@ impl<T> Token<T> {
 /// A structure holding some kind of SPIR-V entity (e.g., type, constant,
 /// instruction, etc.) that can be referenced.
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(any(feature = "serialize", feature = "deserialize"), serde(transparent))]
 pub struct Storage<T> {
     /// Values of this storage.
     data: Vec<T>,
@@ -78,13 +82,33 @@ impl<T> Storage<T> {
         }
     }
 
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Token<T>, &'a T)> {
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the value pointed to by the token, if it's in range.
+    pub fn get(&self, token: Token<T>) -> Option<&T> {
+        self.data.get(token.index as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Token<T>, &T)> {
         self.data
             .iter()
             .enumerate()
             .map(|(i, v)| (Token::new(i as Index), v))
     }
 
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Token<T>, &mut T)> {
+        self.data
+            .iter_mut()
+            .enumerate()
+            .map(|(i, v)| (Token::new(i as Index), v))
+    }
+
     /// Adds a new value to the storage, returning a typed token.
     ///
     /// The value is not linked to any SPIR-V module.
@@ -113,6 +137,12 @@ impl<T> std::ops::Index<Token<T>> for Storage<T> {
     }
 }
 
+impl<T> std::ops::IndexMut<Token<T>> for Storage<T> {
+    fn index_mut(&mut self, token: Token<T>) -> &mut T {
+        &mut self.data[token.index as usize]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,8 +159,8 @@ mod tests {
     #[test]
     fn append_unique() {
         let mut storage: Storage<f64> = Storage::new();
-        let t1 = storage.append(std::f64::NAN);
-        let t2 = storage.append(std::f64::NAN);
+        let t1 = storage.append(f64::NAN);
+        let t2 = storage.append(f64::NAN);
         assert!(t1 != t2);
         assert!(storage[t1] != storage[t2]);
     }
@@ -147,9 +177,28 @@ mod tests {
     #[test]
     fn fetch_or_append_unique() {
         let mut storage: Storage<f64> = Storage::new();
-        let t1 = storage.fetch_or_append(std::f64::NAN);
-        let t2 = storage.fetch_or_append(std::f64::NAN);
+        let t1 = storage.fetch_or_append(f64::NAN);
+        let t2 = storage.fetch_or_append(f64::NAN);
         assert!(t1 != t2);
         assert!(storage[t1] != storage[t2]);
     }
+
+    #[cfg(all(feature = "serialize", feature = "deserialize"))]
+    #[test]
+    fn serialize_module() {
+        let module = crate::front::wgsl::parse_str("
+            [[location 0]] var<in> a_pos : vec2<f32>;
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            fn main() -> void {
+                o_position = vec4<f32>(a_pos, 0.0, 1.0);
+                return;
+            }
+            entry_point vertex as \"main\" = main;
+        ").unwrap();
+        let text = ron::ser::to_string(&module).unwrap();
+        // tokens are stored as plain indices
+        assert!(text.contains("Compose(ty:2,components:[0,2,3])"));
+        let loaded: crate::Module = ron::de::from_str(&text).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", module));
+    }
 }
@@ -0,0 +1,721 @@
+//! Validation of the IR module.
+//!
+//! The checks are meant to run between a frontend and a backend,
+//! so that backends can rely on the module being well-formed.
+
+use crate::{
+    proc::{ResolveContext, ResolveError, Typifier},
+    storage::{Storage, Token},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FunctionError {
+    InvalidExpression(Token<crate::Expression>),
+    InvalidLocalVariable(Token<crate::LocalVariable>),
+    Resolve(ResolveError),
+    InvalidAccessIndex(Token<crate::Expression>),
+    InvalidStorePointer(Token<crate::Expression>),
+    InvalidStoreValue(Token<crate::Expression>),
+    InvalidCondition(Token<crate::Expression>),
+    InvalidSelector(Token<crate::Expression>),
+    /// The image can't be accessed this way.
+    InvalidImage(Token<crate::Expression>),
+    /// The statements are referred to by their index in `FunctionSpans::statements`.
+    InvalidReturnValue(usize),
+    InvalidCall(Token<crate::Function>),
+    BreakOutsideLoop(usize),
+    ContinueOutsideLoop(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntryPointError {
+    InvalidSignature,
+    InvalidInput(Token<crate::GlobalVariable>),
+    InvalidOutput(Token<crate::GlobalVariable>),
+    /// Another input, or output, of the entry point has the same binding.
+    DuplicateBinding(Token<crate::GlobalVariable>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationError {
+    InvalidType(Token<crate::Type>),
+    InvalidConstant(Token<crate::Constant>),
+    InvalidGlobalVariable(Token<crate::GlobalVariable>),
+    InvalidFunction(Token<crate::Function>),
+    InvalidBinding(Token<crate::GlobalVariable>),
+    DuplicateBinding(Token<crate::GlobalVariable>),
+    Function(Token<crate::Function>, FunctionError),
+    EntryPoint(usize, EntryPointError),
+}
+
+impl FunctionError {
+    /// Returns the span of the function entity that the error is about.
+    pub fn span(&self, fun: &crate::Function) -> crate::Span {
+        match *self {
+            FunctionError::InvalidExpression(token) |
+            FunctionError::InvalidAccessIndex(token) |
+            FunctionError::InvalidStorePointer(token) |
+            FunctionError::InvalidStoreValue(token) |
+            FunctionError::InvalidCondition(token) |
+            FunctionError::InvalidSelector(token) |
+            FunctionError::InvalidImage(token) => fun.spans.expression(token),
+            FunctionError::InvalidReturnValue(index) |
+            FunctionError::BreakOutsideLoop(index) |
+            FunctionError::ContinueOutsideLoop(index) => fun.spans.statement(index),
+            FunctionError::InvalidLocalVariable(_) |
+            FunctionError::Resolve(_) |
+            FunctionError::InvalidCall(_) => crate::Span::default(),
+        }
+    }
+}
+
+impl ValidationError {
+    /// Returns the span of the module entity that the error is about.
+    ///
+    /// Errors within a function fall back to the span of the function
+    /// if the entity isn't located.
+    pub fn span(&self, module: &crate::Module) -> crate::Span {
+        let function_span = |token: Token<crate::Function>| module.spans.function(token);
+        match *self {
+            ValidationError::InvalidType(_) |
+            ValidationError::InvalidConstant(_) => crate::Span::default(),
+            ValidationError::InvalidGlobalVariable(token) |
+            ValidationError::InvalidBinding(token) |
+            ValidationError::DuplicateBinding(token) => module.spans.global_variable(token),
+            ValidationError::InvalidFunction(token) => function_span(token),
+            ValidationError::Function(token, ref error) => {
+                let span = match module.functions.get(token) {
+                    Some(fun) => error.span(fun),
+                    None => crate::Span::default(),
+                };
+                if span.is_unknown() { function_span(token) } else { span }
+            }
+            ValidationError::EntryPoint(index, ref error) => {
+                let ep = &module.entry_points[index];
+                match *error {
+                    EntryPointError::InvalidSignature => function_span(ep.function),
+                    EntryPointError::InvalidInput(token) |
+                    EntryPointError::InvalidOutput(token) |
+                    EntryPointError::DuplicateBinding(token) => module.spans.global_variable(token),
+                }
+            }
+        }
+    }
+}
+
+impl From<ResolveError> for FunctionError {
+    fn from(error: ResolveError) -> Self {
+        FunctionError::Resolve(error)
+    }
+}
+
+fn check_token<T>(
+    token: Token<T>,
+    storage: &Storage<T>,
+) -> Result<(), Token<T>> {
+    if token.index() < storage.len() {
+        Ok(())
+    } else {
+        Err(token)
+    }
+}
+
+/// Flow constructs that a statement is nested in.
+#[derive(Clone, Copy, Default)]
+struct BlockContext {
+    in_loop: bool,
+    in_switch: bool,
+}
+
+pub struct Validator {
+    typifier: Typifier,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Validator {
+            typifier: Typifier::new(),
+        }
+    }
+
+    fn validate_types(module: &crate::Module) -> Result<(), ValidationError> {
+        for (_, ty) in module.types.iter() {
+            match ty.inner {
+                crate::TypeInner::Scalar { .. } |
+                crate::TypeInner::Vector { .. } |
+                crate::TypeInner::Matrix { .. } |
+                crate::TypeInner::Sampler { .. } => (),
+                crate::TypeInner::Pointer { base, .. } |
+                crate::TypeInner::Array { base, .. } |
+                crate::TypeInner::Image { base, .. } => {
+                    check_token(base, &module.types).map_err(ValidationError::InvalidType)?;
+                }
+                crate::TypeInner::Struct { ref members } => {
+                    for member in members {
+                        check_token(member.ty, &module.types).map_err(ValidationError::InvalidType)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_global_variables(module: &crate::Module) -> Result<(), ValidationError> {
+        let mut bindings = Vec::new();
+        for (token, var) in module.global_variables.iter() {
+            check_token(var.ty, &module.types).map_err(ValidationError::InvalidType)?;
+            let binding = match var.binding {
+                Some(ref binding) => binding,
+                None => continue,
+            };
+            let is_interface = var.class == spirv::StorageClass::Input ||
+                var.class == spirv::StorageClass::Output;
+            match *binding {
+                // interface bindings are only unique within an entry point
+                crate::Binding::BuiltIn(_) |
+                crate::Binding::Location(_) if is_interface => (),
+                // descriptors are shared between all the resource classes
+                crate::Binding::Descriptor { .. } if !is_interface => {
+                    if bindings.contains(&binding) {
+                        return Err(ValidationError::DuplicateBinding(token));
+                    }
+                    bindings.push(binding);
+                }
+                _ => return Err(ValidationError::InvalidBinding(token)),
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_expression_tokens(
+        fun: &crate::Function,
+        module: &crate::Module,
+    ) -> Result<(), FunctionError> {
+        for (current, expression) in fun.expressions.iter() {
+            // expressions may only refer to the ones defined before them
+            let check_expr = |token: Token<crate::Expression>| {
+                if token.index() < current.index() {
+                    Ok(())
+                } else {
+                    Err(FunctionError::InvalidExpression(current))
+                }
+            };
+            match *expression {
+                crate::Expression::Access { base, index } => {
+                    check_expr(base)?;
+                    check_expr(index)?;
+                }
+                crate::Expression::AccessIndex { base, .. } => check_expr(base)?,
+                crate::Expression::Constant(token) => {
+                    check_token(token, &module.constants).map_err(|_| FunctionError::InvalidExpression(current))?;
+                }
+                crate::Expression::Compose { ty, ref components } => {
+                    check_token(ty, &module.types).map_err(|_| FunctionError::InvalidExpression(current))?;
+                    for &component in components {
+                        check_expr(component)?;
+                    }
+                }
+                crate::Expression::FunctionParameter(index) => {
+                    if index as usize >= fun.parameter_types.len() {
+                        return Err(FunctionError::InvalidExpression(current));
+                    }
+                }
+                crate::Expression::GlobalVariable(token) => {
+                    check_token(token, &module.global_variables).map_err(|_| FunctionError::InvalidExpression(current))?;
+                }
+                crate::Expression::LocalVariable(token) => {
+                    check_token(token, &fun.local_variables).map_err(FunctionError::InvalidLocalVariable)?;
+                }
+                crate::Expression::Load { pointer } => check_expr(pointer)?,
+                crate::Expression::ImageSample { image, sampler, coordinate } => {
+                    check_expr(image)?;
+                    check_expr(sampler)?;
+                    check_expr(coordinate)?;
+                }
+                crate::Expression::ImageLoad { image, coordinate, index } => {
+                    check_expr(image)?;
+                    check_expr(coordinate)?;
+                    if let Some(index) = index {
+                        check_expr(index)?;
+                    }
+                }
+                crate::Expression::ImageDimensions { image, level } => {
+                    check_expr(image)?;
+                    if let Some(level) = level {
+                        check_expr(level)?;
+                    }
+                }
+                crate::Expression::Unary { expr, .. } => check_expr(expr)?,
+                crate::Expression::Binary { left, right, .. } => {
+                    check_expr(left)?;
+                    check_expr(right)?;
+                }
+                crate::Expression::Call { function, ref arguments } => {
+                    check_token(function, &module.functions).map_err(|_| FunctionError::InvalidExpression(current))?;
+                    for &argument in arguments {
+                        check_expr(argument)?;
+                    }
+                }
+                crate::Expression::Math { fun: math_fun, ref arguments } => {
+                    if arguments.len() != math_fun.argument_count() {
+                        return Err(FunctionError::InvalidExpression(current));
+                    }
+                    for &argument in arguments {
+                        check_expr(argument)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_expressions(
+        &self,
+        fun: &crate::Function,
+        module: &crate::Module,
+    ) -> Result<(), FunctionError> {
+        for (token, expression) in fun.expressions.iter() {
+            match *expression {
+                crate::Expression::AccessIndex { base, index } => {
+                    let base_inner = match *self.typifier.get(base, &module.types) {
+                        crate::TypeInner::Pointer { base, .. } => &module.types[base].inner,
+                        ref other => other,
+                    };
+                    let limit = match *base_inner {
+                        crate::TypeInner::Vector { size, .. } => size as u32,
+                        crate::TypeInner::Matrix { columns, .. } => columns as u32,
+                        crate::TypeInner::Array { size: crate::ArraySize::Static(length), .. } => length,
+                        crate::TypeInner::Array { size: crate::ArraySize::Dynamic, .. } => !0,
+                        crate::TypeInner::Struct { ref members } => members.len() as u32,
+                        _ => 0,
+                    };
+                    if index >= limit {
+                        return Err(FunctionError::InvalidAccessIndex(token));
+                    }
+                }
+                crate::Expression::ImageLoad { image, index, .. } => {
+                    let flags = self.image_flags(image, module).ok_or(FunctionError::InvalidImage(image))?;
+                    // storage images are the only ones without levels or samples
+                    let valid = if flags.contains(crate::ImageFlags::SAMPLED) {
+                        index.is_some()
+                    } else {
+                        flags.contains(crate::ImageFlags::CAN_LOAD) && index.is_none()
+                    };
+                    if !valid {
+                        return Err(FunctionError::InvalidImage(token));
+                    }
+                }
+                crate::Expression::ImageDimensions { image, level } => {
+                    let flags = self.image_flags(image, module).ok_or(FunctionError::InvalidImage(image))?;
+                    let has_levels = flags.contains(crate::ImageFlags::SAMPLED) &&
+                        !flags.contains(crate::ImageFlags::MULTISAMPLED);
+                    if level.is_some() && !has_levels {
+                        return Err(FunctionError::InvalidImage(token));
+                    }
+                }
+                crate::Expression::Call { function, ref arguments } => {
+                    let callee = &module.functions[function];
+                    if callee.return_type.is_none() {
+                        return Err(FunctionError::InvalidCall(function));
+                    }
+                    self.validate_call_arguments(function, arguments, module)?;
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn image_flags(&self, image: Token<crate::Expression>, module: &crate::Module) -> Option<crate::ImageFlags> {
+        match *self.typifier.get(image, &module.types) {
+            crate::TypeInner::Image { flags, .. } => Some(flags),
+            _ => None,
+        }
+    }
+
+    fn validate_call_arguments(
+        &self,
+        function: Token<crate::Function>,
+        arguments: &[Token<crate::Expression>],
+        module: &crate::Module,
+    ) -> Result<(), FunctionError> {
+        let callee = &module.functions[function];
+        if callee.parameter_types.len() != arguments.len() {
+            return Err(FunctionError::InvalidCall(function));
+        }
+        for (&argument, &ty) in arguments.iter().zip(callee.parameter_types.iter()) {
+            if self.typifier.get(argument, &module.types) != &module.types[ty].inner {
+                return Err(FunctionError::InvalidCall(function));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_block(
+        &self,
+        block: &[crate::Statement],
+        context: BlockContext,
+        index: &mut usize,
+        fun: &crate::Function,
+        module: &crate::Module,
+    ) -> Result<(), FunctionError> {
+        let check_expr = |token: Token<crate::Expression>| {
+            check_token(token, &fun.expressions).map_err(FunctionError::InvalidExpression)
+        };
+        for statement in block {
+            let current = *index;
+            *index += 1;
+            match *statement {
+                crate::Statement::Block(ref inner) => {
+                    self.validate_block(inner, context, index, fun, module)?;
+                }
+                crate::Statement::If { condition, ref accept, ref reject } => {
+                    check_expr(condition)?;
+                    match *self.typifier.get(condition, &module.types) {
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Bool, .. } => (),
+                        _ => return Err(FunctionError::InvalidCondition(condition)),
+                    }
+                    self.validate_block(accept, context, index, fun, module)?;
+                    self.validate_block(reject, context, index, fun, module)?;
+                }
+                crate::Statement::Switch { selector, ref cases, ref default } => {
+                    check_expr(selector)?;
+                    match *self.typifier.get(selector, &module.types) {
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Sint, .. } |
+                        crate::TypeInner::Scalar { kind: crate::ScalarKind::Uint, .. } => (),
+                        _ => return Err(FunctionError::InvalidSelector(selector)),
+                    }
+                    let case_context = BlockContext { in_switch: true, ..context };
+                    for (_, case, _) in cases {
+                        self.validate_block(case, case_context, index, fun, module)?;
+                    }
+                    self.validate_block(default, case_context, index, fun, module)?;
+                }
+                crate::Statement::Loop { ref body, ref continuing } => {
+                    let loop_context = BlockContext { in_loop: true, in_switch: false };
+                    self.validate_block(body, loop_context, index, fun, module)?;
+                    self.validate_block(continuing, loop_context, index, fun, module)?;
+                }
+                crate::Statement::Break => {
+                    if !context.in_loop && !context.in_switch {
+                        return Err(FunctionError::BreakOutsideLoop(current));
+                    }
+                }
+                crate::Statement::Continue => {
+                    if !context.in_loop {
+                        return Err(FunctionError::ContinueOutsideLoop(current));
+                    }
+                }
+                crate::Statement::Return { value } => {
+                    match (value, fun.return_type) {
+                        (None, None) => (),
+                        (Some(value), Some(ty)) => {
+                            check_expr(value)?;
+                            if self.typifier.get(value, &module.types) != &module.types[ty].inner {
+                                return Err(FunctionError::InvalidReturnValue(current));
+                            }
+                        }
+                        _ => return Err(FunctionError::InvalidReturnValue(current)),
+                    }
+                }
+                crate::Statement::Kill => (),
+                crate::Statement::Store { pointer, value } => {
+                    check_expr(pointer)?;
+                    check_expr(value)?;
+                    let expected = match *self.typifier.get(pointer, &module.types) {
+                        crate::TypeInner::Pointer { base, .. } => &module.types[base].inner,
+                        // global variables are references by themselves
+                        ref other if is_global_reference(&fun.expressions, pointer) => other,
+                        _ => return Err(FunctionError::InvalidStorePointer(pointer)),
+                    };
+                    if self.typifier.get(value, &module.types) != expected {
+                        return Err(FunctionError::InvalidStoreValue(value));
+                    }
+                }
+                crate::Statement::ImageStore { image, coordinate, value } => {
+                    check_expr(image)?;
+                    check_expr(coordinate)?;
+                    check_expr(value)?;
+                    match self.image_flags(image, module) {
+                        Some(flags) if flags.contains(crate::ImageFlags::CAN_STORE) => (),
+                        _ => return Err(FunctionError::InvalidImage(image)),
+                    }
+                }
+                crate::Statement::Call { function, ref arguments } => {
+                    check_token(function, &module.functions).map_err(FunctionError::InvalidCall)?;
+                    for &argument in arguments {
+                        check_expr(argument)?;
+                    }
+                    if module.functions[function].return_type.is_some() {
+                        return Err(FunctionError::InvalidCall(function));
+                    }
+                    self.validate_call_arguments(function, arguments, module)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_function(
+        &mut self,
+        fun: &crate::Function,
+        module: &crate::Module,
+    ) -> Result<(), FunctionError> {
+        for (index, &ty) in fun.parameter_types.iter().enumerate() {
+            check_token(ty, &module.types)
+                .map_err(|_| FunctionError::Resolve(ResolveError::InvalidParameter(index as u32)))?;
+        }
+        for (token, var) in fun.local_variables.iter() {
+            check_token(var.ty, &module.types).map_err(|_| FunctionError::InvalidLocalVariable(token))?;
+            if let Some(init) = var.init {
+                check_token(init, &module.constants).map_err(|_| FunctionError::InvalidLocalVariable(token))?;
+            }
+        }
+        Self::validate_expression_tokens(fun, module)?;
+        self.typifier.resolve_all(&fun.expressions, &ResolveContext {
+            types: &module.types,
+            constants: &module.constants,
+            global_vars: &module.global_variables,
+            local_vars: &fun.local_variables,
+            functions: &module.functions,
+            parameter_types: &fun.parameter_types,
+        })?;
+        self.validate_expressions(fun, module)?;
+        self.validate_block(&fun.body, BlockContext::default(), &mut 0, fun, module)
+    }
+
+    fn validate_entry_point(
+        ep: &crate::EntryPoint,
+        module: &crate::Module,
+    ) -> Result<(), EntryPointError> {
+        let fun = &module.functions[ep.function];
+        if !fun.parameter_types.is_empty() || fun.return_type.is_some() {
+            return Err(EntryPointError::InvalidSignature);
+        }
+        let mut bindings = Vec::new();
+        for &token in ep.inputs.iter() {
+            match module.global_variables.get(token) {
+                Some(var) if var.class == spirv::StorageClass::Input => {
+                    if let Some(ref binding) = var.binding {
+                        if bindings.contains(&binding) {
+                            return Err(EntryPointError::DuplicateBinding(token));
+                        }
+                        bindings.push(binding);
+                    }
+                }
+                _ => return Err(EntryPointError::InvalidInput(token)),
+            }
+        }
+        bindings.clear();
+        for &token in ep.outputs.iter() {
+            match module.global_variables.get(token) {
+                Some(var) if var.class == spirv::StorageClass::Output => {
+                    if let Some(ref binding) = var.binding {
+                        if bindings.contains(&binding) {
+                            return Err(EntryPointError::DuplicateBinding(token));
+                        }
+                        bindings.push(binding);
+                    }
+                }
+                _ => return Err(EntryPointError::InvalidOutput(token)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the given module to be valid.
+    pub fn validate(&mut self, module: &crate::Module) -> Result<(), ValidationError> {
+        Self::validate_types(module)?;
+        Self::validate_global_variables(module)?;
+
+        for (token, fun) in module.functions.iter() {
+            if let Some(ty) = fun.return_type {
+                check_token(ty, &module.types).map_err(ValidationError::InvalidType)?;
+            }
+            self.validate_function(fun, module)
+                .map_err(|e| ValidationError::Function(token, e))?;
+        }
+
+        for (index, ep) in module.entry_points.iter().enumerate() {
+            check_token(ep.function, &module.functions).map_err(ValidationError::InvalidFunction)?;
+            Self::validate_entry_point(ep, module)
+                .map_err(|e| ValidationError::EntryPoint(index, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_global_reference(
+    expressions: &Storage<crate::Expression>,
+    mut token: Token<crate::Expression>,
+) -> bool {
+    loop {
+        match expressions[token] {
+            crate::Expression::GlobalVariable(_) => return true,
+            crate::Expression::Access { base, .. } |
+            crate::Expression::AccessIndex { base, .. } => token = base,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn empty_function() -> crate::Function {
+        crate::Function {
+            name: None,
+            control: spirv::FunctionControl::NONE,
+            parameter_types: Vec::new(),
+            return_type: None,
+            local_variables: Storage::new(),
+            expressions: Storage::new(),
+            body: Vec::new(),
+            spans: Default::default(),
+        }
+    }
+
+    #[test]
+    fn validate_parsed() {
+        let module = crate::front::wgsl::parse_str("
+            [[location 0]] var<out> color : vec4<f32>;
+            fn main() -> void {
+                var a : f32 = 1.0;
+                a = a * 2.0;
+                color = vec4<f32>(a, a, a, 1.0);
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap();
+        assert_eq!(Validator::new().validate(&module), Ok(()));
+    }
+
+    #[test]
+    fn error_span() {
+        let source = "
+            fn main() -> f32 {
+                var a : f32 = 1.0;
+                return;
+            }
+        ";
+        let module = crate::front::wgsl::parse_str(source).unwrap();
+        let error = Validator::new().validate(&module).unwrap_err();
+        assert_eq!(&source[error.span(&module).to_range()], "return;");
+    }
+
+    #[test]
+    fn duplicate_binding() {
+        let mut module = crate::Module::generate_empty();
+        let ty = module.types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Scalar { kind: crate::ScalarKind::Float, width: 32 },
+        });
+        let binding = Some(crate::Binding::Descriptor { set: 0, binding: 1 });
+        module.global_variables.append(crate::GlobalVariable {
+            name: None,
+            class: spirv::StorageClass::Uniform,
+            binding: binding.clone(),
+            ty,
+        });
+        let second = module.global_variables.append(crate::GlobalVariable {
+            name: None,
+            class: spirv::StorageClass::StorageBuffer,
+            binding,
+            ty,
+        });
+        assert_eq!(
+            Validator::new().validate(&module),
+            Err(ValidationError::DuplicateBinding(second)),
+        );
+    }
+
+    #[test]
+    fn interface_bindings() {
+        let source = "
+            [[location 0]] var<in> a_pos : vec2<f32>;
+            [[location 0]] var<out> v_uv : vec2<f32>;
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            fn vs_main() -> void {
+                v_uv = a_pos;
+                o_position = vec4<f32>(a_pos, 0.0, 1.0);
+                return;
+            }
+            entry_point vertex as \"main\" = vs_main;
+            [[builtin position]] var<out> o_shadow_position : vec4<f32>;
+            fn shadow_main() -> void {
+                o_shadow_position = vec4<f32>(a_pos, 0.0, 1.0);
+                return;
+            }
+            entry_point vertex as \"shadow\" = shadow_main;
+            [[location 0]] var<in> f_uv : vec2<f32>;
+            [[location 0]] var<out> o_color : vec4<f32>;
+            fn fs_main() -> void {
+                o_color = vec4<f32>(f_uv, 0.0, 1.0);
+                return;
+            }
+            entry_point fragment as \"main\" = fs_main;
+        ";
+        let mut module = crate::front::wgsl::parse_str(source).unwrap();
+        let globals = module.global_variables.iter().map(|(token, _)| token).collect::<Vec<_>>();
+        let interfaces = [(&[0][..], &[1, 2][..]), (&[0], &[3]), (&[4], &[5])];
+        for (ep, &(inputs, outputs)) in module.entry_points.iter_mut().zip(interfaces.iter()) {
+            ep.inputs = inputs.iter().map(|&index| globals[index]).collect();
+            ep.outputs = outputs.iter().map(|&index| globals[index]).collect();
+        }
+        assert_eq!(Validator::new().validate(&module), Ok(()));
+
+        let mut module = crate::front::wgsl::parse_str("
+            [[location 0]] var<out> o_color : vec4<f32>;
+            [[location 0]] var<out> o_normal : vec4<f32>;
+            fn main() -> void {
+                o_color = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+                o_normal = o_color;
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ").unwrap();
+        let globals = module.global_variables.iter().map(|(token, _)| token).collect::<Vec<_>>();
+        module.entry_points[0].outputs = globals.clone();
+        assert_eq!(
+            Validator::new().validate(&module),
+            Err(ValidationError::EntryPoint(0, EntryPointError::DuplicateBinding(globals[1]))),
+        );
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        let mut module = crate::Module::generate_empty();
+        let mut fun = empty_function();
+        fun.body.push(crate::Statement::Break);
+        let token = module.functions.append(fun);
+        assert_eq!(
+            Validator::new().validate(&module),
+            Err(ValidationError::Function(token, FunctionError::BreakOutsideLoop(0))),
+        );
+    }
+
+    #[test]
+    fn missing_return_value() {
+        let mut module = crate::Module::generate_empty();
+        let ty = module.types.append(crate::Type {
+            name: None,
+            inner: crate::TypeInner::Scalar { kind: crate::ScalarKind::Sint, width: 32 },
+        });
+        let mut fun = empty_function();
+        fun.return_type = Some(ty);
+        fun.body.push(crate::Statement::Return { value: None });
+        let token = module.functions.append(fun);
+        assert_eq!(
+            Validator::new().validate(&module),
+            Err(ValidationError::Function(token, FunctionError::InvalidReturnValue(0))),
+        );
+    }
+}